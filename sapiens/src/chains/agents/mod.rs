@@ -1,10 +1,17 @@
 /// OODA agents
 pub mod ooda;
 
+use std::time::Duration;
+
+use tracing::{info_span, warn, Instrument};
+
 use crate::chains::Outcome;
-use crate::context;
+use crate::context::ChatEntry;
+use crate::models::{ChatInput, ModelResponse, Role, ToolCalling, Usage};
 use crate::prompt::Task;
-use crate::tools::ToolUseError;
+use crate::tools::toolbox::Toolbox;
+use crate::tools::{LongResultPolicy, ToolDescription, ToolUseError};
+use crate::{context, SapiensConfig};
 
 /// Error from the agent
 #[derive(thiserror::Error, Debug)]
@@ -15,6 +22,81 @@ pub enum Error {
     /// Error from the model
     #[error("Error from the model: {0}")]
     ModelError(#[from] crate::models::Error),
+    /// The model did not respond in time - see
+    /// [`SapiensConfig::model_timeout`]
+    #[error("The model did not respond within {0:?}")]
+    ModelTimeout(Duration),
+}
+
+/// What the model is told when it responds with nothing
+const EMPTY_RESPONSE_NUDGE: &str =
+    "Your response was empty. Respond in the format described above.";
+
+/// Query the model of `config` with `input` - again, with a nudge, when it
+/// responds with nothing, up to [`SapiensConfig::empty_response_retries`]
+/// times
+///
+/// The usage of all the queries is accounted for in the response. Each query
+/// fails with [`Error::ModelTimeout`] if the model takes longer than
+/// [`SapiensConfig::model_timeout`] to respond.
+pub(crate) async fn query_model(
+    config: &SapiensConfig,
+    mut input: ChatInput,
+    max_tokens: usize,
+) -> Result<ModelResponse, Error> {
+    let mut usage: Option<Usage> = None;
+    let mut retries = 0;
+
+    loop {
+        let query = config
+            .model
+            .query(input.clone(), Some(max_tokens))
+            .instrument(info_span!("query_model"));
+
+        let res = match config.model_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, query).await.map_err(|_| {
+                warn!(?timeout, "The model did not respond in time");
+                Error::ModelTimeout(timeout)
+            })?,
+            None => query.await,
+        }?
+        .with_estimated_usage(&**config.model, input.clone())
+        .await;
+
+        usage = match (usage, &res.usage) {
+            (Some(mut usage), Some(more)) => {
+                usage += more;
+                Some(usage)
+            }
+            (usage, more) => usage.or_else(|| more.clone()),
+        };
+
+        if !res.msg.trim().is_empty()
+            || !res.tool_calls.is_empty()
+            || retries >= config.empty_response_retries
+        {
+            return Ok(ModelResponse { usage, ..res });
+        }
+
+        retries += 1;
+        warn!(retries, "Empty response from the model - retrying");
+        input.chat.push(ChatEntry {
+            role: Role::User,
+            msg: EMPTY_RESPONSE_NUDGE.to_string(),
+        });
+    }
+}
+
+/// The tools of `toolbox` the model can call natively - none unless
+/// [`SapiensConfig::tool_calling`] is [`ToolCalling::Native`]
+pub(crate) async fn callable_tools(
+    config: &SapiensConfig,
+    toolbox: &Toolbox,
+) -> Vec<ToolDescription> {
+    match config.tool_calling {
+        ToolCalling::Yaml => vec![],
+        ToolCalling::Native => toolbox.describe().await.into_values().collect(),
+    }
 }
 
 /// Format the outcome of a task
@@ -24,23 +106,48 @@ pub(crate) fn format_outcome(
     invocation_count: usize,
     tool_name: &Option<String>,
     outcome: &Outcome,
+    max_response_chars: usize,
+    long_results: LongResultPolicy,
 ) -> String {
-    /// Maximum number of characters in the response
-    const MAX_RESPONSE_CHAR: usize = 2048;
-
     match outcome {
-        Outcome::Success { result } => {
+        Outcome::Success {
+            result,
+            hint,
+            rendered,
+            all_ran,
+        } => {
+            // a result rendered by the tool is shown instead of the YAML
+            let formatted = rendered.is_some();
+            let result = rendered.as_ref().unwrap_or(result);
+
+            // no need to warn about the ignored Actions if they all ran
+            let invocation_count = if *all_ran { 1 } else { invocation_count };
+
             let msg = Task::action_success_prompt(
                 tool_name.clone().unwrap_or_else(|| "unknown".to_string()),
                 invocation_count,
                 result,
+                hint.as_deref(),
+                formatted,
             );
 
-            // if the response is too long, we add an error message to the chat
-            // history instead
-            if msg.len() > MAX_RESPONSE_CHAR {
+            // if the response is too long, we either keep only its beginning -
+            // a stashed result is already short, unless the limit is tiny...
+            if msg.len() > max_response_chars && long_results != LongResultPolicy::Reject {
+                let overflow = msg.len() - max_response_chars;
+                let msg = Task::action_success_prompt(
+                    tool_name.clone().unwrap_or_else(|| "unknown".to_string()),
+                    invocation_count,
+                    truncate(result, result.len().saturating_sub(overflow)),
+                    hint.as_deref(),
+                    formatted,
+                );
+                format!("{}\n{}", msg, task.to_prompt())
+            }
+            // ... or we add an error message to the chat history instead
+            else if msg.len() > max_response_chars {
                 let msg = format!("The response is too long ({}B). Max allowed is {}B. Ask for a shorter response or use SandboxedPython Tool to process the response the data.",
-                                      msg.len(), MAX_RESPONSE_CHAR);
+                                      msg.len(), max_response_chars);
                 let e = ToolUseError::InvocationFailed(msg);
                 let msg = Task::action_failed_prompt(
                     tool_name.clone().unwrap_or_else(|| "unknown".to_string()),
@@ -65,3 +172,252 @@ pub(crate) fn format_outcome(
         }
     }
 }
+
+/// Keep at most `max_len` bytes of `result` - followed by a marker telling how
+/// many were cut
+fn truncate(result: &str, max_len: usize) -> String {
+    let mut at = max_len.min(result.len());
+    while !result.is_char_boundary(at) {
+        at -= 1;
+    }
+
+    format!(
+        "{}...[truncated {} bytes]\n",
+        &result[..at],
+        result.len() - at
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chains::Message;
+    use crate::prompt::{Manager, PromptTemplates};
+    use crate::tools::redact::Redactor;
+    use crate::tools::toolbox::{invoke_tool, InvokeResult, Toolbox};
+    use crate::tools::{Format, Tool, ToolDescription};
+
+    fn task() -> Task {
+        Manager::new(Toolbox::default(), PromptTemplates::default()).build_task_prompt("Do it")
+    }
+
+    fn success(result: &str) -> Outcome {
+        Outcome::Success {
+            result: result.to_string(),
+            hint: None,
+            rendered: None,
+            all_ran: false,
+        }
+    }
+
+    #[test]
+    fn accepts_a_result_within_the_configured_limit() {
+        let tool_name = Some("Dummy".to_string());
+
+        let entry = format_outcome(
+            &task(),
+            1,
+            &tool_name,
+            &success("a: 1\n"),
+            64,
+            LongResultPolicy::Reject,
+        );
+
+        assert!(entry.starts_with("# Action Dummy response: \n```yaml\na: 1\n```"));
+        assert!(!entry.contains("too long"));
+    }
+
+    #[test]
+    fn rejects_a_result_over_the_configured_limit() {
+        let tool_name = Some("Dummy".to_string());
+        let result = format!("a: {}\n", "x".repeat(100));
+
+        let entry = format_outcome(
+            &task(),
+            1,
+            &tool_name,
+            &success(&result),
+            64,
+            LongResultPolicy::Reject,
+        );
+
+        assert!(entry.contains("Max allowed is 64B."));
+        assert!(!entry.contains(&result));
+    }
+
+    #[test]
+    fn truncates_a_result_over_the_configured_limit() {
+        let tool_name = Some("Dummy".to_string());
+        let result = format!("a: {}\n", "x".repeat(100));
+
+        let entry = format_outcome(
+            &task(),
+            1,
+            &tool_name,
+            &success(&result),
+            64,
+            LongResultPolicy::Truncate,
+        );
+
+        assert!(!entry.contains("too long"));
+        assert!(entry.starts_with("# Action Dummy response: \n```yaml\na: xxx"));
+        assert!(entry.contains("x...[truncated 77 bytes]\n```"));
+    }
+
+    #[test]
+    fn warns_about_the_ignored_actions() {
+        let tool_name = Some("Dummy".to_string());
+
+        let entry = format_outcome(
+            &task(),
+            2,
+            &tool_name,
+            &success("a: 1\n"),
+            2048,
+            LongResultPolicy::Reject,
+        );
+        assert!(entry.contains("Only one of them was considered."));
+
+        let outcome = Outcome::Success {
+            result: "a: 1\n".to_string(),
+            hint: None,
+            rendered: None,
+            all_ran: true,
+        };
+        let entry = format_outcome(
+            &task(),
+            2,
+            &tool_name,
+            &outcome,
+            2048,
+            LongResultPolicy::Reject,
+        );
+        assert!(entry.starts_with("# Action Dummy response: \n```yaml\na: 1\n```"));
+    }
+
+    #[test]
+    fn appends_the_hint_of_the_tool() {
+        let tool_name = Some("LightStatus".to_string());
+        let outcome = Outcome::Success {
+            result: "on: true\n".to_string(),
+            hint: Some("Use SetLightStatus to change the state of a light.".to_string()),
+            rendered: None,
+            all_ran: false,
+        };
+
+        let entry = format_outcome(
+            &task(),
+            1,
+            &tool_name,
+            &outcome,
+            2048,
+            LongResultPolicy::Reject,
+        );
+
+        assert!(entry.starts_with(
+            "# Action LightStatus response: \n```yaml\non: true\n```\nHint: Use SetLightStatus to change the state of a light.\n"
+        ));
+    }
+
+    /// A tool that renders its output as a single line
+    struct RoomsTool;
+
+    #[async_trait::async_trait]
+    impl Tool for RoomsTool {
+        fn description(&self) -> ToolDescription {
+            ToolDescription::new(
+                "Rooms",
+                "Lists the rooms",
+                Format::default(),
+                Format::default(),
+            )
+        }
+
+        fn format_result(&self, output: &serde_yaml::Value) -> Option<String> {
+            let rooms = output["rooms"]
+                .as_sequence()?
+                .iter()
+                .filter_map(serde_yaml::Value::as_str)
+                .collect::<Vec<_>>();
+            Some(format!("rooms: {}\n", rooms.join(", ")))
+        }
+
+        async fn invoke(
+            &self,
+            _input: serde_yaml::Value,
+        ) -> Result<serde_yaml::Value, ToolUseError> {
+            Ok(serde_yaml::from_str("rooms: [Kitchen, Bedroom]").unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn shows_the_result_as_rendered_by_the_tool() {
+        let toolbox = Toolbox::default();
+        toolbox.add_tool(RoomsTool).await;
+
+        let res = invoke_tool(toolbox, "```yaml\ntool_name: Rooms\nparameters: {}\n```\n").await;
+
+        // the result is still the YAML for the others
+        let InvokeResult::Success { result, .. } = &res else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert_eq!(result, "rooms:\n- Kitchen\n- Bedroom\n");
+
+        let Message::ActionResult {
+            tool_name, outcome, ..
+        } = res.into()
+        else {
+            panic!("not an action result");
+        };
+        let entry = format_outcome(
+            &task(),
+            1,
+            &tool_name,
+            &outcome,
+            2048,
+            LongResultPolicy::Reject,
+        );
+
+        assert!(entry.starts_with("# Action Rooms response: \nrooms: Kitchen, Bedroom\n"));
+        assert!(!entry.contains("```yaml"));
+    }
+
+    #[test]
+    fn truncates_on_a_char_boundary() {
+        assert_eq!(truncate("été", 2), "é...[truncated 3 bytes]\n");
+        assert_eq!(truncate("ab", 5), "ab...[truncated 0 bytes]\n");
+    }
+
+    #[test]
+    fn hides_the_secrets_of_a_result() {
+        let res = InvokeResult::Success {
+            invocation_count: 1,
+            invocation_index: 0,
+            all_ran: true,
+            tool_name: "Dummy".to_string(),
+            extracted_input: "tool_name: Dummy".to_string(),
+            result: "api_key: sk-proj-aBcDeFgHiJkLmNoPqRsTuVwXyZ012345\n".to_string(),
+            hint: None,
+            rendered: None,
+            artifacts: vec![],
+        };
+
+        let Message::ActionResult {
+            tool_name, outcome, ..
+        } = Redactor::default().redact_result(res).into()
+        else {
+            panic!("not an action result");
+        };
+        let entry = format_outcome(
+            &task(),
+            1,
+            &tool_name,
+            &outcome,
+            2048,
+            LongResultPolicy::Reject,
+        );
+
+        assert!(entry.contains("api_key: [REDACTED]\n"));
+        assert!(!entry.contains("sk-proj"));
+    }
+}
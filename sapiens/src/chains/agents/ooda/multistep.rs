@@ -2,14 +2,15 @@ use std::fmt::{Debug, Formatter};
 
 use tracing::{debug, trace};
 
-use crate::chains::agents::{format_outcome, Error};
+use crate::chains::agents::{callable_tools, format_outcome, query_model, Error};
 use crate::chains::{Context, Message};
 use crate::context::{ChatEntry, ChatHistory};
 use crate::models::Role;
+use crate::prompt::{PromptTemplates, WarmUpExample, WarmUpTurn};
 use crate::tools::toolbox::Toolbox;
 use crate::{chains, prompt, SapiensConfig, WeakRuntimeObserver};
 
-const PREFIX: &str = r"You are part of a group of cooperating assistants named Sapiens. Use available tools to answer the question as best as you can.
+const PREFIX: &str = r"You are part of a group of cooperating assistants named {agent_name}. Use available tools to answer the question as best as you can.
 You will collectively proceed iteratively using an OODA loop. Don't overstep your role.
 
 - Action response will be provided. 
@@ -259,10 +260,8 @@ impl AgentRole {
         &self,
         mut chat_history: ChatHistory,
         context: &Context,
+        config: &SapiensConfig,
     ) -> Result<ChatHistory, Error> {
-        // build the examples
-        let examples = self.build_examples();
-
         let prompt_manager = match self {
             Self::Observer { prompt_manager }
             | Self::Orienter { prompt_manager }
@@ -272,7 +271,7 @@ impl AgentRole {
 
         // Add the prompts to the chat history
         prompt_manager
-            .populate_chat_history(&mut chat_history, examples)
+            .populate_chat_history(&mut chat_history)
             .await;
 
         // Convert the context to a chat history
@@ -320,12 +319,18 @@ impl AgentRole {
                             outcome,
                             ..
                         } => {
-                            let entry =
-                                format_outcome(&task, *invocation_count, tool_name, outcome);
+                            let entry = format_outcome(
+                                &task,
+                                *invocation_count,
+                                tool_name,
+                                outcome,
+                                config.max_tool_result_chars,
+                                config.long_tool_results,
+                            );
 
                             user_msg.push(entry);
                         }
-                        Message::Task { .. } => {
+                        Message::Conversation { .. } | Message::Task { .. } => {
                             // Nothing
                         }
                     }
@@ -365,12 +370,18 @@ impl AgentRole {
                             outcome,
                             ..
                         } => {
-                            let entry =
-                                format_outcome(&task, *invocation_count, tool_name, outcome);
+                            let entry = format_outcome(
+                                &task,
+                                *invocation_count,
+                                tool_name,
+                                outcome,
+                                config.max_tool_result_chars,
+                                config.long_tool_results,
+                            );
 
                             user_msg.push(entry);
                         }
-                        Message::Task { .. } => {
+                        Message::Conversation { .. } | Message::Task { .. } => {
                             // Nothing
                         }
                     }
@@ -408,12 +419,18 @@ impl AgentRole {
                             outcome,
                             ..
                         } => {
-                            let entry =
-                                format_outcome(&task, *invocation_count, tool_name, outcome);
+                            let entry = format_outcome(
+                                &task,
+                                *invocation_count,
+                                tool_name,
+                                outcome,
+                                config.max_tool_result_chars,
+                                config.long_tool_results,
+                            );
 
                             user_msg.push(entry);
                         }
-                        Message::Task { .. } => {
+                        Message::Conversation { .. } | Message::Task { .. } => {
                             // Nothing
                         }
                     }
@@ -450,12 +467,18 @@ impl AgentRole {
                             outcome,
                             ..
                         } => {
-                            let entry =
-                                format_outcome(&task, *invocation_count, tool_name, outcome);
+                            let entry = format_outcome(
+                                &task,
+                                *invocation_count,
+                                tool_name,
+                                outcome,
+                                config.max_tool_result_chars,
+                                config.long_tool_results,
+                            );
 
                             user_msg.push(entry);
                         }
-                        Message::Task { .. } => {
+                        Message::Conversation { .. } | Message::Task { .. } => {
                             // Nothing
                         }
                     }
@@ -479,99 +502,66 @@ impl AgentRole {
             });
         }
 
+        // Add the conversation the task comes from before everything else
+        chat_history.prepend_chitchat(context.conversation());
+
         // prune the history if needed
         chat_history.purge().await?;
 
         Ok(chat_history)
     }
+}
 
-    fn build_examples(&self) -> Vec<(String, String)> {
-        match self {
-            Self::Observer { prompt_manager } => {
-                let warmup_task =
-                    prompt_manager.build_task_prompt("Sort in ascending order: [2, 3, 1, 4, 5]");
-
-                vec![
-                    (
-                        warmup_task.to_prompt(),
-                        OBSERVER_PROTO_INITIAL_RESPONSE.trim().to_string(),
-                    ),
-                    (
-                        (format!(
-                            "{}{}",
-                            OBSERVER_PROTO_SECOND_INPUT.trim(),
-                            warmup_task.to_prompt()
-                        ))
-                        .trim()
-                        .to_string(),
-                        OBSERVER_PROTO_SECOND_RESPONSE.trim().to_string(),
-                    ),
-                ]
-            }
-            Self::Orienter { prompt_manager } => {
-                let warmup_task =
-                    prompt_manager.build_task_prompt("Sort in ascending order: [2, 3, 1, 4, 5]");
-
-                vec![
-                    (
-                        warmup_task.to_prompt(),
-                        ORIENTER_PROTO_INITIAL_RESPONSE.trim().to_string(),
-                    ),
-                    (
-                        (format!(
-                            "{}{}",
-                            ORIENTER_PROTO_SECOND_INPUT.trim(),
-                            warmup_task.to_prompt()
-                        ))
-                        .trim()
-                        .to_string(),
-                        ORIENTER_PROTO_SECOND_RESPONSE.trim().to_string(),
-                    ),
-                ]
-            }
-            Self::Decider { prompt_manager } => {
-                let warmup_task =
-                    prompt_manager.build_task_prompt("Sort in ascending order: [2, 3, 1, 4, 5]");
-
-                vec![
-                    (
-                        warmup_task.to_prompt(),
-                        DECIDER_PROTO_INITIAL_RESPONSE.trim().to_string(),
-                    ),
-                    (
-                        (format!(
-                            "{}{}",
-                            DECIDER_PROTO_SECOND_INPUT.trim(),
-                            warmup_task.to_prompt()
-                        ))
-                        .trim()
-                        .to_string(),
-                        DECIDER_PROTO_SECOND_RESPONSE.trim().to_string(),
-                    ),
-                ]
-            }
-            Self::Actor { prompt_manager } => {
-                let warmup_task =
-                    prompt_manager.build_task_prompt("Sort in ascending order: [2, 3, 1, 4, 5]");
-
-                vec![
-                    (
-                        warmup_task.to_prompt(),
-                        ACTOR_PROTO_INITIAL_RESPONSE.trim().to_string(),
-                    ),
-                    (
-                        (format!(
-                            "{}{}",
-                            ACTOR_PROTO_SECOND_INPUT.trim(),
-                            warmup_task.to_prompt()
-                        ))
-                        .trim()
-                        .to_string(),
-                        ACTOR_PROTO_SECOND_RESPONSE.trim().to_string(),
-                    ),
-                ]
-            }
-        }
+/// The example of the role: sorting a list
+fn sort_example(
+    initial_response: &str,
+    second_input: &str,
+    second_response: &str,
+) -> Vec<WarmUpExample> {
+    vec![WarmUpExample {
+        task: "Sort in ascending order: [2, 3, 1, 4, 5]".to_string(),
+        turns: vec![
+            WarmUpTurn {
+                response: initial_response.to_string(),
+                action_result: Some(second_input.trim().to_string()),
+            },
+            WarmUpTurn {
+                response: second_response.to_string(),
+                action_result: None,
+            },
+        ],
+    }]
+}
+
+/// The templates of an agent of the group: its role-specific prompts with the
+/// `agent_name`, `persona`, `world` and `acknowledgment` of the
+/// [`SapiensConfig::prompt_templates`]
+fn templates(
+    config: &SapiensConfig,
+    system_prompt: &str,
+    prompt: &str,
+    response_format: &str,
+    examples: Vec<WarmUpExample>,
+) -> PromptTemplates {
+    let PromptTemplates {
+        agent_name,
+        persona,
+        world,
+        acknowledgment,
+        ..
+    } = config.prompt_templates.clone().unwrap_or_default();
+
+    PromptTemplates {
+        agent_name,
+        persona,
+        world,
+        system_prompt: system_prompt.to_string(),
+        prompt: prompt.to_string(),
+        prefix: PREFIX.to_string(),
+        tool_prefix: TOOL_PREFIX.to_string(),
+        response_format: response_format.to_string(),
+        examples,
+        acknowledgment,
     }
 }
 
@@ -590,20 +580,21 @@ impl Agent {
         toolbox: Toolbox,
         observer: WeakRuntimeObserver,
     ) -> Self {
-        let system_prompt =
-            "You are part of Sapiens agents and your role is to observe and report.".to_string();
-
-        let prompt = "What are your observations?".to_string();
-
-        let prompt_manager = prompt::Manager::new(
-            toolbox,
-            system_prompt,
-            prompt,
-            PREFIX.to_string(),
-            TOOL_PREFIX.to_string(),
-            OBSERVER_RESPONSE_FORMAT.to_string(),
+        let templates = templates(
+            &config,
+            "You are part of {agent_name} agents and your role is to observe and report.",
+            "What are your observations?",
+            OBSERVER_RESPONSE_FORMAT,
+            sort_example(
+                OBSERVER_PROTO_INITIAL_RESPONSE,
+                OBSERVER_PROTO_SECOND_INPUT,
+                OBSERVER_PROTO_SECOND_RESPONSE,
+            ),
         );
 
+        let prompt_manager =
+            prompt::Manager::new(toolbox, templates).with_clock(config.clock.clone());
+
         Self {
             role: AgentRole::Observer { prompt_manager },
             config,
@@ -618,21 +609,17 @@ impl Agent {
         toolbox: Toolbox,
         observer: WeakRuntimeObserver,
     ) -> Self {
-        let system_prompt =
-            "You are part of Sapiens agents and your role is to orient the other agents based on the observations."
-                .to_string();
-
-        let prompt = "What is your orientation?".to_string();
-
-        let prompt_manager = prompt::Manager::new(
-            toolbox,
-            system_prompt,
-            prompt,
-            PREFIX.to_string(),
-            TOOL_PREFIX.to_string(),
-            ORIENTER_RESPONSE_FORMAT.to_string(),
+        let templates = templates(
+            &config,
+            "You are part of {agent_name} agents and your role is to orient the other agents based on the observations.",
+            "What is your orientation?",
+            ORIENTER_RESPONSE_FORMAT,
+            sort_example(ORIENTER_PROTO_INITIAL_RESPONSE, ORIENTER_PROTO_SECOND_INPUT, ORIENTER_PROTO_SECOND_RESPONSE),
         );
 
+        let prompt_manager =
+            prompt::Manager::new(toolbox, templates).with_clock(config.clock.clone());
+
         Self {
             role: AgentRole::Orienter { prompt_manager },
             config,
@@ -647,21 +634,17 @@ impl Agent {
         toolbox: Toolbox,
         observer: WeakRuntimeObserver,
     ) -> Self {
-        let system_prompt =
-            "You are part of Sapiens agents and your role is to decide what need to be done based on the observations and guidance you got."
-                .to_string();
-
-        let prompt = "What is your decision?".to_string();
-
-        let prompt_manager = prompt::Manager::new(
-            toolbox,
-            system_prompt,
-            prompt,
-            PREFIX.to_string(),
-            TOOL_PREFIX.to_string(),
-            DECIDER_RESPONSE_FORMAT.to_string(),
+        let templates = templates(
+            &config,
+            "You are part of {agent_name} agents and your role is to decide what need to be done based on the observations and guidance you got.",
+            "What is your decision?",
+            DECIDER_RESPONSE_FORMAT,
+            sort_example(DECIDER_PROTO_INITIAL_RESPONSE, DECIDER_PROTO_SECOND_INPUT, DECIDER_PROTO_SECOND_RESPONSE),
         );
 
+        let prompt_manager =
+            prompt::Manager::new(toolbox, templates).with_clock(config.clock.clone());
+
         Self {
             role: AgentRole::Decider { prompt_manager },
             config,
@@ -676,21 +659,19 @@ impl Agent {
         toolbox: Toolbox,
         observer: WeakRuntimeObserver,
     ) -> Self {
-        let system_prompt =
-            "You are part of Sapiens agents and your role is to act on the world as it has been decided."
-                .to_string();
-
-        let prompt = "What is your action?".to_string();
-
-        let prompt_manager = prompt::Manager::new(
-            toolbox,
-            system_prompt,
-            prompt,
-            PREFIX.to_string(),
-            TOOL_PREFIX.to_string(),
-            ACTOR_RESPONSE_FORMAT.to_string(),
+        let templates = templates(
+            &config,
+            "You are part of {agent_name} agents and your role is to act on the world as it has been decided.",
+            "What is your action?",
+            ACTOR_RESPONSE_FORMAT,
+            sort_example(ACTOR_PROTO_INITIAL_RESPONSE, ACTOR_PROTO_SECOND_INPUT, ACTOR_PROTO_SECOND_RESPONSE),
         );
 
+        let prompt_manager = prompt::Manager::new(toolbox, templates)
+            .with_clock(config.clock.clone())
+            // Only the Actor is expected to call the tools
+            .with_tool_calling(config.tool_calling);
+
         Self {
             role: AgentRole::Actor { prompt_manager },
             config,
@@ -702,12 +683,10 @@ impl Agent {
         &self,
         context: &Context,
     ) -> Result<ChatHistory, Error> {
-        let max_token = self.config.model.context_size().await;
-
         // Create a new chat history
-        let chat_history = ChatHistory::new(self.config.clone(), max_token);
+        let chat_history = ChatHistory::for_model(self.config.clone()).await;
         self.role
-            .convert_context_to_chat_history(chat_history, context)
+            .convert_context_to_chat_history(chat_history, context, &self.config)
             .await
     }
 }
@@ -720,11 +699,16 @@ impl chains::Agent for Agent {
         let chat_history = self.convert_context_to_chat_history(context).await?;
 
         // Query the model
-        let input = chat_history.make_input();
+        let mut input = context.make_input(&chat_history, self.config.temperature_ramp);
+        // Only the Actor is expected to call the tools
+        if let AgentRole::Actor { prompt_manager } = &self.role {
+            input.tools = callable_tools(&self.config, prompt_manager.toolbox()).await;
+        }
+        let max_tokens = chat_history.max_completion_tokens().await;
 
         debug!(
             min_tokens = self.config.min_tokens_for_completion,
-            max_tokens = self.config.max_tokens,
+            max_tokens,
             role = ?self.role,
             "Querying model with {} entries",
             input.chat.len()
@@ -732,11 +716,16 @@ impl chains::Agent for Agent {
 
         trace!("Querying model:\n{:#?}", input);
 
-        let res = self
-            .config
-            .model
-            .query(input, self.config.max_tokens)
-            .await?;
+        let res = query_model(&self.config, input, max_tokens).await?;
+
+        // Only the Actor is expected to give an Action
+        let res = match &self.role {
+            AgentRole::Actor { prompt_manager } => {
+                res.select_action(prompt_manager.toolbox(), self.config.multiple_actions)
+                    .await
+            }
+            _ => res,
+        };
 
         trace!("Got model response:\n{:#?}", res);
 
@@ -764,8 +753,9 @@ impl chains::Agent for Agent {
                 usage: res.usage,
             }),
             AgentRole::Actor { .. } => Ok(Message::Action {
-                content: res.msg,
+                content: res.msg_with_tool_calls(),
                 usage: res.usage,
+                tool_calls: res.tool_calls,
             }),
         }
     }
@@ -782,6 +772,37 @@ mod tests {
     use crate::chains::Outcome;
     use crate::void_observer;
 
+    #[test]
+    fn uses_the_configured_identity_of_the_agents() {
+        let config = SapiensConfig {
+            prompt_templates: Some(PromptTemplates {
+                agent_name: "Felix".to_string(),
+                persona: "a cat".to_string(),
+                world: "the household".to_string(),
+                acknowledgment: Some("Meow.".to_string()),
+                ..PromptTemplates::default()
+            }),
+            ..SapiensConfig::default()
+        };
+
+        let templates = templates(
+            &config,
+            "system",
+            "prompt",
+            OBSERVER_RESPONSE_FORMAT,
+            vec![],
+        );
+
+        assert_eq!(templates.agent_name, "Felix");
+        assert_eq!(templates.persona, "a cat");
+        assert_eq!(templates.world, "the household");
+        assert_eq!(templates.acknowledgment.as_deref(), Some("Meow."));
+        // the prompts of the role are kept
+        assert_eq!(templates.system_prompt, "system");
+        assert_eq!(templates.prefix, PREFIX);
+        assert_eq!(templates.response_format, OBSERVER_RESPONSE_FORMAT);
+    }
+
     #[tokio::test]
     async fn observer_converts_context_to_chat_history() {
         let context = build_dummy_context();
@@ -972,6 +993,7 @@ mod tests {
             .trim()
             .to_string(),
             usage: None,
+            tool_calls: Vec::new(),
         });
 
         context.add_message(Message::ActionResult {
@@ -997,6 +1019,9 @@ mod tests {
                 "}
                 .trim()
                 .to_string(),
+                hint: None,
+                rendered: None,
+                all_ran: false,
             },
         });
         context
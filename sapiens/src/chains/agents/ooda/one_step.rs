@@ -1,9 +1,10 @@
 use tracing::{debug, trace};
 
-use crate::chains::agents::{format_outcome, Error};
+use crate::chains::agents::{callable_tools, format_outcome, query_model, Error};
 use crate::chains::{Context, Message};
 use crate::context::{ChatEntry, ChatHistory};
 use crate::models::Role;
+use crate::prompt::{WarmUpExample, WarmUpTurn};
 use crate::tools::toolbox::Toolbox;
 use crate::{chains, prompt, SapiensConfig, WeakRuntimeObserver};
 
@@ -14,7 +15,12 @@ pub struct Agent {
     observer: WeakRuntimeObserver,
 }
 
-const PREFIX: &str = r"You are Sapiens, a large language model assisting the WORLD. Use available tools to answer the question as best as you can.
+pub(crate) const SYSTEM_PROMPT: &str =
+    "You are an agent named {agent_name} interacting with {world}. Listen to {world}!";
+
+pub(crate) const PROMPT: &str = "Do you have the answer? Use the Conclude Tool to terminate the task.\nObservations, Orientation, Decision, The ONLY Action?";
+
+pub(crate) const PREFIX: &str = r"You are {agent_name}, {persona} assisting {world}. Use available tools to answer the question as best as you can.
 You will proceed iteratively using an OODA loop.
 
 - Action response will be provided to you. 
@@ -25,11 +31,11 @@ You will proceed iteratively using an OODA loop.
 - You cannot use jinja2 templating in your response. Be concise. 
 ";
 
-const TOOL_PREFIX: &str = r"
+pub(crate) const TOOL_PREFIX: &str = r"
 # The following are the ONLY Tools you can use for your Actions:
 ";
 
-const RESPONSE_FORMAT: &str = r"
+pub(crate) const RESPONSE_FORMAT: &str = r"
 # Format of your response
 
 You must use the following format for your response. Comments are in bold and should be removed from your response.
@@ -113,24 +119,32 @@ parameters:
 ```
 ";
 
+/// The default example: sorting a list with Python
+pub(crate) fn examples() -> Vec<WarmUpExample> {
+    vec![WarmUpExample {
+        task: "Sort in ascending order: [2, 3, 1, 4, 5]".to_string(),
+        turns: vec![
+            WarmUpTurn {
+                response: PROTO_EXCHANGE_2.to_string(),
+                action_result: Some(PROTO_EXCHANGE_3.to_string()),
+            },
+            WarmUpTurn {
+                response: PROTO_EXCHANGE_4.to_string(),
+                action_result: None,
+            },
+        ],
+    }]
+}
+
 impl Agent {
     /// Create a new [`Agent`].
     #[must_use]
     pub fn new(config: SapiensConfig, toolbox: Toolbox, observer: WeakRuntimeObserver) -> Self {
-        let system_prompt =
-            "You are an agent named Sapiens interacting with the WORLD. Listen to the WORLD!"
-                .to_string();
-
-        let prompt = "Do you have the answer? Use the Conclude Tool to terminate the task.\nObservations, Orientation, Decision, The ONLY Action?".to_string();
-
-        let prompt_manager = prompt::Manager::new(
-            toolbox,
-            system_prompt,
-            prompt,
-            PREFIX.to_string(),
-            TOOL_PREFIX.to_string(),
-            RESPONSE_FORMAT.to_string(),
-        );
+        let templates = config.prompt_templates.clone().unwrap_or_default();
+
+        let prompt_manager = prompt::Manager::new(toolbox, templates)
+            .with_clock(config.clock.clone())
+            .with_tool_calling(config.tool_calling);
         Self {
             prompt_manager,
             config,
@@ -143,26 +157,11 @@ impl Agent {
         context: &Context,
     ) -> Result<ChatHistory, Error> {
         // Create a new chat history
-        let max_token = { self.config.model.context_size().await };
-        let mut chat_history = ChatHistory::new(self.config.clone(), max_token);
-
-        let warmup_task = self
-            .prompt_manager
-            .build_task_prompt("Sort in ascending order: [2, 3, 1, 4, 5]");
-
-        let examples = vec![
-            (warmup_task.to_prompt(), PROTO_EXCHANGE_2.trim().to_string()),
-            (
-                (format!("{}{}", PROTO_EXCHANGE_3, warmup_task.to_prompt()))
-                    .trim()
-                    .to_string(),
-                PROTO_EXCHANGE_4.trim().to_string(),
-            ),
-        ];
+        let mut chat_history = ChatHistory::for_model(self.config.clone()).await;
 
         // Add the prompts to the chat history
         self.prompt_manager
-            .populate_chat_history(&mut chat_history, examples)
+            .populate_chat_history(&mut chat_history)
             .await;
 
         // Convert the context to a chat history
@@ -186,7 +185,14 @@ impl Agent {
                     outcome,
                     ..
                 } => {
-                    let entry = format_outcome(&task, *invocation_count, tool_name, outcome);
+                    let entry = format_outcome(
+                        &task,
+                        *invocation_count,
+                        tool_name,
+                        outcome,
+                        self.config.max_tool_result_chars,
+                        self.config.long_tool_results,
+                    );
 
                     // add an error message to the chat history
                     let entry = ChatEntry {
@@ -211,6 +217,9 @@ impl Agent {
             });
         }
 
+        // Add the conversation the task comes from before everything else
+        chat_history.prepend_chitchat(context.conversation());
+
         // prune the history if needed
         chat_history.purge().await?;
 
@@ -226,22 +235,23 @@ impl chains::Agent for Agent {
         let chat_history = self.convert_context_to_chat_history(context).await?;
 
         // Query the model
-        let input = chat_history.make_input();
+        let mut input = context.make_input(&chat_history, self.config.temperature_ramp);
+        input.tools = callable_tools(&self.config, self.prompt_manager.toolbox()).await;
+        let max_tokens = chat_history.max_completion_tokens().await;
 
         debug!(
             min_tokens = self.config.min_tokens_for_completion,
-            max_tokens = self.config.max_tokens,
+            max_tokens,
             "Querying model with {} entries",
             input.chat.len()
         );
 
         trace!("Querying model:\n{:#?}", input);
 
-        let res = self
-            .config
-            .model
-            .query(input, self.config.max_tokens)
-            .await?;
+        let res = query_model(&self.config, input, max_tokens)
+            .await?
+            .select_action(self.prompt_manager.toolbox(), self.config.multiple_actions)
+            .await;
 
         trace!("Got model response:\n{:#?}", res);
 
@@ -256,8 +266,9 @@ impl chains::Agent for Agent {
 
         // Return the response as an Action message
         Ok(Message::Action {
-            content: res.msg,
+            content: res.msg_with_tool_calls(),
             usage: res.usage,
+            tool_calls: res.tool_calls,
         })
     }
 }
@@ -306,6 +317,7 @@ mod tests {
             "#
             }.to_string(),
             usage: None,
+            tool_calls: Vec::new(),
         });
 
         context.add_message(Message::ActionResult {
@@ -329,6 +341,9 @@ mod tests {
                 stderr: ''
                 "}
                 .to_string(),
+                hint: None,
+                rendered: None,
+                all_ran: false,
             },
         });
 
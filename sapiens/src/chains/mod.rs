@@ -18,6 +18,8 @@
 pub mod agents;
 /// Schedulers are responsible for deciding which agent to run next.
 pub mod schedulers;
+/// Verification of the conclusions before a task terminates
+pub mod verify;
 
 #[cfg(test)]
 mod tests;
@@ -25,14 +27,38 @@ mod tests;
 use std::fmt::Display;
 
 use serde::{Deserialize, Serialize};
+use tracing::Span;
 
 use crate::chains::agents::ooda::{multistep, one_step};
 use crate::chains::schedulers::{MultiAgentScheduler, SingleAgentScheduler};
-use crate::context::ContextDump;
-use crate::models::Usage;
-use crate::tools::toolbox::{invoke_tool, InvokeResult, Toolbox};
-use crate::tools::{TerminationMessage, ToolUseError};
-use crate::{invocation, SapiensConfig, WeakRuntimeObserver};
+use crate::chains::verify::{ConclusionCheckerRef, Verdict};
+use crate::context::{ChatEntry, ChatHistory, ContextDump};
+use crate::models::{ChatInput, Role, TemperatureRamp, ToolCall, Usage};
+use crate::tools::redact::Redactor;
+use crate::tools::toolbox::{invoke_found, InvokeResult, Toolbox};
+use crate::tools::{
+    chosen_action, find_invocations, LongResultPolicy, MultipleActionsPolicy, TerminationMessage,
+    TerminationStatus, ToolUseError,
+};
+use crate::{invocation, ActionNotification, SapiensConfig, WeakRuntimeObserver};
+
+/// Record the outcome of an invocation in the current span
+fn record_invocation(res: &InvokeResult) {
+    let span = Span::current();
+    match res {
+        InvokeResult::Success { tool_name, .. } => {
+            span.record("tool_name", tool_name.as_str());
+            span.record("success", true);
+        }
+        InvokeResult::Error { tool_name, .. } => {
+            span.record("tool_name", tool_name.as_str());
+            span.record("success", false);
+        }
+        InvokeResult::NoInvocationsFound { .. } | InvokeResult::NoValidInvocationsFound { .. } => {
+            span.record("success", false);
+        }
+    }
+}
 
 /// Outcome of an invocation
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -41,6 +67,17 @@ pub enum Outcome {
     Success {
         /// The result of the invocation
         result: String,
+        /// A suggestion of what to do next - see [`crate::tools::Tool::hint`]
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hint: Option<String>,
+        /// The result as rendered by the tool to be shown to the model
+        /// instead of `result` - see [`crate::tools::Tool::format_result`]
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rendered: Option<String>,
+        /// Whether all the invocations found in the message were run - see
+        /// [`crate::tools::toolbox::invoke_tools_in_parallel`]
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        all_ran: bool,
     },
     /// No valid invocation was found
     NoValidInvocationsFound {
@@ -62,6 +99,14 @@ pub enum Outcome {
 /// A message that can be produced by an agent for another agent
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Message {
+    /// A message of the conversation the task comes from - e.g. the prior
+    /// messages of a chat channel
+    Conversation {
+        /// The author of the message
+        role: Role,
+        /// The message
+        content: String,
+    },
     /// A new task to be performed
     Task {
         /// The description of the task
@@ -94,6 +139,11 @@ pub enum Message {
         content: String,
         /// Token usage
         usage: Option<Usage>,
+        /// The tools called natively by the model - invoked instead of the
+        /// Actions in `content` when there are some - see
+        /// [`crate::models::ToolCalling::Native`]
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        tool_calls: Vec<ToolCall>,
     },
     /// A new result
     ActionResult {
@@ -108,9 +158,24 @@ pub enum Message {
     },
 }
 
+impl Message {
+    /// The token usage of the model call that produced this message, if any
+    #[must_use]
+    pub const fn usage(&self) -> Option<&Usage> {
+        match self {
+            Self::Observation { usage, .. }
+            | Self::Orientation { usage, .. }
+            | Self::Decision { usage, .. }
+            | Self::Action { usage, .. } => usage.as_ref(),
+            Self::Conversation { .. } | Self::Task { .. } | Self::ActionResult { .. } => None,
+        }
+    }
+}
+
 impl Display for Message {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Self::Conversation { role, content } => write!(f, "Conversation ({role}): {content}"),
             Self::Task { content } => write!(f, "Task: {content}"),
             Self::Observation { content,.. } => write!(f, "Observation: {content}"),
             Self::Orientation { content ,..} => write!(f, "Orientation: {content}"),
@@ -149,14 +214,23 @@ impl From<InvokeResult> for Message {
             },
             InvokeResult::Success {
                 invocation_count,
+                all_ran,
                 tool_name,
                 extracted_input,
                 result,
+                hint,
+                rendered,
+                ..
             } => Self::ActionResult {
                 invocation_count,
                 tool_name: Some(tool_name),
                 extracted_input: Some(extracted_input),
-                outcome: Outcome::Success { result },
+                outcome: Outcome::Success {
+                    result,
+                    hint,
+                    rendered,
+                    all_ran,
+                },
             },
             InvokeResult::Error {
                 invocation_count,
@@ -203,18 +277,83 @@ impl Context {
         })
     }
 
+    /// The conversation the task comes from - see [`Message::Conversation`]
+    pub(crate) fn conversation(&self) -> Vec<ChatEntry> {
+        self.messages
+            .iter()
+            .filter_map(|m| match m {
+                Message::Conversation { role, content } => Some(ChatEntry {
+                    role: role.clone(),
+                    msg: content.clone(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the number of actions that failed since the last one that
+    /// succeeded - or since the latest task
+    #[must_use]
+    pub fn consecutive_failures(&self) -> usize {
+        self.messages
+            .iter()
+            .rev()
+            .take_while(|m| {
+                !matches!(
+                    m,
+                    Message::Task { .. }
+                        | Message::ActionResult {
+                            outcome: Outcome::Success { .. },
+                            ..
+                        }
+                )
+            })
+            .filter(|m| matches!(m, Message::ActionResult { .. }))
+            .count()
+    }
+
+    /// The input of the model for `chat_history` - with the temperature
+    /// raised after consecutive failed actions, per `temperature_ramp`
+    pub(crate) fn make_input(
+        &self,
+        chat_history: &ChatHistory,
+        temperature_ramp: Option<TemperatureRamp>,
+    ) -> ChatInput {
+        let mut input = chat_history.make_input();
+        input.temperature_increase =
+            temperature_ramp.map_or(0., |ramp| ramp.increase(self.consecutive_failures()));
+        input
+    }
+
     /// Add a message to the context
     pub fn add_message(&mut self, message: Message) {
         self.messages.push(message);
     }
 }
 
+/// A snapshot of the conversational state of a [`Chain`] - see
+/// [`Chain::checkpoint`]
+///
+/// The tools are not part of it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The messages so far - including the task
+    pub messages: Vec<Message>,
+    /// The token usage so far
+    pub usage: Usage,
+    /// The number of steps run so far
+    pub steps: usize,
+}
+
 /// An error that can occur during the creation or execution of a [`Chain`]
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     /// No terminal tool in the toolbox
     #[error("No terminal tool")]
     NoTerminalTool,
+    /// Several tools of the toolbox have the same name
+    #[error("Several tools are named: {}", .0.join(", "))]
+    DuplicateToolNames(Vec<String>),
     /// Max steps reached
     #[error("Max steps reached")]
     MaxStepsReached,
@@ -247,8 +386,25 @@ pub struct Runtime {
     toolbox: Toolbox,
     scheduler: Box<dyn Scheduler>,
     observer: WeakRuntimeObserver,
+    usage: Usage,
+    steps: usize,
+    max_total_tokens: Option<u32>,
+    /// The last tool invocation (tool name, input) and how many times in a
+    /// row it has been requested
+    last_invocation: Option<((String, String), usize)>,
+    max_repeated_actions: Option<usize>,
+    partial_answer_on_max_steps: bool,
+    parallel_actions: bool,
+    multiple_actions: MultipleActionsPolicy,
+    redactor: Redactor,
+    stashed_results: Option<usize>,
+    conclusion_checker: Option<ConclusionCheckerRef>,
 }
 
+/// How much of a stashed result is shown to the model - see
+/// [`Runtime::with_stashed_results`]
+const STASH_PREVIEW_CHARS: usize = 256;
+
 /// The state of the runtime after it terminates
 pub struct TerminalState {
     /// The messages produced by the runtime when it terminated
@@ -267,14 +423,219 @@ impl Runtime {
             return Err(Error::NoTerminalTool);
         }
 
+        let duplicates = toolbox.duplicate_tool_names().await;
+        if !duplicates.is_empty() {
+            return Err(Error::DuplicateToolNames(duplicates));
+        }
+
         Ok(Self {
             context: Context::default(),
             toolbox,
             scheduler,
             observer,
+            usage: Usage::default(),
+            steps: 0,
+            max_total_tokens: None,
+            last_invocation: None,
+            max_repeated_actions: None,
+            partial_answer_on_max_steps: false,
+            parallel_actions: false,
+            multiple_actions: MultipleActionsPolicy::default(),
+            redactor: Redactor::default(),
+            stashed_results: None,
+            conclusion_checker: None,
         })
     }
 
+    /// Stop the runtime once more than `max_total_tokens` have been used
+    #[must_use]
+    pub const fn with_max_total_tokens(mut self, max_total_tokens: Option<u32>) -> Self {
+        self.max_total_tokens = max_total_tokens;
+        self
+    }
+
+    /// Stop the runtime once the same action has been requested
+    /// `max_repeated_actions` times in a row
+    #[must_use]
+    pub const fn with_max_repeated_actions(mut self, max_repeated_actions: Option<usize>) -> Self {
+        self.max_repeated_actions = max_repeated_actions;
+        self
+    }
+
+    /// Stop with a partial answer - the last successful result, if any -
+    /// when the scheduler runs out of steps instead of failing with
+    /// [`Error::MaxStepsReached`]
+    #[must_use]
+    pub const fn with_partial_answer_on_max_steps(mut self, partial_answer: bool) -> Self {
+        self.partial_answer_on_max_steps = partial_answer;
+        self
+    }
+
+    /// The answer to stop with when the scheduler runs out of steps - see
+    /// [`Runtime::with_partial_answer_on_max_steps`]
+    fn partial_answer(&self) -> TerminationMessage {
+        let conclusion = format!(
+            "The task was stopped before completion: the maximum number of steps was reached after {} steps.",
+            self.steps
+        );
+
+        let last_result = self.context.messages.iter().rev().find_map(|m| match m {
+            Message::ActionResult {
+                outcome: Outcome::Success { result, .. },
+                ..
+            } => Some(result),
+            _ => None,
+        });
+        let conclusion = match last_result {
+            Some(result) => format!("{conclusion} The last result was:\n{result}"),
+            None => conclusion,
+        };
+
+        TerminationMessage {
+            conclusion,
+            original_question: self.context.get_latest_task().unwrap_or_default(),
+            data: None,
+            tool_name: None,
+            status: TerminationStatus::Failure,
+        }
+    }
+
+    /// Invoke all the actions of a message concurrently when they all are
+    /// parallel-safe - see [`crate::tools::toolbox::invoke_tools_in_parallel`]
+    #[must_use]
+    pub const fn with_parallel_actions(mut self, parallel_actions: bool) -> Self {
+        self.parallel_actions = parallel_actions;
+        self
+    }
+
+    /// Choose which of the actions of a message is invoked when it has several
+    #[must_use]
+    pub const fn with_multiple_actions(mut self, multiple_actions: MultipleActionsPolicy) -> Self {
+        self.multiple_actions = multiple_actions;
+        self
+    }
+
+    /// Hide the secrets in the results of the actions with `redactor` before
+    /// they are added to the context or reported to the observer
+    #[must_use]
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// Stash the results of the actions longer than `max_result_chars` in the
+    /// [`Toolbox`] - the model gets a handle to fetch them with the
+    /// `SandboxedPython` tool instead
+    #[must_use]
+    pub const fn with_stashed_results(mut self, max_result_chars: Option<usize>) -> Self {
+        self.stashed_results = max_result_chars;
+        self
+    }
+
+    /// Check the conclusions with `conclusion_checker` before terminating -
+    /// a rejected conclusion is recorded as a failed action with the feedback
+    /// of the checker and the task goes on
+    #[must_use]
+    pub fn with_conclusion_checker(
+        mut self,
+        conclusion_checker: Option<ConclusionCheckerRef>,
+    ) -> Self {
+        self.conclusion_checker = conclusion_checker;
+        self
+    }
+
+    /// The feedback of the [`Runtime::with_conclusion_checker`] checker if it
+    /// rejects one of the successful `termination_messages`
+    async fn reject_conclusion(
+        &self,
+        termination_messages: &[TerminationMessage],
+    ) -> Option<String> {
+        let checker = self.conclusion_checker.as_ref()?;
+
+        for message in termination_messages
+            .iter()
+            .filter(|message| message.status.is_success())
+        {
+            if let Verdict::Reject { feedback } = checker.check(message).await {
+                return Some(feedback);
+            }
+        }
+
+        None
+    }
+
+    /// Replace a result longer than allowed by a handle to it - see
+    /// [`Runtime::with_stashed_results`]
+    fn stash_long_result(&self, res: InvokeResult) -> InvokeResult {
+        match (res, self.stashed_results) {
+            (
+                InvokeResult::Success {
+                    invocation_count,
+                    invocation_index,
+                    all_ran,
+                    tool_name,
+                    extracted_input,
+                    result,
+                    hint,
+                    rendered,
+                    artifacts,
+                },
+                Some(max_result_chars),
+            ) if rendered.as_ref().unwrap_or(&result).len() > max_result_chars => {
+                let mut preview_len = STASH_PREVIEW_CHARS.min(max_result_chars / 2);
+                while !result.is_char_boundary(preview_len) {
+                    preview_len -= 1;
+                }
+                let preview = result[..preview_len].to_string();
+
+                let len = result.len();
+                let handle = self.toolbox.stash_result(result);
+                let result = format!(
+                    "The result is too long ({len}B) to be shown. It is stored under the handle `{handle}`: use the SandboxedPython tool and `tools.fetch(\"{handle}\")` to get it as a string and process it. It starts with:\n{preview}...\n"
+                );
+
+                InvokeResult::Success {
+                    invocation_count,
+                    invocation_index,
+                    all_ran,
+                    tool_name,
+                    extracted_input,
+                    result,
+                    hint,
+                    rendered: None,
+                    artifacts,
+                }
+            }
+            (res, _) => res,
+        }
+    }
+
+    /// Keep track of the consecutive identical invocations
+    fn track_invocation(&mut self, res: &InvokeResult) {
+        let invocation = match res {
+            InvokeResult::Success {
+                tool_name,
+                extracted_input,
+                ..
+            }
+            | InvokeResult::Error {
+                tool_name,
+                extracted_input,
+                ..
+            } => (tool_name.clone(), extracted_input.clone()),
+            InvokeResult::NoInvocationsFound { .. }
+            | InvokeResult::NoValidInvocationsFound { .. } => {
+                self.last_invocation = None;
+                return;
+            }
+        };
+
+        self.last_invocation = match self.last_invocation.take() {
+            Some((last, count)) if last == invocation => Some((last, count + 1)),
+            _ => Some((invocation, 1)),
+        };
+    }
+
     /// Run the runtime until it terminates.
     pub async fn run(&mut self) -> Result<TerminalState, Error> {
         loop {
@@ -286,8 +647,38 @@ impl Runtime {
     }
 
     /// Run one step of the runtime.
+    ///
+    /// The step runs in a `step` span - with the queries to the model and the
+    /// invocations of the tools nested in it.
+    #[tracing::instrument(
+        name = "step",
+        skip(self),
+        fields(
+            step = self.steps + 1,
+            tool_name,
+            success,
+            prompt_tokens,
+            completion_tokens,
+            total_tokens
+        )
+    )]
     pub async fn step(&mut self) -> Result<Vec<TerminationMessage>, Error> {
-        let message = self.scheduler.schedule(&self.context).await?;
+        let message = match self.scheduler.schedule(&self.context).await {
+            Err(Error::MaxStepsReached) if self.partial_answer_on_max_steps => {
+                return Ok(vec![self.partial_answer()]);
+            }
+            message => message?,
+        };
+        self.steps += 1;
+
+        if let Some(usage) = message.usage() {
+            let span = Span::current();
+            span.record("prompt_tokens", usage.prompt_tokens);
+            span.record("completion_tokens", usage.completion_tokens);
+            span.record("total_tokens", usage.total_tokens);
+
+            self.usage += usage;
+        }
 
         self.context.messages.push(message.clone());
 
@@ -300,8 +691,37 @@ impl Runtime {
         }
 
         // any action?
-        if let Message::Action { content, .. } = message {
-            let res = invoke_tool(self.toolbox.clone(), &content).await;
+        let res = if let Message::Action {
+            content,
+            tool_calls,
+            ..
+        } = message
+        {
+            let tool_invocations = find_invocations(&content, &tool_calls);
+
+            if let Some(observer) = self.observer.upgrade() {
+                let action = tool_invocations
+                    .clone()
+                    .and_then(|invocations| chosen_action(invocations, self.multiple_actions));
+                observer
+                    .lock()
+                    .await
+                    .on_action(ActionNotification { action })
+                    .await;
+            }
+
+            let res = invoke_found(
+                self.toolbox.clone(),
+                tool_invocations,
+                self.multiple_actions,
+                self.parallel_actions,
+            )
+            .await;
+
+            record_invocation(&res);
+            self.track_invocation(&res);
+            let res = self.redactor.redact_result(res);
+            let res = self.stash_long_result(res);
 
             if let Some(observer) = self.observer.upgrade() {
                 observer
@@ -311,11 +731,110 @@ impl Runtime {
                     .await;
             }
 
-            self.context.messages.push(res.into());
+            Some(res)
+        } else {
+            None
+        };
+
+        // are we done? - checked before the result is recorded: a failure
+        // after a terminal tool succeeded - e.g. the code of the
+        // `SandboxedPython` tool failing after it concluded - would only leave
+        // a misleading message in the history
+        let mut termination_messages = self.toolbox.termination_messages().await;
+
+        // is it really done?
+        let res = match self.reject_conclusion(&termination_messages).await {
+            Some(feedback) => {
+                termination_messages.clear();
+                res.map(|res| match res {
+                    InvokeResult::Success {
+                        invocation_count,
+                        invocation_index,
+                        tool_name,
+                        extracted_input,
+                        ..
+                    } => InvokeResult::Error {
+                        invocation_count,
+                        invocation_index,
+                        tool_name,
+                        extracted_input,
+                        e: ToolUseError::InvocationFailed(format!(
+                            "The conclusion was rejected: {feedback}"
+                        )),
+                    },
+                    res => res,
+                })
+            }
+            None => res,
+        };
+
+        if let Some(res) = res {
+            if termination_messages.is_empty() || !matches!(res, InvokeResult::Error { .. }) {
+                self.context.messages.push(res.into());
+            }
+        }
+
+        if !termination_messages.is_empty() {
+            return Ok(termination_messages);
+        }
+
+        // are we out of budget?
+        if let Some(max_total_tokens) = self.max_total_tokens {
+            if self.usage.total_tokens > max_total_tokens {
+                return Ok(vec![TerminationMessage {
+                    conclusion: format!(
+                        "The task was stopped before completion: {} tokens were used, the budget was {max_total_tokens} tokens.",
+                        self.usage.total_tokens
+                    ),
+                    original_question: self.context.get_latest_task().unwrap_or_default(),
+                    data: None,
+                    tool_name: None,
+                    status: TerminationStatus::Failure,
+                }]);
+            }
+        }
+
+        // are we going in circles?
+        if let (Some(max_repeated_actions), Some(((tool_name, _), count))) =
+            (self.max_repeated_actions, &self.last_invocation)
+        {
+            if *count >= max_repeated_actions {
+                return Ok(vec![TerminationMessage {
+                    conclusion: format!(
+                        "The task was stopped before completion: the same action was requested {count} times in a row with the tool {tool_name}."
+                    ),
+                    original_question: self.context.get_latest_task().unwrap_or_default(),
+                    data: None,
+                    tool_name: None,
+                    status: TerminationStatus::Failure,
+                }]);
+            }
+        }
+
+        Ok(termination_messages)
+    }
+
+    /// The token usage accumulated over the steps so far
+    #[must_use]
+    pub const fn usage_so_far(&self) -> &Usage {
+        &self.usage
+    }
+
+    /// Snapshot the conversational state
+    #[must_use]
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            messages: self.context.messages.clone(),
+            usage: self.usage.clone(),
+            steps: self.steps,
         }
+    }
 
-        // are we done?
-        Ok(self.toolbox.termination_messages().await)
+    /// Restore the conversational state from a [`Checkpoint`]
+    fn restore(&mut self, checkpoint: Checkpoint) {
+        self.context.messages = checkpoint.messages;
+        self.usage = checkpoint.usage;
+        self.steps = checkpoint.steps;
     }
 }
 
@@ -327,6 +846,12 @@ pub trait Chain: Send + Sync {
 
     /// Execute a single step of the chain
     async fn step(&mut self) -> Result<Vec<TerminationMessage>, Error>;
+
+    /// The token usage accumulated over the steps so far
+    fn usage_so_far(&self) -> Usage;
+
+    /// Snapshot the conversational state of the chain
+    fn checkpoint(&self) -> Checkpoint;
 }
 
 /// A single-step OODA chain
@@ -346,10 +871,57 @@ impl SingleStepOODAChain {
         let scheduler =
             SingleAgentScheduler::new(config.max_steps, Box::new(agent), observer.clone());
         Ok(Self {
-            runtime: Runtime::new(toolbox, Box::new(scheduler), observer).await?,
+            runtime: Runtime::new(toolbox, Box::new(scheduler), observer)
+                .await?
+                .with_max_total_tokens(config.max_total_tokens)
+                .with_max_repeated_actions(config.max_repeated_actions)
+                .with_partial_answer_on_max_steps(config.partial_answer_on_max_steps)
+                .with_parallel_actions(config.parallel_actions)
+                .with_multiple_actions(config.multiple_actions)
+                .with_redactor(config.redactor.clone())
+                .with_conclusion_checker(config.conclusion_checker.clone())
+                .with_stashed_results(
+                    (config.long_tool_results == LongResultPolicy::Stash)
+                        .then_some(config.max_tool_result_chars),
+                ),
         })
     }
 
+    /// Resume a [`SingleStepOODAChain`] from a [`Checkpoint`]
+    ///
+    /// The steps already run count towards [`SapiensConfig::max_steps`].
+    pub async fn resume(
+        config: SapiensConfig,
+        toolbox: Toolbox,
+        observer: WeakRuntimeObserver,
+        checkpoint: Checkpoint,
+    ) -> Result<Self, Error> {
+        let config = SapiensConfig {
+            max_steps: config.max_steps.saturating_sub(checkpoint.steps),
+            ..config
+        };
+
+        let mut chain = Self::new(config, toolbox, observer).await?;
+        chain.runtime.restore(checkpoint);
+
+        Ok(chain)
+    }
+
+    /// Add the conversation the task comes from to the OODA chain - before
+    /// the task, see [`Message::Conversation`]
+    #[must_use]
+    pub fn with_conversation(mut self, conversation: Vec<ChatEntry>) -> Self {
+        self.runtime
+            .context
+            .messages
+            .extend(conversation.into_iter().map(|e| Message::Conversation {
+                role: e.role,
+                content: e.msg,
+            }));
+
+        self
+    }
+
     /// Add a new task to the OODA chain
     #[must_use]
     pub fn with_task(mut self, task: String) -> Self {
@@ -371,6 +943,14 @@ impl Chain for SingleStepOODAChain {
     async fn step(&mut self) -> Result<Vec<TerminationMessage>, Error> {
         self.runtime.step().await
     }
+
+    fn usage_so_far(&self) -> Usage {
+        self.runtime.usage_so_far().clone()
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        self.runtime.checkpoint()
+    }
 }
 
 /// Multistep OODA chain
@@ -385,6 +965,38 @@ impl MultiStepOODAChain {
         config: SapiensConfig,
         toolbox: Toolbox,
         observer: WeakRuntimeObserver,
+    ) -> Result<Self, Error> {
+        Self::build(config, toolbox, observer, 0).await
+    }
+
+    /// Resume a [`MultiStepOODAChain`] from a [`Checkpoint`]
+    ///
+    /// The steps already run count towards [`SapiensConfig::max_steps`].
+    pub async fn resume(
+        config: SapiensConfig,
+        toolbox: Toolbox,
+        observer: WeakRuntimeObserver,
+        checkpoint: Checkpoint,
+    ) -> Result<Self, Error> {
+        let config = SapiensConfig {
+            max_steps: config.max_steps.saturating_sub(checkpoint.steps),
+            ..config
+        };
+
+        // one agent per step
+        let next_agent = checkpoint.steps;
+
+        let mut chain = Self::build(config, toolbox, observer, next_agent).await?;
+        chain.runtime.restore(checkpoint);
+
+        Ok(chain)
+    }
+
+    async fn build(
+        config: SapiensConfig,
+        toolbox: Toolbox,
+        observer: WeakRuntimeObserver,
+        next_agent: usize,
     ) -> Result<Self, Error> {
         let agents = vec![
             multistep::Agent::new_observer(config.clone(), toolbox.clone(), observer.clone()),
@@ -393,17 +1005,46 @@ impl MultiStepOODAChain {
             multistep::Agent::new_actor(config.clone(), toolbox.clone(), observer.clone()),
         ];
 
-        let agents = agents
+        let agents: Vec<_> = agents
             .into_iter()
             .map(|a| Box::new(a) as Box<dyn Agent<Error = agents::Error>>)
             .collect();
+        let next_agent = next_agent % agents.len();
 
-        let scheduler = MultiAgentScheduler::new(config.max_steps, agents, observer.clone());
+        let scheduler = MultiAgentScheduler::new(config.max_steps, agents, observer.clone())
+            .with_next_agent(next_agent);
         Ok(Self {
-            runtime: Runtime::new(toolbox, Box::new(scheduler), observer).await?,
+            runtime: Runtime::new(toolbox, Box::new(scheduler), observer)
+                .await?
+                .with_max_total_tokens(config.max_total_tokens)
+                .with_max_repeated_actions(config.max_repeated_actions)
+                .with_partial_answer_on_max_steps(config.partial_answer_on_max_steps)
+                .with_parallel_actions(config.parallel_actions)
+                .with_multiple_actions(config.multiple_actions)
+                .with_redactor(config.redactor.clone())
+                .with_conclusion_checker(config.conclusion_checker.clone())
+                .with_stashed_results(
+                    (config.long_tool_results == LongResultPolicy::Stash)
+                        .then_some(config.max_tool_result_chars),
+                ),
         })
     }
 
+    /// Add the conversation the task comes from to the OODA chain - before
+    /// the task, see [`Message::Conversation`]
+    #[must_use]
+    pub fn with_conversation(mut self, conversation: Vec<ChatEntry>) -> Self {
+        self.runtime
+            .context
+            .messages
+            .extend(conversation.into_iter().map(|e| Message::Conversation {
+                role: e.role,
+                content: e.msg,
+            }));
+
+        self
+    }
+
     /// Add a new task to the OODA chain
     #[must_use]
     pub fn with_task(mut self, task: String) -> Self {
@@ -425,4 +1066,12 @@ impl Chain for MultiStepOODAChain {
     async fn step(&mut self) -> Result<Vec<TerminationMessage>, Error> {
         self.runtime.step().await
     }
+
+    fn usage_so_far(&self) -> Usage {
+        self.runtime.usage_so_far().clone()
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        self.runtime.checkpoint()
+    }
 }
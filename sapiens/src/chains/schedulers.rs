@@ -75,6 +75,13 @@ impl<E> MultiAgentScheduler<E> {
             observer,
         }
     }
+
+    /// Start with the agent at index `next_agent` - e.g. to resume a chain
+    #[must_use]
+    pub const fn with_next_agent(mut self, next_agent: usize) -> Self {
+        self.next_agent = next_agent;
+        self
+    }
 }
 
 #[async_trait::async_trait]
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use indoc::indoc;
@@ -5,8 +7,21 @@ use serde_yaml::Value;
 use tokio::sync::Mutex;
 
 use super::*;
-use crate::tools::{FieldFormat, Format, TerminalTool, Tool, ToolDescription};
-use crate::void_observer;
+use crate::context::{ChatEntry, ChatEntryFormatter, ChatHistory, ChatMessage};
+use crate::events::StepEventObserver;
+use crate::metrics::Metrics;
+use crate::models::{
+    ChatEntryTokenNumber, ChatInput, Model, ModelResponse, Pricing, Role, TemperatureRamp,
+    ToolCall, ToolCalling, Usage,
+};
+use crate::tools::{
+    FieldFormat, Format, SideEffects, TerminalTool, TerminationStatus, Tool, ToolDescription,
+};
+use crate::{
+    void_observer, wrap_observer, ActionNotification, ContextDump, InvocationResultNotification,
+    MessageNotification, ModelNotification, RuntimeObserver, SapiensConfig, TaskState,
+    TerminationNotification,
+};
 
 struct SimpleAgent {}
 
@@ -48,6 +63,8 @@ impl Tool for ConcludeTool {
                 }],
             },
             responses_content: Format::default(),
+            examples: Vec::new(),
+            side_effects: SideEffects::Mutating,
         }
     }
 
@@ -81,6 +98,9 @@ impl TerminalTool for ConcludeTool {
             done.take().map(|input| TerminationMessage {
                 conclusion: input,
                 original_question: "tbd".to_string(),
+                data: None,
+                tool_name: None,
+                status: TerminationStatus::default(),
             })
         }
     }
@@ -138,6 +158,7 @@ impl Agent for NoAsSimpleAgent {
                 }
                 .to_string(),
                 usage: None,
+                tool_calls: Vec::new(),
             })
         }
     }
@@ -177,3 +198,2015 @@ async fn observes_and_conclude() {
     let message = &terminal_state.messages[0];
     assert_eq!(message.conclusion, "Done");
 }
+
+/// A model that always answers with the same action
+struct CannedModel {}
+
+#[async_trait::async_trait]
+impl ChatEntryTokenNumber for CannedModel {
+    async fn num_tokens(&self, input: ChatInput) -> usize {
+        input
+            .context
+            .iter()
+            .chain(input.examples.iter().flat_map(|(a, b)| [a, b]))
+            .chain(input.chat.iter())
+            .map(|e| e.msg.split_whitespace().count())
+            .sum()
+    }
+
+    async fn context_size(&self) -> usize {
+        4096
+    }
+}
+
+#[async_trait::async_trait]
+impl Model for CannedModel {
+    async fn query(
+        &self,
+        _input: ChatInput,
+        _max_tokens: Option<usize>,
+    ) -> Result<ModelResponse, crate::models::Error> {
+        Ok(ModelResponse {
+            msg: indoc! {r#"
+            ```yaml
+            tool_name: ConcludeTool
+            parameters:
+                conclusion: "Done"
+            ```
+            "#
+            }
+            .to_string(),
+            alternatives: Vec::new(),
+            usage: Some(Usage {
+                prompt_tokens: 10,
+                completion_tokens: 5,
+                total_tokens: 15,
+                ..Usage::default()
+            }),
+            finish_reason: None,
+            tool_calls: Vec::new(),
+        })
+    }
+}
+
+#[tokio::test]
+async fn runs_with_a_custom_model() {
+    let toolbox = {
+        let toolbox = Toolbox::default();
+        toolbox.add_terminal_tool(ConcludeTool::default()).await;
+        toolbox
+    };
+
+    let config = SapiensConfig {
+        model: Arc::new(Box::new(CannedModel {})),
+        ..SapiensConfig::default()
+    };
+
+    let observer = void_observer();
+    let observer = Arc::downgrade(&observer);
+
+    let mut chain = SingleStepOODAChain::new(config, toolbox, observer)
+        .await
+        .unwrap()
+        .with_task("Conclude.".to_string());
+
+    let messages = chain.step().await.unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].conclusion, "Done");
+}
+
+#[tokio::test]
+async fn runs_with_a_client_built_by_the_caller() {
+    use async_openai::config::OpenAIConfig;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "gpt-3.5-turbo",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "```yaml\ntool_name: ConcludeTool\nparameters:\n    conclusion: Done\n```"
+                },
+                "finish_reason": "stop"
+            }],
+            "usage": { "prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15 }
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let http_client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(5))
+        .build()
+        .unwrap();
+    let client = async_openai::Client::with_config(
+        OpenAIConfig::new()
+            .with_api_base(format!("{}/v1", server.uri()))
+            .with_api_key("test"),
+    )
+    .with_http_client(http_client);
+
+    let toolbox = {
+        let toolbox = Toolbox::default();
+        toolbox.add_terminal_tool(ConcludeTool::default()).await;
+        toolbox
+    };
+
+    let config = SapiensConfig {
+        model: crate::models::openai::build_with_client(
+            crate::models::SupportedModel::GPT3_5Turbo,
+            client,
+            Some(0.),
+        )
+        .unwrap(),
+        ..SapiensConfig::default()
+    };
+
+    let observer = void_observer();
+    let observer = Arc::downgrade(&observer);
+
+    let mut chain = SingleStepOODAChain::new(config, toolbox, observer)
+        .await
+        .unwrap()
+        .with_task("Conclude.".to_string());
+
+    let messages = chain.step().await.unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].conclusion, "Done");
+}
+
+#[tokio::test]
+async fn runs_with_an_explicit_api_key() {
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .and(header("Authorization", "Bearer sk-explicit"))
+        .and(header("OpenAI-Organization", "org-explicit"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 0,
+            "model": "gpt-3.5-turbo",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": "```yaml\ntool_name: ConcludeTool\nparameters:\n    conclusion: Done\n```"
+                },
+                "finish_reason": "stop"
+            }],
+            "usage": { "prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15 }
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let toolbox = Toolbox::builder()
+        .with_terminal_tool(ConcludeTool::default())
+        .build();
+
+    let config = SapiensConfig {
+        model: crate::models::openai::build(
+            crate::models::SupportedModel::GPT3_5Turbo,
+            crate::models::openai::ClientOptions {
+                api_key: Some("sk-explicit".to_string()),
+                org_id: Some("org-explicit".to_string()),
+                api_base: Some(format!("{}/v1", server.uri())),
+            },
+            Some(0.),
+        )
+        .unwrap(),
+        ..SapiensConfig::default()
+    };
+
+    let stop = TaskState::new(config, toolbox, "Conclude.".to_string())
+        .await
+        .unwrap()
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(stop.termination_messages[0].conclusion, "Done");
+}
+
+struct CountingAgent {}
+
+#[async_trait::async_trait]
+impl Agent for CountingAgent {
+    type Error = ();
+
+    async fn act(&self, _context: &Context) -> Result<Message, ()> {
+        Ok(Message::Observation {
+            content: "Hello".to_string(),
+            usage: Some(Usage {
+                prompt_tokens: 100,
+                completion_tokens: 20,
+                total_tokens: 120,
+                ..Usage::default()
+            }),
+        })
+    }
+}
+
+#[tokio::test]
+async fn accumulates_usage() {
+    let toolbox = {
+        let toolbox = Toolbox::default();
+        toolbox.add_terminal_tool(ConcludeTool::default()).await;
+        toolbox
+    };
+    let observer = void_observer();
+    let observer = Arc::downgrade(&observer);
+
+    let scheduler = Box::new(schedulers::SingleAgentScheduler::new(
+        10,
+        Box::new(CountingAgent {}),
+        observer.clone(),
+    ));
+    let mut runtime = Runtime::new(toolbox, scheduler, observer).await.unwrap();
+
+    runtime.step().await.unwrap();
+    runtime.step().await.unwrap();
+
+    let usage = runtime.usage_so_far();
+    assert_eq!(
+        usage,
+        &Usage {
+            prompt_tokens: 200,
+            completion_tokens: 40,
+            total_tokens: 240,
+            ..Usage::default()
+        }
+    );
+
+    let pricing = Pricing {
+        prompt_per_1k_tokens: 1.5,
+        completion_per_1k_tokens: 2.,
+    };
+    assert!((pricing.cost(usage) - 0.38).abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn stops_when_out_of_budget() {
+    let toolbox = {
+        let toolbox = Toolbox::default();
+        toolbox.add_terminal_tool(ConcludeTool::default()).await;
+        toolbox
+    };
+    let observer = void_observer();
+    let observer = Arc::downgrade(&observer);
+
+    let scheduler = Box::new(schedulers::SingleAgentScheduler::new(
+        10,
+        Box::new(CountingAgent {}),
+        observer.clone(),
+    ));
+    let mut runtime = Runtime::new(toolbox, scheduler, observer)
+        .await
+        .unwrap()
+        .with_max_total_tokens(Some(100));
+    runtime.context.add_message(Message::Task {
+        content: "Count.".to_string(),
+    });
+
+    let terminal_state = runtime.run().await.unwrap();
+
+    // one step only
+    assert_eq!(runtime.usage_so_far().total_tokens, 120);
+
+    assert_eq!(terminal_state.messages.len(), 1);
+    let message = &terminal_state.messages[0];
+    assert_eq!(message.original_question, "Count.");
+    assert!(message.conclusion.contains("budget"));
+}
+
+/// An observer that records the callbacks it receives
+#[derive(Default)]
+struct RecordingObserver {
+    events: Vec<String>,
+}
+
+#[async_trait::async_trait]
+impl RuntimeObserver for RecordingObserver {
+    async fn on_task(&mut self, task: &str) {
+        self.events.push(format!("task: {task}"));
+    }
+
+    async fn on_start(&mut self, _context: ContextDump) {
+        self.events.push("start".to_string());
+    }
+
+    async fn on_model_update(&mut self, _event: ModelNotification) {
+        self.events.push("model_update".to_string());
+    }
+
+    async fn on_message(&mut self, event: MessageNotification) {
+        let kind = match event.message {
+            Message::Action { .. } => "action",
+            _ => "other",
+        };
+        self.events.push(format!("message: {kind}"));
+    }
+
+    async fn on_action(&mut self, event: ActionNotification) {
+        let kind = match event.action {
+            Ok(action) => action.tool_name,
+            Err(_) => "invalid".to_string(),
+        };
+        self.events.push(format!("action: {kind}"));
+    }
+
+    async fn on_invocation_result(&mut self, event: InvocationResultNotification) {
+        let kind = match event {
+            InvocationResultNotification::InvocationSuccess(_) => "success",
+            InvocationResultNotification::InvocationFailure(_) => "failure",
+            InvocationResultNotification::InvalidInvocation(_) => "invalid",
+        };
+        self.events.push(format!("invocation_result: {kind}"));
+    }
+
+    async fn on_termination(&mut self, event: TerminationNotification) {
+        self.events
+            .push(format!("termination: {}", event.messages[0].conclusion));
+    }
+}
+
+#[tokio::test]
+async fn notifies_the_observer_in_order() {
+    let toolbox = {
+        let toolbox = Toolbox::default();
+        toolbox.add_terminal_tool(ConcludeTool::default()).await;
+        toolbox
+    };
+
+    let config = SapiensConfig {
+        model: Arc::new(Box::new(CannedModel {})),
+        ..SapiensConfig::default()
+    };
+
+    let observer = wrap_observer(RecordingObserver::default());
+    let weak_observer = Arc::downgrade(&observer);
+
+    let task_state =
+        TaskState::with_observer(config, toolbox, "Conclude.".to_string(), weak_observer)
+            .await
+            .unwrap();
+    task_state.run().await.unwrap();
+
+    assert_eq!(
+        observer.lock().await.events,
+        vec![
+            "task: Conclude.",
+            "start",
+            "model_update",
+            "message: action",
+            "action: ConcludeTool",
+            "invocation_result: success",
+            "termination: Done",
+        ]
+    );
+}
+
+/// An observer that keeps the Actions it is notified of
+#[derive(Default)]
+struct ActionObserver {
+    actions: Vec<ActionNotification>,
+}
+
+#[async_trait::async_trait]
+impl RuntimeObserver for ActionObserver {
+    async fn on_action(&mut self, event: ActionNotification) {
+        self.actions.push(event);
+    }
+}
+
+#[tokio::test]
+async fn notifies_the_observer_of_the_parsed_action() {
+    let toolbox = {
+        let toolbox = Toolbox::default();
+        toolbox.add_terminal_tool(ConcludeTool::default()).await;
+        toolbox
+    };
+
+    let config = SapiensConfig {
+        model: Arc::new(Box::new(CannedModel {})),
+        ..SapiensConfig::default()
+    };
+
+    let observer = wrap_observer(ActionObserver::default());
+    let weak_observer = Arc::downgrade(&observer);
+
+    let task_state =
+        TaskState::with_observer(config, toolbox, "Conclude.".to_string(), weak_observer)
+            .await
+            .unwrap();
+    task_state.run().await.unwrap();
+
+    let actions = &observer.lock().await.actions;
+    assert_eq!(actions.len(), 1);
+
+    let action = actions[0].action.as_ref().unwrap();
+    assert_eq!(action.tool_name, "ConcludeTool");
+    assert_eq!(
+        action.parameters,
+        serde_yaml::from_str::<Value>("conclusion: Done").unwrap()
+    );
+}
+
+#[tokio::test]
+async fn runs_a_task_to_the_end() {
+    let toolbox = {
+        let toolbox = Toolbox::default();
+        toolbox.add_terminal_tool(ConcludeTool::default()).await;
+        toolbox
+    };
+
+    let config = SapiensConfig {
+        model: Arc::new(Box::new(CannedModel {})),
+        ..SapiensConfig::default()
+    };
+
+    let stop = TaskState::new(config, toolbox, "Conclude.".to_string())
+        .await
+        .unwrap()
+        .run()
+        .await
+        .unwrap();
+
+    assert_eq!(stop.termination_messages.len(), 1);
+    assert_eq!(stop.termination_messages[0].conclusion, "Done");
+    assert_eq!(stop.steps, 1);
+    assert_eq!(stop.usage.total_tokens, 15);
+    assert_eq!(stop.estimated_cost, None);
+}
+
+/// A model answering with several candidates - only the second has a valid
+/// Action
+struct CandidatesModel {}
+
+#[async_trait::async_trait]
+impl ChatEntryTokenNumber for CandidatesModel {
+    async fn num_tokens(&self, input: ChatInput) -> usize {
+        CannedModel {}.num_tokens(input).await
+    }
+
+    async fn context_size(&self) -> usize {
+        4096
+    }
+}
+
+#[async_trait::async_trait]
+impl Model for CandidatesModel {
+    async fn query(
+        &self,
+        input: ChatInput,
+        max_tokens: Option<usize>,
+    ) -> Result<ModelResponse, crate::models::Error> {
+        let canned = CannedModel {}.query(input, max_tokens).await?;
+
+        Ok(ModelResponse {
+            msg: "I will conclude.".to_string(),
+            alternatives: vec![
+                indoc! {r"
+                ```yaml
+                tool_name: MissingTool
+                parameters: {}
+                ```
+                "}
+                .to_string(),
+                canned.msg,
+            ],
+            ..canned
+        })
+    }
+}
+
+#[tokio::test]
+async fn selects_the_candidate_with_a_valid_action() {
+    let toolbox = {
+        let toolbox = Toolbox::default();
+        toolbox.add_terminal_tool(ConcludeTool::default()).await;
+        toolbox
+    };
+
+    let res = CandidatesModel {}
+        .query(
+            ChatHistory::<ChatEntry>::new(SapiensConfig::default(), 4096).make_input(),
+            None,
+        )
+        .await
+        .unwrap()
+        .select_action(&toolbox, MultipleActionsPolicy::default())
+        .await;
+    assert!(res.msg.contains("tool_name: ConcludeTool"));
+    assert_eq!(res.alternatives.len(), 2);
+
+    let config = SapiensConfig {
+        model: Arc::new(Box::new(CandidatesModel {})),
+        ..SapiensConfig::default()
+    };
+
+    let stop = TaskState::new(config, toolbox, "Conclude.".to_string())
+        .await
+        .unwrap()
+        .run()
+        .await
+        .unwrap();
+
+    assert_eq!(stop.steps, 1);
+    assert_eq!(stop.termination_messages[0].conclusion, "Done");
+}
+
+#[tokio::test]
+async fn keeps_the_first_candidate_without_a_valid_action() {
+    let res = ModelResponse {
+        msg: "first".to_string(),
+        alternatives: vec!["second".to_string()],
+        usage: None,
+        finish_reason: None,
+        tool_calls: Vec::new(),
+    }
+    .select_action(&Toolbox::default(), MultipleActionsPolicy::default())
+    .await;
+
+    assert_eq!(res.msg, "first");
+    assert_eq!(res.alternatives, vec!["second"]);
+}
+
+struct StuckAgent {}
+
+#[async_trait::async_trait]
+impl Agent for StuckAgent {
+    type Error = ();
+
+    async fn act(&self, _context: &Context) -> Result<Message, ()> {
+        Ok(Message::Action {
+            content: indoc! {r#"
+            ```yaml
+            tool_name: MissingTool
+            parameters:
+                query: "Again"
+            ```
+            "#
+            }
+            .to_string(),
+            usage: None,
+            tool_calls: Vec::new(),
+        })
+    }
+}
+
+#[tokio::test]
+async fn stops_on_repeated_actions() {
+    let toolbox = {
+        let toolbox = Toolbox::default();
+        toolbox.add_terminal_tool(ConcludeTool::default()).await;
+        toolbox
+    };
+    let observer = void_observer();
+    let observer = Arc::downgrade(&observer);
+
+    let scheduler = Box::new(schedulers::SingleAgentScheduler::new(
+        10,
+        Box::new(StuckAgent {}),
+        observer.clone(),
+    ));
+    let mut runtime = Runtime::new(toolbox, scheduler, observer)
+        .await
+        .unwrap()
+        .with_max_repeated_actions(Some(2));
+    runtime.context.add_message(Message::Task {
+        content: "Loop.".to_string(),
+    });
+
+    assert!(runtime.step().await.unwrap().is_empty());
+
+    let messages = runtime.step().await.unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].original_question, "Loop.");
+    assert!(messages[0].conclusion.contains("2 times in a row"));
+}
+
+/// An agent that answers without an Action block
+struct RamblingAgent {}
+
+#[async_trait::async_trait]
+impl Agent for RamblingAgent {
+    type Error = ();
+
+    async fn act(&self, _context: &Context) -> Result<Message, ()> {
+        Ok(Message::Action {
+            content: "I should probably do something.".to_string(),
+            usage: None,
+            tool_calls: Vec::new(),
+        })
+    }
+}
+
+#[tokio::test]
+async fn notifies_the_observer_of_an_invalid_action() {
+    let toolbox = {
+        let toolbox = Toolbox::default();
+        toolbox.add_terminal_tool(ConcludeTool::default()).await;
+        toolbox
+    };
+    let observer = wrap_observer(ActionObserver::default());
+    let weak_observer = Arc::downgrade(&observer);
+
+    let scheduler = Box::new(schedulers::SingleAgentScheduler::new(
+        10,
+        Box::new(RamblingAgent {}),
+        weak_observer.clone(),
+    ));
+    let mut runtime = Runtime::new(toolbox, scheduler, weak_observer)
+        .await
+        .unwrap();
+    runtime.context.add_message(Message::Task {
+        content: "Ramble.".to_string(),
+    });
+    runtime.step().await.unwrap();
+
+    let actions = &observer.lock().await.actions;
+    assert_eq!(actions.len(), 1);
+    assert!(matches!(
+        actions[0].action,
+        Err(invocation::Error::NoInvocationFound)
+    ));
+}
+
+/// A model that thinks for one step and then concludes
+#[derive(Default)]
+struct SlowModel {
+    calls: AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl ChatEntryTokenNumber for SlowModel {
+    async fn num_tokens(&self, input: ChatInput) -> usize {
+        CannedModel {}.num_tokens(input).await
+    }
+
+    async fn context_size(&self) -> usize {
+        4096
+    }
+}
+
+#[async_trait::async_trait]
+impl Model for SlowModel {
+    async fn query(
+        &self,
+        input: ChatInput,
+        max_tokens: Option<usize>,
+    ) -> Result<ModelResponse, crate::models::Error> {
+        if self.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            return Ok(ModelResponse {
+                msg: "Let me think about it.".to_string(),
+                alternatives: Vec::new(),
+                usage: None,
+                finish_reason: None,
+                tool_calls: Vec::new(),
+            });
+        }
+
+        CannedModel {}.query(input, max_tokens).await
+    }
+}
+
+#[tokio::test]
+async fn resumes_from_a_checkpoint() {
+    let toolbox = || async {
+        let toolbox = Toolbox::default();
+        toolbox.add_terminal_tool(ConcludeTool::default()).await;
+        toolbox
+    };
+
+    let config = SapiensConfig {
+        model: Arc::new(Box::new(SlowModel::default())),
+        ..SapiensConfig::default()
+    };
+
+    let task_state = TaskState::new(config.clone(), toolbox().await, "Conclude.".to_string())
+        .await
+        .unwrap()
+        .step()
+        .await
+        .unwrap();
+    assert!(task_state.is_done().is_none());
+
+    let checkpoint = task_state.checkpoint().unwrap();
+    assert_eq!(checkpoint.steps, 1);
+    assert!(matches!(checkpoint.messages[0], Message::Task { .. }));
+
+    // round-trip through a serialized form
+    let checkpoint = serde_yaml::to_string(&checkpoint).unwrap();
+    drop(task_state);
+    let checkpoint: Checkpoint = serde_yaml::from_str(&checkpoint).unwrap();
+
+    let observer = void_observer();
+    let observer = Arc::downgrade(&observer);
+
+    let stop = TaskState::resume(config, toolbox().await, checkpoint, observer)
+        .await
+        .unwrap()
+        .run()
+        .await
+        .unwrap();
+
+    assert_eq!(stop.termination_messages.len(), 1);
+    assert_eq!(stop.termination_messages[0].conclusion, "Done");
+    assert_eq!(stop.steps, 2);
+}
+
+#[tokio::test]
+async fn estimates_usage_when_the_model_does_not_report_it() {
+    let toolbox = Toolbox::default();
+    toolbox.add_terminal_tool(ConcludeTool::default()).await;
+
+    let config = SapiensConfig {
+        model: Arc::new(Box::new(SlowModel::default())),
+        ..SapiensConfig::default()
+    };
+
+    // the first response has no usage
+    let task_state = TaskState::new(config, toolbox, "Conclude.".to_string())
+        .await
+        .unwrap()
+        .step()
+        .await
+        .unwrap();
+
+    let usage = task_state.usage_so_far();
+    assert!(usage.estimated);
+    // "Let me think about it."
+    assert_eq!(usage.completion_tokens, 5);
+    assert!(usage.prompt_tokens > 0);
+    assert_eq!(
+        usage.total_tokens,
+        usage.prompt_tokens + usage.completion_tokens
+    );
+}
+
+#[tokio::test]
+async fn fails_when_the_task_is_too_long() {
+    let toolbox = Toolbox::default();
+    toolbox.add_terminal_tool(ConcludeTool::default()).await;
+
+    let config = SapiensConfig {
+        model: Arc::new(Box::new(CannedModel {})),
+        ..SapiensConfig::default()
+    };
+
+    // far more words than the 4096 tokens of the model
+    let task = "Conclude. ".repeat(10_000);
+
+    let res = TaskState::new(config, toolbox, task)
+        .await
+        .unwrap()
+        .run()
+        .await;
+
+    let Err(crate::Error::ChainError(Error::AgentFailed(agents::Error::ChatHistoryError(
+        crate::context::Error::PromptTooLong { num_tokens, budget },
+    )))) = res
+    else {
+        panic!("unexpected result: {:?}", res.err());
+    };
+    assert!(num_tokens > 10_000);
+    // 4096 - the default `min_tokens_for_completion`
+    assert_eq!(budget, 3840);
+}
+
+#[tokio::test]
+async fn sizes_the_history_with_the_configured_context_size() {
+    let config = SapiensConfig {
+        model: Arc::new(Box::new(CannedModel {})),
+        ..SapiensConfig::default()
+    };
+
+    let mut history = ChatHistory::for_model(config.clone()).await;
+    history.add_chitchat(ChatEntry {
+        role: Role::User,
+        msg: "one two three".to_string(),
+    });
+    // what the model tells
+    assert_eq!(history.max_completion_tokens().await, 4096 - 3);
+
+    let config = SapiensConfig {
+        context_size: Some(300),
+        ..config
+    };
+
+    let mut history = ChatHistory::for_model(config).await;
+    history.add_chitchat(ChatEntry {
+        role: Role::User,
+        msg: "one two three".to_string(),
+    });
+    assert_eq!(history.max_completion_tokens().await, 300 - 3);
+
+    // 50 words do not fit in the 300 - 256 tokens left for the prompt
+    history.add_chitchat(ChatEntry {
+        role: Role::User,
+        msg: "word ".repeat(50),
+    });
+    assert!(matches!(
+        history.purge().await,
+        Err(crate::context::Error::PromptTooLong {
+            num_tokens: 50,
+            budget: 44
+        })
+    ));
+}
+
+#[tokio::test]
+async fn marks_the_pruned_messages() {
+    struct Plain;
+
+    impl ChatEntryFormatter for Plain {
+        fn format(&self, entry: &ChatEntry) -> String {
+            entry.msg.clone()
+        }
+    }
+
+    let config = SapiensConfig {
+        model: Arc::new(Box::new(CannedModel {})),
+        context_size: Some(300),
+        ..SapiensConfig::default()
+    };
+
+    let mut history = ChatHistory::for_model(config).await;
+    history.set_context(vec![ChatEntry {
+        role: Role::System,
+        msg: "system".to_string(),
+    }]);
+    for i in 0..5 {
+        history.add_chitchat(ChatEntry {
+            role: if i % 2 == 0 {
+                Role::User
+            } else {
+                Role::Assistant
+            },
+            msg: format!("message{i} {}", "word ".repeat(19)),
+        });
+    }
+    assert_eq!(history.format(&Plain).len(), 6);
+
+    // only the 2 most recent messages fit in the 300 - 256 tokens left
+    assert_eq!(history.purge().await.unwrap(), 2);
+    assert_eq!(history.pruned(), 3);
+
+    let formatted = history.format(&Plain);
+    assert_eq!(formatted.len(), 4);
+    assert_eq!(formatted[0], "system");
+    assert_eq!(formatted[1], "[… 3 earlier messages pruned …]");
+    assert!(formatted[2].starts_with("message3 "));
+}
+
+#[tokio::test]
+async fn keeps_a_history_of_other_messages() {
+    /// A message with its role in its text
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Line(String);
+
+    impl ChatMessage for Line {
+        fn new(role: Role, content: String) -> Self {
+            Self(format!("{role}> {content}"))
+        }
+
+        fn role(&self) -> Role {
+            match self.0.split_once("> ") {
+                Some(("assistant", _)) => Role::Assistant,
+                Some(("system", _)) => Role::System,
+                _ => Role::User,
+            }
+        }
+
+        fn content(&self) -> Cow<'_, str> {
+            Cow::Borrowed(self.0.split_once("> ").map_or(&*self.0, |(_, c)| c))
+        }
+    }
+
+    let config = SapiensConfig {
+        model: Arc::new(Box::new(CannedModel {})),
+        context_size: Some(300),
+        ..SapiensConfig::default()
+    };
+
+    // the content is counted, not the role in the text
+    let line = Line::new(Role::User, "two words".to_string());
+    assert_eq!(line.num_tokens(&config.model).await, 2);
+
+    let mut history = ChatHistory::<Line>::sized_for_model(config).await;
+    history.set_context(vec![Line::new(Role::System, "system".to_string())]);
+    for i in 0..5 {
+        let role = if i % 2 == 0 {
+            Role::User
+        } else {
+            Role::Assistant
+        };
+        history.add_chitchat(Line::new(
+            role,
+            format!("message{i} {}", "word ".repeat(19)),
+        ));
+    }
+    // two in a row from the same role: the last one is kept
+    history.add_chitchat(Line::new(Role::User, "last".to_string()));
+    assert_eq!(history.iter().count(), 6);
+
+    // the messages are counted by the model as the entries they stand for
+    assert_eq!(history.purge().await.unwrap(), 3);
+    assert_eq!(history.pruned(), 2);
+
+    let lines = history.iter().map(|l| l.0.as_str()).collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], "system> system");
+    assert!(lines[1].starts_with("user> message2 "));
+    assert_eq!(lines[3], "user> last");
+
+    let input = history.make_input();
+    assert_eq!(input.chat[2].role, Role::User);
+    assert_eq!(input.chat[2].msg, "last");
+}
+
+#[tokio::test]
+async fn rejects_a_min_tokens_for_completion_larger_than_the_context() {
+    let config = SapiensConfig {
+        model: Arc::new(Box::new(CannedModel {})),
+        min_tokens_for_completion: 5000,
+        ..SapiensConfig::default()
+    };
+
+    let mut history = ChatHistory::for_model(config).await;
+    history.add_chitchat(ChatEntry {
+        role: Role::User,
+        msg: "one two three".to_string(),
+    });
+
+    let err = history.purge().await.unwrap_err();
+    assert!(matches!(
+        err,
+        crate::context::Error::NoRoomForPrompt {
+            min_tokens_for_completion: 5000,
+            context_size: 4096
+        }
+    ));
+    assert_eq!(
+        err.to_string(),
+        "min_tokens_for_completion (5000) must be smaller than the context size of the model (4096)"
+    );
+    // the history is left untouched
+    assert_eq!(history.make_input().chat.len(), 1);
+}
+
+#[tokio::test]
+async fn refuses_tools_with_the_same_name() {
+    let toolbox = Toolbox::default();
+    toolbox.add_tool(ConcludeTool::default()).await;
+    toolbox.add_terminal_tool(ConcludeTool::default()).await;
+
+    let res = TaskState::new(SapiensConfig::default(), toolbox, "Conclude.".to_string()).await;
+
+    let Err(crate::Error::ChainError(Error::DuplicateToolNames(names))) = res else {
+        panic!("unexpected result: {:?}", res.err());
+    };
+    assert_eq!(names, vec!["ConcludeTool"]);
+}
+
+#[tokio::test]
+async fn collects_metrics_across_tasks() {
+    let metrics = wrap_observer(Metrics::default());
+    let weak_metrics = Arc::downgrade(&metrics);
+
+    for (i, model) in [
+        Box::new(CannedModel {}) as Box<dyn Model>,
+        Box::new(SlowModel::default()),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let toolbox = Toolbox::default();
+        toolbox.add_terminal_tool(ConcludeTool::default()).await;
+
+        let config = SapiensConfig {
+            model: Arc::new(model),
+            ..SapiensConfig::default()
+        };
+
+        TaskState::with_observer(
+            config,
+            toolbox,
+            "Conclude.".to_string(),
+            weak_metrics.clone(),
+        )
+        .await
+        .unwrap()
+        .run()
+        .await
+        .unwrap();
+
+        if i == 0 {
+            let map = metrics.lock().await.to_map();
+            let value = |name: &str| map[name].to_string();
+            assert_eq!(value("sapiens_tasks_started_total"), "1");
+            assert_eq!(value("sapiens_tasks_completed_total"), "1");
+            assert_eq!(value("sapiens_steps_total"), "1");
+            assert_eq!(value("sapiens_steps_per_task"), "1");
+            assert_eq!(value("sapiens_prompt_tokens_total"), "10");
+            assert_eq!(value("sapiens_completion_tokens_total"), "5");
+            assert_eq!(value("sapiens_tokens_total"), "15");
+            assert_eq!(value("sapiens_invalid_actions_total"), "0");
+            assert_eq!(
+                value(r#"sapiens_tool_invocations_total{tool="ConcludeTool",outcome="success"}"#),
+                "1"
+            );
+            assert!(map.contains_key("sapiens_step_latency_seconds"));
+        }
+    }
+
+    let metrics = metrics.lock().await.clone();
+    assert_eq!(metrics.tasks_started, 2);
+    assert_eq!(metrics.tasks_completed, 2);
+    // the slow model thinks for one step before concluding
+    assert_eq!(metrics.steps, 3);
+    assert_eq!(metrics.average_steps_per_task(), Some(1.5));
+    assert_eq!(metrics.invalid_actions, 1);
+    assert_eq!(metrics.tool_successes["ConcludeTool"], 2);
+    assert!(metrics.tool_failures.is_empty());
+    assert!(metrics.total_tokens > 30);
+}
+
+/// A span recorded by [`SpanRecorder`]
+#[derive(Debug, Default)]
+struct RecordedSpan {
+    name: &'static str,
+    parent: Option<&'static str>,
+    fields: std::collections::BTreeMap<&'static str, String>,
+}
+
+impl tracing::field::Visit for RecordedSpan {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.fields.insert(field.name(), format!("{value:?}"));
+    }
+
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.fields.insert(field.name(), value.to_string());
+    }
+}
+
+/// A [`tracing_subscriber::Layer`] that records the spans and their fields
+#[derive(Clone, Default)]
+struct SpanRecorder {
+    spans: Arc<std::sync::Mutex<Vec<RecordedSpan>>>,
+}
+
+impl<S> tracing_subscriber::Layer<S> for SpanRecorder
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    fn on_new_span(
+        &self,
+        attrs: &tracing::span::Attributes<'_>,
+        id: &tracing::span::Id,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let span = ctx.span(id).unwrap();
+        let mut recorded = RecordedSpan {
+            name: span.name(),
+            parent: span.parent().map(|parent| parent.name()),
+            ..RecordedSpan::default()
+        };
+        attrs.record(&mut recorded);
+
+        let mut spans = self.spans.lock().unwrap();
+        span.extensions_mut().insert(spans.len());
+        spans.push(recorded);
+    }
+
+    fn on_record(
+        &self,
+        id: &tracing::span::Id,
+        values: &tracing::span::Record<'_>,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let span = ctx.span(id).unwrap();
+        let index = *span.extensions().get::<usize>().unwrap();
+        values.record(&mut self.spans.lock().unwrap()[index]);
+    }
+}
+
+#[tokio::test]
+async fn traces_the_steps() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let recorder = SpanRecorder::default();
+    let subscriber = tracing_subscriber::registry().with(recorder.clone());
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let toolbox = {
+        let toolbox = Toolbox::default();
+        toolbox.add_terminal_tool(ConcludeTool::default()).await;
+        toolbox
+    };
+
+    let config = SapiensConfig {
+        model: Arc::new(Box::new(CannedModel {})),
+        ..SapiensConfig::default()
+    };
+
+    TaskState::new(config, toolbox, "Conclude.".to_string())
+        .await
+        .unwrap()
+        .run()
+        .await
+        .unwrap();
+
+    let spans = recorder.spans.lock().unwrap();
+    let find = |name: &str| {
+        spans
+            .iter()
+            .find(|span| span.name == name)
+            .unwrap_or_else(|| panic!("no {name} span in {spans:?}"))
+    };
+
+    let step = find("step");
+    assert_eq!(step.parent, None);
+    assert_eq!(step.fields["step"], "1");
+    assert_eq!(step.fields["tool_name"], "ConcludeTool");
+    assert_eq!(step.fields["success"], "true");
+    assert_eq!(step.fields["prompt_tokens"], "10");
+    assert_eq!(step.fields["completion_tokens"], "5");
+    assert_eq!(step.fields["total_tokens"], "15");
+
+    assert_eq!(find("query_model").parent, Some("step"));
+    assert_eq!(find("invoke_tool").parent, Some("step"));
+}
+
+/// A tool with a result longer than the model can see
+struct VerboseTool {}
+
+#[async_trait::async_trait]
+impl Tool for VerboseTool {
+    fn description(&self) -> ToolDescription {
+        ToolDescription {
+            name: "VerboseTool".to_string(),
+            description: "A tool with a very long result".to_string(),
+            parameters: Format::default(),
+            responses_content: Format::default(),
+            examples: Vec::new(),
+            side_effects: SideEffects::ReadOnly,
+        }
+    }
+
+    async fn invoke(&self, _input: Value) -> Result<Value, ToolUseError> {
+        Ok(Value::String("lorem-ipsum;".repeat(100)))
+    }
+}
+
+struct VerboseAgent {}
+
+#[async_trait::async_trait]
+impl Agent for VerboseAgent {
+    type Error = ();
+
+    async fn act(&self, _context: &Context) -> Result<Message, ()> {
+        Ok(Message::Action {
+            content: indoc! {r"
+            ```yaml
+            tool_name: VerboseTool
+            parameters: {}
+            ```
+            "
+            }
+            .to_string(),
+            usage: None,
+            tool_calls: Vec::new(),
+        })
+    }
+}
+
+#[tokio::test]
+async fn stashes_the_long_results() {
+    let toolbox = Toolbox::builder()
+        .with_terminal_tool(ConcludeTool::default())
+        .with_tool(VerboseTool {})
+        .build();
+    let observer = void_observer();
+    let observer = Arc::downgrade(&observer);
+
+    let scheduler = Box::new(schedulers::SingleAgentScheduler::new(
+        10,
+        Box::new(VerboseAgent {}),
+        observer.clone(),
+    ));
+    let mut runtime = Runtime::new(toolbox.clone(), scheduler, observer)
+        .await
+        .unwrap()
+        .with_stashed_results(Some(100));
+
+    runtime.step().await.unwrap();
+
+    let Some(Message::ActionResult {
+        outcome: Outcome::Success { result, .. },
+        ..
+    }) = runtime.context.messages.last()
+    else {
+        panic!("expected a successful action result");
+    };
+    assert!(result.len() < 300);
+    assert!(result.contains("`result-1`"));
+    assert!(result.contains(r#"tools.fetch("result-1")"#));
+
+    let full = toolbox.fetch_result("result-1").unwrap();
+    assert_eq!(full.trim_end(), "lorem-ipsum;".repeat(100));
+    assert!(toolbox.fetch_result("result-2").is_none());
+}
+
+#[tokio::test]
+async fn stops_with_a_partial_answer_on_max_steps() {
+    let toolbox = Toolbox::builder()
+        .with_terminal_tool(ConcludeTool::default())
+        .with_tool(VerboseTool {})
+        .build();
+    let observer = void_observer();
+    let observer = Arc::downgrade(&observer);
+
+    let runtime = |partial_answer| {
+        let scheduler = Box::new(schedulers::SingleAgentScheduler::new(
+            2,
+            Box::new(VerboseAgent {}),
+            observer.clone(),
+        ));
+        let toolbox = toolbox.clone();
+        let observer = observer.clone();
+        async move {
+            let mut runtime = Runtime::new(toolbox, scheduler, observer)
+                .await
+                .unwrap()
+                .with_partial_answer_on_max_steps(partial_answer);
+            runtime.context.add_message(Message::Task {
+                content: "Never conclude.".to_string(),
+            });
+            runtime
+        }
+    };
+
+    // fails by default
+    let res = runtime(false).await.run().await;
+    assert!(matches!(res, Err(Error::MaxStepsReached)));
+
+    let terminal_state = runtime(true).await.run().await.unwrap();
+    assert_eq!(terminal_state.messages.len(), 1);
+
+    let message = &terminal_state.messages[0];
+    assert_eq!(message.original_question, "Never conclude.");
+    assert!(message
+        .conclusion
+        .contains("the maximum number of steps was reached after 2 steps"));
+    assert!(message.conclusion.contains("lorem-ipsum;"));
+}
+
+/// A model giving scripted answers - recording how much the temperature was
+/// raised for each of them
+struct ScriptedModel {
+    answers: Vec<&'static str>,
+    temperature_increases: Arc<std::sync::Mutex<Vec<f32>>>,
+}
+
+#[async_trait::async_trait]
+impl ChatEntryTokenNumber for ScriptedModel {
+    async fn num_tokens(&self, input: ChatInput) -> usize {
+        CannedModel {}.num_tokens(input).await
+    }
+
+    async fn context_size(&self) -> usize {
+        4096
+    }
+}
+
+#[async_trait::async_trait]
+impl Model for ScriptedModel {
+    async fn query(
+        &self,
+        input: ChatInput,
+        _max_tokens: Option<usize>,
+    ) -> Result<ModelResponse, crate::models::Error> {
+        let mut temperature_increases = self.temperature_increases.lock().unwrap();
+        temperature_increases.push(input.temperature_increase());
+        let msg = self.answers[temperature_increases.len() - 1].to_string();
+        drop(temperature_increases);
+
+        Ok(ModelResponse {
+            msg,
+            alternatives: Vec::new(),
+            usage: None,
+            finish_reason: None,
+            tool_calls: Vec::new(),
+        })
+    }
+}
+
+#[tokio::test]
+async fn ramps_the_temperature_up_after_failed_actions() {
+    let toolbox = Toolbox::builder()
+        .with_terminal_tool(ConcludeTool::default())
+        .with_tool(VerboseTool {})
+        .build();
+
+    let temperature_increases = Arc::new(std::sync::Mutex::default());
+    let model = ScriptedModel {
+        answers: vec![
+            "Let me think about it.",
+            "Let me think about it some more.",
+            indoc! {r"
+            ```yaml
+            tool_name: VerboseTool
+            parameters: {}
+            ```
+            "},
+            indoc! {r#"
+            ```yaml
+            tool_name: ConcludeTool
+            parameters:
+                conclusion: "Done"
+            ```
+            "#},
+        ],
+        temperature_increases: temperature_increases.clone(),
+    };
+
+    let config = SapiensConfig {
+        model: Arc::new(Box::new(model)),
+        temperature_ramp: Some(TemperatureRamp {
+            step: 0.25,
+            cap: 0.4,
+        }),
+        ..SapiensConfig::default()
+    };
+
+    let stop = TaskState::new(config, toolbox, "Conclude.".to_string())
+        .await
+        .unwrap()
+        .run()
+        .await
+        .unwrap();
+    assert_eq!(stop.steps, 4);
+
+    // raised after each failure, up to the cap - back to normal after a
+    // success
+    assert_eq!(
+        *temperature_increases.lock().unwrap(),
+        vec![0., 0.25, 0.4, 0.]
+    );
+}
+
+#[tokio::test]
+#[allow(clippy::too_many_lines)]
+async fn streams_serializable_step_events() {
+    let toolbox = Toolbox::builder()
+        .with_terminal_tool(ConcludeTool::default())
+        .build();
+
+    let model = ScriptedModel {
+        answers: vec![
+            "Let me think about it.",
+            indoc! {r"
+            ```yaml
+            tool_name: Unknown
+            parameters: {}
+            ```
+            "},
+            indoc! {r#"
+            ```yaml
+            tool_name: ConcludeTool
+            parameters:
+                conclusion: "Done"
+            ```
+            "#},
+        ],
+        temperature_increases: Arc::default(),
+    };
+
+    let config = SapiensConfig {
+        model: Arc::new(Box::new(model)),
+        ..SapiensConfig::default()
+    };
+
+    let (observer, mut rx) = StepEventObserver::channel();
+    let observer = wrap_observer(observer);
+    let weak_observer = Arc::downgrade(&observer);
+
+    TaskState::with_observer(config, toolbox, "Conclude.".to_string(), weak_observer)
+        .await
+        .unwrap()
+        .run()
+        .await
+        .unwrap();
+    drop(observer);
+
+    let mut events = Vec::new();
+    while let Some(event) = rx.recv().await {
+        let mut event = serde_json::to_value(event).unwrap();
+        // the usage is estimated from the prompt - keep it out of the way
+        if let Some(usage) = event.get_mut("usage") {
+            assert_eq!(usage["estimated"], true);
+            *usage = serde_json::Value::Null;
+        }
+        events.push(event);
+    }
+
+    assert_eq!(
+        events,
+        vec![
+            serde_json::json!({
+                "type": "model_response",
+                "content": "Let me think about it.",
+                "usage": null,
+            }),
+            serde_json::json!({
+                "type": "tool_error",
+                "tool_name": null,
+                "input": null,
+                "error": "No Action found. Give one Action as a YAML block with the fields `tool_name` and `parameters`.",
+                "invocation_count": 0,
+            }),
+            serde_json::json!({
+                "type": "model_response",
+                "content": "```yaml\ntool_name: Unknown\nparameters: {}\n```\n",
+                "usage": null,
+            }),
+            serde_json::json!({
+                "type": "parsed_action",
+                "tool_name": "Unknown",
+                "parameters": {},
+            }),
+            serde_json::json!({
+                "type": "tool_error",
+                "tool_name": "Unknown",
+                "input": "{}\n",
+                "error": "Tool not found: Unknown",
+                "invocation_count": 1,
+            }),
+            serde_json::json!({
+                "type": "model_response",
+                "content": "```yaml\ntool_name: ConcludeTool\nparameters:\n    conclusion: \"Done\"\n```\n",
+                "usage": null,
+            }),
+            serde_json::json!({
+                "type": "parsed_action",
+                "tool_name": "ConcludeTool",
+                "parameters": {"conclusion": "Done"},
+            }),
+            serde_json::json!({
+                "type": "tool_result",
+                "tool_name": "ConcludeTool",
+                "input": "conclusion: Done\n",
+                "result": "null\n",
+                "invocation_count": 1,
+                "invocation_index": 0,
+                "all_ran": true,
+            }),
+            serde_json::json!({
+                "type": "termination",
+                "messages": [{
+                    "conclusion": "Done",
+                    "original_question": "tbd",
+                    "tool_name": "ConcludeTool",
+                }],
+            }),
+        ]
+    );
+}
+
+/// A terminal tool failing after it concluded - as the code of the
+/// `SandboxedPython` tool can after it called the `Conclude` tool
+#[derive(Default)]
+struct ConcludeThenFailTool {
+    conclude: ConcludeTool,
+}
+
+#[async_trait::async_trait]
+impl Tool for ConcludeThenFailTool {
+    fn description(&self) -> ToolDescription {
+        ToolDescription {
+            name: "ConcludeThenFailTool".to_string(),
+            ..self.conclude.description()
+        }
+    }
+
+    async fn invoke(&self, input: Value) -> Result<Value, ToolUseError> {
+        self.conclude.invoke(input).await?;
+
+        Err(ToolUseError::InvocationFailed(
+            "Something went wrong after the conclusion.".to_string(),
+        ))
+    }
+}
+
+#[async_trait::async_trait]
+impl TerminalTool for ConcludeThenFailTool {
+    async fn is_done(&self) -> bool {
+        self.conclude.is_done().await
+    }
+
+    async fn take_done(&self) -> Option<TerminationMessage> {
+        self.conclude.take_done().await
+    }
+}
+
+struct ConcludeThenFailAgent {}
+
+#[async_trait::async_trait]
+impl Agent for ConcludeThenFailAgent {
+    type Error = ();
+
+    async fn act(&self, _context: &Context) -> Result<Message, ()> {
+        Ok(Message::Action {
+            content: indoc! {r#"
+            ```yaml
+            tool_name: ConcludeThenFailTool
+            parameters:
+                conclusion: "Done"
+            ```
+            "#
+            }
+            .to_string(),
+            usage: None,
+            tool_calls: Vec::new(),
+        })
+    }
+}
+
+#[tokio::test]
+async fn does_not_record_a_failure_after_the_conclusion() {
+    let toolbox = Toolbox::builder()
+        .with_terminal_tool(ConcludeThenFailTool::default())
+        .build();
+
+    let observer = void_observer();
+    let observer = Arc::downgrade(&observer);
+
+    let scheduler = Box::new(schedulers::SingleAgentScheduler::new(
+        10,
+        Box::new(ConcludeThenFailAgent {}),
+        observer.clone(),
+    ));
+    let mut runtime = Runtime::new(toolbox, scheduler, observer).await.unwrap();
+
+    let terminal_state = runtime.run().await.unwrap();
+    assert_eq!(terminal_state.messages.len(), 1);
+    assert_eq!(terminal_state.messages[0].conclusion, "Done");
+
+    // the Action is recorded - not its failure
+    assert!(matches!(
+        runtime.context.messages.last(),
+        Some(Message::Action { .. })
+    ));
+    assert!(!runtime
+        .context
+        .messages
+        .iter()
+        .any(|m| matches!(m, Message::ActionResult { .. })));
+}
+
+struct ConcludeAgent {}
+
+#[async_trait::async_trait]
+impl Agent for ConcludeAgent {
+    type Error = ();
+
+    async fn act(&self, _context: &Context) -> Result<Message, ()> {
+        Ok(Message::Action {
+            content: indoc! {r#"
+            ```yaml
+            tool_name: ConcludeTool
+            parameters:
+                conclusion: "Done"
+            ```
+            "#
+            }
+            .to_string(),
+            usage: None,
+            tool_calls: Vec::new(),
+        })
+    }
+}
+
+#[tokio::test]
+async fn goes_on_when_the_conclusion_is_rejected() {
+    let toolbox = Toolbox::builder()
+        .with_terminal_tool(ConcludeTool::default())
+        .build();
+
+    let observer = void_observer();
+    let observer = Arc::downgrade(&observer);
+
+    let checks = Arc::new(AtomicUsize::new(0));
+    let checker = {
+        let checks = checks.clone();
+        move |conclusion: &TerminationMessage| {
+            if checks.fetch_add(1, Ordering::SeqCst) == 0 {
+                verify::Verdict::Reject {
+                    feedback: format!("'{}' is not an answer.", conclusion.conclusion),
+                }
+            } else {
+                verify::Verdict::Accept
+            }
+        }
+    };
+
+    let scheduler = Box::new(schedulers::SingleAgentScheduler::new(
+        10,
+        Box::new(ConcludeAgent {}),
+        observer.clone(),
+    ));
+    let mut runtime = Runtime::new(toolbox, scheduler, observer)
+        .await
+        .unwrap()
+        .with_conclusion_checker(Some(Arc::new(checker)));
+
+    let terminal_state = runtime.run().await.unwrap();
+    assert_eq!(terminal_state.messages.len(), 1);
+    assert_eq!(terminal_state.messages[0].conclusion, "Done");
+    assert_eq!(checks.load(Ordering::SeqCst), 2);
+    assert_eq!(runtime.steps, 2);
+
+    // the rejection is shown to the model
+    let rejection = runtime
+        .context
+        .messages
+        .iter()
+        .find_map(|m| match m {
+            Message::ActionResult {
+                tool_name,
+                outcome: Outcome::ToolUseError { e },
+                ..
+            } => Some((tool_name.clone(), e.to_string())),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(
+        rejection,
+        (
+            Some("ConcludeTool".to_string()),
+            "Tool invocation failed: The conclusion was rejected: 'Done' is not an answer."
+                .to_string()
+        )
+    );
+}
+
+#[tokio::test]
+async fn does_not_check_the_tasks_given_up_on() {
+    let toolbox = Toolbox::builder()
+        .with_terminal_tool(ConcludeTool::default())
+        .build();
+
+    let observer = void_observer();
+    let observer = Arc::downgrade(&observer);
+
+    let checker = |_: &TerminationMessage| verify::Verdict::Reject {
+        feedback: "Not an answer.".to_string(),
+    };
+
+    let scheduler = Box::new(schedulers::SingleAgentScheduler::new(
+        10,
+        Box::new(ConcludeAgent {}),
+        observer.clone(),
+    ));
+    let runtime = Runtime::new(toolbox, scheduler, observer)
+        .await
+        .unwrap()
+        .with_conclusion_checker(Some(Arc::new(checker)));
+
+    let mut message = TerminationMessage {
+        conclusion: "I cannot do it.".to_string(),
+        original_question: "tbd".to_string(),
+        data: None,
+        tool_name: None,
+        status: TerminationStatus::Failure,
+    };
+    assert_eq!(runtime.reject_conclusion(&[message.clone()]).await, None);
+
+    message.status = TerminationStatus::Success;
+    assert_eq!(
+        runtime.reject_conclusion(&[message]).await,
+        Some("Not an answer.".to_string())
+    );
+}
+
+#[tokio::test]
+async fn asks_again_after_an_empty_response() {
+    let answers = vec![
+        " \n",
+        indoc! {r#"
+        ```yaml
+        tool_name: ConcludeTool
+        parameters:
+            conclusion: "Done"
+        ```
+        "#},
+    ];
+
+    for (empty_response_retries, expected_steps) in [(1, 1), (0, 2)] {
+        let toolbox = Toolbox::builder()
+            .with_terminal_tool(ConcludeTool::default())
+            .build();
+
+        let queries = Arc::new(std::sync::Mutex::default());
+        let model = ScriptedModel {
+            answers: answers.clone(),
+            temperature_increases: queries.clone(),
+        };
+
+        let config = SapiensConfig {
+            model: Arc::new(Box::new(model)),
+            empty_response_retries,
+            ..SapiensConfig::default()
+        };
+
+        let stop = TaskState::new(config, toolbox, "Conclude.".to_string())
+            .await
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        // the empty response is not handed to the tools when it is retried
+        assert_eq!(stop.steps, expected_steps);
+        assert_eq!(stop.termination_messages[0].conclusion, "Done");
+        assert_eq!(queries.lock().unwrap().len(), 2);
+    }
+}
+
+/// A model taking its time before answering like [`CannedModel`]
+struct LaggingModel {
+    delay: std::time::Duration,
+}
+
+#[async_trait::async_trait]
+impl ChatEntryTokenNumber for LaggingModel {
+    async fn num_tokens(&self, input: ChatInput) -> usize {
+        CannedModel {}.num_tokens(input).await
+    }
+
+    async fn context_size(&self) -> usize {
+        CannedModel {}.context_size().await
+    }
+}
+
+#[async_trait::async_trait]
+impl Model for LaggingModel {
+    async fn query(
+        &self,
+        input: ChatInput,
+        max_tokens: Option<usize>,
+    ) -> Result<ModelResponse, crate::models::Error> {
+        tokio::time::sleep(self.delay).await;
+        CannedModel {}.query(input, max_tokens).await
+    }
+}
+
+#[tokio::test]
+async fn gives_up_on_a_model_taking_too_long() {
+    let timeout = std::time::Duration::from_millis(20);
+
+    for (delay, timed_out) in [
+        (std::time::Duration::from_secs(30), true),
+        (timeout / 4, false),
+    ] {
+        let toolbox = Toolbox::builder()
+            .with_terminal_tool(ConcludeTool::default())
+            .build();
+
+        let config = SapiensConfig {
+            model: Arc::new(Box::new(LaggingModel { delay })),
+            model_timeout: Some(timeout),
+            ..SapiensConfig::default()
+        };
+
+        let res = TaskState::new(config, toolbox, "Conclude.".to_string())
+            .await
+            .unwrap()
+            .run()
+            .await;
+
+        if timed_out {
+            let Err(crate::Error::ChainError(Error::AgentFailed(agents::Error::ModelTimeout(t)))) =
+                res
+            else {
+                panic!("unexpected result: {:?}", res.err());
+            };
+            assert_eq!(t, timeout);
+        } else {
+            assert_eq!(res.unwrap().termination_messages[0].conclusion, "Done");
+        }
+    }
+}
+
+/// A model calling the tools natively when it is given some - it gives an
+/// Action as usual otherwise
+struct ToolCallingModel {
+    /// The names of the tools given to the model - by query
+    offered: Arc<std::sync::Mutex<Vec<Vec<String>>>>,
+}
+
+#[async_trait::async_trait]
+impl ChatEntryTokenNumber for ToolCallingModel {
+    async fn num_tokens(&self, input: ChatInput) -> usize {
+        CannedModel {}.num_tokens(input).await
+    }
+
+    async fn context_size(&self) -> usize {
+        CannedModel {}.context_size().await
+    }
+}
+
+#[async_trait::async_trait]
+impl Model for ToolCallingModel {
+    async fn query(
+        &self,
+        input: ChatInput,
+        max_tokens: Option<usize>,
+    ) -> Result<ModelResponse, crate::models::Error> {
+        let offered = input
+            .tools()
+            .iter()
+            .map(|tool| tool.name.clone())
+            .collect::<Vec<_>>();
+        self.offered.lock().unwrap().push(offered.clone());
+
+        if offered.is_empty() {
+            return CannedModel {}.query(input, max_tokens).await;
+        }
+
+        Ok(ModelResponse {
+            msg: "Let me conclude.".to_string(),
+            alternatives: Vec::new(),
+            usage: None,
+            finish_reason: None,
+            tool_calls: vec![ToolCall {
+                tool_name: "ConcludeTool".to_string(),
+                parameters: serde_yaml::from_str("conclusion: Done").unwrap(),
+            }],
+        })
+    }
+}
+
+#[tokio::test]
+async fn invokes_the_tools_called_natively() {
+    for tool_calling in [ToolCalling::Native, ToolCalling::Yaml] {
+        let toolbox = Toolbox::builder()
+            .with_terminal_tool(ConcludeTool::default())
+            .build();
+
+        let offered = Arc::new(std::sync::Mutex::default());
+        let model = ToolCallingModel {
+            offered: offered.clone(),
+        };
+
+        let config = SapiensConfig {
+            model: Arc::new(Box::new(model)),
+            tool_calling,
+            ..SapiensConfig::default()
+        };
+
+        let stop = TaskState::new(config, toolbox, "Conclude.".to_string())
+            .await
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        // the message has no Action: the tool call is invoked as is
+        assert_eq!(stop.steps, 1);
+        assert_eq!(stop.termination_messages[0].conclusion, "Done");
+
+        let offered = offered.lock().unwrap().clone();
+        match tool_calling {
+            ToolCalling::Native => assert_eq!(offered, vec![vec!["ConcludeTool".to_string()]]),
+            ToolCalling::Yaml => assert_eq!(offered, vec![Vec::<String>::new()]),
+        }
+    }
+}
+
+#[test]
+fn keeps_the_tool_calls_in_the_chat_history() {
+    let res = ModelResponse {
+        msg: "Let me conclude.".to_string(),
+        alternatives: Vec::new(),
+        usage: None,
+        finish_reason: None,
+        tool_calls: vec![ToolCall {
+            tool_name: "ConcludeTool".to_string(),
+            parameters: serde_yaml::from_str("conclusion: Done").unwrap(),
+        }],
+    };
+
+    let content = res.msg_with_tool_calls();
+    assert_eq!(
+        content,
+        "Let me conclude.\n```yaml\ntool_name: ConcludeTool\nparameters:\n  conclusion: Done\n```"
+    );
+    // as the Action the model would have given
+    let action = crate::tools::parse_action(&content, MultipleActionsPolicy::default()).unwrap();
+    assert_eq!(action.tool_name, "ConcludeTool");
+}
+
+/// A model recording the chat it is given - answering as [`CannedModel`]
+struct ChatRecordingModel {
+    chats: Arc<std::sync::Mutex<Vec<Vec<ChatEntry>>>>,
+}
+
+#[async_trait::async_trait]
+impl ChatEntryTokenNumber for ChatRecordingModel {
+    async fn num_tokens(&self, input: ChatInput) -> usize {
+        CannedModel {}.num_tokens(input).await
+    }
+
+    async fn context_size(&self) -> usize {
+        CannedModel {}.context_size().await
+    }
+}
+
+#[async_trait::async_trait]
+impl Model for ChatRecordingModel {
+    async fn query(
+        &self,
+        input: ChatInput,
+        max_tokens: Option<usize>,
+    ) -> Result<ModelResponse, crate::models::Error> {
+        self.chats.lock().unwrap().push(input.chat.clone());
+
+        CannedModel {}.query(input, max_tokens).await
+    }
+}
+
+#[tokio::test]
+async fn shows_the_conversation_before_the_task() {
+    for chain_type in [
+        crate::ChainType::SingleStepOODA,
+        crate::ChainType::MultiStepOODA,
+    ] {
+        let toolbox = Toolbox::builder()
+            .with_terminal_tool(ConcludeTool::default())
+            .build();
+
+        let chats = Arc::new(std::sync::Mutex::default());
+        let model = ChatRecordingModel {
+            chats: chats.clone(),
+        };
+
+        let config = SapiensConfig {
+            model: Arc::new(Box::new(model)),
+            chain_type,
+            ..SapiensConfig::default()
+        };
+
+        let conversation = vec![
+            ChatEntry {
+                role: Role::User,
+                msg: "What time is it in France?".to_string(),
+            },
+            ChatEntry {
+                role: Role::Assistant,
+                msg: "It is 12:00 in France.".to_string(),
+            },
+            ChatEntry {
+                role: Role::User,
+                msg: "Thanks!".to_string(),
+            },
+        ];
+
+        let observer = void_observer();
+        let observer = Arc::downgrade(&observer);
+        TaskState::with_conversation(
+            config,
+            toolbox,
+            "And in Japan?".to_string(),
+            conversation,
+            observer,
+        )
+        .await
+        .unwrap()
+        .run()
+        .await
+        .unwrap();
+
+        let chat = chats.lock().unwrap()[0].clone();
+        assert_eq!(chat.len(), 3, "{chat:?}");
+        assert_eq!(chat[0].msg, "What time is it in France?");
+        assert_eq!(chat[1].role, Role::Assistant);
+        // the last message of the conversation and the task - kept as is
+        assert_eq!(chat[2].role, Role::User);
+        assert!(chat[2].msg.starts_with("Thanks!\n\n"), "{}", chat[2].msg);
+        assert!(chat[2].msg.contains("And in Japan?"), "{}", chat[2].msg);
+    }
+}
@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use tracing::warn;
+
+use crate::chains::agents::query_model;
+use crate::context::ChatEntry;
+use crate::models::{ChatInput, Role};
+use crate::tools::TerminationMessage;
+use crate::SapiensConfig;
+
+/// What a [`ConclusionChecker`] thinks of a conclusion
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// The conclusion answers the original question - the task terminates
+    Accept,
+    /// The conclusion does not answer the original question - the task goes
+    /// on
+    Reject {
+        /// Why - shown to the model
+        feedback: String,
+    },
+}
+
+/// Judges whether a conclusion answers the original question before the task
+/// terminates - see [`crate::SapiensConfig::conclusion_checker`]
+///
+/// Only the successful conclusions are checked: a task given up on - see
+/// [`crate::tools::TerminationStatus::Failure`] - terminates as is.
+///
+/// Implemented for the `Fn(&TerminationMessage) -> Verdict` closures - see
+/// [`ModelChecker`] to ask a model instead.
+#[async_trait::async_trait]
+pub trait ConclusionChecker: Send + Sync {
+    /// Judge `conclusion`
+    async fn check(&self, conclusion: &TerminationMessage) -> Verdict;
+}
+
+/// A reference to a [`ConclusionChecker`]
+pub type ConclusionCheckerRef = Arc<dyn ConclusionChecker>;
+
+#[async_trait::async_trait]
+impl<F> ConclusionChecker for F
+where
+    F: Fn(&TerminationMessage) -> Verdict + Send + Sync,
+{
+    async fn check(&self, conclusion: &TerminationMessage) -> Verdict {
+        self(conclusion)
+    }
+}
+
+const CHECKER_SYSTEM_PROMPT: &str = "You review the answers given to questions. You reply `YES` if the answer actually answers the question. Otherwise you reply `NO: ` followed by what is missing or wrong.";
+
+/// The maximum number of tokens of the verdict of the model
+const CHECKER_MAX_TOKENS: usize = 256;
+
+/// A [`ConclusionChecker`] asking a model
+///
+/// The conclusions are accepted when the model cannot be queried.
+pub struct ModelChecker {
+    config: SapiensConfig,
+}
+
+impl ModelChecker {
+    /// Create a new [`ModelChecker`] asking `config.model` - within
+    /// `config.model_timeout` and with `config.empty_response_retries`
+    #[must_use]
+    pub const fn new(config: SapiensConfig) -> Self {
+        Self { config }
+    }
+
+    /// The [`Verdict`] in the response of the model
+    fn parse(response: &str) -> Verdict {
+        let response = response.trim();
+
+        if Self::after_word(response, "YES").is_some() {
+            return Verdict::Accept;
+        }
+
+        let feedback = Self::after_word(response, "NO")
+            .map_or(response, |rest| rest.trim_start_matches([':', ',', ' ']));
+        Verdict::Reject {
+            feedback: feedback.to_string(),
+        }
+    }
+
+    /// What follows `word` if `response` starts with it as a word of its own
+    /// - in any case and followed by `:`, `,`, `.`, a whitespace or nothing
+    fn after_word<'a>(response: &'a str, word: &str) -> Option<&'a str> {
+        let prefix = response.get(..word.len())?;
+        let rest = &response[word.len()..];
+
+        (prefix.eq_ignore_ascii_case(word)
+            && rest
+                .chars()
+                .next()
+                .is_none_or(|c| c.is_whitespace() || [':', ',', '.'].contains(&c)))
+        .then_some(rest)
+    }
+}
+
+#[async_trait::async_trait]
+impl ConclusionChecker for ModelChecker {
+    async fn check(&self, conclusion: &TerminationMessage) -> Verdict {
+        let input = ChatInput {
+            context: vec![ChatEntry {
+                role: Role::System,
+                msg: CHECKER_SYSTEM_PROMPT.to_string(),
+            }],
+            examples: vec![],
+            chat: vec![ChatEntry {
+                role: Role::User,
+                msg: format!(
+                    "Question:\n{}\n\nAnswer:\n{}",
+                    conclusion.original_question, conclusion.conclusion
+                ),
+            }],
+            stop: vec![],
+            temperature_increase: 0.,
+            tools: vec![],
+        };
+
+        match query_model(&self.config, input, CHECKER_MAX_TOKENS).await {
+            Ok(response) => Self::parse(&response.msg),
+            Err(e) => {
+                warn!(error = %e, "Failed to check the conclusion - accepted");
+                Verdict::Accept
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_verdict_of_the_model() {
+        assert_eq!(ModelChecker::parse("YES"), Verdict::Accept);
+        assert_eq!(ModelChecker::parse(" yes, it does."), Verdict::Accept);
+        assert_eq!(
+            ModelChecker::parse("NO: the year is missing"),
+            Verdict::Reject {
+                feedback: "the year is missing".to_string()
+            }
+        );
+        assert_eq!(
+            ModelChecker::parse("no: the year is missing"),
+            Verdict::Reject {
+                feedback: "the year is missing".to_string()
+            }
+        );
+        assert_eq!(
+            ModelChecker::parse("no"),
+            Verdict::Reject {
+                feedback: String::new()
+            }
+        );
+        assert_eq!(
+            ModelChecker::parse("Nothing is missing"),
+            Verdict::Reject {
+                feedback: "Nothing is missing".to_string()
+            }
+        );
+        assert_eq!(
+            ModelChecker::parse("Yesterday is missing"),
+            Verdict::Reject {
+                feedback: "Yesterday is missing".to_string()
+            }
+        );
+        assert_eq!(
+            ModelChecker::parse("It does not say which moon."),
+            Verdict::Reject {
+                feedback: "It does not say which moon.".to_string()
+            }
+        );
+    }
+}
@@ -1,24 +1,46 @@
 //! Maintain the context for the bot.
+use std::borrow::Cow;
 use std::fmt::{Debug, Formatter};
 
 use tracing::trace;
 
 use crate::chains::Message;
-use crate::models::{ChatInput, Role};
+use crate::models::{ChatInput, ModelRef, Role};
 use crate::SapiensConfig;
 
 /// A trait for formatting entries for the chat history
 pub trait ChatEntryFormatter {
     /// Format the entry
     fn format(&self, entry: &ChatEntry) -> String;
+
+    /// Format the note standing for the `count` messages pruned from the
+    /// history - see [`ChatHistory::pruned`]
+    fn format_pruned(&self, count: usize) -> String {
+        format!("[… {count} earlier messages pruned …]")
+    }
 }
 
 /// An error that can occur when adding a prompt to the chat history
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
-    /// The prompt is too long
-    #[error("The prompt is too long")]
-    PromptTooLong,
+    /// The prompt is too long - even with no history left - e.g. because
+    /// the task itself is too long
+    #[error("The prompt is too long: {num_tokens} tokens, at most {budget} fit in the context of the model. Try a shorter task.")]
+    PromptTooLong {
+        /// The number of tokens of the prompt
+        num_tokens: usize,
+        /// The number of tokens available for the prompt
+        budget: usize,
+    },
+    /// [`SapiensConfig::min_tokens_for_completion`] leaves no room for the
+    /// prompt in the context of the model
+    #[error("min_tokens_for_completion ({min_tokens_for_completion}) must be smaller than the context size of the model ({context_size})")]
+    NoRoomForPrompt {
+        /// The configured minimum number of tokens for the completion
+        min_tokens_for_completion: usize,
+        /// The size of the context of the model
+        context_size: usize,
+    },
 }
 
 /// A history entry
@@ -36,6 +58,69 @@ impl Debug for ChatEntry {
     }
 }
 
+/// A message of a [`ChatHistory`]
+///
+/// [`ChatEntry`] is the default implementation. The messages are sent to the
+/// model - and their tokens counted by it - as the [`ChatEntry`] they stand
+/// for - see [`ChatMessage::to_entry`]. What is not in the entry - e.g. the
+/// name of the author - is not sent.
+#[async_trait::async_trait]
+pub trait ChatMessage: Clone + Debug + Send + Sync {
+    /// Create a message from `role` with `content`
+    fn new(role: Role, content: String) -> Self;
+
+    /// The role of the author of the message
+    fn role(&self) -> Role;
+
+    /// The content of the message
+    fn content(&self) -> Cow<'_, str>;
+
+    /// The [`ChatEntry`] standing for the message
+    fn to_entry(&self) -> ChatEntry {
+        ChatEntry {
+            role: self.role(),
+            msg: self.content().into_owned(),
+        }
+    }
+
+    /// The number of tokens of the message - by default, as counted by
+    /// `model` for a request made of its [`ChatEntry`] alone
+    ///
+    /// It must not be less than the tokens the message adds to a request:
+    /// [`ChatHistory::purge`] relies on it to know how many messages to
+    /// prune.
+    async fn num_tokens(&self, model: &ModelRef) -> usize {
+        let input = ChatInput {
+            context: vec![],
+            examples: vec![],
+            chat: vec![self.to_entry()],
+            stop: vec![],
+            temperature_increase: 0.,
+            tools: vec![],
+        };
+
+        model.num_tokens(input).await
+    }
+}
+
+impl ChatMessage for ChatEntry {
+    fn new(role: Role, content: String) -> Self {
+        Self { role, msg: content }
+    }
+
+    fn role(&self) -> Role {
+        self.role.clone()
+    }
+
+    fn content(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.msg)
+    }
+
+    fn to_entry(&self) -> ChatEntry {
+        self.clone()
+    }
+}
+
 /// Maintain a chat history that can be truncated (from the head) to ensure
 /// we have enough tokens to complete the task
 ///
@@ -47,21 +132,26 @@ impl Debug for ChatEntry {
 /// To ensure we have enough tokens to complete the task, we truncate the
 /// chitchat history when new messages are added - with
 /// [`ChatHistory::add_chitchat`].
+///
+/// The messages are [`ChatEntry`]s unless told otherwise - see
+/// [`ChatMessage`].
 #[derive(Clone)]
-pub struct ChatHistory {
+pub struct ChatHistory<M: ChatMessage = ChatEntry> {
     /// Config - contains a ref to the model
     config: SapiensConfig,
     /// The maximum number of tokens we can have in the input for the model
     max_token: usize,
     /// The 'context' - first messages.
-    context: Vec<ChatEntry>,
+    context: Vec<M>,
     /// The examples
-    examples: Vec<(ChatEntry, ChatEntry)>,
+    examples: Vec<(M, M)>,
     /// The other messages
-    chitchat: Vec<ChatEntry>,
+    chitchat: Vec<M>,
+    /// The number of messages pruned from the head of `chitchat`
+    pruned: usize,
 }
 
-impl Debug for ChatHistory {
+impl<M: ChatMessage> Debug for ChatHistory<M> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("ChatHistory")
             .field("config", &self.config)
@@ -69,49 +159,74 @@ impl Debug for ChatHistory {
             .field("context", &self.context)
             .field("examples", &self.examples)
             .field("chitchat", &self.chitchat)
+            .field("pruned", &self.pruned)
             .finish()
     }
 }
 
 impl ChatHistory {
-    /// Create a new chat history
+    /// Create a new chat history of [`ChatEntry`]s
+    ///
+    /// See [`ChatHistory::with_max_token`] for other types of messages.
     #[must_use]
     pub const fn new(config: SapiensConfig, max_token: usize) -> Self {
+        Self::with_max_token(config, max_token)
+    }
+
+    /// Create a new chat history of [`ChatEntry`]s sized for the context of
+    /// the model
+    ///
+    /// See [`ChatHistory::sized_for_model`] for other types of messages.
+    pub async fn for_model(config: SapiensConfig) -> Self {
+        Self::sized_for_model(config).await
+    }
+}
+
+impl<M: ChatMessage> ChatHistory<M> {
+    /// Create a new chat history of `M`s
+    #[must_use]
+    pub const fn with_max_token(config: SapiensConfig, max_token: usize) -> Self {
         Self {
             config,
             max_token,
             context: vec![],
             examples: vec![],
             chitchat: vec![],
+            pruned: 0,
         }
     }
 
+    /// Create a new chat history of `M`s sized for the context of the model:
+    /// [`SapiensConfig::context_size`] if set, what the model tells otherwise
+    pub async fn sized_for_model(config: SapiensConfig) -> Self {
+        let max_token = match config.context_size {
+            Some(context_size) => context_size,
+            None => config.model.context_size().await,
+        };
+
+        Self::with_max_token(config, max_token)
+    }
+
     /// Set the context msg
-    pub fn set_context(&mut self, context: Vec<ChatEntry>) {
+    pub fn set_context(&mut self, context: Vec<M>) {
         self.context = context;
     }
 
     /// add a prompt to the history
     pub fn add_example(&mut self, user: String, bot: String) {
-        let msg_user = ChatEntry {
-            role: Role::User,
-            msg: user,
-        };
+        let msg_user = M::new(Role::User, user);
 
-        let msg_bot = ChatEntry {
-            role: Role::Assistant,
-            msg: bot,
-        };
+        let msg_bot = M::new(Role::Assistant, bot);
 
         self.examples.push((msg_user, msg_bot));
     }
 
     /// add a message to the chitchat history, and prune the history if needed
     /// returns the number of messages in the chitchat history
-    pub fn add_chitchat(&mut self, entry: ChatEntry) {
+    pub fn add_chitchat(&mut self, entry: M) {
         // ensure we don't have two consecutive messages from the same role
         if let Some(last) = self.chitchat.last() {
-            if last.role == entry.role {
+            if last.role() == entry.role() {
                 self.chitchat.pop();
             }
         }
@@ -119,12 +234,42 @@ impl ChatHistory {
         self.chitchat.push(entry);
     }
 
+    /// add messages at the head of the chitchat history - e.g. the
+    /// conversation a task comes from. The consecutive messages from the same
+    /// role are merged.
+    pub fn prepend_chitchat(&mut self, entries: impl IntoIterator<Item = M>) {
+        let mut chitchat: Vec<M> = vec![];
+
+        for entry in entries.into_iter().chain(self.chitchat.drain(..)) {
+            match chitchat.pop() {
+                Some(last) if last.role() == entry.role() => {
+                    let content = format!("{}\n\n{}", last.content(), entry.content());
+                    chitchat.push(M::new(entry.role(), content));
+                }
+                Some(last) => {
+                    chitchat.push(last);
+                    chitchat.push(entry);
+                }
+                None => chitchat.push(entry),
+            }
+        }
+
+        self.chitchat = chitchat;
+    }
+
     /// Prepare the input for the model
     pub(crate) fn make_input(&self) -> ChatInput {
         ChatInput {
-            context: self.context.clone(),
-            examples: self.examples.clone(),
-            chat: self.chitchat.clone(),
+            context: self.context.iter().map(ChatMessage::to_entry).collect(),
+            examples: self
+                .examples
+                .iter()
+                .map(|(user, bot)| (user.to_entry(), bot.to_entry()))
+                .collect(),
+            chat: self.chitchat.iter().map(ChatMessage::to_entry).collect(),
+            stop: self.config.stop_sequences.clone(),
+            temperature_increase: 0.,
+            tools: vec![],
         }
     }
 
@@ -133,10 +278,21 @@ impl ChatHistory {
         self.chitchat.is_empty()
     }
 
-    /// uses [`tiktoken_rs::num_tokens_from_messages`] prune
-    /// the chitchat history starting from the head until we have enough
-    /// tokens to complete the task
+    /// prune the examples then the chitchat history starting from the head
+    /// until we have enough tokens to complete the task
+    ///
+    /// The tokens are counted by the model for the whole request - with the
+    /// context, the examples and the overhead of the messages. The chitchat
+    /// messages are pruned by as many as needed to free the excess - see
+    /// [`ChatMessage::num_tokens`].
     pub async fn purge(&mut self) -> Result<usize, Error> {
+        if self.config.min_tokens_for_completion >= self.max_token {
+            return Err(Error::NoRoomForPrompt {
+                min_tokens_for_completion: self.config.min_tokens_for_completion,
+                context_size: self.max_token,
+            });
+        }
+
         if self.chitchat.is_empty() {
             return Ok(0);
         }
@@ -147,6 +303,11 @@ impl ChatHistory {
             "purging history"
         );
 
+        // the number of tokens left for the prompt
+        let budget = self
+            .max_token
+            .saturating_sub(self.config.min_tokens_for_completion);
+
         // start by pruning the examples
         while !self.examples.is_empty() {
             let input = self.make_input();
@@ -159,7 +320,7 @@ impl ChatHistory {
                 "purging history - examples"
             );
 
-            if num_tokens <= self.max_token - self.config.min_tokens_for_completion {
+            if num_tokens <= budget {
                 return Ok(self.chitchat.len());
             }
             // remove oldest message
@@ -178,26 +339,53 @@ impl ChatHistory {
                 "purging history - loop"
             );
 
-            if num_tokens <= self.max_token - self.config.min_tokens_for_completion {
+            if num_tokens <= budget {
                 return Ok(self.chitchat.len());
             }
 
-            // remove oldest message
-            self.chitchat.remove(0);
+            // remove the oldest messages until they free the excess - counted
+            // again on the next iteration
+            let excess = num_tokens - budget;
+            let mut freed = 0;
+            while self.chitchat.len() > 1 && freed < excess {
+                let msg = self.chitchat.remove(0);
+                freed += msg.num_tokens(&self.config.model).await;
+                self.pruned += 1;
+            }
         }
 
         let input = self.make_input();
         let num_tokens = self.config.model.num_tokens(input).await;
 
-        if num_tokens <= self.max_token - self.config.min_tokens_for_completion {
+        if num_tokens <= budget {
             return Ok(self.chitchat.len());
         }
 
-        Err(Error::PromptTooLong)
+        Err(Error::PromptTooLong { num_tokens, budget })
+    }
+
+    /// Number of tokens the model can generate for this history
+    ///
+    /// This is what is left of the context once the history is accounted
+    /// for, capped by [`SapiensConfig::max_tokens`] if set.
+    pub async fn max_completion_tokens(&self) -> usize {
+        let input = self.make_input();
+        let num_tokens = self.config.model.num_tokens(input).await;
+        let available = self.max_token.saturating_sub(num_tokens);
+
+        self.config
+            .max_tokens
+            .map_or(available, |max_tokens| max_tokens.min(available))
+    }
+
+    /// Number of chitchat messages pruned so far by [`ChatHistory::purge`]
+    #[must_use]
+    pub const fn pruned(&self) -> usize {
+        self.pruned
     }
 
     /// iterate over the prompt and chitchat messages
-    pub fn iter(&self) -> impl Iterator<Item = &ChatEntry> {
+    pub fn iter(&self) -> impl Iterator<Item = &M> {
         self.context
             .iter()
             .chain(self.examples.iter().flat_map(|(a, b)| vec![a, b]))
@@ -205,18 +393,34 @@ impl ChatHistory {
     }
 
     /// format the history using the given formatter
+    ///
+    /// If messages were pruned, a note is added between the prompt and the
+    /// oldest message left - see [`ChatEntryFormatter::format_pruned`].
     pub fn format<T>(&self, formatter: &T) -> Vec<String>
     where
         T: ChatEntryFormatter + ?Sized,
     {
-        self.iter()
-            .map(|msg| formatter.format(msg))
+        let prompt = self
+            .context
+            .iter()
+            .chain(self.examples.iter().flat_map(|(a, b)| vec![a, b]))
+            .map(|msg| formatter.format(&msg.to_entry()));
+
+        let pruned = (self.pruned > 0).then(|| formatter.format_pruned(self.pruned));
+
+        prompt
+            .chain(pruned)
+            .chain(
+                self.chitchat
+                    .iter()
+                    .map(|msg| formatter.format(&msg.to_entry())),
+            )
             .collect::<Vec<_>>()
     }
 }
 
-impl From<&ChatHistory> for Vec<ChatEntry> {
-    fn from(val: &ChatHistory) -> Self {
+impl<M: ChatMessage> From<&ChatHistory<M>> for Vec<M> {
+    fn from(val: &ChatHistory<M>) -> Self {
         val.iter().cloned().collect()
     }
 }
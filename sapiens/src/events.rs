@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::models::Usage;
+use crate::tools::{Artifact, TerminationMessage};
+use crate::{
+    ActionNotification, InvocationResultNotification, ModelNotification, RuntimeObserver,
+    TerminationNotification,
+};
+
+/// An event of a step of a task - serializable so a frontend can stream and
+/// render it.
+///
+/// Serialized as an object tagged with its `type`:
+/// ```json
+/// {"type": "tool_result", "tool_name": "Conclude", ...}
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StepEvent {
+    /// The model responded
+    ModelResponse {
+        /// The content of the response
+        content: String,
+        /// The number of tokens used by the model - if it reported it
+        usage: Option<Usage>,
+    },
+    /// An Action was parsed from the response of the model
+    ParsedAction {
+        /// The tool to invoke
+        tool_name: String,
+        /// The parameters of the tool
+        parameters: serde_yaml::Value,
+    },
+    /// A tool was invoked successfully
+    ToolResult {
+        /// The tool name
+        tool_name: String,
+        /// The input passed to the tool
+        input: String,
+        /// The result
+        result: String,
+        /// The number of Actions in the response of the model
+        invocation_count: usize,
+        /// The index of the Action that was run
+        invocation_index: usize,
+        /// Whether all the Actions were run - `invocation_index` is then
+        /// meaningless
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        all_ran: bool,
+        /// The files produced by the tool
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        artifacts: Vec<Artifact>,
+    },
+    /// A tool failed - or no valid Action was found in the response of the
+    /// model
+    ToolError {
+        /// The tool name - `None` if there was no valid Action
+        tool_name: Option<String>,
+        /// The input passed to the tool - `None` if there was no valid Action
+        input: Option<String>,
+        /// What went wrong
+        error: String,
+        /// The number of Actions in the response of the model
+        invocation_count: usize,
+    },
+    /// The task is done
+    Termination {
+        /// The termination messages
+        messages: Vec<TerminationMessage>,
+    },
+}
+
+impl From<ModelNotification> for StepEvent {
+    fn from(event: ModelNotification) -> Self {
+        Self::ModelResponse {
+            content: event.chat_entry.msg,
+            usage: event.usage,
+        }
+    }
+}
+
+impl From<InvocationResultNotification> for StepEvent {
+    fn from(event: InvocationResultNotification) -> Self {
+        match event {
+            InvocationResultNotification::InvocationSuccess(e) => Self::ToolResult {
+                tool_name: e.tool_name,
+                input: e.extracted_input,
+                result: e.result,
+                invocation_count: e.invocation_count,
+                invocation_index: e.invocation_index,
+                all_ran: e.all_ran,
+                artifacts: e.artifacts,
+            },
+            InvocationResultNotification::InvocationFailure(e) => Self::ToolError {
+                tool_name: Some(e.tool_name),
+                input: Some(e.extracted_input),
+                error: e.e.to_string(),
+                invocation_count: e.invocation_count,
+            },
+            InvocationResultNotification::InvalidInvocation(e) => Self::ToolError {
+                tool_name: None,
+                input: None,
+                error: e.e.to_string(),
+                invocation_count: e.invocation_count,
+            },
+        }
+    }
+}
+
+impl From<TerminationNotification> for StepEvent {
+    fn from(event: TerminationNotification) -> Self {
+        Self::Termination {
+            messages: event.messages,
+        }
+    }
+}
+
+/// A [`RuntimeObserver`] sending the [`StepEvent`]s of the tasks it observes
+/// to a channel.
+///
+/// The Actions that cannot be parsed are reported as
+/// [`StepEvent::ToolError`] with the result of the invocation.
+#[derive(Debug, Clone)]
+pub struct StepEventObserver {
+    tx: mpsc::UnboundedSender<StepEvent>,
+}
+
+impl StepEventObserver {
+    /// Create a new [`StepEventObserver`] sending the events to `tx`
+    #[must_use]
+    pub const fn new(tx: mpsc::UnboundedSender<StepEvent>) -> Self {
+        Self { tx }
+    }
+
+    /// Create a new [`StepEventObserver`] and the receiving end of its
+    /// channel
+    #[must_use]
+    pub fn channel() -> (Self, mpsc::UnboundedReceiver<StepEvent>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self::new(tx), rx)
+    }
+
+    /// Send an event - dropped if nobody is listening anymore
+    fn send(&self, event: StepEvent) {
+        let _ = self.tx.send(event);
+    }
+}
+
+#[async_trait::async_trait]
+impl RuntimeObserver for StepEventObserver {
+    async fn on_model_update(&mut self, event: ModelNotification) {
+        self.send(event.into());
+    }
+
+    async fn on_action(&mut self, event: ActionNotification) {
+        if let Ok(action) = event.action {
+            self.send(StepEvent::ParsedAction {
+                tool_name: action.tool_name,
+                parameters: action.parameters,
+            });
+        }
+    }
+
+    async fn on_invocation_result(&mut self, event: InvocationResultNotification) {
+        self.send(event.into());
+    }
+
+    async fn on_termination(&mut self, event: TerminationNotification) {
+        self.send(event.into());
+    }
+}
@@ -30,6 +30,14 @@ pub mod models;
 
 pub mod chains;
 
+/// Metrics about the tasks - for monitoring
+pub mod metrics;
+
+/// Serializable events of the steps - for frontends
+pub mod events;
+
+pub mod text;
+
 use std::fmt::Debug;
 use std::str::FromStr;
 use std::sync::{Arc, Weak};
@@ -38,12 +46,18 @@ use clap::builder::PossibleValue;
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 
-use crate::chains::{Chain, Message, MultiStepOODAChain, SingleStepOODAChain};
+use crate::chains::verify::ConclusionCheckerRef;
+use crate::chains::{Chain, Checkpoint, Message, MultiStepOODAChain, SingleStepOODAChain};
 use crate::context::{ChatEntry, ContextDump};
 use crate::models::openai::OpenAI;
-use crate::models::{ModelRef, ModelResponse, Role, Usage};
+use crate::models::{ModelRef, ModelResponse, Pricing, Role, TemperatureRamp, ToolCalling, Usage};
+use crate::prompt::{ClockRef, PromptTemplates};
+use crate::tools::redact::Redactor;
 use crate::tools::toolbox::{InvokeResult, Toolbox};
-use crate::tools::{invocation, TerminationMessage, ToolUseError};
+use crate::tools::{
+    invocation, Artifact, LongResultPolicy, MultipleActionsPolicy, ParsedAction,
+    TerminationMessage, ToolUseError,
+};
 
 /// The error type for the bot
 #[derive(thiserror::Error, Debug)]
@@ -112,8 +126,62 @@ pub struct SapiensConfig {
     pub chain_type: ChainType,
     /// The minimum number of tokens that need to be available for completion
     pub min_tokens_for_completion: usize,
-    /// Maximum number of tokens for the model to generate
+    /// Size of the context of the model, in tokens - overrides what the model
+    /// tells, e.g. for fine-tuned or local models it does not know
+    pub context_size: Option<usize>,
+    /// Maximum number of tokens for the model to generate - if not set, the
+    /// model can use whatever is left of its context after the prompt
     pub max_tokens: Option<usize>,
+    /// The sequences the model stops generating at - by default, the header
+    /// of the response of an Action so the model does not make it up. Not
+    /// all the models support it.
+    pub stop_sequences: Vec<String>,
+    /// Price of the tokens - to estimate the cost of a task
+    pub pricing: Option<Pricing>,
+    /// Maximum number of tokens a task can use before it is stopped
+    pub max_total_tokens: Option<u32>,
+    /// Maximum number of times the same action can be requested in a row
+    /// before the task is stopped
+    pub max_repeated_actions: Option<usize>,
+    /// Stop with a partial answer when `max_steps` is reached - instead of
+    /// failing with [`chains::Error::MaxStepsReached`]
+    pub partial_answer_on_max_steps: bool,
+    /// Invoke all the actions of a message concurrently when they all are
+    /// parallel-safe - only the one chosen by `multiple_actions` is invoked
+    /// otherwise, and none with [`MultipleActionsPolicy::RejectMultiple`]
+    pub parallel_actions: bool,
+    /// Which action is invoked when a message has several of them
+    pub multiple_actions: MultipleActionsPolicy,
+    /// Maximum size (in bytes) of the formatted result of an action
+    pub max_tool_result_chars: usize,
+    /// What to do with the results longer than `max_tool_result_chars`
+    pub long_tool_results: LongResultPolicy,
+    /// Hides the secrets in the results of the actions before they are shown
+    /// to the model or to the observer - see [`Redactor::default`]
+    pub redactor: Redactor,
+    /// The prompts of the single-step OODA agent - defaults to
+    /// [`PromptTemplates::default`] - see [`prompt::languages`] for
+    /// translations
+    pub prompt_templates: Option<PromptTemplates>,
+    /// The clock used to tell the current date and time in the 'system'
+    /// prompt - e.g. [`prompt::SystemClock`] - not told if not set
+    pub clock: Option<ClockRef>,
+    /// Raise the temperature of the model after consecutive failed actions -
+    /// not raised if not set
+    pub temperature_ramp: Option<TemperatureRamp>,
+    /// Judges whether a conclusion answers the original question before the
+    /// task terminates - e.g. [`chains::verify::ModelChecker`] - the
+    /// conclusions are accepted if not set
+    pub conclusion_checker: Option<ConclusionCheckerRef>,
+    /// Number of times the model is asked again when it responds with
+    /// nothing - e.g. because of the stop sequences
+    pub empty_response_retries: usize,
+    /// How long to wait for the model to respond to a query - forever if not
+    /// set
+    pub model_timeout: Option<std::time::Duration>,
+    /// How the model invokes the tools - with YAML blocks in its messages by
+    /// default
+    pub tool_calling: ToolCalling,
 }
 
 #[allow(clippy::missing_fields_in_debug)]
@@ -123,7 +191,28 @@ impl Debug for SapiensConfig {
             .field("max_steps", &self.max_steps)
             .field("chain_type", &self.chain_type)
             .field("min_tokens_for_completion", &self.min_tokens_for_completion)
+            .field("context_size", &self.context_size)
             .field("max_tokens", &self.max_tokens)
+            .field("stop_sequences", &self.stop_sequences)
+            .field("pricing", &self.pricing)
+            .field("max_total_tokens", &self.max_total_tokens)
+            .field("max_repeated_actions", &self.max_repeated_actions)
+            .field(
+                "partial_answer_on_max_steps",
+                &self.partial_answer_on_max_steps,
+            )
+            .field("parallel_actions", &self.parallel_actions)
+            .field("multiple_actions", &self.multiple_actions)
+            .field("max_tool_result_chars", &self.max_tool_result_chars)
+            .field("long_tool_results", &self.long_tool_results)
+            .field("redactor", &self.redactor)
+            .field("prompt_templates", &self.prompt_templates)
+            .field("clock", &self.clock.is_some())
+            .field("temperature_ramp", &self.temperature_ramp)
+            .field("conclusion_checker", &self.conclusion_checker.is_some())
+            .field("empty_response_retries", &self.empty_response_retries)
+            .field("model_timeout", &self.model_timeout)
+            .field("tool_calling", &self.tool_calling)
             .finish()
     }
 }
@@ -135,7 +224,25 @@ impl Default for SapiensConfig {
             max_steps: 10,
             chain_type: ChainType::SingleStepOODA,
             min_tokens_for_completion: 256,
+            context_size: None,
             max_tokens: None,
+            stop_sequences: vec!["\n# Action ".to_string()],
+            pricing: None,
+            max_total_tokens: None,
+            max_repeated_actions: None,
+            partial_answer_on_max_steps: false,
+            parallel_actions: false,
+            multiple_actions: MultipleActionsPolicy::default(),
+            max_tool_result_chars: 2048,
+            long_tool_results: LongResultPolicy::default(),
+            redactor: Redactor::default(),
+            prompt_templates: None,
+            clock: None,
+            temperature_ramp: None,
+            conclusion_checker: None,
+            empty_response_retries: 1,
+            model_timeout: None,
+            tool_calling: ToolCalling::default(),
         }
     }
 }
@@ -154,7 +261,7 @@ impl From<ModelResponse> for ModelNotification {
         Self {
             chat_entry: ChatEntry {
                 role: Role::Assistant,
-                msg: res.msg,
+                msg: res.msg_with_tool_calls(),
             },
             usage: res.usage,
         }
@@ -174,6 +281,13 @@ impl From<Message> for MessageNotification {
     }
 }
 
+/// Notification of the Action parsed from a message - before it is invoked
+#[derive(Debug, Clone)]
+pub struct ActionNotification {
+    /// The Action or why it could not be parsed
+    pub action: Result<ParsedAction, invocation::Error>,
+}
+
 /// Notification of the result of a tool invocation
 pub enum InvocationResultNotification {
     /// Invocation success notification
@@ -202,22 +316,31 @@ impl From<InvokeResult> for InvocationResultNotification {
             }),
             InvokeResult::Success {
                 invocation_count,
+                invocation_index,
+                all_ran,
                 tool_name,
                 extracted_input,
                 result,
+                artifacts,
+                ..
             } => Self::InvocationSuccess(InvocationSuccessNotification {
                 invocation_count,
+                invocation_index,
+                all_ran,
                 tool_name,
                 extracted_input,
                 result,
+                artifacts,
             }),
             InvokeResult::Error {
                 invocation_count,
+                invocation_index,
                 tool_name,
                 extracted_input,
                 e,
             } => Self::InvocationFailure(InvocationFailureNotification {
                 invocation_count,
+                invocation_index,
                 tool_name,
                 extracted_input,
                 e,
@@ -230,18 +353,29 @@ impl From<InvokeResult> for InvocationResultNotification {
 pub struct InvocationSuccessNotification {
     /// The number of invocation blocks in the message
     pub invocation_count: usize,
+    /// The index of the invocation that was run among the `invocation_count`
+    /// ones - the others were ignored, unless `all_ran`
+    pub invocation_index: usize,
+    /// Whether all the `invocation_count` invocations were run - see
+    /// [`crate::tools::toolbox::invoke_tools_in_parallel`]
+    pub all_ran: bool,
     /// The tool name
     pub tool_name: String,
     /// The input that was extracted from the message and passed to `tool_name`
     pub extracted_input: String,
     /// The result
     pub result: String,
+    /// The files produced by the tool - only referenced in `result`
+    pub artifacts: Vec<Artifact>,
 }
 
 /// Invocation failure notification
 pub struct InvocationFailureNotification {
     /// Number of invocation  blocks in the message
     pub invocation_count: usize,
+    /// The index of the invocation that was run among the `invocation_count`
+    /// ones - the others were ignored
+    pub invocation_index: usize,
     /// The tool name
     pub tool_name: String,
     /// The input that was extracted from the message and passed to `tool_name`
@@ -279,6 +413,9 @@ pub trait RuntimeObserver: Send {
     /// Called when the scheduler has selected a message
     async fn on_message(&mut self, _event: MessageNotification) {}
 
+    /// Called when an Action was parsed from the message - or failed to be
+    async fn on_action(&mut self, _event: ActionNotification) {}
+
     /// Called when the tool invocation was successful
     async fn on_invocation_result(&mut self, _event: InvocationResultNotification) {}
 
@@ -317,12 +454,17 @@ pub struct Step {
     task_chain: Box<dyn Chain>,
     /// The observer
     observer: WeakRuntimeObserver,
+    /// The price of the tokens
+    pricing: Option<Pricing>,
+    /// The number of steps run so far
+    steps: usize,
 }
 
 impl Step {
     /// Run the task for a single step
     async fn step(mut self) -> Result<TaskState, Error> {
         let termination_messages = self.task_chain.step().await?;
+        self.steps += 1;
 
         // check if the task is done
         if !termination_messages.is_empty() {
@@ -336,21 +478,45 @@ impl Step {
                     .await;
             }
 
+            let usage = self.task_chain.usage_so_far();
             return Ok(TaskState::Stop {
                 stop: Stop {
                     termination_messages,
+                    estimated_cost: self.pricing.map(|p| p.cost(&usage)),
+                    usage,
+                    steps: self.steps,
                 },
             });
         }
 
         Ok(TaskState::Step { step: self })
     }
+
+    /// The token usage accumulated over the steps so far
+    #[must_use]
+    pub fn usage_so_far(&self) -> Usage {
+        self.task_chain.usage_so_far()
+    }
+
+    /// The estimated cost of the steps so far - if
+    /// [`SapiensConfig::pricing`] is set
+    #[must_use]
+    pub fn estimated_cost(&self) -> Option<f64> {
+        self.pricing.map(|p| p.cost(&self.usage_so_far()))
+    }
 }
 
 /// The task is done
 pub struct Stop {
     /// The termination messages
     pub termination_messages: Vec<TerminationMessage>,
+    /// The token usage for the whole task
+    pub usage: Usage,
+    /// The estimated cost of the whole task - if [`SapiensConfig::pricing`]
+    /// is set
+    pub estimated_cost: Option<f64>,
+    /// The number of steps it took
+    pub steps: usize,
 }
 
 /// The state machine of a task
@@ -391,21 +557,45 @@ impl TaskState {
         toolbox: Toolbox,
         task: String,
         observer: WeakRuntimeObserver,
+    ) -> Result<Self, Error> {
+        Self::with_conversation(config, toolbox, task, vec![], observer).await
+    }
+
+    /// Create a new [`TaskState`] for a `task` coming from a `conversation` -
+    /// e.g. the prior messages of a chat channel, oldest first.
+    ///
+    /// The models see the `conversation` before the task. See
+    /// [`TaskState::with_observer`] for the `observer`.
+    ///
+    /// # Errors
+    ///
+    /// If the chain cannot be created, an error is returned.
+    pub async fn with_conversation(
+        config: SapiensConfig,
+        toolbox: Toolbox,
+        task: String,
+        conversation: Vec<ChatEntry>,
+        observer: WeakRuntimeObserver,
     ) -> Result<Self, Error> {
         if let Some(observer) = observer.upgrade() {
             observer.lock().await.on_task(&task).await;
         }
 
+        let pricing = config.pricing;
+        let toolbox = toolbox.for_task();
+
         let task_chain = match config.chain_type {
             ChainType::SingleStepOODA => {
                 let chain = SingleStepOODAChain::new(config, toolbox, observer.clone())
                     .await?
+                    .with_conversation(conversation)
                     .with_task(task);
                 Box::new(chain) as Box<dyn Chain>
             }
             ChainType::MultiStepOODA => {
                 let chain = MultiStepOODAChain::new(config, toolbox, observer.clone())
                     .await?
+                    .with_conversation(conversation)
                     .with_task(task);
                 Box::new(chain) as Box<dyn Chain>
             }
@@ -420,10 +610,70 @@ impl TaskState {
             step: Step {
                 task_chain,
                 observer,
+                pricing,
+                steps: 0,
             },
         })
     }
 
+    /// Resume a [`TaskState`] from a [`Checkpoint`] - see
+    /// [`TaskState::checkpoint`].
+    ///
+    /// The `toolbox` and `config` are expected to be equivalent to the ones
+    /// the task was started with.
+    ///
+    /// # Errors
+    ///
+    /// If the chain cannot be created, an error is returned.
+    pub async fn resume(
+        config: SapiensConfig,
+        toolbox: Toolbox,
+        checkpoint: Checkpoint,
+        observer: WeakRuntimeObserver,
+    ) -> Result<Self, Error> {
+        let pricing = config.pricing;
+        let steps = checkpoint.steps;
+        let toolbox = toolbox.for_task();
+
+        let task_chain = match config.chain_type {
+            ChainType::SingleStepOODA => {
+                let chain =
+                    SingleStepOODAChain::resume(config, toolbox, observer.clone(), checkpoint)
+                        .await?;
+                Box::new(chain) as Box<dyn Chain>
+            }
+            ChainType::MultiStepOODA => {
+                let chain =
+                    MultiStepOODAChain::resume(config, toolbox, observer.clone(), checkpoint)
+                        .await?;
+                Box::new(chain) as Box<dyn Chain>
+            }
+        };
+
+        // call the observer
+        if let Some(observer) = observer.upgrade() {
+            observer.lock().await.on_start(task_chain.dump()).await;
+        }
+
+        Ok(Self::Step {
+            step: Step {
+                task_chain,
+                observer,
+                pricing,
+                steps,
+            },
+        })
+    }
+
+    /// Snapshot the conversational state of the task - if it is not done yet
+    #[must_use]
+    pub fn checkpoint(&self) -> Option<Checkpoint> {
+        match self {
+            Self::Step { step } => Some(step.task_chain.checkpoint()),
+            Self::Stop { .. } => None,
+        }
+    }
+
     /// Run the task until it is done
     pub async fn run(mut self) -> Result<Stop, Error> {
         loop {
@@ -446,6 +696,25 @@ impl TaskState {
         }
     }
 
+    /// The token usage accumulated over the steps so far
+    #[must_use]
+    pub fn usage_so_far(&self) -> Usage {
+        match self {
+            Self::Step { step } => step.usage_so_far(),
+            Self::Stop { stop } => stop.usage.clone(),
+        }
+    }
+
+    /// The estimated cost of the steps so far - if
+    /// [`SapiensConfig::pricing`] is set
+    #[must_use]
+    pub fn estimated_cost(&self) -> Option<f64> {
+        match self {
+            Self::Step { step } => step.estimated_cost(),
+            Self::Stop { stop } => stop.estimated_cost,
+        }
+    }
+
     /// is the task done?
     #[must_use]
     pub fn is_done(&self) -> Option<Vec<TerminationMessage>> {
@@ -459,7 +728,9 @@ impl TaskState {
 /// Run until the task is done or the maximum number of steps is reached
 ///
 /// See [`TaskState::new`], [`TaskState::step`] and [`TaskState::run`] for
-/// more flexible ways to run a task
+/// more flexible ways to run a task - [`TaskState::run`] returns a [`Stop`]
+/// with the number of steps and the token usage along with the termination
+/// messages.
 #[tracing::instrument(skip(toolbox, observer, config))]
 pub async fn run_to_the_end(
     config: SapiensConfig,
@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::chains::Message;
+use crate::context::ContextDump;
+use crate::{
+    InvocationResultNotification, MessageNotification, ModelNotification, RuntimeObserver,
+    TerminationNotification,
+};
+
+/// Counters accumulated over the tasks it observes - attach it to the tasks
+/// as a [`RuntimeObserver`] and export it with [`Metrics::to_map`] - to feed
+/// a Prometheus registry for example.
+///
+/// A step ends with the result of its Action - or with its message if it is
+/// not an Action.
+#[derive(Debug, Default, Clone)]
+pub struct Metrics {
+    /// The number of tasks started
+    pub tasks_started: u64,
+    /// The number of tasks completed
+    pub tasks_completed: u64,
+    /// The number of steps
+    pub steps: u64,
+    /// The number of tokens of the prompts
+    pub prompt_tokens: u64,
+    /// The number of tokens of the completions
+    pub completion_tokens: u64,
+    /// The total number of tokens
+    pub total_tokens: u64,
+    /// The number of successful invocations - by tool name
+    pub tool_successes: BTreeMap<String, u64>,
+    /// The number of failed invocations - by tool name
+    pub tool_failures: BTreeMap<String, u64>,
+    /// The number of messages without a valid Action
+    pub invalid_actions: u64,
+    /// The time spent in the steps
+    pub step_duration: Duration,
+    /// When the current step started
+    step_start: Option<Instant>,
+}
+
+impl Metrics {
+    /// The average duration of a step - if any
+    #[must_use]
+    pub fn average_step_latency(&self) -> Option<Duration> {
+        u32::try_from(self.steps)
+            .ok()
+            .filter(|steps| *steps > 0)
+            .map(|steps| self.step_duration / steps)
+    }
+
+    /// The average number of steps of the completed tasks - if any
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn average_steps_per_task(&self) -> Option<f64> {
+        (self.tasks_completed > 0).then(|| self.steps as f64 / self.tasks_completed as f64)
+    }
+
+    /// Export the metrics as a flat map - from the name of the metric (with
+    /// its labels, Prometheus style) to its value.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn to_map(&self) -> BTreeMap<String, f64> {
+        let mut map = BTreeMap::from([
+            (
+                "sapiens_tasks_started_total".to_string(),
+                self.tasks_started as f64,
+            ),
+            (
+                "sapiens_tasks_completed_total".to_string(),
+                self.tasks_completed as f64,
+            ),
+            ("sapiens_steps_total".to_string(), self.steps as f64),
+            (
+                "sapiens_prompt_tokens_total".to_string(),
+                self.prompt_tokens as f64,
+            ),
+            (
+                "sapiens_completion_tokens_total".to_string(),
+                self.completion_tokens as f64,
+            ),
+            ("sapiens_tokens_total".to_string(), self.total_tokens as f64),
+            (
+                "sapiens_invalid_actions_total".to_string(),
+                self.invalid_actions as f64,
+            ),
+            (
+                "sapiens_step_duration_seconds_total".to_string(),
+                self.step_duration.as_secs_f64(),
+            ),
+        ]);
+
+        if let Some(steps_per_task) = self.average_steps_per_task() {
+            map.insert("sapiens_steps_per_task".to_string(), steps_per_task);
+        }
+
+        if let Some(latency) = self.average_step_latency() {
+            map.insert(
+                "sapiens_step_latency_seconds".to_string(),
+                latency.as_secs_f64(),
+            );
+        }
+
+        for (outcome, counts) in [
+            ("success", &self.tool_successes),
+            ("failure", &self.tool_failures),
+        ] {
+            for (tool_name, count) in counts {
+                map.insert(
+                    format!(
+                        "sapiens_tool_invocations_total{{tool=\"{tool_name}\",outcome=\"{outcome}\"}}"
+                    ),
+                    *count as f64,
+                );
+            }
+        }
+
+        map
+    }
+
+    /// End the current step
+    fn end_step(&mut self) {
+        let now = Instant::now();
+        if let Some(start) = self.step_start.replace(now) {
+            self.step_duration += now - start;
+        }
+        self.steps += 1;
+    }
+}
+
+#[async_trait::async_trait]
+impl RuntimeObserver for Metrics {
+    async fn on_start(&mut self, _context: ContextDump) {
+        self.tasks_started += 1;
+        self.step_start = Some(Instant::now());
+    }
+
+    async fn on_model_update(&mut self, event: ModelNotification) {
+        if let Some(usage) = event.usage {
+            self.prompt_tokens += u64::from(usage.prompt_tokens);
+            self.completion_tokens += u64::from(usage.completion_tokens);
+            self.total_tokens += u64::from(usage.total_tokens);
+        }
+    }
+
+    async fn on_message(&mut self, event: MessageNotification) {
+        // the step of an Action ends with its result
+        if !matches!(event.message, Message::Action { .. }) {
+            self.end_step();
+        }
+    }
+
+    async fn on_invocation_result(&mut self, event: InvocationResultNotification) {
+        match event {
+            InvocationResultNotification::InvocationSuccess(e) => {
+                *self.tool_successes.entry(e.tool_name).or_default() += 1;
+            }
+            InvocationResultNotification::InvocationFailure(e) => {
+                *self.tool_failures.entry(e.tool_name).or_default() += 1;
+            }
+            InvocationResultNotification::InvalidInvocation(_) => {
+                self.invalid_actions += 1;
+            }
+        }
+
+        self.end_step();
+    }
+
+    async fn on_termination(&mut self, _event: TerminationNotification) {
+        self.tasks_completed += 1;
+        self.step_start = None;
+    }
+}
@@ -1,14 +1,19 @@
 pub mod ollama;
 pub mod openai;
+pub mod replay;
 pub mod vertex_ai;
 
 use std::fmt::{Debug, Display};
+use std::ops::AddAssign;
 use std::str::FromStr;
 use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
+use tracing::debug;
 
 use crate::context::ChatEntry;
+use crate::tools::toolbox::Toolbox;
+use crate::tools::{MultipleActionsPolicy, ToolDescription};
 
 /// A model reference
 pub type ModelRef = Arc<Box<dyn Model>>;
@@ -34,6 +39,9 @@ pub enum Error {
     /// Ollama error
     #[error("Ollama error: {0}")]
     OllamaError(#[from] ollama_rs::error::OllamaError),
+    /// All the recorded responses have been replayed
+    #[error("No more recorded responses: all {0} have been replayed")]
+    TranscriptExhausted(usize),
 }
 
 /// Roles in the conversation
@@ -67,7 +75,6 @@ impl Display for Role {
 
 // FUTURE(ssoudan) support pure completion API
 // FUTURE(ssoudan) support ability to run multistep chains to come to response
-// FUTURE(ssoudan) support local llam.cpp models
 
 /// Something that can count the number of tokens in a chat entry
 #[async_trait::async_trait]
@@ -88,9 +95,97 @@ pub struct ChatInput {
     pub(crate) examples: Vec<(ChatEntry, ChatEntry)>,
     /// The chat history
     pub(crate) chat: Vec<ChatEntry>,
+    /// The sequences the model stops generating at - see
+    /// [`crate::SapiensConfig::stop_sequences`]
+    pub(crate) stop: Vec<String>,
+    /// How much to raise the temperature of the model - see
+    /// [`crate::SapiensConfig::temperature_ramp`]
+    pub(crate) temperature_increase: f32,
+    /// The tools the model can call natively - none unless
+    /// [`crate::SapiensConfig::tool_calling`] is [`ToolCalling::Native`]
+    pub(crate) tools: Vec<ToolDescription>,
+}
+
+impl ChatInput {
+    /// How much to raise the temperature of the model - after consecutive
+    /// failed actions - see [`crate::SapiensConfig::temperature_ramp`]
+    #[must_use]
+    pub const fn temperature_increase(&self) -> f32 {
+        self.temperature_increase
+    }
+
+    /// The tools the model can call natively - see [`ToolCalling::Native`]
+    #[must_use]
+    pub fn tools(&self) -> &[ToolDescription] {
+        &self.tools
+    }
+
+    /// The temperature to query the model at: `temperature` - `default` if
+    /// not set - raised by [`ChatInput::temperature_increase`], up to `max`
+    #[must_use]
+    pub fn ramped_temperature(
+        &self,
+        temperature: Option<f32>,
+        default: f32,
+        max: f32,
+    ) -> Option<f32> {
+        if self.temperature_increase > 0. {
+            Some((temperature.unwrap_or(default) + self.temperature_increase).min(max))
+        } else {
+            temperature
+        }
+    }
+}
+
+/// Raise the temperature of the model after consecutive failed actions - to
+/// shake it out of a loop - see [`crate::SapiensConfig::temperature_ramp`]
+///
+/// The temperature goes back to normal after an action succeeds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TemperatureRamp {
+    /// How much the temperature is raised after each failed action
+    pub step: f32,
+    /// How much the temperature can be raised at most
+    pub cap: f32,
+}
+
+impl TemperatureRamp {
+    /// How much to raise the temperature after `consecutive_failures` failed
+    /// actions
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn increase(&self, consecutive_failures: usize) -> f32 {
+        (self.step * consecutive_failures as f32).min(self.cap)
+    }
+}
+
+/// How the model invokes the tools
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ToolCalling {
+    /// The model gives the Actions as YAML blocks in its messages
+    #[default]
+    Yaml,
+    /// The tools are given to the model as functions it calls natively - see
+    /// [`ModelResponse::tool_calls`]. The models without native tool calling
+    /// give YAML blocks as usual.
+    Native,
+}
+
+/// A call of a tool by a model with native tool calling - see
+/// [`ToolCalling::Native`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// The tool to invoke
+    pub tool_name: String,
+    /// The input to the tool
+    pub parameters: serde_yaml::Value,
 }
 
 /// A model
+///
+/// Implement this trait (and [`ChatEntryTokenNumber`]) to plug another
+/// language model - local or remote - into [`crate::SapiensConfig::model`].
+/// [`openai::OpenAI`] is the default implementation.
 #[async_trait::async_trait]
 pub trait Model: ChatEntryTokenNumber + Send + Sync {
     /// Query the model
@@ -104,31 +199,128 @@ pub trait Model: ChatEntryTokenNumber + Send + Sync {
 /// Response from a language model
 #[derive(Clone)]
 pub struct ModelResponse {
-    // TODO(ssoudan) support getting multiple candidates
     /// The message
     pub msg: String,
+    /// The other candidate messages - when the model is asked for more than
+    /// one - see [`ModelResponse::select_action`]
+    pub alternatives: Vec<String>,
     /// The usage
     pub usage: Option<Usage>,
     /// Finish reason
     pub finish_reason: Option<String>,
+    /// The tools called natively by the model - the message is not searched
+    /// for Actions when there are some
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl ModelResponse {
+    /// Estimate the usage with the tokenizer of `model` if the model did not
+    /// report it - `input` is the input the response was produced from.
+    pub async fn with_estimated_usage(mut self, model: &dyn Model, input: ChatInput) -> Self {
+        if self.usage.is_some() {
+            return self;
+        }
+
+        let prompt_tokens = model.num_tokens(input).await;
+        let completion_tokens = model
+            .num_tokens(ChatInput {
+                context: vec![],
+                examples: vec![],
+                chat: vec![ChatEntry {
+                    role: Role::Assistant,
+                    msg: self.msg.clone(),
+                }],
+                stop: vec![],
+                temperature_increase: 0.,
+                tools: vec![],
+            })
+            .await;
+
+        let prompt_tokens = u32::try_from(prompt_tokens).unwrap_or(u32::MAX);
+        let completion_tokens = u32::try_from(completion_tokens).unwrap_or(u32::MAX);
+
+        self.usage = Some(Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens.saturating_add(completion_tokens),
+            estimated: true,
+        });
+
+        self
+    }
+
+    /// Prefer the first candidate message - [`ModelResponse::msg`] then
+    /// [`ModelResponse::alternatives`] - with a valid Action for a tool of
+    /// `toolbox` - with `policy` if there are several Actions. Keep
+    /// [`ModelResponse::msg`] if none has one.
+    pub async fn select_action(mut self, toolbox: &Toolbox, policy: MultipleActionsPolicy) -> Self {
+        if self.alternatives.is_empty()
+            || !self.tool_calls.is_empty()
+            || toolbox.validate_invocation(&self.msg, policy).await.is_ok()
+        {
+            return self;
+        }
+
+        for i in 0..self.alternatives.len() {
+            if toolbox
+                .validate_invocation(&self.alternatives[i], policy)
+                .await
+                .is_ok()
+            {
+                debug!(
+                    candidate = i + 1,
+                    "Selected a candidate with a valid Action"
+                );
+                std::mem::swap(&mut self.msg, &mut self.alternatives[i]);
+                break;
+            }
+        }
+
+        self
+    }
+
+    /// The message followed by the [`ModelResponse::tool_calls`] as a YAML
+    /// Action - what the chat history keeps of the response
+    #[must_use]
+    pub fn msg_with_tool_calls(&self) -> String {
+        let action = match self.tool_calls.as_slice() {
+            [] => return self.msg.clone(),
+            [call] => serde_yaml::to_string(call),
+            calls => serde_yaml::to_string(calls),
+        }
+        .unwrap_or_else(|e| format!("# Failed to serialize the tool calls: {e}\n"));
+
+        let msg = self.msg.trim_end();
+        if msg.is_empty() {
+            format!("```yaml\n{action}```")
+        } else {
+            format!("{msg}\n```yaml\n{action}```")
+        }
+    }
 }
 
 impl Debug for ModelResponse {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "ModelResponse {{ ")?;
         write!(f, "msg: \n{}, \n", &self.msg)?;
+        if !self.alternatives.is_empty() {
+            writeln!(f, "alternatives: {}, ", self.alternatives.len())?;
+        }
         if let Some(usage) = &self.usage {
             writeln!(f, "usage: {usage:#?}, ")?;
         }
         if let Some(finish_reason) = &self.finish_reason {
             writeln!(f, "finish_reason: {}, ", &finish_reason)?;
         }
+        if !self.tool_calls.is_empty() {
+            writeln!(f, "tool_calls: {:?}, ", self.tool_calls)?;
+        }
         write!(f, "}}")
     }
 }
 
 /// Token usage
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Usage {
     /// The number of tokens used for the prompt
     pub prompt_tokens: u32,
@@ -136,6 +328,37 @@ pub struct Usage {
     pub completion_tokens: u32,
     /// The total number of tokens used
     pub total_tokens: u32,
+    /// Whether the numbers are estimated locally - when the model does not
+    /// report its usage - see [`ModelResponse::with_estimated_usage`]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub estimated: bool,
+}
+
+impl AddAssign<&Self> for Usage {
+    fn add_assign(&mut self, rhs: &Self) {
+        self.prompt_tokens += rhs.prompt_tokens;
+        self.completion_tokens += rhs.completion_tokens;
+        self.total_tokens += rhs.total_tokens;
+        self.estimated |= rhs.estimated;
+    }
+}
+
+/// Price of the tokens - per 1K tokens
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pricing {
+    /// Price of 1K prompt tokens
+    pub prompt_per_1k_tokens: f64,
+    /// Price of 1K completion tokens
+    pub completion_per_1k_tokens: f64,
+}
+
+impl Pricing {
+    /// Estimate the cost of the given [`Usage`]
+    #[must_use]
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        f64::from(usage.prompt_tokens) * self.prompt_per_1k_tokens / 1000.
+            + f64::from(usage.completion_tokens) * self.completion_per_1k_tokens / 1000.
+    }
 }
 
 /// Supported models
@@ -148,6 +371,8 @@ pub enum SupportedModel {
     GPT3_5Turbo0613,
     /// GPT 3.5 Turbo 16k
     GPT3_5Turbo16k,
+    /// GPT 4
+    GPT4,
     /// Vicuna 7B 1.1
     Vicuna7B1_1,
     /// Vicuna 13B 1.1
@@ -170,6 +395,7 @@ impl Display for SupportedModel {
             Self::GPT3_5Turbo => write!(f, "gpt-3.5-turbo"),
             Self::GPT3_5Turbo0613 => write!(f, "gpt-3.5-turbo-0613"),
             Self::GPT3_5Turbo16k => write!(f, "gpt-3.5-turbo-16k"),
+            Self::GPT4 => write!(f, "gpt-4"),
             Self::Vicuna7B1_1 => write!(f, "vicuna-7b-1.1"),
             Self::Vicuna13B1_1 => write!(f, "vicuna-13b-1.1"),
             Self::ChatBison001 => write!(f, "chat-bison-001"),
@@ -187,6 +413,7 @@ impl Debug for SupportedModel {
             Self::GPT3_5Turbo => write!(f, "gpt-3.5-turbo"),
             Self::GPT3_5Turbo0613 => write!(f, "gpt-3.5-turbo-0613"),
             Self::GPT3_5Turbo16k => write!(f, "gpt-3.5-turbo-16k"),
+            Self::GPT4 => write!(f, "gpt-4"),
             Self::Vicuna7B1_1 => write!(f, "vicuna-7b-1.1"),
             Self::Vicuna13B1_1 => write!(f, "vicuna-13b-1.1"),
             Self::ChatBison001 => write!(f, "chat-bison-001"),
@@ -206,6 +433,7 @@ impl FromStr for SupportedModel {
             "gpt-3.5-turbo" => Ok(Self::GPT3_5Turbo),
             "gpt-3.5-turbo-0613" => Ok(Self::GPT3_5Turbo0613),
             "gpt-3.5-turbo-16k" => Ok(Self::GPT3_5Turbo16k),
+            "gpt-4" => Ok(Self::GPT4),
             "vicuna-7b-1.1" => Ok(Self::Vicuna7B1_1),
             "vicuna-13b-1.1" => Ok(Self::Vicuna13B1_1),
             "chat-bison-001" => Ok(Self::ChatBison001),
@@ -225,6 +453,7 @@ impl clap::ValueEnum for SupportedModel {
             Self::GPT3_5Turbo,
             Self::GPT3_5Turbo0613,
             Self::GPT3_5Turbo16k,
+            Self::GPT4,
             Self::Vicuna7B1_1,
             Self::Vicuna13B1_1,
             Self::ChatBison001,
@@ -240,6 +469,7 @@ impl clap::ValueEnum for SupportedModel {
             Self::GPT3_5Turbo => Some(clap::builder::PossibleValue::new("gpt-3.5-turbo")),
             Self::GPT3_5Turbo0613 => Some(clap::builder::PossibleValue::new("gpt-3.5-turbo-0613")),
             Self::GPT3_5Turbo16k => Some(clap::builder::PossibleValue::new("gpt-3.5-turbo-16k")),
+            Self::GPT4 => Some(clap::builder::PossibleValue::new("gpt-4")),
             Self::Vicuna7B1_1 => Some(clap::builder::PossibleValue::new("vicuna-7b-1.1")),
             Self::Vicuna13B1_1 => Some(clap::builder::PossibleValue::new("vicuna-13b-1.1")),
             Self::ChatBison001 => Some(clap::builder::PossibleValue::new("chat-bison-001")),
@@ -5,6 +5,7 @@ use std::sync::Arc;
 
 use ollama_rs::generation::chat::request::ChatMessageRequest;
 use ollama_rs::generation::chat::ChatMessage;
+use ollama_rs::generation::options::GenerationOptions;
 use ollama_rs::Ollama;
 use tokio::sync::Mutex;
 use tracing::debug;
@@ -76,7 +77,13 @@ impl LanguageModel {
 
         debug!("model_name: {}", model_name);
 
-        ChatMessageRequest::new(model_name.to_string(), messages)
+        let request = ChatMessageRequest::new(model_name.to_string(), messages);
+
+        if input.stop.is_empty() {
+            request
+        } else {
+            request.options(GenerationOptions::default().stop(input.stop.clone()))
+        }
     }
 }
 
@@ -129,8 +136,10 @@ impl models::Model for LanguageModel {
 
         Ok(ModelResponse {
             msg: resp.message.unwrap().content,
+            alternatives: Vec::new(),
             usage: None,
             finish_reason: None,
+            tool_calls: Vec::new(),
         })
     }
 }
@@ -1,147 +1,433 @@
 //! `OpenAI` models
 
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::num::NonZeroU8;
 use std::str::FromStr;
 use std::sync::Arc;
 
-use async_openai::config::OpenAIConfig;
+use async_openai::config::{AzureConfig, Config, OpenAIConfig};
 pub use async_openai::error::OpenAIError;
 use async_openai::types::{
-    ChatCompletionRequestAssistantMessage, ChatCompletionRequestMessage,
-    ChatCompletionRequestSystemMessage, ChatCompletionRequestUserMessage,
-    ChatCompletionRequestUserMessageContent, CreateChatCompletionRequest,
+    ChatCompletionRequestAssistantMessage, ChatCompletionRequestFunctionMessage,
+    ChatCompletionRequestMessage, ChatCompletionRequestSystemMessage,
+    ChatCompletionRequestToolMessage, ChatCompletionRequestUserMessage,
+    ChatCompletionRequestUserMessageContent, ChatCompletionResponseMessage, ChatCompletionTool,
+    ChatCompletionToolType, CreateChatCompletionRequest, FunctionObject, Stop,
 };
 use lazy_static::lazy_static;
 use tracing::{error, trace};
 
-use crate::context::ChatEntry;
+use crate::context::{ChatEntry, ChatMessage};
 use crate::models::{
     ChatEntryTokenNumber, ChatInput, Error, Model, ModelRef, ModelResponse, Role, SupportedModel,
-    Usage,
+    ToolCall, Usage,
 };
+use crate::tools::ToolDescription;
+/// Where and how to reach the `OpenAI` API - see [`client`]
+#[derive(Clone, Default)]
+pub struct ClientOptions {
+    /// The `OpenAI` API key - defaults to `OPENAI_API_KEY`
+    pub api_key: Option<String>,
+    /// The `OpenAI` organization ID - if any
+    pub org_id: Option<String>,
+    /// The `OpenAI` API base URL - defaults to <https://api.openai.com/v1>
+    pub api_base: Option<String>,
+}
+
+impl ClientOptions {
+    /// The options set in the environment - `OPENAI_API_KEY`,
+    /// `OPENAI_ORG_ID` and `OPENAI_API_BASE`
+    #[must_use]
+    pub fn from_env() -> Self {
+        Self {
+            api_key: std::env::var("OPENAI_API_KEY").ok(),
+            org_id: std::env::var("OPENAI_ORG_ID").ok(),
+            api_base: std::env::var("OPENAI_API_BASE").ok(),
+        }
+    }
+}
+
+/// Build an `OpenAI` client
+///
+/// The API key is read from `OPENAI_API_KEY` when the client is built - not
+/// when it is used - if it is not given. Build the clients before the
+/// environment is cleared - as the frontends do once they are started - or
+/// pass the key explicitly.
+#[must_use]
+pub fn client(options: ClientOptions) -> async_openai::Client<OpenAIConfig> {
+    let ClientOptions {
+        api_key,
+        org_id,
+        api_base,
+    } = options;
+
+    let mut config = OpenAIConfig::new();
+
+    if let Some(api_key) = api_key {
+        config = config.with_api_key(api_key);
+    }
+
+    if let Some(org_id) = org_id {
+        config = config.with_org_id(org_id);
+    }
+
+    if let Some(api_base) = api_base {
+        config = config.with_api_base(api_base);
+    }
+
+    async_openai::Client::with_config(config)
+}
+
 /// Build an `OpenAI` model
+///
+/// See [`client`] for when the API key is read.
 /// # Arguments
 /// * `model_name` - The model to use
-/// * `api_key` - The `OpenAI` API key
-/// * `api_base` - The `OpenAI` API base URL - defaults to <https://api.openai.com/v1>
+/// * `options` - Where and how to reach the `OpenAI` API
 /// * `temperature` - The `OpenAI` chat completion request temperature. min: 0,
 ///   max: 2, default: 1. The higher the temperature, the crazier the text.
 pub fn build(
     model: SupportedModel,
+    options: ClientOptions,
+    temperature: Option<f32>,
+) -> Result<ModelRef, Error> {
+    build_with_client(model, client(options), temperature)
+}
+
+/// Build an `OpenAI` model with a client configured by the caller - e.g. with
+/// a `reqwest::Client` with custom timeouts or proxies, or shared with the
+/// rest of the application
+/// # Arguments
+/// * `model_name` - The model to use
+/// * `client` - The client - see [`async_openai::Client::with_http_client`]
+/// * `temperature` - The `OpenAI` chat completion request temperature. min: 0,
+///   max: 2, default: 1. The higher the temperature, the crazier the text.
+pub fn build_with_client<C: Config + Send + Sync + 'static>(
+    model: SupportedModel,
+    client: async_openai::Client<C>,
+    temperature: Option<f32>,
+) -> Result<ModelRef, Error> {
+    let model = OpenAI::with_client(model, temperature, client);
+
+    Ok(Arc::new(Box::new(model)))
+}
+
+/// Build an Azure `OpenAI` model
+/// # Arguments
+/// * `model_name` - The model to use
+/// * `api_base` - The Azure `OpenAI` resource URL - e.g. <https://my-resource.openai.azure.com>
+/// * `api_version` - The Azure `OpenAI` API version - e.g. `2023-05-15`
+/// * `deployment_id` - The name of the model deployment
+/// * `api_key` - The Azure `OpenAI` API key - defaults to `OPENAI_API_KEY`
+/// * `temperature` - The `OpenAI` chat completion request temperature. min: 0,
+///   max: 2, default: 1. The higher the temperature, the crazier the text.
+pub fn build_azure(
+    model: SupportedModel,
+    api_base: String,
+    api_version: String,
+    deployment_id: String,
     api_key: Option<String>,
-    api_base: Option<String>,
     temperature: Option<f32>,
 ) -> Result<ModelRef, Error> {
-    let mut config = OpenAIConfig::new();
+    let mut config = AzureConfig::new()
+        .with_api_base(api_base)
+        .with_api_version(api_version)
+        .with_deployment_id(deployment_id);
 
-    if let Some(api_key) = api_key.as_ref() {
+    if let Some(api_key) = api_key {
         config = config.with_api_key(api_key);
     }
 
-    if let Some(api_base) = api_base.as_ref() {
-        config = config.with_api_base(api_base.clone());
-    }
-
-    let openai_client = async_openai::Client::with_config(config);
-
-    let model = OpenAI::new(model, temperature, openai_client, api_base, api_key);
+    let model = OpenAI::new(model, temperature, config);
 
     Ok(Arc::new(Box::new(model)))
 }
 
 /// `OpenAI` model
-pub struct OpenAI {
+///
+/// `C` is the configuration of the endpoint - [`OpenAIConfig`] for `OpenAI`
+/// and compatible APIs, [`AzureConfig`] for Azure `OpenAI`.
+///
+/// The sampling parameters are set on the model - like the temperature -
+/// rather than on [`crate::SapiensConfig`]: they are specific to the `OpenAI`
+/// API and the other models take their own.
+pub struct OpenAI<C: Config = OpenAIConfig> {
     /// The model
     model: SupportedModel,
     /// The `OpenAI` chat completion request temperature
     /// min: 0, max: 2, default: 1,
     /// The higher the temperature, the crazier the text.
     pub temperature: Option<f32>,
+    /// The `OpenAI` chat completion request nucleus sampling probability mass
+    /// min: 0, max: 1, default: 1
+    pub top_p: Option<f32>,
+    /// The `OpenAI` chat completion request presence penalty
+    /// min: -2, max: 2, default: 0
+    pub presence_penalty: Option<f32>,
+    /// The `OpenAI` chat completion request frequency penalty
+    /// min: -2, max: 2, default: 0
+    pub frequency_penalty: Option<f32>,
+    /// The `OpenAI` chat completion request seed - for (best effort)
+    /// deterministic sampling, if the model supports it
+    pub seed: Option<i64>,
+    /// The `OpenAI` chat completion request logit bias - maps token ids (as
+    /// strings) to a bias between -100 and 100. Can be used to discourage the
+    /// model from emitting more than one action block for example.
+    pub logit_bias: Option<HashMap<String, i32>>,
+    /// The number of candidate completions to request - see
+    /// [`ModelResponse::select_action`]
+    /// default: 1
+    pub candidates: NonZeroU8,
+    /// How the roles of the messages are mapped for the endpoint
+    pub role_mapping: RoleMapping,
     /// The client
-    client: async_openai::Client<OpenAIConfig>,
-
-    /// API base
-    api_base: Option<String>,
-
-    /// API key
-    api_key: Option<String>,
+    client: async_openai::Client<C>,
+    /// The endpoint configuration
+    config: C,
 }
 
-impl Clone for OpenAI {
+impl<C: Config> Clone for OpenAI<C> {
     fn clone(&self) -> Self {
-        let mut config = OpenAIConfig::new();
-
-        if let Some(api_key) = &self.api_key {
-            config = config.with_api_key(api_key.clone());
-        }
-
-        if let Some(api_base) = &self.api_base {
-            config = config.with_api_base(api_base.clone());
-        }
-
-        let client = async_openai::Client::with_config(config);
-
         Self {
             model: self.model.clone(),
             temperature: self.temperature,
-            client,
-            api_base: self.api_base.clone(),
-            api_key: self.api_key.clone(),
+            top_p: self.top_p,
+            presence_penalty: self.presence_penalty,
+            frequency_penalty: self.frequency_penalty,
+            seed: self.seed,
+            logit_bias: self.logit_bias.clone(),
+            candidates: self.candidates,
+            role_mapping: self.role_mapping,
+            client: self.client.clone(),
+            config: self.config.clone(),
         }
     }
 }
 
 #[allow(clippy::missing_fields_in_debug)]
-impl Debug for OpenAI {
+impl<C: Config> Debug for OpenAI<C> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("OpenAI")
             .field("model", &self.model)
             .field("temperature", &self.temperature)
+            .field("top_p", &self.top_p)
+            .field("presence_penalty", &self.presence_penalty)
+            .field("frequency_penalty", &self.frequency_penalty)
+            .field("seed", &self.seed)
+            .field("logit_bias", &self.logit_bias)
+            .field("candidates", &self.candidates)
+            .field("role_mapping", &self.role_mapping)
+            .field("api_base", &self.config.api_base())
             .finish()
     }
 }
 
-impl OpenAI {
+impl<C: Config> OpenAI<C> {
     /// Create a new `OpenAI` model
     #[must_use]
-    pub const fn new(
+    pub fn new(model: SupportedModel, temperature: Option<f32>, config: C) -> Self {
+        Self::with_client(
+            model,
+            temperature,
+            async_openai::Client::with_config(config),
+        )
+    }
+
+    /// Create a new `OpenAI` model with a client configured by the caller -
+    /// its HTTP client, backoff and endpoint configuration are kept
+    #[must_use]
+    pub fn with_client(
         model: SupportedModel,
         temperature: Option<f32>,
-        client: async_openai::Client<OpenAIConfig>,
-        api_base: Option<String>,
-        api_key: Option<String>,
+        client: async_openai::Client<C>,
     ) -> Self {
+        let config = client.config().clone();
+
         Self {
             model,
             temperature,
+            top_p: None,
+            presence_penalty: None,
+            frequency_penalty: None,
+            seed: None,
+            logit_bias: None,
+            candidates: NonZeroU8::MIN,
+            role_mapping: RoleMapping::default(),
             client,
-            api_base,
-            api_key,
+            config,
+        }
+    }
+
+    /// Set the nucleus sampling probability mass
+    #[must_use]
+    pub const fn with_top_p(mut self, top_p: f32) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    /// Set the presence penalty
+    #[must_use]
+    pub const fn with_presence_penalty(mut self, presence_penalty: f32) -> Self {
+        self.presence_penalty = Some(presence_penalty);
+        self
+    }
+
+    /// Set the frequency penalty
+    #[must_use]
+    pub const fn with_frequency_penalty(mut self, frequency_penalty: f32) -> Self {
+        self.frequency_penalty = Some(frequency_penalty);
+        self
+    }
+
+    /// Set the seed
+    #[must_use]
+    pub const fn with_seed(mut self, seed: i64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Set the logit bias
+    #[must_use]
+    pub fn with_logit_bias(mut self, logit_bias: HashMap<String, i32>) -> Self {
+        self.logit_bias = Some(logit_bias);
+        self
+    }
+
+    /// Set the number of candidate completions to request
+    #[must_use]
+    pub const fn with_candidates(mut self, candidates: NonZeroU8) -> Self {
+        self.candidates = candidates;
+        self
+    }
+
+    /// Set how the roles of the messages are mapped - for the endpoints that
+    /// reject some of them
+    #[must_use]
+    pub const fn with_role_mapping(mut self, role_mapping: RoleMapping) -> Self {
+        self.role_mapping = role_mapping;
+        self
+    }
+}
+
+/// How the roles of the messages are mapped before they are sent - some
+/// `OpenAI`-compatible endpoints reject the `System` role
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RoleMapping {
+    /// Send the messages as they are
+    #[default]
+    Identity,
+    /// Fold the `System` messages into the `User` message that follows them -
+    /// or into a `User` message of their own if an `Assistant` message
+    /// follows them
+    SystemIntoUser,
+}
+
+impl RoleMapping {
+    /// Map the roles of `messages`
+    #[must_use]
+    pub fn apply(
+        self,
+        messages: Vec<ChatCompletionRequestMessage>,
+    ) -> Vec<ChatCompletionRequestMessage> {
+        match self {
+            Self::Identity => messages,
+            Self::SystemIntoUser => {
+                let mut mapped = Vec::with_capacity(messages.len());
+                let mut system = Vec::new();
+
+                let user = |content: String| {
+                    ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+                        content: ChatCompletionRequestUserMessageContent::Text(content),
+                        ..Default::default()
+                    })
+                };
+
+                for message in messages {
+                    match message {
+                        ChatCompletionRequestMessage::System(m) => system.push(m.content),
+                        ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+                            content: ChatCompletionRequestUserMessageContent::Text(content),
+                            ..
+                        }) if !system.is_empty() => {
+                            system.push(content);
+                            mapped.push(user(system.join("\n\n")));
+                            system.clear();
+                        }
+                        message => {
+                            if !system.is_empty() {
+                                mapped.push(user(system.join("\n\n")));
+                                system.clear();
+                            }
+                            mapped.push(message);
+                        }
+                    }
+                }
+
+                if !system.is_empty() {
+                    mapped.push(user(system.join("\n\n")));
+                }
+
+                mapped
+            }
         }
     }
 }
 
 impl Default for OpenAI {
     fn default() -> Self {
-        Self {
-            model: SupportedModel::GPT3_5Turbo,
-            temperature: Some(0.),
-            client: async_openai::Client::new(),
-            api_base: None,
-            api_key: None,
-        }
+        Self::new(SupportedModel::GPT3_5Turbo, Some(0.), OpenAIConfig::new())
     }
 }
 
 const LLAMA_TOKENIZER_JSON: &str = include_str!("tokenizer.json");
 
+/// The tokens wrapping each message: `<|start|>{role}\n{content}<|end|>\n`
+const TOKENS_PER_MESSAGE: usize = 3;
+/// The tokens added when a message has a name
+const TOKENS_PER_NAME: usize = 1;
+/// The tokens priming the reply: `<|start|>assistant<|message|>`
+const TOKENS_PER_REPLY: usize = 3;
+
+/// The number of prompt tokens billed for `messages` by the GPT-3.5 and GPT-4
+/// models - the content and the overhead of each message and of the request
+///
+/// See <https://github.com/openai/openai-cookbook/blob/main/examples/How_to_count_tokens_with_tiktoken.ipynb>
+fn num_tokens_from_messages(messages: &[ChatCompletionRequestMessage]) -> usize {
+    let bpe = tiktoken_rs::cl100k_base_singleton();
+    let bpe = bpe.lock();
+    let count = |s: &str| bpe.encode_with_special_tokens(s).len();
+
+    let messages = messages
+        .iter()
+        .map(|m| {
+            let (role, name) = match m {
+                ChatCompletionRequestMessage::System(m) => ("system", m.name.as_deref()),
+                ChatCompletionRequestMessage::User(m) => ("user", m.name.as_deref()),
+                ChatCompletionRequestMessage::Assistant(m) => ("assistant", m.name.as_deref()),
+                ChatCompletionRequestMessage::Tool(_) => ("tool", None),
+                ChatCompletionRequestMessage::Function(m) => ("function", Some(m.name.as_str())),
+            };
+            let content = ChatEntry::from(m).msg;
+
+            TOKENS_PER_MESSAGE
+                + count(role)
+                + count(&content)
+                + name.map_or(0, |name| count(name) + TOKENS_PER_NAME)
+        })
+        .sum::<usize>();
+
+    messages + TOKENS_PER_REPLY
+}
+
 lazy_static! {
     static ref LLAMA_TOKENIZER: tokenizers::Tokenizer =
         tokenizers::Tokenizer::from_str(LLAMA_TOKENIZER_JSON).unwrap();
 }
 
 #[async_trait::async_trait]
-impl ChatEntryTokenNumber for OpenAI {
+impl<C: Config + Send + Sync> ChatEntryTokenNumber for OpenAI<C> {
     async fn num_tokens(&self, input: ChatInput) -> usize {
         let req = self.prepare_chat_completion_request(input, None);
 
@@ -149,8 +435,8 @@ impl ChatEntryTokenNumber for OpenAI {
             SupportedModel::GPT3_5Turbo
             | SupportedModel::GPT3_5Turbo0613
             | SupportedModel::GPT3_5Turbo16k
-            | SupportedModel::Vicuna7B1_1
-            | SupportedModel::Vicuna13B1_1 => {
+            | SupportedModel::GPT4 => num_tokens_from_messages(&req.messages),
+            SupportedModel::Vicuna7B1_1 | SupportedModel::Vicuna13B1_1 => {
                 // See https://github.com/lm-sys/FastChat/blob/667c584ad437b4655f29ca99d480d96833470860/fastchat/conversation.py#LL62C24-L62C24
                 let seps = [" ", "</s>"];
 
@@ -206,13 +492,14 @@ impl ChatEntryTokenNumber for OpenAI {
         match &self.model {
             SupportedModel::GPT3_5Turbo | SupportedModel::GPT3_5Turbo0613 => 4096,
             SupportedModel::GPT3_5Turbo16k => 16384,
+            SupportedModel::GPT4 => 8192,
             SupportedModel::Vicuna7B1_1 | SupportedModel::Vicuna13B1_1 => 2048,
             _ => panic!("model not supported"),
         }
     }
 }
 
-impl OpenAI {
+impl<C: Config> OpenAI<C> {
     /// prepare the [`ChatCompletionRequest`] to be passed to `OpenAI`
     fn prepare_chat_completion_request(
         &self,
@@ -220,8 +507,11 @@ impl OpenAI {
         max_tokens: Option<usize>,
     ) -> CreateChatCompletionRequest {
         let mut messages = vec![];
-
-        // TODO(ssoudan) support https://platform.openai.com/docs/api-reference/chat/create#chat/create-function_call
+        let stop = (!input.stop.is_empty()).then(|| Stop::StringArray(input.stop.clone()));
+        // min: 0, max: 2, default: 1
+        let temperature = input.ramped_temperature(self.temperature, 1., 2.);
+        let tools = (!input.tools.is_empty())
+            .then(|| input.tools.iter().map(tool_definition).collect::<Vec<_>>());
 
         for m in input.context {
             if let Ok(m) = ChatCompletionRequestMessage::try_from(m) {
@@ -251,18 +541,66 @@ impl OpenAI {
             }
         }
 
-        let temperature = self.temperature;
+        let messages = self.role_mapping.apply(messages);
+
         CreateChatCompletionRequest {
             model: self.model.to_string(),
             messages,
             temperature,
-            n: Some(1),
+            top_p: self.top_p,
+            presence_penalty: self.presence_penalty,
+            frequency_penalty: self.frequency_penalty,
+            seed: self.seed,
+            logit_bias: self.logit_bias.as_ref().map(|logit_bias| {
+                logit_bias
+                    .iter()
+                    .map(|(token, bias)| (token.clone(), serde_json::Value::from(*bias)))
+                    .collect()
+            }),
+            n: Some(self.candidates.get()),
+            stop,
             max_tokens: max_tokens.map(|x| x as u32),
+            tools,
             ..Default::default()
         }
     }
 }
 
+/// The definition of the function standing for the tool described by
+/// `description` - see [`crate::models::ToolCalling::Native`]
+fn tool_definition(description: &ToolDescription) -> ChatCompletionTool {
+    ChatCompletionTool {
+        r#type: ChatCompletionToolType::Function,
+        function: FunctionObject {
+            name: description.name.clone(),
+            description: Some(description.description.clone()),
+            parameters: Some(description.parameters_schema()),
+        },
+    }
+}
+
+/// The tools called natively in `message` - the arguments are expected to be
+/// JSON, they are given as is to the tool otherwise
+#[allow(deprecated)]
+fn tool_calls(message: &ChatCompletionResponseMessage) -> Vec<ToolCall> {
+    let calls = message
+        .tool_calls
+        .iter()
+        .flatten()
+        .map(|call| &call.function)
+        .chain(message.function_call.as_ref());
+
+    calls
+        .map(|call| ToolCall {
+            tool_name: call.name.clone(),
+            parameters: serde_json::from_str::<serde_json::Value>(&call.arguments)
+                .ok()
+                .and_then(|arguments| serde_yaml::to_value(arguments).ok())
+                .unwrap_or_else(|| serde_yaml::Value::String(call.arguments.clone())),
+        })
+        .collect()
+}
+
 impl TryFrom<ChatEntry> for ChatCompletionRequestMessage {
     type Error = ();
 
@@ -286,7 +624,7 @@ impl TryFrom<ChatEntry> for ChatCompletionRequestMessage {
 }
 
 #[async_trait::async_trait]
-impl Model for OpenAI {
+impl<C: Config + Send + Sync> Model for OpenAI<C> {
     async fn query(
         &self,
         input: ChatInput,
@@ -306,10 +644,19 @@ impl Model for OpenAI {
 
         let msg = first.message.content.clone();
 
+        let alternatives = res
+            .choices
+            .iter()
+            .skip(1)
+            .map(|choice| choice.message.content.clone().unwrap_or_default())
+            .collect();
+
         Ok(ModelResponse {
             msg: msg.unwrap_or_default(),
+            alternatives,
             usage: res.usage.as_ref().map(Into::into),
             finish_reason: first.finish_reason.map(|x| format!("{x:?}")),
+            tool_calls: tool_calls(&first.message),
         })
     }
 }
@@ -389,6 +736,49 @@ impl From<&ChatCompletionRequestMessage> for ChatEntry {
     }
 }
 
+/// The `OpenAI` messages can make a [`crate::context::ChatHistory`] - with no
+/// name, no tool call and no function call when created from a role and a
+/// content
+impl ChatMessage for ChatCompletionRequestMessage {
+    fn new(role: Role, content: String) -> Self {
+        match role {
+            Role::Function => Self::Function(ChatCompletionRequestFunctionMessage {
+                content: Some(content),
+                ..Default::default()
+            }),
+            Role::Tool => Self::Tool(ChatCompletionRequestToolMessage {
+                content,
+                ..Default::default()
+            }),
+            role => (&ChatEntry { role, msg: content }).into(),
+        }
+    }
+
+    fn role(&self) -> Role {
+        match self {
+            Self::System(_) => Role::System,
+            Self::User(_) => Role::User,
+            Self::Assistant(_) => Role::Assistant,
+            Self::Tool(_) => Role::Tool,
+            Self::Function(_) => Role::Function,
+        }
+    }
+
+    fn content(&self) -> Cow<'_, str> {
+        match self {
+            Self::System(m) => Cow::Borrowed(&m.content),
+            Self::Assistant(m) => Cow::Borrowed(m.content.as_deref().unwrap_or_default()),
+            Self::Tool(m) => Cow::Borrowed(&m.content),
+            Self::Function(m) => Cow::Borrowed(m.content.as_deref().unwrap_or_default()),
+            Self::User(_) => Cow::Owned(ChatEntry::from(self).msg),
+        }
+    }
+
+    fn to_entry(&self) -> ChatEntry {
+        self.into()
+    }
+}
+
 impl From<async_openai::types::Role> for Role {
     fn from(value: async_openai::types::Role) -> Self {
         match value {
@@ -407,6 +797,7 @@ impl From<&async_openai::types::CompletionUsage> for Usage {
             prompt_tokens: usage.prompt_tokens,
             completion_tokens: usage.completion_tokens,
             total_tokens: usage.total_tokens,
+            estimated: false,
         }
     }
 }
@@ -522,7 +913,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_vicuna_sizes() {
-        let model = build(SupportedModel::Vicuna7B1_1, None, None, None).unwrap();
+        let model = build(SupportedModel::Vicuna7B1_1, ClientOptions::default(), None).unwrap();
 
         assert_eq!(model.context_size().await, 2048);
 
@@ -556,6 +947,9 @@ mod tests {
                     msg: "That's great to hear!".to_string(),
                 },
             ],
+            stop: vec![],
+            temperature_increase: 0.,
+            tools: vec![],
         };
 
         let token_sz = model.num_tokens(input).await;
@@ -563,9 +957,81 @@ mod tests {
         assert_eq!(token_sz, 80);
     }
 
+    /// The number of tokens of the request for `model` - as counted by
+    /// `tiktoken_rs`
+    fn tiktoken_num_tokens(model: &str, req: &CreateChatCompletionRequest) -> usize {
+        let messages = req
+            .messages
+            .iter()
+            .map(|m| tiktoken_rs::ChatCompletionRequestMessage {
+                role: serde_json::to_value(m).unwrap()["role"]
+                    .as_str()
+                    .unwrap()
+                    .to_string(),
+                content: Some(ChatEntry::from(m).msg),
+                name: None,
+                function_call: None,
+            })
+            .collect::<Vec<_>>();
+
+        tiktoken_rs::num_tokens_from_messages(model, &messages).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_num_tokens_of_a_full_history() {
+        for model in [SupportedModel::GPT3_5Turbo, SupportedModel::GPT4] {
+            let model = OpenAI::new(model, None, OpenAIConfig::new());
+            let config = crate::SapiensConfig {
+                model: Arc::new(Box::new(model.clone())),
+                ..Default::default()
+            };
+
+            // the prompts, the warm-up exchanges and a few steps of a task
+            let mut chat_history = crate::context::ChatHistory::for_model(config).await;
+            crate::prompt::Manager::new(
+                crate::tools::toolbox::Toolbox::default(),
+                crate::prompt::PromptTemplates::default(),
+            )
+            .populate_chat_history(&mut chat_history)
+            .await;
+            for (role, msg) in [
+                (Role::User, "# Your turn\nOriginal question: How many moons has Mars?"),
+                (
+                    Role::Assistant,
+                    "## Observations:\n- Nothing yet.\n## Action:\n```yaml\ntool_name: Wikipedia\nparameters:\n  query: Moons of Mars\n```",
+                ),
+                (
+                    Role::User,
+                    "# Action Wikipedia response: \n```yaml\nresult: Mars has two moons, Phobos and Deimos.\n```",
+                ),
+            ] {
+                chat_history.add_chitchat(ChatEntry {
+                    role,
+                    msg: msg.to_string(),
+                });
+            }
+            // nothing pruned
+            assert_eq!(chat_history.purge().await.unwrap(), 3);
+
+            let input = chat_history.make_input();
+            let num_tokens = model.num_tokens(input.clone()).await;
+            let req = model.prepare_chat_completion_request(input, None);
+
+            let expected = if matches!(model.model, SupportedModel::GPT4) {
+                tiktoken_num_tokens("gpt-4", &req)
+            } else {
+                // `tiktoken_rs` counts one more token per message for all the
+                // GPT-3.5 models - as `gpt-3.5-turbo-0301` used to
+                tiktoken_num_tokens("gpt-3.5-turbo", &req) - req.messages.len()
+            };
+
+            assert_eq!(num_tokens, expected, "{}", model.model);
+        }
+    }
+
     #[tokio::test]
     async fn test_gpt3_sizes() {
-        let model = build(SupportedModel::GPT3_5Turbo, None, None, None).unwrap();
+        let model = build(SupportedModel::GPT3_5Turbo, ClientOptions::default(), None).unwrap();
 
         assert_eq!(model.context_size().await, 4096);
 
@@ -599,10 +1065,389 @@ mod tests {
                     msg: "That's great to hear!".to_string(),
                 },
             ],
+            stop: vec![],
+            temperature_increase: 0.,
+            tools: vec![],
         };
 
         let token_sz = model.num_tokens(input).await;
 
-        assert_eq!(token_sz, 80);
+        assert_eq!(token_sz, 74);
+    }
+
+    #[test]
+    fn test_custom_api_base() {
+        let config = OpenAIConfig::new().with_api_base("http://localhost:8000/v1");
+        let model = OpenAI::new(SupportedModel::Vicuna7B1_1, None, config);
+
+        assert_eq!(
+            model.config.url("/chat/completions"),
+            "http://localhost:8000/v1/chat/completions"
+        );
+    }
+
+    #[test]
+    fn test_azure_api_base() {
+        let config = AzureConfig::new()
+            .with_api_base("https://my-resource.openai.azure.com")
+            .with_api_version("2023-05-15")
+            .with_deployment_id("gpt-35-turbo")
+            .with_api_key("secret");
+        let model = OpenAI::new(SupportedModel::GPT3_5Turbo, None, config);
+
+        assert_eq!(
+            model.config.url("/chat/completions"),
+            "https://my-resource.openai.azure.com/openai/deployments/gpt-35-turbo/chat/completions"
+        );
+        assert_eq!(model.config.query(), vec![("api-version", "2023-05-15")]);
+    }
+
+    #[test]
+    fn test_ramped_temperature() {
+        let input = |temperature_increase| ChatInput {
+            context: vec![],
+            examples: vec![],
+            chat: vec![],
+            stop: vec![],
+            temperature_increase,
+            tools: vec![],
+        };
+
+        let model = OpenAI::new(SupportedModel::GPT3_5Turbo, Some(0.5), OpenAIConfig::new());
+        let req = model.prepare_chat_completion_request(input(0.25), None);
+        assert_eq!(req.temperature, Some(0.75));
+
+        // raised from the default of the API - up to its max
+        let model = OpenAI::new(SupportedModel::GPT3_5Turbo, None, OpenAIConfig::new());
+        let req = model.prepare_chat_completion_request(input(1.5), None);
+        assert_eq!(req.temperature, Some(2.));
+
+        let req = model.prepare_chat_completion_request(input(0.), None);
+        assert_eq!(req.temperature, None);
+    }
+
+    #[test]
+    fn test_sampling_parameters() {
+        let model = OpenAI::new(SupportedModel::GPT3_5Turbo, Some(0.7), OpenAIConfig::new())
+            .with_top_p(0.9)
+            .with_presence_penalty(0.5)
+            .with_frequency_penalty(-0.5)
+            .with_seed(42)
+            .with_candidates(NonZeroU8::new(3).unwrap());
+
+        let input = ChatInput {
+            context: vec![],
+            examples: vec![],
+            chat: vec![],
+            stop: vec![],
+            temperature_increase: 0.,
+            tools: vec![],
+        };
+
+        let req = model.prepare_chat_completion_request(input, None);
+
+        assert_eq!(req.temperature, Some(0.7));
+        assert_eq!(req.top_p, Some(0.9));
+        assert_eq!(req.presence_penalty, Some(0.5));
+        assert_eq!(req.frequency_penalty, Some(-0.5));
+        assert_eq!(req.seed, Some(42));
+        assert_eq!(req.n, Some(3));
+
+        // defaults are left to the API
+        let req = OpenAI::default().prepare_chat_completion_request(
+            ChatInput {
+                context: vec![],
+                examples: vec![],
+                chat: vec![],
+                stop: vec![],
+                temperature_increase: 0.,
+                tools: vec![],
+            },
+            None,
+        );
+
+        assert_eq!(req.top_p, None);
+        assert_eq!(req.presence_penalty, None);
+        assert_eq!(req.frequency_penalty, None);
+        assert_eq!(req.seed, None);
+        assert_eq!(req.n, Some(1));
+    }
+
+    #[test]
+    fn test_logit_bias() {
+        // discourage the model from opening more code fences
+        let logit_bias = HashMap::from([("74694".to_string(), -50), ("15506".to_string(), -100)]);
+
+        let model = OpenAI::default().with_logit_bias(logit_bias.clone());
+
+        let req = model.prepare_chat_completion_request(
+            ChatInput {
+                context: vec![],
+                examples: vec![],
+                chat: vec![],
+                stop: vec![],
+                temperature_increase: 0.,
+                tools: vec![],
+            },
+            None,
+        );
+
+        let expected = logit_bias
+            .into_iter()
+            .map(|(token, bias)| (token, serde_json::Value::from(bias)))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(req.logit_bias, Some(expected));
+    }
+
+    #[test]
+    fn test_role_mapping() {
+        let input = ChatInput {
+            context: vec![
+                ChatEntry {
+                    role: Role::System,
+                    msg: "A chat between a user and an assistant.".to_string(),
+                },
+                ChatEntry {
+                    role: Role::User,
+                    msg: "My name is Marcel".to_string(),
+                },
+            ],
+            examples: vec![],
+            chat: vec![ChatEntry {
+                role: Role::User,
+                msg: "Hello Assistant!".to_string(),
+            }],
+            stop: vec![],
+            temperature_increase: 0.,
+            tools: vec![],
+        };
+
+        let entries = |req: CreateChatCompletionRequest| {
+            req.messages
+                .iter()
+                .map(ChatEntry::from)
+                .map(|e| (e.role, e.msg))
+                .collect::<Vec<_>>()
+        };
+
+        // identity by default
+        let req = OpenAI::default().prepare_chat_completion_request(input.clone(), None);
+        assert_eq!(entries(req)[0].0, Role::System);
+
+        let model = OpenAI::default().with_role_mapping(RoleMapping::SystemIntoUser);
+        let req = model.prepare_chat_completion_request(input, None);
+
+        assert_eq!(
+            entries(req),
+            vec![
+                (
+                    Role::User,
+                    "A chat between a user and an assistant.\n\nMy name is Marcel".to_string()
+                ),
+                (Role::Assistant, "Got it.".to_string()),
+                (Role::User, "Hello Assistant!".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tool_calling() {
+        use crate::tools::{FieldFormat, Format};
+
+        let model = OpenAI::default();
+
+        let weather = ToolDescription::new(
+            "Weather",
+            "Tells the weather",
+            vec![
+                FieldFormat {
+                    name: "city".to_string(),
+                    r#type: "str".to_string(),
+                    optional: false,
+                    description: "The city".to_string(),
+                },
+                FieldFormat {
+                    name: "days".to_string(),
+                    r#type: "Optional[int]".to_string(),
+                    optional: true,
+                    description: "The number of days".to_string(),
+                },
+                FieldFormat {
+                    name: "hours".to_string(),
+                    r#type: "Optional[list[int]]".to_string(),
+                    optional: true,
+                    description: "The hours of the day".to_string(),
+                },
+                FieldFormat {
+                    name: "stations".to_string(),
+                    r#type: "list[Station]".to_string(),
+                    optional: false,
+                    description: "The weather stations".to_string(),
+                },
+            ]
+            .into(),
+            Format::default(),
+        );
+
+        let input = |tools| ChatInput {
+            context: vec![],
+            examples: vec![],
+            chat: vec![ChatEntry {
+                role: Role::User,
+                msg: "What's the weather in Paris?".to_string(),
+            }],
+            stop: vec![],
+            temperature_increase: 0.,
+            tools,
+        };
+
+        // no tools, no functions
+        let req = model.prepare_chat_completion_request(input(vec![]), None);
+        assert_eq!(req.tools, None);
+
+        let req = model.prepare_chat_completion_request(input(vec![weather]), None);
+        let tools = req.tools.unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].function.name, "Weather");
+        assert_eq!(
+            tools[0].function.parameters,
+            Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "city": { "type": "string", "description": "The city" },
+                    "days": { "type": "integer", "description": "The number of days" },
+                    "hours": {
+                        "type": "array",
+                        "items": { "type": "integer" },
+                        "description": "The hours of the day",
+                    },
+                    "stations": {
+                        "type": "array",
+                        "items": {},
+                        "description": "The weather stations",
+                    },
+                },
+                "required": ["city", "stations"],
+            }))
+        );
+
+        let message: ChatCompletionResponseMessage = serde_json::from_value(serde_json::json!({
+            "role": "assistant",
+            "content": null,
+            "tool_calls": [{
+                "id": "call_0",
+                "type": "function",
+                "function": { "name": "Weather", "arguments": "{\"city\": \"Paris\"}" }
+            }]
+        }))
+        .unwrap();
+        assert_eq!(
+            tool_calls(&message),
+            vec![ToolCall {
+                tool_name: "Weather".to_string(),
+                parameters: serde_yaml::from_str("city: Paris").unwrap(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_chat_message() {
+        let msg = ChatCompletionRequestMessage::new(Role::User, "Hello".to_string());
+        assert_eq!(msg.role(), Role::User);
+        assert_eq!(msg.content(), "Hello");
+
+        let msg = ChatCompletionRequestMessage::new(Role::Tool, "42".to_string());
+        assert!(matches!(msg, ChatCompletionRequestMessage::Tool(_)));
+        assert_eq!(msg.to_entry().role, Role::Tool);
+        assert_eq!(msg.to_entry().msg, "42");
+
+        let config = crate::SapiensConfig::default();
+        let mut chat_history =
+            crate::context::ChatHistory::<ChatCompletionRequestMessage>::with_max_token(
+                config, 4096,
+            );
+        chat_history.add_chitchat(ChatCompletionRequestMessage::new(
+            Role::Assistant,
+            "Hi".to_string(),
+        ));
+        assert_eq!(chat_history.make_input().chat[0].msg, "Hi");
+    }
+
+    #[test]
+    fn test_stop_sequences() {
+        let model = OpenAI::default();
+
+        let config = crate::SapiensConfig {
+            model: Arc::new(Box::new(model.clone())),
+            stop_sequences: vec!["\n# Action ".to_string(), "\n## Observations".to_string()],
+            ..Default::default()
+        };
+        let chat_history: crate::context::ChatHistory =
+            crate::context::ChatHistory::new(config, 4096);
+
+        let req = model.prepare_chat_completion_request(chat_history.make_input(), None);
+
+        assert_eq!(
+            req.stop,
+            Some(Stop::StringArray(vec![
+                "\n# Action ".to_string(),
+                "\n## Observations".to_string()
+            ]))
+        );
+
+        // none configured, none sent
+        let config = crate::SapiensConfig {
+            model: Arc::new(Box::new(model.clone())),
+            stop_sequences: vec![],
+            ..Default::default()
+        };
+        let chat_history: crate::context::ChatHistory =
+            crate::context::ChatHistory::new(config, 4096);
+
+        let req = model.prepare_chat_completion_request(chat_history.make_input(), None);
+
+        assert_eq!(req.stop, None);
+    }
+
+    #[tokio::test]
+    async fn test_max_tokens_from_remaining_budget() {
+        let model = OpenAI::default();
+
+        let config = crate::SapiensConfig {
+            model: Arc::new(Box::new(model.clone())),
+            ..Default::default()
+        };
+        let max_token = model.context_size().await;
+
+        let mut chat_history = crate::context::ChatHistory::new(config.clone(), max_token);
+        chat_history.set_context(vec![ChatEntry {
+            role: Role::System,
+            msg: "A chat between a user and an assistant.".to_string(),
+        }]);
+        chat_history.add_chitchat(ChatEntry {
+            role: Role::User,
+            msg: "Hello Assistant!".to_string(),
+        });
+
+        let input = chat_history.make_input();
+        let num_tokens = model.num_tokens(input.clone()).await;
+        let max_tokens = chat_history.max_completion_tokens().await;
+
+        let req = model.prepare_chat_completion_request(input, Some(max_tokens));
+
+        assert_eq!(req.max_tokens, Some((max_token - num_tokens) as u32));
+
+        // capped by the config
+        let config = crate::SapiensConfig {
+            max_tokens: Some(100),
+            ..config
+        };
+        let mut chat_history = crate::context::ChatHistory::new(config, max_token);
+        chat_history.set_context(vec![ChatEntry {
+            role: Role::System,
+            msg: "A chat between a user and an assistant.".to_string(),
+        }]);
+
+        assert_eq!(chat_history.max_completion_tokens().await, 100);
     }
 }
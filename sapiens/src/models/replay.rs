@@ -0,0 +1,129 @@
+//! A model replaying a recorded transcript - to test prompts and chains
+//! without calling a remote model
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::models::{ChatEntryTokenNumber, ChatInput, Error, Model, ModelResponse};
+
+/// A [`Model`] that returns recorded responses in order - one per query.
+///
+/// Fails with [`Error::TranscriptExhausted`] once all of them have been
+/// returned. The tokens are counted as whitespace-separated words.
+///
+/// ```
+/// # use std::sync::Arc;
+/// # use sapiens::models::replay::ReplayModel;
+/// # use sapiens::SapiensConfig;
+/// let config = SapiensConfig {
+///     model: Arc::new(Box::new(ReplayModel::new(vec!["Hello".to_string()]))),
+///     ..SapiensConfig::default()
+/// };
+/// ```
+#[derive(Debug)]
+pub struct ReplayModel {
+    /// The responses not replayed yet
+    responses: Mutex<VecDeque<String>>,
+    /// The number of recorded responses
+    len: usize,
+    /// The size of the context window
+    context_size: usize,
+}
+
+impl ReplayModel {
+    /// Create a model replaying `responses` - with a context of 4096 tokens
+    #[must_use]
+    pub fn new(responses: Vec<String>) -> Self {
+        Self {
+            len: responses.len(),
+            responses: Mutex::new(responses.into()),
+            context_size: 4096,
+        }
+    }
+
+    /// Set the size of the context window
+    #[must_use]
+    pub const fn with_context_size(mut self, context_size: usize) -> Self {
+        self.context_size = context_size;
+        self
+    }
+
+    /// The number of responses not replayed yet
+    ///
+    /// # Panics
+    ///
+    /// If the lock is poisoned.
+    #[must_use]
+    pub fn remaining(&self) -> usize {
+        self.responses.lock().unwrap().len()
+    }
+}
+
+#[async_trait::async_trait]
+impl ChatEntryTokenNumber for ReplayModel {
+    async fn num_tokens(&self, input: ChatInput) -> usize {
+        input
+            .context
+            .iter()
+            .chain(input.examples.iter().flat_map(|(a, b)| [a, b]))
+            .chain(input.chat.iter())
+            .map(|e| e.msg.split_whitespace().count())
+            .sum()
+    }
+
+    async fn context_size(&self) -> usize {
+        self.context_size
+    }
+}
+
+#[async_trait::async_trait]
+impl Model for ReplayModel {
+    async fn query(
+        &self,
+        _input: ChatInput,
+        _max_tokens: Option<usize>,
+    ) -> Result<ModelResponse, Error> {
+        let msg = self
+            .responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or(Error::TranscriptExhausted(self.len))?;
+
+        Ok(ModelResponse {
+            msg,
+            alternatives: Vec::new(),
+            usage: None,
+            finish_reason: None,
+            tool_calls: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input() -> ChatInput {
+        ChatInput {
+            context: vec![],
+            examples: vec![],
+            chat: vec![],
+            stop: vec![],
+            temperature_increase: 0.,
+            tools: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_the_responses_in_order() {
+        let model = ReplayModel::new(vec!["first".to_string(), "second".to_string()]);
+
+        assert_eq!(model.query(input(), None).await.unwrap().msg, "first");
+        assert_eq!(model.remaining(), 1);
+        assert_eq!(model.query(input(), None).await.unwrap().msg, "second");
+
+        let e = model.query(input(), None).await.err().unwrap();
+        assert!(matches!(e, Error::TranscriptExhausted(2)));
+    }
+}
@@ -1,6 +1,7 @@
 //! GCP Vertex AI Generative AI API
 
 use core::fmt::Debug;
+use std::num::NonZeroU8;
 use std::sync::Arc;
 
 use gcp_vertex_ai_generative_language::google::ai::generativelanguage::v1beta2::content_filter::BlockedReason;
@@ -24,6 +25,9 @@ pub struct LanguageModel {
 
     /// the temperature
     pub temperature: Option<f32>,
+    /// the number of candidate messages to request - see
+    /// [`ModelResponse::select_action`]
+    pub candidates: NonZeroU8,
     /// The GCP Vertex AI client
     client: Arc<Mutex<LanguageClient>>,
 }
@@ -33,6 +37,7 @@ impl Debug for LanguageModel {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("LanguageModel")
             .field("temperature", &self.temperature)
+            .field("candidates", &self.candidates)
             .field("model", &self.model)
             .finish()
     }
@@ -51,6 +56,7 @@ pub async fn build(api_key: String, temperature: Option<f32>) -> Result<ModelRef
     let model = LanguageModel {
         model: SupportedModel::ChatBison001,
         temperature,
+        candidates: NonZeroU8::MIN,
         client: Arc::new(Mutex::new(client)),
     };
 
@@ -147,12 +153,14 @@ impl models::Model for LanguageModel {
         _max_tokens: Option<usize>,
     ) -> Result<ModelResponse, Error> {
         let prompt = Self::prepare_input(&input);
+        // min: 0, max: 1, default: 0.25
+        let temperature = input.ramped_temperature(self.temperature, 0.25, 1.);
 
         let req = GenerateMessageRequest {
             model: format!("models/{}", self.model),
             prompt: Some(prompt),
-            temperature: self.temperature,
-            candidate_count: Some(1),
+            temperature,
+            candidate_count: Some(i32::from(self.candidates.get())),
             top_p: None,
             top_k: None,
         };
@@ -191,8 +199,13 @@ impl models::Model for LanguageModel {
 
         Ok(ModelResponse {
             msg: resp.candidates[0].content.clone(),
+            alternatives: resp.candidates[1..]
+                .iter()
+                .map(|candidate| candidate.content.clone())
+                .collect(),
             usage: None,
             finish_reason: None,
+            tool_calls: Vec::new(),
         })
     }
 }
@@ -1,56 +1,187 @@
 use std::fmt;
 use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
 
+use chrono::{DateTime, FixedOffset, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::chains::agents::ooda::one_step;
 use crate::context::{ChatEntry, ChatHistory};
-use crate::models::Role;
+use crate::models::{Role, ToolCalling};
 use crate::tools::invocation::Error;
 use crate::tools::toolbox::Toolbox;
 use crate::tools::{ToolDescription, ToolUseError};
 
+pub mod languages;
+
+/// The placeholder replaced by [`PromptTemplates::agent_name`]
+pub const AGENT_NAME_PLACEHOLDER: &str = "{agent_name}";
+
+/// The placeholder replaced by [`PromptTemplates::persona`]
+pub const PERSONA_PLACEHOLDER: &str = "{persona}";
+
+/// The placeholder replaced by [`PromptTemplates::world`]
+pub const WORLD_PLACEHOLDER: &str = "{world}";
+
+/// The sections of the prompts
+///
+/// [`Default`] gives the ones of the single-step OODA agent. Translated ones
+/// can be obtained from a [`languages::LanguagePack`].
+///
+/// [`AGENT_NAME_PLACEHOLDER`], [`PERSONA_PLACEHOLDER`] and
+/// [`WORLD_PLACEHOLDER`] are replaced by `agent_name`, `persona` and `world`
+/// in the system prompt and in the prefix.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptTemplates {
+    /// The name of the agent
+    pub agent_name: String,
+    /// What the agent is - e.g. `a large language model`
+    pub persona: String,
+    /// Who the agent works for - e.g. `the WORLD`
+    pub world: String,
+    /// The 'system' prompt describing the role of the agent
+    pub system_prompt: String,
+    /// The prompt recalled after the task at each step
+    pub prompt: String,
+    /// The introduction of the warm-up prompt
+    pub prefix: String,
+    /// The introduction of the tool descriptions
+    pub tool_prefix: String,
+    /// The description of the expected format of the responses
+    pub response_format: String,
+    /// The exchanges given as examples before the task
+    pub examples: Vec<WarmUpExample>,
+    /// The reply of the assistant to the warm-up prompt, e.g. `Understood.` -
+    /// the examples follow the warm-up prompt directly if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acknowledgment: Option<String>,
+}
+
+/// An example of a task carried out by the agent
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WarmUpExample {
+    /// The task of the example
+    pub task: String,
+    /// The turns of the assistant
+    pub turns: Vec<WarmUpTurn>,
+}
+
+/// A turn of the assistant in a [`WarmUpExample`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WarmUpTurn {
+    /// The response of the assistant
+    pub response: String,
+    /// The result of the Action of the response - shown to the assistant
+    /// before the next turn
+    pub action_result: Option<String>,
+}
+
+/// The default name of the agent
+pub const DEFAULT_AGENT_NAME: &str = "Sapiens";
+
+/// The default persona of the agent
+pub const DEFAULT_PERSONA: &str = "a large language model";
+
+/// The default world of the agent
+pub const DEFAULT_WORLD: &str = "the WORLD";
+
+impl PromptTemplates {
+    /// Replace the placeholders with the persona, the world and the name of
+    /// the agent - the persona can mention the world and the agent name
+    fn fill_placeholders(&self, s: &str) -> String {
+        s.replace(PERSONA_PLACEHOLDER, &self.persona)
+            .replace(WORLD_PLACEHOLDER, &self.world)
+            .replace(AGENT_NAME_PLACEHOLDER, &self.agent_name)
+    }
+}
+
+impl Default for PromptTemplates {
+    fn default() -> Self {
+        Self {
+            agent_name: DEFAULT_AGENT_NAME.to_string(),
+            persona: DEFAULT_PERSONA.to_string(),
+            world: DEFAULT_WORLD.to_string(),
+            system_prompt: one_step::SYSTEM_PROMPT.to_string(),
+            prompt: one_step::PROMPT.to_string(),
+            prefix: one_step::PREFIX.to_string(),
+            tool_prefix: one_step::TOOL_PREFIX.to_string(),
+            response_format: one_step::RESPONSE_FORMAT.to_string(),
+            examples: one_step::examples(),
+            acknowledgment: None,
+        }
+    }
+}
+
+/// A source of the current date and time
+pub trait Clock: Send + Sync {
+    /// The current date and time
+    fn now(&self) -> DateTime<FixedOffset>;
+}
+
+/// A reference to a [`Clock`]
+pub type ClockRef = Arc<dyn Clock>;
+
+/// The [`Clock`] of the system - in the local timezone
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<FixedOffset> {
+        Local::now().fixed_offset()
+    }
+}
+
 // FUTURE(ssoudan) prompt a-la: "below are a series of dialogues between..." for
 // non-instruct models
 /// Prompt manager
 #[derive(Clone)]
 pub(crate) struct Manager {
     toolbox: Toolbox,
-    system_prompt: String,
-    prompt: String,
-    prefix: String,
-    tool_prefix: String,
-    response_format: String,
+    templates: PromptTemplates,
+    clock: Option<ClockRef>,
+    tool_calling: ToolCalling,
 }
 
 impl Manager {
     /// Create a new prompt manager
     #[must_use]
-    pub(crate) const fn new(
-        toolbox: Toolbox,
-        system_prompt: String,
-        prompt: String,
-        prefix: String,
-        tool_prefix: String,
-        response_format: String,
-    ) -> Self {
+    pub(crate) const fn new(toolbox: Toolbox, templates: PromptTemplates) -> Self {
         Self {
             toolbox,
-            system_prompt,
-            prompt,
-            prefix,
-            tool_prefix,
-            response_format,
+            templates,
+            clock: None,
+            tool_calling: ToolCalling::Yaml,
         }
     }
 
-    /// Create the prompt describing the tools
-    async fn create_tool_description(&self) -> String {
-        let prefix = self.tool_prefix.to_string();
+    /// The toolbox the prompt describes
+    pub(crate) const fn toolbox(&self) -> &Toolbox {
+        &self.toolbox
+    }
 
-        let tool_desc = self.toolbox.describe().await;
+    /// Prepend the current date and time to the 'system' prompt - `None` to
+    /// disable
+    #[must_use]
+    pub(crate) fn with_clock(mut self, clock: Option<ClockRef>) -> Self {
+        self.clock = clock;
+        self
+    }
 
-        let mut tool_desc: Vec<ToolDescription> = tool_desc.into_values().collect();
+    /// How the model calls the tools - with [`ToolCalling::Native`], the
+    /// tools are given to the model along with the chat so the prompts
+    /// neither describe them nor show the format of the Actions
+    #[must_use]
+    pub(crate) const fn with_tool_calling(mut self, tool_calling: ToolCalling) -> Self {
+        self.tool_calling = tool_calling;
+        self
+    }
+
+    /// Create the prompt describing the tools
+    async fn create_tool_description(&self) -> String {
+        let prefix = self.templates.tool_prefix.clone();
 
-        // sort by tool name
-        tool_desc.sort_by(|a, b| a.name.cmp(&b.name));
+        // sorted by tool name
+        let tool_desc: Vec<ToolDescription> = self.toolbox.describe().await.into_values().collect();
 
         // yaml serialize the tool description
         let tool_desc = serde_yaml::to_string(&tool_desc).unwrap();
@@ -60,14 +191,26 @@ impl Manager {
 
     /// Create the prompt describing the tools and how to use them
     async fn create_tool_warm_up(&self) -> String {
+        let prefix = self.templates.fill_placeholders(&self.templates.prefix);
+
+        if self.tool_calling == ToolCalling::Native {
+            return prefix;
+        }
+
         let tool_prompt = self.create_tool_description().await;
 
-        format!("{}{}{}", self.prefix, self.response_format, tool_prompt)
+        format!(
+            "{}{}{}",
+            prefix, self.templates.response_format, tool_prompt
+        )
     }
 
     /// Create the prompt for the task
     pub(crate) fn build_task_prompt(&self, task: &str) -> Task {
-        let prompt = format!("# Your turn\nOriginal question: {}\n{}", task, self.prompt,);
+        let prompt = format!(
+            "# Your turn\nOriginal question: {}\n{}",
+            task, self.templates.prompt,
+        );
         Task {
             task: task.to_string(),
             prompt,
@@ -76,18 +219,53 @@ impl Manager {
 
     /// Create the 'system' prompt to describe the roles.
     fn create_system_prompt(&self) -> String {
-        self.system_prompt.clone()
+        let system_prompt = self
+            .templates
+            .fill_placeholders(&self.templates.system_prompt);
+
+        match &self.clock {
+            Some(clock) => format!(
+                "Current date and time: {}\n{}",
+                clock.now().format("%A %Y-%m-%d %H:%M:%S %:z"),
+                system_prompt
+            ),
+            None => system_prompt,
+        }
     }
 
-    pub(crate) async fn populate_chat_history(
-        &self,
-        chat_history: &mut ChatHistory,
-        examples: Vec<(String, String)>,
-    ) {
+    /// Create the prompts and responses of the examples - none with
+    /// [`ToolCalling::Native`] as their Actions are in the YAML format
+    fn build_examples(&self) -> Vec<(String, String)> {
+        let mut examples = vec![];
+
+        if self.tool_calling == ToolCalling::Native {
+            return examples;
+        }
+
+        for example in &self.templates.examples {
+            let task = self.build_task_prompt(&example.task).to_prompt();
+
+            let mut prompt = task.clone();
+            for turn in &example.turns {
+                examples.push((prompt.trim().to_string(), turn.response.trim().to_string()));
+
+                prompt = format!(
+                    "{}{}",
+                    turn.action_result.as_deref().unwrap_or_default(),
+                    task
+                );
+            }
+        }
+
+        examples
+    }
+
+    pub(crate) async fn populate_chat_history(&self, chat_history: &mut ChatHistory) {
+        let examples = self.build_examples();
         let warm_up_prompt = self.create_tool_warm_up().await;
         let system_prompt = self.create_system_prompt();
 
-        chat_history.set_context(vec![
+        let mut context = vec![
             ChatEntry {
                 role: Role::System,
                 msg: system_prompt.trim().to_string(),
@@ -96,7 +274,14 @@ impl Manager {
                 role: Role::User,
                 msg: warm_up_prompt.trim().to_string(),
             },
-        ]);
+        ];
+        if let Some(acknowledgment) = &self.templates.acknowledgment {
+            context.push(ChatEntry {
+                role: Role::Assistant,
+                msg: acknowledgment.clone(),
+            });
+        }
+        chat_history.set_context(context);
 
         for (prompt, response) in examples {
             chat_history.add_example(prompt, response);
@@ -137,29 +322,39 @@ impl Task {
 
     /// Create the prompt to react to invalid action specification
     pub(crate) fn invalid_action_prompt(e: &Error) -> String {
-        format!("# No valid Action found:\n{e:?}\nSomething was incorrect in previous response.")
+        format!("# No valid Action found:\n{e}\nSomething was incorrect in previous response.")
     }
 
-    /// Create the prompt to react to an action success
+    /// Create the prompt to react to an action success - followed by the
+    /// `hint` of the tool, if any
     pub(crate) fn action_success_prompt(
         tool_name: impl AsRef<str>,
         available_invocation_count: usize,
         result: impl AsRef<str>,
+        hint: Option<&str>,
+        formatted: bool,
     ) -> String {
-        if available_invocation_count == 1 {
-            format!(
-                "# Action {} response: \n```yaml\n{}```",
-                tool_name.as_ref(),
-                result.as_ref(),
-            )
+        // a result rendered by the tool is shown as is
+        let result = if formatted {
+            result.as_ref().to_string()
+        } else {
+            format!("```yaml\n{}```", result.as_ref())
+        };
+
+        let msg = if available_invocation_count == 1 {
+            format!("# Action {} response: \n{}", tool_name.as_ref(), result)
         } else {
             format!(
-                "# Action {} response: \nYou must give only one Action at a time. There was {}. Only the first one was considered.\n```yaml\n{}```",
+                "# Action {} response: \nYou must give only one Action at a time. There was {}. Only one of them was considered.\n{}",
                 tool_name.as_ref(),
                 available_invocation_count,
-                result.as_ref(),
-
+                result,
             )
+        };
+
+        match hint {
+            Some(hint) => format!("{msg}\nHint: {hint}"),
+            None => msg,
         }
     }
 }
@@ -173,6 +368,44 @@ impl fmt::Display for Task {
 #[cfg(test)]
 mod tests {
 
+    #[tokio::test]
+    async fn tool_description_with_examples() {
+        use super::*;
+        use crate::tools::{Format, Tool, ToolExample, ToolUseError};
+        use crate::Toolbox;
+
+        struct EchoTool;
+
+        #[async_trait::async_trait]
+        impl Tool for EchoTool {
+            fn description(&self) -> ToolDescription {
+                ToolDescription::new("Echo", "Echoes", Format::default(), Format::default())
+                    .with_examples(vec![ToolExample::new(
+                        &serde_yaml::Mapping::from_iter([("text".into(), "knock knock".into())]),
+                        &serde_yaml::Mapping::from_iter([("echo".into(), "knock knock".into())]),
+                    )])
+            }
+
+            async fn invoke(
+                &self,
+                input: serde_yaml::Value,
+            ) -> Result<serde_yaml::Value, ToolUseError> {
+                Ok(input)
+            }
+        }
+
+        let toolbox = Toolbox::default();
+        toolbox.add_tool(EchoTool).await;
+
+        let manager = Manager::new(toolbox, PromptTemplates::default());
+
+        let description = manager.create_tool_description().await;
+
+        assert!(description.contains(
+            "  examples:\n  - parameters:\n      text: knock knock\n    responses_content:\n      echo: knock knock\n"
+        ));
+    }
+
     #[tokio::test]
     async fn populate_chat_history() {
         use super::*;
@@ -181,23 +414,30 @@ mod tests {
 
         let toolbox = Toolbox::default();
         let system_prompt =
-            "You are an agent named Sapiens interacting with the WORLD. Listen to the WORLD!"
+            "You are an agent named {agent_name} interacting with the WORLD. Listen to the WORLD!"
                 .to_string();
 
         let prompt = "Do you have the answer? Use the Conclude Tool to terminate the task.\nObservations, Orientation, Decision, The ONLY Action?".to_string();
 
-        let prefix = "Sapiens:".to_string();
+        let prefix = "{agent_name}:".to_string();
         let tool_prefix = "Tool:".to_string();
         let response_format =
             "Something very long with Observations, Orientation, Decision, Action\n\n".to_string();
 
         let manager = Manager::new(
             toolbox,
-            system_prompt,
-            prompt,
-            prefix,
-            tool_prefix,
-            response_format,
+            PromptTemplates {
+                agent_name: DEFAULT_AGENT_NAME.to_string(),
+                persona: DEFAULT_PERSONA.to_string(),
+                world: DEFAULT_WORLD.to_string(),
+                system_prompt,
+                prompt,
+                prefix,
+                tool_prefix,
+                response_format,
+                examples: vec![],
+                acknowledgment: None,
+            },
         );
 
         let config = crate::SapiensConfig::default();
@@ -205,17 +445,284 @@ mod tests {
         let max_token = config.model.context_size().await;
         let mut chat_history = ChatHistory::new(config.clone(), max_token);
 
-        let examples = vec![];
-
-        manager
-            .populate_chat_history(&mut chat_history, examples)
-            .await;
+        manager.populate_chat_history(&mut chat_history).await;
 
         // let prompts: Vec<ChatEntry> = chat_history.iter().cloned().collect();
 
         // println!("{:?}", prompts);
         let tokens = config.model.num_tokens(chat_history.make_input()).await;
 
-        assert_eq!(tokens, 64);
+        assert_eq!(tokens, 56);
+    }
+
+    #[tokio::test]
+    async fn populate_chat_history_for_native_tool_calling() {
+        use super::*;
+        use crate::context::ChatHistory;
+        use crate::tools::Format;
+        use crate::Toolbox;
+
+        struct EchoTool;
+
+        #[async_trait::async_trait]
+        impl crate::tools::Tool for EchoTool {
+            fn description(&self) -> ToolDescription {
+                ToolDescription::new("Echo", "Echoes", Format::default(), Format::default())
+            }
+
+            async fn invoke(
+                &self,
+                input: serde_yaml::Value,
+            ) -> Result<serde_yaml::Value, crate::tools::ToolUseError> {
+                Ok(input)
+            }
+        }
+
+        let toolbox = Toolbox::default();
+        toolbox.add_tool(EchoTool).await;
+
+        let config = crate::SapiensConfig::default();
+        let max_token = config.model.context_size().await;
+
+        for tool_calling in [ToolCalling::Yaml, ToolCalling::Native] {
+            let manager = Manager::new(toolbox.clone(), PromptTemplates::default())
+                .with_tool_calling(tool_calling);
+
+            let mut chat_history = ChatHistory::new(config.clone(), max_token);
+            manager.populate_chat_history(&mut chat_history).await;
+
+            let prompts = chat_history
+                .iter()
+                .map(|e| e.msg.clone())
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            let native = tool_calling == ToolCalling::Native;
+            assert_eq!(!prompts.contains("name: Echo"), native);
+            assert_eq!(!prompts.contains("```yaml"), native);
+        }
+    }
+
+    #[tokio::test]
+    async fn populate_chat_history_with_custom_templates() {
+        use super::*;
+        use crate::context::ChatHistory;
+        use crate::Toolbox;
+
+        let manager = Manager::new(
+            Toolbox::default(),
+            PromptTemplates {
+                system_prompt: "You are a pirate. Arrr!".to_string(),
+                ..PromptTemplates::default()
+            },
+        );
+
+        let config = crate::SapiensConfig::default();
+        let max_token = config.model.context_size().await;
+        let mut chat_history = ChatHistory::new(config, max_token);
+
+        manager.populate_chat_history(&mut chat_history).await;
+
+        let first = chat_history.iter().next().unwrap();
+        assert_eq!(first.role, Role::System);
+        assert_eq!(first.msg, "You are a pirate. Arrr!");
+    }
+
+    #[test]
+    fn custom_agent_name() {
+        use super::*;
+        use crate::Toolbox;
+
+        let manager = Manager::new(
+            Toolbox::default(),
+            PromptTemplates {
+                agent_name: "Homer".to_string(),
+                ..PromptTemplates::default()
+            },
+        );
+
+        let system_prompt = manager.create_system_prompt();
+        assert!(system_prompt.contains("named Homer"));
+        assert!(!system_prompt.contains("Sapiens"));
+        assert!(!system_prompt.contains(AGENT_NAME_PLACEHOLDER));
+    }
+
+    #[tokio::test]
+    async fn custom_framing() {
+        use super::*;
+        use crate::Toolbox;
+
+        let manager = Manager::new(
+            Toolbox::default(),
+            PromptTemplates {
+                persona: "a helpful assistant".to_string(),
+                world: "the support team".to_string(),
+                ..PromptTemplates::default()
+            },
+        );
+
+        let system_prompt = manager.create_system_prompt();
+        assert!(system_prompt.contains("interacting with the support team"));
+
+        let warm_up = manager.create_tool_warm_up().await;
+        assert!(warm_up.contains("a helpful assistant assisting the support team"));
+
+        for text in [&system_prompt, &warm_up] {
+            assert!(!text.contains("WORLD"));
+            assert!(!text.contains("large language model"));
+            assert!(!text.contains(WORLD_PLACEHOLDER));
+            assert!(!text.contains(PERSONA_PLACEHOLDER));
+        }
+    }
+
+    #[test]
+    fn system_prompt_with_date_time() {
+        use chrono::TimeZone;
+
+        use super::*;
+        use crate::Toolbox;
+
+        struct FixedClock;
+
+        impl Clock for FixedClock {
+            fn now(&self) -> DateTime<FixedOffset> {
+                FixedOffset::east_opt(9 * 3600)
+                    .unwrap()
+                    .with_ymd_and_hms(2023, 5, 17, 13, 37, 0)
+                    .unwrap()
+            }
+        }
+
+        let manager = Manager::new(Toolbox::default(), PromptTemplates::default());
+        assert!(!manager.create_system_prompt().contains("Current date"));
+
+        let manager = manager.with_clock(Some(Arc::new(FixedClock)));
+        let system_prompt = manager.create_system_prompt();
+        assert!(system_prompt
+            .starts_with("Current date and time: Wednesday 2023-05-17 13:37:00 +09:00\n"));
+    }
+
+    #[tokio::test]
+    async fn populate_chat_history_in_french() {
+        use super::*;
+        use crate::context::ChatHistory;
+        use crate::Toolbox;
+
+        let pack = languages::find("fr").unwrap();
+        let manager = Manager::new(Toolbox::default(), PromptTemplates::from(pack));
+
+        let config = crate::SapiensConfig::default();
+        let max_token = config.model.context_size().await;
+        let mut chat_history = ChatHistory::new(config, max_token);
+
+        manager.populate_chat_history(&mut chat_history).await;
+
+        let entries: Vec<_> = chat_history.iter().collect();
+        assert_eq!(
+            entries[0].msg,
+            "Tu es un agent nommé Sapiens qui interagit avec le MONDE. Écoute le MONDE !"
+        );
+        assert!(entries[1].msg.starts_with("Tu es Sapiens, un grand modèle"));
+        assert!(entries[1].msg.contains("# Format de ta réponse"));
+    }
+
+    #[test]
+    fn missing_translations_fall_back_to_english() {
+        use super::*;
+
+        let pack = languages::LanguagePack {
+            code: "xx",
+            system_prompt: Some("Translated"),
+            ..languages::ENGLISH
+        };
+
+        let templates = PromptTemplates::from(&pack);
+        assert_eq!(templates.system_prompt, "Translated");
+        assert_eq!(templates.prefix, PromptTemplates::default().prefix);
+    }
+
+    #[tokio::test]
+    async fn populate_chat_history_with_custom_examples() {
+        use super::*;
+        use crate::context::ChatHistory;
+        use crate::Toolbox;
+
+        let manager = Manager::new(
+            Toolbox::default(),
+            PromptTemplates {
+                examples: vec![WarmUpExample {
+                    task: "What is the capital of France?".to_string(),
+                    turns: vec![
+                        WarmUpTurn {
+                            response: "tool_name: Search".to_string(),
+                            action_result: Some("Paris is the capital of France.\n".to_string()),
+                        },
+                        WarmUpTurn {
+                            response: "tool_name: Conclude".to_string(),
+                            action_result: None,
+                        },
+                    ],
+                }],
+                ..PromptTemplates::default()
+            },
+        );
+
+        let config = crate::SapiensConfig::default();
+        let max_token = config.model.context_size().await;
+        let mut chat_history = ChatHistory::new(config, max_token);
+
+        manager.populate_chat_history(&mut chat_history).await;
+
+        let entries: Vec<_> = chat_history.iter().collect();
+        assert_eq!(entries.len(), 6);
+        assert!(entries[2]
+            .msg
+            .contains("Original question: What is the capital of France?"));
+        assert_eq!(entries[3].msg, "tool_name: Search");
+        assert!(entries[4]
+            .msg
+            .starts_with("Paris is the capital of France.\n# Your turn"));
+        assert_eq!(entries[5].msg, "tool_name: Conclude");
+        assert!(entries
+            .iter()
+            .all(|e| !e.msg.contains("Sort in ascending order")));
+    }
+
+    #[tokio::test]
+    async fn populate_chat_history_with_an_acknowledgment() {
+        use super::*;
+        use crate::context::ChatHistory;
+        use crate::Toolbox;
+
+        let manager = Manager::new(Toolbox::default(), PromptTemplates::default());
+
+        let config = crate::SapiensConfig::default();
+        let max_token = config.model.context_size().await;
+        let mut chat_history = ChatHistory::new(config.clone(), max_token);
+
+        manager.populate_chat_history(&mut chat_history).await;
+
+        // the examples come right after the warm-up prompt by default
+        let entries: Vec<_> = chat_history.iter().collect();
+        assert_eq!(entries[1].role, Role::User);
+        assert_eq!(entries[2].role, Role::User);
+
+        let manager = Manager::new(
+            Toolbox::default(),
+            PromptTemplates {
+                acknowledgment: Some("Roger that, ready for the task.".to_string()),
+                ..PromptTemplates::default()
+            },
+        );
+
+        let mut chat_history = ChatHistory::new(config, max_token);
+
+        manager.populate_chat_history(&mut chat_history).await;
+
+        let entries: Vec<_> = chat_history.iter().collect();
+        assert_eq!(entries[1].role, Role::User);
+        assert_eq!(entries[2].role, Role::Assistant);
+        assert_eq!(entries[2].msg, "Roger that, ready for the task.");
+        assert!(entries[3].msg.contains("Original question: "));
     }
 }
@@ -0,0 +1,136 @@
+//! Translations of the prompts
+//!
+//! A [`LanguagePack`] only needs to translate the sections it cares about; the
+//! others fall back to English. To add a language, define a new `const`
+//! [`LanguagePack`] here and list it in [`ALL`].
+
+use crate::chains::agents::ooda::one_step;
+use crate::prompt::{PromptTemplates, DEFAULT_AGENT_NAME, DEFAULT_PERSONA, DEFAULT_WORLD};
+
+/// A set of translated prompt sections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LanguagePack {
+    /// The language code - ISO 639-1
+    pub code: &'static str,
+    /// What the agent is
+    pub persona: Option<&'static str>,
+    /// Who the agent works for
+    pub world: Option<&'static str>,
+    /// The 'system' prompt
+    pub system_prompt: Option<&'static str>,
+    /// The prompt recalled after the task at each step
+    pub prompt: Option<&'static str>,
+    /// The introduction of the warm-up prompt
+    pub prefix: Option<&'static str>,
+    /// The introduction of the tool descriptions
+    pub tool_prefix: Option<&'static str>,
+    /// The description of the expected format of the responses
+    pub response_format: Option<&'static str>,
+}
+
+impl From<&LanguagePack> for PromptTemplates {
+    fn from(pack: &LanguagePack) -> Self {
+        Self {
+            agent_name: DEFAULT_AGENT_NAME.to_string(),
+            persona: pack.persona.unwrap_or(DEFAULT_PERSONA).to_string(),
+            world: pack.world.unwrap_or(DEFAULT_WORLD).to_string(),
+            system_prompt: pack
+                .system_prompt
+                .unwrap_or(one_step::SYSTEM_PROMPT)
+                .to_string(),
+            prompt: pack.prompt.unwrap_or(one_step::PROMPT).to_string(),
+            prefix: pack.prefix.unwrap_or(one_step::PREFIX).to_string(),
+            tool_prefix: pack
+                .tool_prefix
+                .unwrap_or(one_step::TOOL_PREFIX)
+                .to_string(),
+            response_format: pack
+                .response_format
+                .unwrap_or(one_step::RESPONSE_FORMAT)
+                .to_string(),
+            examples: one_step::examples(),
+            acknowledgment: None,
+        }
+    }
+}
+
+/// Find a [`LanguagePack`] by its code
+#[must_use]
+pub fn find(code: &str) -> Option<&'static LanguagePack> {
+    ALL.iter().copied().find(|pack| pack.code == code)
+}
+
+/// All the bundled [`LanguagePack`]s
+pub const ALL: &[&LanguagePack] = &[&ENGLISH, &FRENCH];
+
+/// English - the original prompts
+pub const ENGLISH: LanguagePack = LanguagePack {
+    code: "en",
+    persona: None,
+    world: None,
+    system_prompt: None,
+    prompt: None,
+    prefix: None,
+    tool_prefix: None,
+    response_format: None,
+};
+
+/// French
+pub const FRENCH: LanguagePack = LanguagePack {
+    code: "fr",
+    persona: Some("un grand modèle de langage"),
+    world: Some("le MONDE"),
+    system_prompt: Some(
+        "Tu es un agent nommé {agent_name} qui interagit avec {world}. Écoute {world} !",
+    ),
+    prompt: Some("As-tu la réponse ? Utilise l'outil Conclude pour terminer la tâche.\nObservations, Orientation, Décision, L'UNIQUE Action ?"),
+    prefix: Some(
+        r"Tu es {agent_name}, {persona} qui assiste {world}. Utilise les outils disponibles pour répondre au mieux à la question.
+Tu procèdes de manière itérative avec une boucle OODA.
+
+- La réponse à l'Action te sera fournie.
+- Ne produis jamais la réponse d'une Action.
+- Utilise uniquement du YAML pour l'Action.
+- La boucle se répète jusqu'à ce que tu aies la réponse à la question d'origine.
+- Aucune tâche n'est terminée tant que l'outil Conclude n'a pas été utilisé pour donner la réponse.
+- Tu ne peux pas utiliser de templates jinja2 dans ta réponse. Sois concis.
+",
+    ),
+    tool_prefix: Some(
+        r"
+# Voici les SEULS outils que tu peux utiliser pour tes Actions :
+",
+    ),
+    response_format: Some(
+        r"
+# Format de ta réponse
+
+Tu dois utiliser le format suivant pour ta réponse. Les commentaires sont en gras et doivent être retirés de ta réponse.
+====================
+## Observations :
+**Que sais-tu être vrai ? Que ne sais-tu pas ? Quelles sont tes sources ? Note les informations importantes pour plus tard.**
+- <...>
+## Orientation :
+**Planifie les objectifs intermédiaires pour répondre à la question d'origine. Tiens à jour la liste des objectifs en cours.**
+- <...>
+## Décision :
+**Décide quoi faire en premier pour répondre à la question. Pourquoi ? Comment sauras-tu si cela réussit ? Ou si cela échoue ?**
+- <...>
+## L'UNIQUE Action :
+**Effectue une seule Action composée d'exactement une paire `tool_name` et `parameters`. Ne donne jamais plus d'un YAML.**
+```yaml
+tool_name: <ToolName>
+parameters:
+    <...>
+```
+Nous agirons ensuite en fonction de la réponse.
+====================
+
+Notes :
+- Une Action a les champs suivants : `tool_name` et `parameters` UNIQUEMENT.
+- `parameters` utilise le format spécifié pour l'outil.
+- `responses_content` est le format de la réponse attendue de l'Action. Tu peux t'en servir pour t'orienter mais ne l'utilise jamais dans ta réponse.
+- Une Action à la fois. Pas plus. Pas moins.
+",
+    ),
+};
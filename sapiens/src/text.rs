@@ -0,0 +1,173 @@
+//! Text utilities shared by the frontends
+
+/// The marker of the start and end of code blocks
+const FENCE: &str = "```";
+
+/// Split a message into chunks of at most `limit` bytes - e.g. for a chat
+/// platform that limits the size of the messages
+///
+/// Prefers to split on newlines and only splits lines on char boundaries when
+/// they are too long. Code blocks split across chunks are closed at the end of
+/// a chunk and reopened - with the same info string - at the start of the next
+/// one.
+#[must_use]
+pub fn split_message(text: &str, limit: usize) -> Vec<String> {
+    if text.len() <= limit {
+        return vec![text.to_string()];
+    }
+
+    // room to close a code block at the end of a chunk
+    let closing = FENCE.len() + 2;
+
+    let mut chunks = vec![];
+    let mut chunk = String::new();
+    // the line that opened the current code block - if any
+    let mut open_fence: Option<String> = None;
+
+    for line in text.split_inclusive('\n') {
+        let mut rest = line;
+
+        while !rest.is_empty() {
+            let available = limit.saturating_sub(chunk.len() + closing);
+
+            if rest.len() <= available {
+                chunk.push_str(rest);
+                break;
+            }
+
+            // split the line itself only if it cannot fit in an empty chunk
+            let reopening = open_fence.as_ref().map_or(0, |f| f.len() + 1);
+            if chunk.len() > reopening {
+                flush(&mut chunks, &mut chunk, open_fence.as_deref());
+                continue;
+            }
+
+            let at = floor_char_boundary(rest, available.max(1));
+            let (head, tail) = rest.split_at(at);
+            chunk.push_str(head);
+            flush(&mut chunks, &mut chunk, open_fence.as_deref());
+            rest = tail;
+        }
+
+        if line.trim_start().starts_with(FENCE) {
+            open_fence = match open_fence {
+                Some(_) => None,
+                None => Some(line.trim_end().to_string()),
+            };
+        }
+    }
+
+    if !chunk.trim().is_empty() {
+        chunks.push(chunk);
+    }
+
+    chunks
+}
+
+/// Push `chunk` - closing the code block if needed - and start a new one -
+/// reopening the code block if needed
+fn flush(chunks: &mut Vec<String>, chunk: &mut String, open_fence: Option<&str>) {
+    if open_fence.is_some() {
+        if !chunk.ends_with('\n') {
+            chunk.push('\n');
+        }
+        chunk.push_str(FENCE);
+    }
+
+    chunks.push(std::mem::take(chunk));
+
+    if let Some(fence) = open_fence {
+        chunk.push_str(fence);
+        chunk.push('\n');
+    }
+}
+
+/// The largest char boundary of `s` that is not after `index` - but at least
+/// one char
+fn floor_char_boundary(s: &str, index: usize) -> usize {
+    if index >= s.len() {
+        return s.len();
+    }
+
+    let at = (0..=index)
+        .rev()
+        .find(|&i| s.is_char_boundary(i))
+        .unwrap_or_default();
+
+    if at == 0 {
+        s.chars().next().map_or(0, char::len_utf8)
+    } else {
+        at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_a_message_at_the_limit() {
+        let msg = "a".repeat(50);
+
+        assert_eq!(split_message(&msg, 50), vec![msg.clone()]);
+        assert_eq!(split_message("", 50), vec![String::new()]);
+    }
+
+    #[test]
+    fn splits_a_message_over_the_limit_on_newlines() {
+        let msg = "0123456789\n".repeat(10);
+
+        let chunks = split_message(&msg, 50);
+
+        assert_eq!(chunks.len(), 3);
+        for chunk in &chunks {
+            assert!(chunk.len() <= 50, "{chunk:?}");
+            assert!(chunk.ends_with('\n'), "{chunk:?}");
+        }
+        assert_eq!(chunks.concat(), msg);
+    }
+
+    #[test]
+    fn split_multibyte_characters() {
+        let msg = "héllo wörld 🦀 ".repeat(40);
+
+        let chunks = split_message(&msg, 50);
+
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(chunk.len() <= 50, "{chunk:?}");
+        }
+        assert_eq!(chunks.concat(), msg);
+    }
+
+    #[test]
+    fn split_long_code_block() {
+        use std::fmt::Write;
+
+        let code = (0..40).fold(String::new(), |mut code, i| {
+            writeln!(code, "print(\"line {i}\")").unwrap();
+            code
+        });
+        let msg = format!("Some code:\n```python\n{code}```\nThat's all.\n");
+
+        let chunks = split_message(&msg, 120);
+
+        assert!(chunks.len() > 2);
+        for chunk in &chunks {
+            assert!(chunk.len() <= 120, "{chunk:?}");
+            // fences are balanced in each chunk
+            assert_eq!(chunk.matches("```").count() % 2, 0, "{chunk:?}");
+        }
+        for chunk in &chunks[1..chunks.len() - 1] {
+            assert!(chunk.starts_with("```python\n"), "{chunk:?}");
+        }
+        assert!(chunks.last().unwrap().ends_with("That's all.\n"));
+
+        let lines = chunks
+            .iter()
+            .flat_map(|c| c.lines())
+            .filter(|l| l.starts_with("print"))
+            .count();
+        assert_eq!(lines, 40);
+    }
+}
@@ -8,10 +8,13 @@ use crate::tools::ToolInvocationInput;
 #[derive(Debug, thiserror::Error, Clone, Serialize, Deserialize)]
 pub enum Error {
     /// Invalid yaml
-    #[error("Invalid yaml: {0}")]
+    #[error("The Action is not valid YAML: {0}. Fix its syntax.")]
     InvalidYaml(String),
+    /// Invalid json
+    #[error("The Action is not valid JSON: {0}. Fix its syntax.")]
+    InvalidJson(String),
     /// No invocation found in the document
-    #[error("No Action found")]
+    #[error("No Action found. Give one Action as a YAML block with the fields `tool_name` and `parameters`.")]
     NoInvocationFound,
     /// No valid invocation found in the document
     #[error("No valid Action found: {0}")]
@@ -19,6 +22,15 @@ pub enum Error {
     /// Too many yaml blocks
     #[error("Too many ({0}) yaml blocks. Only one is expected.")]
     TooManyYamlBlocks(usize),
+    /// The invocation has fields other than `tool_name` and `parameters`
+    #[error(
+        "The Action cannot have the fields: {}. An Action has exactly two fields: `tool_name` and `parameters`:\n```yaml\ntool_name: <ToolName>\nparameters:\n    <...>\n```\nThe response of the Action will be provided to you - never include it.",
+        .0.iter().map(|f| format!("`{f}`")).collect::<Vec<_>>().join(", ")
+    )]
+    UnexpectedFields(Vec<String>),
+    /// Too many invocations
+    #[error("Too many ({0}) Actions. Give exactly one Action.")]
+    TooManyInvocations(usize),
 }
 
 /// One of several T
@@ -65,15 +77,79 @@ where
     }
 }
 
+/// extract one or several T from a json string.
+///
+/// `data` can be a single json object of T or a list of T.
+fn extract_from_json<T>(data: &str) -> Result<Vec<T>, Error>
+where
+    T: DeserializeOwned,
+{
+    let invocations = match serde_json::from_str(data) {
+        Ok(Invocation::Single(t)) => vec![t],
+        Ok(Invocation::Multiple(ts)) => ts,
+        Err(e) => {
+            debug!(error = %e, "Failed to deserialize as a list of T or a single T");
+            return Err(Error::InvalidJson(e.to_string()));
+        }
+    };
+
+    if invocations.is_empty() {
+        Err(Error::NoInvocationFound)
+    } else {
+        Ok(invocations)
+    }
+}
+
 /// Extracted invocations
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct ExtractedInvocations {
     pub(crate) invocations: Vec<ToolInvocationInput>,
+    /// The number of yaml or json blocks
     pub(crate) yaml_block_count: usize,
 }
 
-/// Find all the invocations in a markdown document.
+/// Find all the invocations in the yaml or json blocks of a markdown document.
+///
+/// If none can be found, try again once the document is normalized - see
+/// [`normalize`].
 pub(crate) fn find_all(data: &str) -> Result<ExtractedInvocations, Error> {
+    let res = find_all_as_is(data);
+    if res.is_ok() {
+        return res;
+    }
+
+    let normalized = normalize(data);
+    if normalized == data {
+        return res;
+    }
+
+    debug!("No valid Action found - trying again with the normalized message");
+    find_all_as_is(&normalized).or(res)
+}
+
+/// Replace the typographic characters models sometimes produce and that are
+/// not valid YAML syntax: zero-width characters are removed, curly quotes
+/// become straight ones and unicode spaces become plain spaces.
+fn normalize(data: &str) -> String {
+    data.chars()
+        .filter_map(|c| match c {
+            '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{2060}' | '\u{FEFF}' => None,
+            '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' => Some('"'),
+            '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' => Some('\''),
+            '\u{00A0}' | '\u{2000}'..='\u{200A}' | '\u{202F}' | '\u{205F}' | '\u{3000}' => {
+                Some(' ')
+            }
+            c => Some(c),
+        })
+        .collect()
+}
+
+/// Find all the invocations in the yaml or json blocks of a markdown document
+/// - as it is.
+///
+/// The language of a block is `yaml`, `yml` or `json` - in any case. A block
+/// without language counts only if it is a valid Action.
+fn find_all_as_is(data: &str) -> Result<ExtractedInvocations, Error> {
     let mut err: Option<Error> = None;
 
     let mut invocations = vec![];
@@ -86,32 +162,71 @@ pub(crate) fn find_all(data: &str) -> Result<ExtractedInvocations, Error> {
             continue;
         }
 
-        // we have start of a yaml block
-        if line.trim().starts_with("```yaml") {
-            // collect the lines until the end of the yaml block
-            let mut yaml = vec![];
+        // we have start of a code block - `yaml`, `yml` or `json`, in any case,
+        // or no language at all
+        let Some(tag) = line.trim().strip_prefix("```") else {
+            continue;
+        };
+        let language = tag
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        // collect the lines until the end of the block
+        let mut block = vec![];
+
+        for line in lines.by_ref() {
+            if line.trim().starts_with("```") {
+                break;
+            }
+
+            block.push(line);
+        }
+
+        // put them together
+        let block = block.join("\n");
 
-            for line in lines.by_ref() {
-                if line.trim().starts_with("```") {
-                    break;
+        let extract = match language.as_str() {
+            "yaml" | "yml" => extract_from_yaml,
+            "json" => extract_from_json,
+            // a block without language is an Action only if it parses as one
+            "" => {
+                if let Ok(more) = extract_from_yaml::<ToolInvocationInput>(&block) {
+                    yaml_block_count += 1;
+                    invocations.extend(more);
                 }
+                continue;
+            }
+            // some other code
+            _ => continue,
+        };
+
+        yaml_block_count += 1;
 
-                yaml.push(line);
+        // does that make valid invocations?
+        match extract(&block) {
+            Ok(more) => {
+                invocations.extend(more);
             }
+            Err(e) => {
+                // debug!(error = %e, "Failed to extract invocation from yaml");
 
-            // put them together
-            let yaml = yaml.join("\n");
+                err = Some(e);
+            }
+        }
+    }
 
+    // no block at all, maybe the model forgot the fences
+    if yaml_block_count == 0 {
+        if let Some(block) = find_unfenced(data) {
             yaml_block_count += 1;
 
-            // does that make valid invocations?
-            match extract_from_yaml(&yaml) {
+            match extract_from_yaml(&block) {
                 Ok(more) => {
                     invocations.extend(more);
                 }
                 Err(e) => {
-                    // debug!(error = %e, "Failed to extract invocation from yaml");
-
                     err = Some(e);
                 }
             }
@@ -128,6 +243,36 @@ pub(crate) fn find_all(data: &str) -> Result<ExtractedInvocations, Error> {
     }
 }
 
+/// Find an invocation that is not in a code block.
+///
+/// Either the whole document is an invocation or it starts at the first line
+/// beginning with `tool_name:` and runs as long as the lines are indented or
+/// start with a key.
+fn find_unfenced(data: &str) -> Option<String> {
+    if extract_from_yaml::<ToolInvocationInput>(data).is_ok() {
+        return Some(data.to_string());
+    }
+
+    let mut lines = data
+        .lines()
+        .skip_while(|line| !line.starts_with("tool_name:"));
+
+    let first = lines.next()?;
+
+    let is_key = |line: &str| {
+        line.split_once(':').is_some_and(|(key, _)| {
+            !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_')
+        })
+    };
+
+    let mut block = vec![first];
+    block.extend(lines.take_while(|line| {
+        line.trim().is_empty() || line.starts_with(char::is_whitespace) || is_key(line)
+    }));
+
+    Some(block.join("\n"))
+}
+
 #[cfg(test)]
 mod tests {
     use indoc::indoc;
@@ -362,4 +507,235 @@ mod tests {
 
         assert_snapshot!(tool_invocations.err().unwrap());
     }
+
+    #[tokio::test]
+    async fn test_extraction_of_one_json() {
+        let data = indoc! {r#"# Some text
+    ```json
+    {
+      "tool_name": "Search",
+      "parameters": {"q": "Marcel Deneuve", "num_results": 10},
+      "output": "Marcel Deneuve is a character in the Resident Evil film series"
+    }
+    ```
+    Some other text
+    "#};
+
+        let tool_invocations = super::find_all(data).unwrap();
+
+        assert_eq!(tool_invocations.invocations.len(), 1);
+        assert_eq!(tool_invocations.yaml_block_count, 1);
+
+        let invocation = &tool_invocations.invocations[0];
+        assert_eq!(invocation.tool_name, "Search");
+        assert_eq!(invocation.parameters.get("q").unwrap(), "Marcel Deneuve");
+        assert_eq!(
+            invocation.parameters.get("num_results").unwrap(),
+            &serde_yaml::Value::Number(Number::from(10))
+        );
+        assert!(invocation.junk.contains_key("output"));
+    }
+
+    #[tokio::test]
+    async fn test_extraction_of_yaml_and_json() {
+        let data = indoc! {r#"# Some text
+    ```yaml
+    tool_name: Search1
+    parameters:
+      q: Marcel Deneuve
+    ```
+    Some other text
+    ```json
+    [
+      {"tool_name": "Search2", "parameters": {"q": "Marcel Proust"}},
+      {"tool_name": "Search3", "parameters": {"q": "Marcel et son Orchestre"}}
+    ]
+    ```
+    That's all folks!
+    "#};
+
+        let tool_invocations = super::find_all(data).unwrap();
+
+        assert_eq!(tool_invocations.yaml_block_count, 2);
+        assert_eq!(tool_invocations.invocations.len(), 3);
+        assert_eq!(tool_invocations.invocations[0].tool_name, "Search1");
+        assert_eq!(tool_invocations.invocations[1].tool_name, "Search2");
+        assert_eq!(tool_invocations.invocations[2].tool_name, "Search3");
+
+        assert!(matches!(
+            crate::tools::choose_invocation(
+                tool_invocations,
+                crate::tools::MultipleActionsPolicy::default()
+            ),
+            Err(super::Error::TooManyYamlBlocks(2))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_extraction_of_broken_json() {
+        let data = indoc! {r#"
+    ```json
+    {"tool_name": "Search", "parameters": {"q": "Marcel Deneuve"
+    ```
+    "#};
+
+        let tool_invocations = super::find_all(data);
+
+        assert!(matches!(
+            tool_invocations,
+            Err(super::Error::InvalidJson(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_extraction_of_unfenced_yaml() {
+        let data = indoc! {r"## Observations:
+    - We need to search for Marcel Deneuve.
+    ## The ONLY Action:
+    tool_name: Search
+    parameters:
+      q: Marcel Deneuve
+
+      num_results: 10
+    We will take further action based on the response.
+    "};
+
+        let tool_invocations = super::find_all(data).unwrap();
+
+        assert_eq!(tool_invocations.invocations.len(), 1);
+
+        let invocation = &tool_invocations.invocations[0];
+        assert_eq!(invocation.tool_name, "Search");
+        assert_eq!(invocation.parameters.get("q").unwrap(), "Marcel Deneuve");
+        assert_eq!(
+            invocation.parameters.get("num_results").unwrap(),
+            &serde_yaml::Value::Number(Number::from(10))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extraction_of_unfenced_document() {
+        let data = indoc! {r"
+    tool_name: Search
+    parameters:
+      q: Marcel Deneuve
+    "};
+
+        let tool_invocations = super::find_all(data).unwrap();
+
+        assert_eq!(tool_invocations.invocations.len(), 1);
+        assert_eq!(tool_invocations.invocations[0].tool_name, "Search");
+    }
+
+    #[tokio::test]
+    async fn test_extraction_of_yml_and_uppercase_fences() {
+        let data = indoc! {r"# Some text
+    ```yml
+    tool_name: Search1
+    parameters:
+      q: Marcel Deneuve
+    ```
+    Some other text
+    ```YAML
+    tool_name: Search2
+    parameters:
+      q: Marcel Deneuve
+    ```
+    "};
+
+        let tool_invocations = super::find_all(data).unwrap();
+
+        assert_eq!(tool_invocations.yaml_block_count, 2);
+        assert_eq!(tool_invocations.invocations.len(), 2);
+        assert_eq!(tool_invocations.invocations[0].tool_name, "Search1");
+        assert_eq!(tool_invocations.invocations[1].tool_name, "Search2");
+    }
+
+    #[tokio::test]
+    async fn test_extraction_of_bare_fences() {
+        let data = indoc! {r#"# Some text
+    ```python
+    print("tool_name: Python")
+    ```
+    ```
+    Not an Action
+    ```
+    ```
+    tool_name: Search
+    parameters:
+      q: Marcel Deneuve
+    ```
+    "#};
+
+        let tool_invocations = super::find_all(data).unwrap();
+
+        assert_eq!(tool_invocations.yaml_block_count, 1);
+        assert_eq!(tool_invocations.invocations.len(), 1);
+        assert_eq!(tool_invocations.invocations[0].tool_name, "Search");
+        assert_eq!(
+            tool_invocations.invocations[0].parameters["q"],
+            "Marcel Deneuve"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_no_extraction_without_action() {
+        let data = "## Observations:\n- Nothing to do here.\n";
+
+        assert!(matches!(
+            super::find_all(data),
+            Err(super::Error::NoInvocationFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_extraction_with_smart_quotes() {
+        let data = indoc! {"
+    ```yaml
+    tool_name: Search
+    parameters:
+      q: \u{201C}Marcel Deneuve\u{201D}
+      excluded_terms: \u{2018}Resident: Evil\u{2019}
+    ```
+    "};
+
+        let tool_invocations = super::find_all(data).unwrap();
+
+        assert_eq!(tool_invocations.invocations.len(), 1);
+        let parameters = &tool_invocations.invocations[0].parameters;
+        assert_eq!(parameters["q"], "Marcel Deneuve");
+        assert_eq!(parameters["excluded_terms"], "Resident: Evil");
+    }
+
+    #[tokio::test]
+    async fn test_extraction_with_unicode_whitespaces() {
+        let data = "```yaml\ntool_name: Search\u{200B}\nparameters:\u{00A0}\n  q: Marcel Deneuve\u{3000}\n```\n";
+
+        let tool_invocations = super::find_all(data).unwrap();
+
+        assert_eq!(tool_invocations.invocations.len(), 1);
+        assert_eq!(tool_invocations.invocations[0].tool_name, "Search");
+        assert_eq!(
+            tool_invocations.invocations[0].parameters["q"],
+            "Marcel Deneuve"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_valid_extraction_is_not_normalized() {
+        let data = indoc! {"
+    ```yaml
+    tool_name: Search
+    parameters:
+      q: \u{201C}Marcel Deneuve\u{201D}\u{00A0}
+    ```
+    "};
+
+        let tool_invocations = super::find_all(data).unwrap();
+
+        assert_eq!(
+            tool_invocations.invocations[0].parameters["q"],
+            "\u{201C}Marcel Deneuve\u{201D}\u{00A0}"
+        );
+    }
 }
@@ -1,11 +1,13 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
 
+use serde::de::DeserializeOwned;
 use serde::ser::SerializeMap;
 use serde::{Deserialize, Serialize, Serializer};
 use toolbox::Toolbox;
 use tracing::warn;
 
+use crate::models::ToolCall;
 use crate::tools::invocation::{Error, ExtractedInvocations};
 
 /// Tools to extract Tool invocations from a messages
@@ -14,6 +16,15 @@ pub mod invocation;
 /// Collection of tools
 pub mod toolbox;
 
+/// Hide the secrets in the results of the tools
+pub mod redact;
+
+/// Retry the invocations of flaky tools
+pub mod retry;
+
+/// Check the inputs of tools against a JSON Schema
+pub mod schema;
+
 /// Part of a [`Format`]
 #[derive(Debug, Clone)]
 pub struct FieldFormat {
@@ -62,6 +73,23 @@ impl From<Vec<FieldFormat>> for Format {
     }
 }
 
+/// Whether invoking a tool changes the state of the world
+///
+/// It is declared apart from [`Tool::is_parallel_safe`]: a
+/// [`SideEffects::ReadOnly`] tool is not run in parallel unless it is also
+/// parallel-safe, and being parallel-safe says nothing about the side effects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SideEffects {
+    /// The tool did not say
+    #[default]
+    Unknown,
+    /// The tool only reads - invoking it twice with the same parameters does
+    /// not change anything
+    ReadOnly,
+    /// The tool changes the state of the world
+    Mutating,
+}
+
 /// Tool description
 #[derive(Debug, Serialize, Clone)]
 pub struct ToolDescription {
@@ -73,6 +101,38 @@ pub struct ToolDescription {
     pub parameters: Format,
     /// Output format
     pub responses_content: Format,
+    /// Worked examples of invocations
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<ToolExample>,
+    /// Whether invoking the tool changes the state of the world - see
+    /// [`Tool::side_effects`]
+    #[serde(skip)]
+    pub side_effects: SideEffects,
+}
+
+/// A worked example of an invocation of a tool
+#[derive(Debug, Serialize, Clone)]
+pub struct ToolExample {
+    /// The parameters of the invocation
+    pub parameters: serde_yaml::Value,
+    /// The content of the response
+    pub responses_content: serde_yaml::Value,
+}
+
+impl ToolExample {
+    /// Create a new example from the input and the output of a tool
+    ///
+    /// # Panics
+    ///
+    /// If `input` or `output` cannot be serialized.
+    #[must_use]
+    pub fn new(input: &impl Serialize, output: &impl Serialize) -> Self {
+        Self {
+            parameters: serde_yaml::to_value(input).expect("Failed to serialize the input"),
+            responses_content: serde_yaml::to_value(output)
+                .expect("Failed to serialize the output"),
+        }
+    }
 }
 
 impl ToolDescription {
@@ -89,8 +149,84 @@ impl ToolDescription {
             description: description.to_string(),
             parameters,
             responses_content,
+            examples: Vec::new(),
+            side_effects: SideEffects::default(),
         }
     }
+
+    /// Add worked examples of invocations
+    #[must_use]
+    pub fn with_examples(mut self, examples: Vec<ToolExample>) -> Self {
+        self.examples = examples;
+        self
+    }
+
+    /// Set whether invoking the tool changes the state of the world
+    #[must_use]
+    pub const fn with_side_effects(mut self, side_effects: SideEffects) -> Self {
+        self.side_effects = side_effects;
+        self
+    }
+
+    /// The JSON Schema of the parameters - for the models calling the tools
+    /// natively, see [`crate::models::ToolCalling::Native`]
+    ///
+    /// A field of a type with no JSON counterpart accepts anything.
+    #[must_use]
+    pub fn parameters_schema(&self) -> serde_json::Value {
+        let properties = self
+            .parameters
+            .fields
+            .iter()
+            .map(|field| {
+                let mut property = json_schema(&field.r#type);
+                property.insert("description".to_string(), field.description.clone().into());
+                (field.name.clone(), property.into())
+            })
+            .collect::<serde_json::Map<_, _>>();
+
+        let required = self
+            .parameters
+            .fields
+            .iter()
+            .filter(|field| !field.optional)
+            .map(|field| field.name.clone())
+            .collect::<Vec<_>>();
+
+        serde_json::json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        })
+    }
+}
+
+/// The JSON schema of a field of type `ty` - as described by [`Describe`] -
+/// empty if the type is unknown
+fn json_schema(ty: &str) -> serde_json::Map<String, serde_json::Value> {
+    let ty = ty
+        .strip_prefix("Optional[")
+        .and_then(|ty| ty.strip_suffix(']'))
+        .unwrap_or(ty);
+
+    let json_type = match ty {
+        "str" => "string",
+        "bool" => "boolean",
+        "float" => "number",
+        "int" | "i8" | "i16" | "isize" | "u8" | "u16" | "u32" | "u64" | "usize" => "integer",
+        _ if ty.starts_with("list[") => "array",
+        _ if ty.starts_with("dict[") => "object",
+        _ => return serde_json::Map::new(),
+    };
+
+    let mut schema = serde_json::Map::new();
+    schema.insert("type".to_string(), json_type.into());
+
+    if let Some(item) = ty.strip_prefix("list[").and_then(|ty| ty.strip_suffix(']')) {
+        schema.insert("items".to_string(), json_schema(item).into());
+    }
+
+    schema
 }
 
 /// Error while using a tool
@@ -111,7 +247,7 @@ pub enum ToolUseError {
 }
 
 /// A tool invocation input
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub(crate) struct ToolInvocationInput {
     /// The tool to invoke
     tool_name: String,
@@ -128,6 +264,34 @@ pub(crate) struct ToolInvocationInput {
 pub trait ProtoToolDescribe {
     /// the description of the tool
     fn description(&self) -> ToolDescription;
+
+    /// can the tool be invoked concurrently with other invocations?
+    fn is_parallel_safe(&self) -> bool {
+        false
+    }
+
+    /// the invocations of the tools with the same key are run one at a time
+    /// - see [`Tool::concurrency_key`]
+    fn concurrency_key(&self) -> Option<String> {
+        None
+    }
+
+    /// does invoking the tool change the state of the world?
+    fn side_effects(&self) -> SideEffects {
+        SideEffects::Unknown
+    }
+
+    /// a suggestion of what to do next after a successful invocation - see
+    /// [`Tool::hint`]
+    fn hint(&self, _output: &serde_yaml::Value) -> Option<String> {
+        None
+    }
+
+    /// how to show the output of a successful invocation - see
+    /// [`Tool::format_result`]
+    fn format_result(&self, _output: &serde_yaml::Value) -> Option<String> {
+        None
+    }
 }
 
 /// Something meant to become a [`Tool`] - invocation
@@ -137,6 +301,68 @@ pub trait ProtoToolInvoke {
     async fn invoke(&self, input: serde_yaml::Value) -> Result<serde_yaml::Value, ToolUseError>;
 }
 
+/// A [`Tool`] with typed parameters and response - the conversions from and
+/// to YAML are taken care of.
+///
+/// An alternative to deriving [`ProtoToolDescribe`] and [`ProtoToolInvoke`].
+#[async_trait::async_trait]
+pub trait TypedTool: Sync + Send {
+    /// The parameters of the tool
+    type Input: DeserializeOwned + Describe + Send + Sync;
+    /// The response of the tool
+    type Output: Serialize + Describe;
+
+    /// The name of the tool
+    const NAME: &'static str;
+
+    /// What the tool does
+    const DESCRIPTION: &'static str;
+
+    /// Whether invoking the tool changes the state of the world
+    const SIDE_EFFECTS: SideEffects = SideEffects::Unknown;
+
+    /// Worked examples of invocations
+    fn examples(&self) -> Vec<ToolExample> {
+        Vec::new()
+    }
+
+    /// Invoke the tool
+    async fn invoke_typed(&self, input: &Self::Input) -> Result<Self::Output, ToolUseError>;
+}
+
+impl<T> ProtoToolDescribe for T
+where
+    T: TypedTool,
+{
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            T::NAME,
+            T::DESCRIPTION,
+            T::Input::describe(),
+            T::Output::describe(),
+        )
+        .with_examples(self.examples())
+        .with_side_effects(T::SIDE_EFFECTS)
+    }
+
+    fn side_effects(&self) -> SideEffects {
+        T::SIDE_EFFECTS
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> ProtoToolInvoke for T
+where
+    T: TypedTool,
+{
+    async fn invoke(&self, input: serde_yaml::Value) -> Result<serde_yaml::Value, ToolUseError> {
+        let input =
+            serde_yaml::from_value(input).map_err(|e| ToolUseError::InvalidInput(e.to_string()))?;
+        let output = self.invoke_typed(&input).await?;
+        serde_yaml::to_value(output).map_err(|e| ToolUseError::InvalidOutput(e.to_string()))
+    }
+}
+
 /// A Tool - the most basic kind of tools. See [`AdvancedTool`] and
 /// [`TerminalTool`] for more.
 #[async_trait::async_trait]
@@ -144,6 +370,45 @@ pub trait Tool: Sync + Send {
     /// the description of the tool
     fn description(&self) -> ToolDescription;
 
+    /// can the tool be invoked concurrently with other invocations?
+    ///
+    /// Only [`Tool`]s that have no side effects should be parallel-safe. See
+    /// [`crate::SapiensConfig::parallel_actions`].
+    fn is_parallel_safe(&self) -> bool {
+        false
+    }
+
+    /// the invocations of the tools with the same key are run one at a time -
+    /// e.g. the tools sharing a resource that is not safe to use concurrently,
+    /// like the Hue bridge. `None` to let them run concurrently.
+    ///
+    /// An [`AdvancedTool`] does not hold its key while it runs - the tools it
+    /// invokes hold theirs - so it can invoke the tools sharing it.
+    fn concurrency_key(&self) -> Option<String> {
+        None
+    }
+
+    /// does invoking the tool change the state of the world?
+    ///
+    /// Features like caching the results only apply to
+    /// [`SideEffects::ReadOnly`] [`Tool`]s.
+    fn side_effects(&self) -> SideEffects {
+        SideEffects::Unknown
+    }
+
+    /// a suggestion of what to do next after a successful invocation that
+    /// returned `output` - e.g. which tool to use to change what was just
+    /// read. It is shown to the model after the result.
+    fn hint(&self, _output: &serde_yaml::Value) -> Option<String> {
+        None
+    }
+
+    /// how to show the `output` of a successful invocation to the model -
+    /// e.g. as a table or a single line. `None` to show it as YAML.
+    fn format_result(&self, _output: &serde_yaml::Value) -> Option<String> {
+        None
+    }
+
     /// Invoke the tool
     // FUTURE(ssoudan) Box<Deserialize>?
     async fn invoke(&self, input: serde_yaml::Value) -> Result<serde_yaml::Value, ToolUseError>;
@@ -158,11 +423,68 @@ where
         self.description()
     }
 
+    fn is_parallel_safe(&self) -> bool {
+        ProtoToolDescribe::is_parallel_safe(self)
+    }
+
+    fn concurrency_key(&self) -> Option<String> {
+        ProtoToolDescribe::concurrency_key(self)
+    }
+
+    fn side_effects(&self) -> SideEffects {
+        ProtoToolDescribe::side_effects(self)
+    }
+
+    fn hint(&self, output: &serde_yaml::Value) -> Option<String> {
+        ProtoToolDescribe::hint(self, output)
+    }
+
+    fn format_result(&self, output: &serde_yaml::Value) -> Option<String> {
+        ProtoToolDescribe::format_result(self, output)
+    }
+
     async fn invoke(&self, input: serde_yaml::Value) -> Result<serde_yaml::Value, ToolUseError> {
         self.invoke(input).await
     }
 }
 
+/// The field of the output of a [`Tool`] holding its [`Artifact`]s
+pub const ARTIFACTS_FIELD: &str = "artifacts";
+
+/// A file produced by a [`Tool`] - e.g. a chart
+///
+/// A tool returns its artifacts in the `artifacts` field of its output - see
+/// [`ARTIFACTS_FIELD`]. They are kept out of the chat history: the model
+/// only sees a reference to them, see [`Artifact::reference`]. The
+/// frontends get them with the result of the invocation.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Artifact {
+    /// The name of the file
+    pub name: String,
+    /// The MIME type of the content - e.g. `image/png`
+    pub mime: String,
+    /// The content
+    pub bytes: Vec<u8>,
+}
+
+impl Debug for Artifact {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Artifact")
+            .field("name", &self.name)
+            .field("mime", &self.mime)
+            .field("bytes", &self.bytes.len())
+            .finish()
+    }
+}
+
+impl Artifact {
+    /// What the model sees of the artifact
+    #[must_use]
+    pub fn reference(&self) -> String {
+        format!("{} ({}, {} bytes)", self.name, self.mime, self.bytes.len())
+    }
+}
+
 /// A termination message
 ///
 /// This is the message that is sent to the user when a chain of exchanges
@@ -173,6 +495,36 @@ pub struct TerminationMessage {
     pub conclusion: String,
     /// The original question that was asked to the user.
     pub original_question: String,
+    /// Machine-readable results for this task - alongside the `conclusion`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_yaml::Value>,
+    /// The name of the [`TerminalTool`] that produced this message - if any.
+    /// Set by the [`toolbox::Toolbox`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+    /// Whether the task was done - or the `conclusion` tells why it could
+    /// not be.
+    #[serde(default, skip_serializing_if = "TerminationStatus::is_success")]
+    pub status: TerminationStatus,
+}
+
+/// Whether a task was done - see [`TerminationMessage::status`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TerminationStatus {
+    /// The task was done
+    #[default]
+    Success,
+    /// The task could not be done
+    Failure,
+}
+
+impl TerminationStatus {
+    /// Was the task done?
+    #[must_use]
+    pub const fn is_success(&self) -> bool {
+        matches!(self, Self::Success)
+    }
 }
 
 /// A [`Tool`] that wraps a chain of exchanges
@@ -200,7 +552,115 @@ pub trait AdvancedTool: Tool {
     ) -> Result<serde_yaml::Value, ToolUseError>;
 }
 
-fn choose_invocation(tool_invocations: ExtractedInvocations) -> Result<ToolInvocationInput, Error> {
+/// What to do when a message contains several Actions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MultipleActionsPolicy {
+    /// Invoke the first one
+    #[default]
+    TakeFirst,
+    /// Invoke the last one
+    TakeLast,
+    /// Invoke none and tell the model to give exactly one
+    RejectMultiple,
+}
+
+/// What to do with the result of an Action that is longer than allowed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LongResultPolicy {
+    /// Drop the result and tell the model to ask for a shorter one
+    #[default]
+    Reject,
+    /// Keep the beginning of the result and mark how much was cut
+    Truncate,
+    /// Stash the result in the [`toolbox::Toolbox`] and give the model a
+    /// handle to fetch it from the `SandboxedPython` tool - see
+    /// [`toolbox::Toolbox::stash_result`]
+    Stash,
+}
+
+/// An Action parsed from a message of the model
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedAction {
+    /// The tool to invoke
+    pub tool_name: String,
+    /// The parameters of the tool
+    pub parameters: serde_yaml::Value,
+}
+
+/// Parse the Action from a message of the model - without invoking it.
+///
+/// If multiple Actions are found, `policy` decides which one is returned.
+///
+/// # Errors
+///
+/// If no valid Action can be found in `data`.
+pub fn parse_action(data: &str, policy: MultipleActionsPolicy) -> Result<ParsedAction, Error> {
+    chosen_action(invocation::find_all(data)?, policy)
+}
+
+/// Parse the Action from the tools called natively by the model - see
+/// [`crate::models::ModelResponse::tool_calls`]
+///
+/// If multiple tools are called, `policy` decides which call is returned.
+///
+/// # Errors
+///
+/// If `tool_calls` is empty.
+pub fn parse_tool_calls(
+    tool_calls: &[ToolCall],
+    policy: MultipleActionsPolicy,
+) -> Result<ParsedAction, Error> {
+    chosen_action(tool_call_invocations(tool_calls), policy)
+}
+
+/// The Action chosen by `policy` among the extracted invocations
+pub(crate) fn chosen_action(
+    tool_invocations: ExtractedInvocations,
+    policy: MultipleActionsPolicy,
+) -> Result<ParsedAction, Error> {
+    let (_, invocation) = choose_invocation(tool_invocations, policy)?;
+
+    Ok(ParsedAction {
+        tool_name: invocation.tool_name,
+        parameters: invocation.parameters,
+    })
+}
+
+/// The invocations in a message of the model - those of the tools it called
+/// natively if any, see [`crate::models::ModelResponse::tool_calls`]
+pub(crate) fn find_invocations(
+    content: &str,
+    tool_calls: &[ToolCall],
+) -> Result<ExtractedInvocations, Error> {
+    if tool_calls.is_empty() {
+        invocation::find_all(content)
+    } else {
+        Ok(tool_call_invocations(tool_calls))
+    }
+}
+
+/// The invocations of the tools called natively by the model - no YAML block
+/// involved
+pub(crate) fn tool_call_invocations(tool_calls: &[ToolCall]) -> ExtractedInvocations {
+    ExtractedInvocations {
+        invocations: tool_calls
+            .iter()
+            .map(|call| ToolInvocationInput {
+                tool_name: call.tool_name.clone(),
+                parameters: call.parameters.clone(),
+                junk: HashMap::new(),
+            })
+            .collect(),
+        yaml_block_count: 0,
+    }
+}
+
+/// Choose the invocation to run according to `policy` - with its index among
+/// the extracted ones
+fn choose_invocation(
+    tool_invocations: ExtractedInvocations,
+    policy: MultipleActionsPolicy,
+) -> Result<(usize, ToolInvocationInput), Error> {
     // TODO(ssoudan) customizable level of strictness
     if tool_invocations.yaml_block_count > 1 {
         return Err(Error::TooManyYamlBlocks(tool_invocations.yaml_block_count));
@@ -211,34 +671,34 @@ fn choose_invocation(tool_invocations: ExtractedInvocations) -> Result<ToolInvoc
         return Err(Error::NoInvocationFound);
     }
 
-    // We just take the first one
-    let mut invocation = tool_invocations.invocations.into_iter().next().unwrap();
+    let invocation_count = tool_invocations.invocations.len();
+    if policy == MultipleActionsPolicy::RejectMultiple && invocation_count > 1 {
+        return Err(Error::TooManyInvocations(invocation_count));
+    }
 
-    // FUTURE(ssoudan) clean up the object and return this one instead
-    // if any tool_invocations have an 'output' field, we return an error
+    let invocation_index = if policy == MultipleActionsPolicy::TakeLast {
+        invocation_count - 1
+    } else {
+        0
+    };
+    let invocation = tool_invocations
+        .invocations
+        .into_iter()
+        .nth(invocation_index)
+        .unwrap();
+
+    // if any tool_invocations have an extra field - like 'output', we return an
+    // error
     if !invocation.junk.is_empty() {
-        let junk_keys = invocation
-            .junk
-            .keys()
-            .cloned()
-            .collect::<Vec<String>>()
-            .join(", ");
-
-        warn!(
-            ?junk_keys,
-            "The Action should not have fields: {}.", junk_keys
-        );
+        let mut junk_keys = invocation.junk.keys().cloned().collect::<Vec<String>>();
+        junk_keys.sort();
 
-        // We just remove them for now
-        invocation.junk.clear();
+        warn!(?junk_keys, "The Action should not have these fields");
 
-        // return Err(InvocationError::NoValidInvocationFound(format!(
-        //     "The Action cannot have fields: {}. Only `command` and `input`
-        // are allowed.",     junk_keys
-        // )));
+        return Err(Error::UnexpectedFields(junk_keys));
     }
 
-    Ok(invocation)
+    Ok((invocation_index, invocation))
 }
 
 #[cfg(test)]
@@ -288,4 +748,133 @@ mod tests {
 
         assert_snapshot!(serialized);
     }
+
+    #[tokio::test]
+    async fn test_rejecting_output_field() {
+        use crate::tools::invocation::{find_all, Error};
+        use crate::tools::{choose_invocation, MultipleActionsPolicy};
+
+        let data = indoc::indoc! {r"
+        ```yaml
+        tool_name: Search
+        parameters:
+          q: Marcel Deneuve
+        output:
+          items: []
+        ```
+        "};
+
+        let invocations = find_all(data).unwrap();
+        let e = choose_invocation(invocations, MultipleActionsPolicy::default()).unwrap_err();
+
+        assert!(matches!(&e, Error::UnexpectedFields(fields) if fields == &["output"]));
+
+        let msg = e.to_string();
+        assert!(msg.contains("`output`"));
+        assert!(msg.contains("`tool_name` and `parameters`"));
+        assert_snapshot!(msg);
+    }
+
+    #[test]
+    fn prefers_the_tool_calls_to_the_yaml_blocks() {
+        use crate::models::ToolCall;
+        use crate::tools::{chosen_action, find_invocations, MultipleActionsPolicy};
+
+        let content = "```yaml\ntool_name: Search\nparameters:\n  q: Mars\n```\n";
+        let tool_calls = vec![ToolCall {
+            tool_name: "Conclude".to_string(),
+            parameters: serde_yaml::Value::Null,
+        }];
+
+        let invocations = find_invocations(content, &tool_calls).unwrap();
+        let action = chosen_action(invocations, MultipleActionsPolicy::default()).unwrap();
+        assert_eq!(action.tool_name, "Conclude");
+
+        let invocations = find_invocations(content, &[]).unwrap();
+        let action = chosen_action(invocations, MultipleActionsPolicy::default()).unwrap();
+        assert_eq!(action.tool_name, "Search");
+    }
+
+    struct EchoTool;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct EchoToolInput {
+        text: String,
+    }
+
+    impl super::Describe for EchoToolInput {
+        fn describe() -> super::Format {
+            super::Format {
+                fields: vec![super::FieldFormat {
+                    name: "text".to_string(),
+                    r#type: "str".to_string(),
+                    optional: false,
+                    description: "What to echo".to_string(),
+                }],
+            }
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct EchoToolOutput {
+        echo: String,
+    }
+
+    impl super::Describe for EchoToolOutput {
+        fn describe() -> super::Format {
+            super::Format {
+                fields: vec![super::FieldFormat {
+                    name: "echo".to_string(),
+                    r#type: "str".to_string(),
+                    optional: false,
+                    description: "The text, twice".to_string(),
+                }],
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl super::TypedTool for EchoTool {
+        type Input = EchoToolInput;
+        type Output = EchoToolOutput;
+
+        const DESCRIPTION: &'static str = "Repeat a text";
+        const NAME: &'static str = "Echo";
+        const SIDE_EFFECTS: super::SideEffects = super::SideEffects::ReadOnly;
+
+        async fn invoke_typed(
+            &self,
+            input: &EchoToolInput,
+        ) -> Result<EchoToolOutput, super::ToolUseError> {
+            Ok(EchoToolOutput {
+                echo: format!("{0} {0}", input.text),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_typed_tool() {
+        use super::{Tool, ToolUseError};
+
+        let description = Tool::description(&EchoTool);
+        assert_eq!(description.name, "Echo");
+        assert_eq!(description.description, "Repeat a text");
+        assert_eq!(description.parameters.fields[0].name, "text");
+        assert_eq!(description.responses_content.fields[0].name, "echo");
+        assert_eq!(description.side_effects, super::SideEffects::ReadOnly);
+        assert_eq!(Tool::side_effects(&EchoTool), super::SideEffects::ReadOnly);
+
+        let output = Tool::invoke(&EchoTool, serde_yaml::from_str("text: hello").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            output,
+            serde_yaml::from_str::<serde_yaml::Value>("echo: hello hello").unwrap()
+        );
+
+        let e = Tool::invoke(&EchoTool, serde_yaml::from_str("txt: hello").unwrap())
+            .await
+            .unwrap_err();
+        assert!(matches!(e, ToolUseError::InvalidInput(_)));
+    }
 }
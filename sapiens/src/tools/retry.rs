@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use tracing::warn;
+
+use crate::tools::{SideEffects, Tool, ToolDescription, ToolUseError};
+
+/// Wraps a [`Tool`] to retry its invocations that fail with
+/// [`ToolUseError::InvocationFailed`] - e.g. a network error.
+///
+/// The other errors - invalid parameters, ... - are returned right away. The
+/// description of the wrapped tool is kept.
+///
+/// ```
+/// # use std::time::Duration;
+/// # use sapiens::tools::retry::RetryTool;
+/// # use sapiens::tools::{Format, Tool, ToolDescription, ToolUseError};
+/// # struct FlakyTool;
+/// # #[async_trait::async_trait]
+/// # impl Tool for FlakyTool {
+/// #     fn description(&self) -> ToolDescription {
+/// #         ToolDescription::new("Flaky", "Fails", Format::default(), Format::default())
+/// #     }
+/// #     async fn invoke(&self, _input: serde_yaml::Value) -> Result<serde_yaml::Value, ToolUseError> {
+/// #         Err(ToolUseError::InvocationFailed("timeout".to_string()))
+/// #     }
+/// # }
+/// let tool = RetryTool::new(FlakyTool)
+///     .with_max_retries(3)
+///     .with_delay(Duration::from_secs(1));
+/// assert_eq!(tool.description().name, "Flaky");
+/// ```
+pub struct RetryTool<T> {
+    /// The wrapped tool
+    inner: T,
+    /// How many times a failed invocation is retried
+    max_retries: usize,
+    /// How long to wait before retrying
+    delay: Duration,
+}
+
+impl<T: Tool> RetryTool<T> {
+    /// Retry the invocations of `inner` twice - after 500ms
+    pub const fn new(inner: T) -> Self {
+        Self {
+            inner,
+            max_retries: 2,
+            delay: Duration::from_millis(500),
+        }
+    }
+
+    /// Set how many times a failed invocation is retried
+    #[must_use]
+    pub const fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set how long to wait before retrying
+    #[must_use]
+    pub const fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: Tool> Tool for RetryTool<T> {
+    fn description(&self) -> ToolDescription {
+        self.inner.description()
+    }
+
+    fn is_parallel_safe(&self) -> bool {
+        self.inner.is_parallel_safe()
+    }
+
+    fn concurrency_key(&self) -> Option<String> {
+        self.inner.concurrency_key()
+    }
+
+    fn side_effects(&self) -> SideEffects {
+        self.inner.side_effects()
+    }
+
+    async fn invoke(&self, input: serde_yaml::Value) -> Result<serde_yaml::Value, ToolUseError> {
+        let mut retries = 0;
+        loop {
+            match self.inner.invoke(input.clone()).await {
+                Err(ToolUseError::InvocationFailed(e)) if retries < self.max_retries => {
+                    retries += 1;
+                    warn!(
+                        tool_name = self.inner.description().name,
+                        retries, e, "Invocation failed - retrying"
+                    );
+                    tokio::time::sleep(self.delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::tools::Format;
+
+    /// A tool that fails `failures` times before succeeding
+    struct FlakyTool {
+        failures: usize,
+        error: ToolUseError,
+        calls: AtomicUsize,
+    }
+
+    impl FlakyTool {
+        fn new(failures: usize, error: ToolUseError) -> Self {
+            Self {
+                failures,
+                error,
+                calls: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Tool for FlakyTool {
+        fn description(&self) -> ToolDescription {
+            ToolDescription::new(
+                "Flaky",
+                "Fails a few times",
+                Format::default(),
+                Format::default(),
+            )
+        }
+
+        async fn invoke(
+            &self,
+            _input: serde_yaml::Value,
+        ) -> Result<serde_yaml::Value, ToolUseError> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                Err(self.error.clone())
+            } else {
+                Ok(serde_yaml::Value::from("done"))
+            }
+        }
+    }
+
+    fn retry(inner: FlakyTool, max_retries: usize) -> RetryTool<FlakyTool> {
+        RetryTool::new(inner)
+            .with_max_retries(max_retries)
+            .with_delay(Duration::ZERO)
+    }
+
+    #[tokio::test]
+    async fn retries_until_success() {
+        let tool = retry(
+            FlakyTool::new(2, ToolUseError::InvocationFailed("timeout".to_string())),
+            2,
+        );
+
+        assert_eq!(tool.description().name, "Flaky");
+        let output = tool.invoke(serde_yaml::Value::Null).await.unwrap();
+        assert_eq!(output, serde_yaml::Value::from("done"));
+        assert_eq!(tool.inner.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let tool = retry(
+            FlakyTool::new(2, ToolUseError::InvocationFailed("timeout".to_string())),
+            1,
+        );
+
+        let e = tool.invoke(serde_yaml::Value::Null).await.unwrap_err();
+        assert!(matches!(e, ToolUseError::InvocationFailed(_)));
+        assert_eq!(tool.inner.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_other_errors() {
+        let tool = retry(
+            FlakyTool::new(2, ToolUseError::InvalidInput("missing field".to_string())),
+            2,
+        );
+
+        let e = tool.invoke(serde_yaml::Value::Null).await.unwrap_err();
+        assert!(matches!(e, ToolUseError::InvalidInput(_)));
+        assert_eq!(tool.inner.calls.load(Ordering::SeqCst), 1);
+    }
+}
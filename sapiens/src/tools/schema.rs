@@ -0,0 +1,198 @@
+use jsonschema::Validator;
+
+use crate::tools::{SideEffects, Tool, ToolDescription, ToolUseError};
+
+/// The JSON Schema given to [`SchemaValidatedTool::new`] is not valid
+#[derive(thiserror::Error, Debug)]
+#[error("Invalid JSON Schema: {0}")]
+pub struct InvalidSchema(String);
+
+/// Wraps a [`Tool`] to check its inputs against a JSON Schema before invoking
+/// it - e.g. for tools exposed to untrusted prompts.
+///
+/// An input violating the schema fails with [`ToolUseError::InvalidInput`]
+/// listing every violation - the wrapped tool is not invoked. The description
+/// of the wrapped tool is kept.
+///
+/// ```
+/// # use sapiens::tools::schema::SchemaValidatedTool;
+/// # use sapiens::tools::{Format, Tool, ToolDescription, ToolUseError};
+/// # struct WeatherTool;
+/// # #[async_trait::async_trait]
+/// # impl Tool for WeatherTool {
+/// #     fn description(&self) -> ToolDescription {
+/// #         ToolDescription::new("Weather", "Tells the weather", Format::default(), Format::default())
+/// #     }
+/// #     async fn invoke(&self, _input: serde_yaml::Value) -> Result<serde_yaml::Value, ToolUseError> {
+/// #         Ok(serde_yaml::Value::from("sunny"))
+/// #     }
+/// # }
+/// let schema = serde_json::json!({
+///     "type": "object",
+///     "properties": { "city": { "type": "string" } },
+///     "required": ["city"]
+/// });
+/// let tool = SchemaValidatedTool::new(WeatherTool, &schema).unwrap();
+/// assert_eq!(tool.description().name, "Weather");
+/// ```
+pub struct SchemaValidatedTool<T> {
+    /// The wrapped tool
+    inner: T,
+    /// The schema of the inputs
+    validator: Validator,
+}
+
+impl<T: Tool> SchemaValidatedTool<T> {
+    /// Check the inputs of `inner` against `schema`
+    ///
+    /// # Errors
+    ///
+    /// If `schema` is not a valid JSON Schema.
+    pub fn new(inner: T, schema: &serde_json::Value) -> Result<Self, InvalidSchema> {
+        let validator =
+            jsonschema::validator_for(schema).map_err(|e| InvalidSchema(e.to_string()))?;
+
+        Ok(Self { inner, validator })
+    }
+
+    /// Check `input` against the schema
+    fn validate(&self, input: &serde_yaml::Value) -> Result<(), ToolUseError> {
+        let input = serde_json::to_value(input).map_err(|e| {
+            ToolUseError::InvalidInput(format!("The input cannot be read as JSON: {e}"))
+        })?;
+
+        let violations = self
+            .validator
+            .iter_errors(&input)
+            .map(|e| match e.instance_path().as_str() {
+                "" => e.to_string(),
+                path => format!("{path}: {e}"),
+            })
+            .collect::<Vec<_>>();
+
+        if violations.is_empty() {
+            return Ok(());
+        }
+
+        Err(ToolUseError::InvalidInput(format!(
+            "The input does not match the schema of the tool: {}",
+            violations.join("; ")
+        )))
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: Tool> Tool for SchemaValidatedTool<T> {
+    fn description(&self) -> ToolDescription {
+        self.inner.description()
+    }
+
+    fn is_parallel_safe(&self) -> bool {
+        self.inner.is_parallel_safe()
+    }
+
+    fn concurrency_key(&self) -> Option<String> {
+        self.inner.concurrency_key()
+    }
+
+    fn side_effects(&self) -> SideEffects {
+        self.inner.side_effects()
+    }
+
+    fn hint(&self, output: &serde_yaml::Value) -> Option<String> {
+        self.inner.hint(output)
+    }
+
+    fn format_result(&self, output: &serde_yaml::Value) -> Option<String> {
+        self.inner.format_result(output)
+    }
+
+    async fn invoke(&self, input: serde_yaml::Value) -> Result<serde_yaml::Value, ToolUseError> {
+        self.validate(&input)?;
+
+        self.inner.invoke(input).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::tools::Format;
+
+    /// A tool that counts its invocations
+    #[derive(Default)]
+    struct WeatherTool {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl Tool for WeatherTool {
+        fn description(&self) -> ToolDescription {
+            ToolDescription::new(
+                "Weather",
+                "Tells the weather",
+                Format::default(),
+                Format::default(),
+            )
+        }
+
+        async fn invoke(
+            &self,
+            _input: serde_yaml::Value,
+        ) -> Result<serde_yaml::Value, ToolUseError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(serde_yaml::Value::from("sunny"))
+        }
+    }
+
+    fn weather() -> SchemaValidatedTool<WeatherTool> {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "city": { "type": "string" },
+                "days": { "type": "integer", "minimum": 1 }
+            },
+            "required": ["city"],
+            "additionalProperties": false
+        });
+
+        SchemaValidatedTool::new(WeatherTool::default(), &schema).unwrap()
+    }
+
+    #[tokio::test]
+    async fn invokes_the_tool_with_a_valid_input() {
+        let tool = weather();
+
+        let input = serde_yaml::from_str("city: Paris\ndays: 3").unwrap();
+        let output = tool.invoke(input).await.unwrap();
+
+        assert_eq!(output, serde_yaml::Value::from("sunny"));
+        assert_eq!(tool.inner.calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn rejects_a_type_mismatch() {
+        let tool = weather();
+
+        let input = serde_yaml::from_str("city: Paris\ndays: three").unwrap();
+        let e = tool.invoke(input).await.unwrap_err();
+
+        let ToolUseError::InvalidInput(msg) = e else {
+            panic!("unexpected error: {e:?}");
+        };
+        assert!(
+            msg.contains("/days: \"three\" is not of type \"integer\""),
+            "{msg}"
+        );
+        assert_eq!(tool.inner.calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn rejects_an_invalid_schema() {
+        let schema = serde_json::json!({ "type": "not-a-type" });
+
+        assert!(SchemaValidatedTool::new(WeatherTool::default(), &schema).is_err());
+    }
+}
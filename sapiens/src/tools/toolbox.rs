@@ -1,15 +1,17 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fmt::Debug;
-use std::sync::Arc;
+use std::sync::{Arc, PoisonError};
 
 use serde::{Deserialize, Serialize};
-use tokio::sync::RwLock;
-use tracing::{debug, info};
+use tokio::sync::{Mutex, OwnedMutexGuard, RwLock};
+use tracing::{debug, info, warn};
 
+use crate::models::ToolCall;
 use crate::tools;
-use crate::tools::invocation::Error;
+use crate::tools::invocation::{Error, ExtractedInvocations};
 use crate::tools::{
-    AdvancedTool, TerminalTool, TerminationMessage, Tool, ToolDescription, ToolUseError,
+    AdvancedTool, Artifact, MultipleActionsPolicy, SideEffects, TerminalTool, TerminationMessage,
+    Tool, ToolDescription, ToolUseError, ARTIFACTS_FIELD,
 };
 
 /// Tool usage statistics
@@ -23,6 +25,21 @@ pub struct Stats {
     pub inexistent_count: HashMap<String, usize>,
 }
 
+/// The results of [`SideEffects::ReadOnly`] [`Tool`]s - by tool name and
+/// parameters
+type ResultCache = HashMap<(String, String), serde_yaml::Value>;
+
+/// How many results are stashed at most - see [`Toolbox::stash_result`]
+const MAX_STASHED_RESULTS: usize = 16;
+
+/// The results too long to be shown to the model - by handle, the least
+/// recently used first
+#[derive(Default)]
+struct ResultStash {
+    results: VecDeque<(String, String)>,
+    next_id: usize,
+}
+
 /// Toolbox
 ///
 /// a [`Toolbox`] is a collection of [`Tool`], [`TerminalTool`] and
@@ -41,6 +58,15 @@ pub struct Toolbox {
 
     /// The tool usage statistics
     stats: Arc<RwLock<Stats>>,
+
+    /// The results of the read-only tools - if enabled
+    cache: Option<Arc<RwLock<ResultCache>>>,
+
+    /// The stashed results - see [`Toolbox::stash_result`]
+    stash: Arc<std::sync::Mutex<ResultStash>>,
+
+    /// The locks of the concurrency keys - see [`Tool::concurrency_key`]
+    concurrency_locks: Arc<RwLock<HashMap<String, Arc<Mutex<()>>>>>,
 }
 
 impl Debug for Toolbox {
@@ -49,15 +75,85 @@ impl Debug for Toolbox {
     }
 }
 
+/// Builder for a [`Toolbox`]
+///
+/// ```
+/// # use sapiens::tools::toolbox::Toolbox;
+/// let toolbox = Toolbox::builder().build();
+/// ```
+#[derive(Default)]
+pub struct ToolboxBuilder {
+    terminal_tools: HashMap<String, Box<dyn TerminalTool>>,
+    tools: HashMap<String, Box<dyn Tool>>,
+    advanced_tools: HashMap<String, Box<dyn AdvancedTool>>,
+    cache: bool,
+}
+
+impl ToolboxBuilder {
+    /// Add a terminal tool - see [`Toolbox::add_terminal_tool`]
+    #[must_use]
+    pub fn with_terminal_tool(mut self, tool: impl TerminalTool + 'static) -> Self {
+        let name = tool.description().name;
+        self.terminal_tools.insert(name, Box::new(tool));
+        self
+    }
+
+    /// Add a tool - see [`Toolbox::add_tool`]
+    #[must_use]
+    pub fn with_tool(mut self, tool: impl Tool + 'static) -> Self {
+        let name = tool.description().name;
+        self.tools.insert(name, Box::new(tool));
+        self
+    }
+
+    /// Add an advanced tool - see [`Toolbox::add_advanced_tool`]
+    #[must_use]
+    pub fn with_advanced_tool(mut self, tool: impl AdvancedTool + 'static) -> Self {
+        let name = tool.description().name;
+        self.advanced_tools.insert(name, Box::new(tool));
+        self
+    }
+
+    /// Cache the results of the read-only tools - see
+    /// [`Toolbox::with_result_cache`]
+    #[must_use]
+    pub const fn with_result_cache(mut self) -> Self {
+        self.cache = true;
+        self
+    }
+
+    /// Build the [`Toolbox`]
+    #[must_use]
+    pub fn build(self) -> Toolbox {
+        Toolbox {
+            terminal_tools: Arc::new(RwLock::new(self.terminal_tools)),
+            tools: Arc::new(RwLock::new(self.tools)),
+            advanced_tools: Arc::new(RwLock::new(self.advanced_tools)),
+            stats: Arc::default(),
+            cache: self.cache.then(Arc::default),
+            stash: Arc::default(),
+            concurrency_locks: Arc::default(),
+        }
+    }
+}
+
 impl Toolbox {
-    /// Collect the termination messages
+    /// Create a [`ToolboxBuilder`]
+    #[must_use]
+    pub fn builder() -> ToolboxBuilder {
+        ToolboxBuilder::default()
+    }
+
+    /// Collect the termination messages - tagged with the name of the
+    /// [`TerminalTool`] they come from
     #[allow(clippy::significant_drop_tightening)]
     #[allow(clippy::significant_drop_in_scrutinee)]
     pub async fn termination_messages(&self) -> Vec<TerminationMessage> {
         let mut messages = Vec::new();
 
-        for tool in self.terminal_tools.read().await.values() {
-            if let Some(message) = tool.take_done().await {
+        for (name, tool) in self.terminal_tools.read().await.iter() {
+            if let Some(mut message) = tool.take_done().await {
+                message.tool_name = Some(name.clone());
                 messages.push(message);
             }
         }
@@ -70,6 +166,7 @@ impl Toolbox {
     /// A [`TerminalTool`] can terminate a chain of exchanges.
     pub async fn add_terminal_tool(&self, tool: impl TerminalTool + 'static) {
         let name = tool.description().name;
+        self.warn_if_taken(&name).await;
         self.terminal_tools
             .write()
             .await
@@ -86,6 +183,7 @@ impl Toolbox {
     /// A [`Tool`] can be invoked by an [`AdvancedTool`].
     pub async fn add_tool(&self, tool: impl Tool + 'static) {
         let name = tool.description().name;
+        self.warn_if_taken(&name).await;
         self.tools.write().await.insert(name, Box::new(tool));
     }
 
@@ -94,17 +192,18 @@ impl Toolbox {
     /// An [`AdvancedTool`] is a [`Tool`] that can invoke another tool.
     pub async fn add_advanced_tool(&self, tool: impl AdvancedTool + 'static) {
         let name = tool.description().name;
+        self.warn_if_taken(&name).await;
         self.advanced_tools
             .write()
             .await
             .insert(name, Box::new(tool));
     }
 
-    /// Get the descriptions of the tools
+    /// Get the descriptions of the tools - sorted by name
     #[allow(clippy::significant_drop_tightening)]
     #[allow(clippy::significant_drop_in_scrutinee)]
-    pub async fn describe(&self) -> HashMap<String, ToolDescription> {
-        let mut descriptions = HashMap::new();
+    pub async fn describe(&self) -> BTreeMap<String, ToolDescription> {
+        let mut descriptions = BTreeMap::new();
 
         for (name, tool) in self.terminal_tools.read().await.iter() {
             descriptions.insert(name.clone(), tool.description());
@@ -121,6 +220,218 @@ impl Toolbox {
         descriptions
     }
 
+    /// Warn if a tool named `tool_name` is already in the toolbox
+    async fn warn_if_taken(&self, tool_name: &str) {
+        if self.has_tool(tool_name).await {
+            warn!(
+                tool_name,
+                "A tool with the same name is already in the toolbox"
+            );
+        }
+    }
+
+    /// The names shared by tools of different kinds - only one of them can be
+    /// invoked
+    #[allow(clippy::significant_drop_tightening)]
+    pub async fn duplicate_tool_names(&self) -> Vec<String> {
+        let terminal_tools = self.terminal_tools.read().await;
+        let tools = self.tools.read().await;
+        let advanced_tools = self.advanced_tools.read().await;
+
+        let mut counts = BTreeMap::<&String, usize>::new();
+        for name in terminal_tools
+            .keys()
+            .chain(tools.keys())
+            .chain(advanced_tools.keys())
+        {
+            *counts.entry(name).or_default() += 1;
+        }
+
+        counts
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Check if the toolbox has a tool named `tool_name` - of any kind
+    pub async fn has_tool(&self, tool_name: &str) -> bool {
+        self.terminal_tools.read().await.contains_key(tool_name)
+            || self.tools.read().await.contains_key(tool_name)
+            || self.advanced_tools.read().await.contains_key(tool_name)
+    }
+
+    /// Find the tool invocation in the chat message like
+    /// [`invoke_tool_with_policy`] does - with `policy` if there are several
+    /// - but without invoking the tool.
+    ///
+    /// Returns the name of the tool and its parameters - e.g. to ask for a
+    /// confirmation before invoking a tool with side effects.
+    ///
+    /// # Errors
+    ///
+    /// - [`ToolUseError::InvalidInput`] if no valid invocation is found - e.g.
+    ///   the Action has an `output` field, or there are several Actions and
+    ///   `policy` is [`MultipleActionsPolicy::RejectMultiple`].
+    /// - [`ToolUseError::ToolNotFound`] if the tool is not in the toolbox.
+    pub async fn validate_invocation(
+        &self,
+        data: &str,
+        policy: MultipleActionsPolicy,
+    ) -> Result<(String, serde_yaml::Value), ToolUseError> {
+        let action = tools::parse_action(data, policy)
+            .map_err(|e| ToolUseError::InvalidInput(e.to_string()))?;
+
+        if !self.has_tool(&action.tool_name).await {
+            return Err(ToolUseError::ToolNotFound(action.tool_name));
+        }
+
+        Ok((action.tool_name, action.parameters))
+    }
+
+    /// Call `f` with the tool named `tool_name` - of any kind - if there is
+    /// one
+    async fn find_tool<R>(&self, tool_name: &str, f: impl FnOnce(&dyn Tool) -> R) -> Option<R> {
+        if let Some(tool) = self.terminal_tools.read().await.get(tool_name) {
+            return Some(f(&**tool));
+        }
+        if let Some(tool) = self.tools.read().await.get(tool_name) {
+            return Some(f(&**tool));
+        }
+        if let Some(tool) = self.advanced_tools.read().await.get(tool_name) {
+            return Some(f(&**tool));
+        }
+        None
+    }
+
+    /// The suggestion of the tool named `tool_name` after it returned
+    /// `output` - see [`Tool::hint`]
+    pub async fn hint(&self, tool_name: &str, output: &serde_yaml::Value) -> Option<String> {
+        self.find_tool(tool_name, |tool| tool.hint(output))
+            .await
+            .flatten()
+    }
+
+    /// The output of the tool named `tool_name` as it should be shown - see
+    /// [`Tool::format_result`]
+    pub async fn format_result(
+        &self,
+        tool_name: &str,
+        output: &serde_yaml::Value,
+    ) -> Option<String> {
+        self.find_tool(tool_name, |tool| tool.format_result(output))
+            .await
+            .flatten()
+    }
+
+    /// Check if a tool can be invoked concurrently with other invocations
+    ///
+    /// Only plain [`Tool`]s can be - not [`TerminalTool`]s or
+    /// [`AdvancedTool`]s.
+    pub async fn is_parallel_safe(&self, tool_name: &str) -> bool {
+        self.tools
+            .read()
+            .await
+            .get(tool_name)
+            .is_some_and(|tool| tool.is_parallel_safe())
+    }
+
+    /// The concurrency key of a tool - see [`Tool::concurrency_key`]
+    pub async fn concurrency_key(&self, tool_name: &str) -> Option<String> {
+        self.find_tool(tool_name, |tool| tool.concurrency_key())
+            .await
+            .flatten()
+    }
+
+    /// Wait for the other invocations of the tools with the same concurrency
+    /// key as `tool_name` to be done - `None` if it has none
+    async fn lock_concurrency_key(&self, tool_name: &str) -> Option<OwnedMutexGuard<()>> {
+        let key = self.concurrency_key(tool_name).await?;
+
+        let lock = self
+            .concurrency_locks
+            .write()
+            .await
+            .entry(key)
+            .or_default()
+            .clone();
+
+        Some(lock.lock_owned().await)
+    }
+
+    /// Cache the results of the [`Tool`]s that are
+    /// [`SideEffects::ReadOnly`] - by tool name and parameters.
+    ///
+    /// The other tools are always invoked - and forget the cached results as
+    /// they might have changed what the read-only tools return. The cache is
+    /// shared by the clones of the [`Toolbox`] but each task gets its own -
+    /// see [`Toolbox::for_task`].
+    #[must_use]
+    pub fn with_result_cache(mut self) -> Self {
+        self.cache = Some(Arc::default());
+        self
+    }
+
+    /// Forget the cached results - see [`Toolbox::with_result_cache`]
+    pub async fn clear_result_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.write().await.clear();
+        }
+    }
+
+    /// A clone of the [`Toolbox`] for a new task - with an empty result cache
+    /// of its own if it has one, see [`Toolbox::with_result_cache`], and an
+    /// empty stash of its own, see [`Toolbox::stash_result`]
+    #[must_use]
+    pub fn for_task(&self) -> Self {
+        Self {
+            cache: self.cache.as_ref().map(|_| Arc::default()),
+            stash: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Forget the cached results if the invoked tool is not
+    /// [`SideEffects::ReadOnly`]
+    async fn invalidate_result_cache(&self, side_effects: SideEffects) {
+        if side_effects != SideEffects::ReadOnly {
+            self.clear_result_cache().await;
+        }
+    }
+
+    /// Keep `result` aside and return the handle to [`Toolbox::fetch_result`]
+    /// it - e.g. a result too long to be shown to the model.
+    ///
+    /// The stash is shared by the clones of the [`Toolbox`] for a task - see
+    /// [`Toolbox::for_task`] - and the `SandboxedPython` tool exposes it as
+    /// `tools.fetch(handle)`. It keeps the [`MAX_STASHED_RESULTS`] most
+    /// recently stashed or fetched results.
+    #[must_use]
+    pub fn stash_result(&self, result: String) -> String {
+        let mut stash = self.stash.lock().unwrap_or_else(PoisonError::into_inner);
+        stash.next_id += 1;
+        let handle = format!("result-{}", stash.next_id);
+        stash.results.push_back((handle.clone(), result));
+        if stash.results.len() > MAX_STASHED_RESULTS {
+            stash.results.pop_front();
+        }
+        drop(stash);
+
+        handle
+    }
+
+    /// Get a result stashed with [`Toolbox::stash_result`]
+    #[must_use]
+    pub fn fetch_result(&self, handle: &str) -> Option<String> {
+        let mut stash = self.stash.lock().unwrap_or_else(PoisonError::into_inner);
+        let idx = stash.results.iter().position(|(h, _)| h == handle)?;
+        let (handle, result) = stash.results.remove(idx)?;
+        stash.results.push_back((handle, result.clone()));
+        drop(stash);
+
+        Some(result)
+    }
+
     /// Reset stats
     pub async fn reset_stats(&self) {
         *self.stats.write().await = Stats::default();
@@ -163,6 +474,10 @@ impl Toolbox {
 }
 
 /// Invoke a [`Tool`] or [`AdvancedTool`] or [`TerminalTool`] from a [`Toolbox`]
+///
+/// The invocations of the tools with the same [`Tool::concurrency_key`] are
+/// run one at a time - but an [`AdvancedTool`] holds no key while it runs,
+/// only the tools it invokes hold theirs.
 #[allow(clippy::significant_drop_tightening)]
 #[allow(clippy::significant_drop_in_scrutinee)]
 async fn invoke_from_toolbox(
@@ -173,6 +488,7 @@ async fn invoke_from_toolbox(
     // test if the tool is an advanced tool
     if let Some(tool) = toolbox.clone().advanced_tools.read().await.get(tool_name) {
         let result = tool.invoke_with_toolbox(toolbox.clone(), input).await;
+        toolbox.invalidate_result_cache(tool.side_effects()).await;
 
         if result.is_ok() {
             toolbox.report_success(tool_name).await;
@@ -183,38 +499,8 @@ async fn invoke_from_toolbox(
         return result;
     }
 
-    // if not, test if the tool is a terminal tool
-    {
-        let guard = toolbox.terminal_tools.read().await;
-        if let Some(tool) = guard.get(tool_name) {
-            let result = tool.invoke(input).await;
-            if result.is_ok() {
-                toolbox.report_success(tool_name).await;
-            } else {
-                toolbox.report_error(tool_name).await;
-            }
-
-            return result;
-        }
-    }
-
-    // otherwise, use the normal tool
-    let guard = toolbox.tools.read().await;
-    let tool = guard.get(tool_name);
-
-    if tool.is_none() {
-        toolbox.report_inexistent(tool_name).await;
-    }
-
-    let tool = tool.ok_or_else(|| ToolUseError::ToolNotFound(tool_name.to_string()))?;
-
-    let result = tool.invoke(input).await;
-    if result.is_ok() {
-        toolbox.report_success(tool_name).await;
-    } else {
-        toolbox.report_error(tool_name).await;
-    }
-    result
+    // if not, a terminal tool or a normal tool
+    invoke_simple_from_toolbox(toolbox, tool_name, input).await
 }
 
 /// Invoke a [`Tool`] or [`TerminalTool`] from a [`Toolbox`].
@@ -223,6 +509,10 @@ async fn invoke_from_toolbox(
 /// It will not invoke another [`AdvancedTool`].
 ///
 /// If you want to invoke an [`AdvancedTool`], use [`invoke_tool`].
+///
+/// The invocations of the tools with the same [`Tool::concurrency_key`] are
+/// run one at a time - the calling [`AdvancedTool`] holds none, so it can
+/// invoke the tools sharing its key.
 #[allow(clippy::significant_drop_tightening)]
 #[allow(clippy::module_name_repetitions)]
 pub async fn invoke_simple_from_toolbox(
@@ -230,11 +520,14 @@ pub async fn invoke_simple_from_toolbox(
     tool_name: &str,
     input: serde_yaml::Value,
 ) -> Result<serde_yaml::Value, ToolUseError> {
+    let _concurrency_guard = toolbox.lock_concurrency_key(tool_name).await;
+
     // test if the tool is a terminal tool
     {
         let guard = toolbox.terminal_tools.read().await;
         if let Some(tool) = guard.get(tool_name) {
             let result = tool.invoke(input).await;
+            toolbox.invalidate_result_cache(tool.side_effects()).await;
             if result.is_ok() {
                 toolbox.report_success(tool_name).await;
             } else {
@@ -246,6 +539,17 @@ pub async fn invoke_simple_from_toolbox(
     }
 
     // the normal tool only
+    invoke_plain_tool(&toolbox, tool_name, input).await
+}
+
+/// Invoke a plain [`Tool`] - from the cache of the [`Toolbox`] if it is
+/// [`SideEffects::ReadOnly`], forgetting the cached results otherwise
+#[allow(clippy::significant_drop_tightening)]
+async fn invoke_plain_tool(
+    toolbox: &Toolbox,
+    tool_name: &str,
+    input: serde_yaml::Value,
+) -> Result<serde_yaml::Value, ToolUseError> {
     let guard = toolbox.tools.read().await;
     let tool = guard.get(tool_name);
 
@@ -255,8 +559,30 @@ pub async fn invoke_simple_from_toolbox(
 
     let tool = tool.ok_or_else(|| ToolUseError::ToolNotFound(tool_name.to_string()))?;
 
+    let cache = toolbox
+        .cache
+        .as_ref()
+        .filter(|_| tool.side_effects() == SideEffects::ReadOnly);
+    let key = cache.and_then(|_| {
+        serde_yaml::to_string(&input)
+            .ok()
+            .map(|input| (tool_name.to_string(), input))
+    });
+
+    if let (Some(cache), Some(key)) = (cache, &key) {
+        if let Some(output) = cache.read().await.get(key) {
+            debug!(tool_name, "Cached result");
+            toolbox.report_success(tool_name).await;
+            return Ok(output.clone());
+        }
+    }
+
     let result = tool.invoke(input).await;
-    if result.is_ok() {
+    toolbox.invalidate_result_cache(tool.side_effects()).await;
+    if let Ok(output) = &result {
+        if let (Some(cache), Some(key)) = (cache, key) {
+            cache.write().await.insert(key, output.clone());
+        }
         toolbox.report_success(tool_name).await;
     } else {
         toolbox.report_error(tool_name).await;
@@ -283,17 +609,34 @@ pub enum InvokeResult {
     Success {
         /// The number of invocations found in the message
         invocation_count: usize,
+        /// The index of the invocation that was run among the
+        /// `invocation_count` found - the others were ignored, unless
+        /// `all_ran`
+        invocation_index: usize,
+        /// Whether all the `invocation_count` invocations were run - see
+        /// [`invoke_tools_in_parallel`]
+        all_ran: bool,
         /// The name of the tool that was invoked
         tool_name: String,
         /// The extracted input for the tool
         extracted_input: String,
-        /// The result of the invocation
+        /// The result of the invocation - as YAML
         result: String,
+        /// A suggestion of what to do next - see [`Tool::hint`]
+        hint: Option<String>,
+        /// The result as rendered by the tool to be shown to the model
+        /// instead of `result` - see [`Tool::format_result`]
+        rendered: Option<String>,
+        /// The files produced by the tool - only referenced in `result`
+        artifacts: Vec<Artifact>,
     },
     /// Error during invocation
     Error {
         /// The number of invocations found in the message
         invocation_count: usize,
+        /// The index of the invocation that was run among the
+        /// `invocation_count` found - the others were ignored
+        invocation_index: usize,
         /// The name of the tool that was invoked
         tool_name: String,
         /// The extracted input for the tool
@@ -306,19 +649,251 @@ pub enum InvokeResult {
 /// Try to find the tool invocation from the chat message and invoke the
 /// corresponding tool.
 ///
-/// If multiple tool invocations are found, only the first one is used.
-#[tracing::instrument(skip(toolbox, data))]
+/// If multiple tool invocations are found, only the first one is used - see
+/// [`invoke_tool_with_policy`] for other options.
 pub async fn invoke_tool(toolbox: Toolbox, data: &str) -> InvokeResult {
-    let tool_invocations = match tools::invocation::find_all(data) {
+    invoke_tool_with_policy(toolbox, data, MultipleActionsPolicy::default()).await
+}
+
+/// Try to find the tool invocation from the chat message and invoke the
+/// corresponding tool.
+///
+/// If multiple tool invocations are found, `policy` decides which one is used.
+pub async fn invoke_tool_with_policy(
+    toolbox: Toolbox,
+    data: &str,
+    policy: MultipleActionsPolicy,
+) -> InvokeResult {
+    invoke_found(toolbox, tools::invocation::find_all(data), policy, false).await
+}
+
+/// Invoke the tools called natively by the model - see
+/// [`crate::models::ModelResponse::tool_calls`]
+///
+/// The calls are invoked like the invocations found in a message - by
+/// [`invoke_tools_in_parallel`] if `parallel`, by [`invoke_tool_with_policy`]
+/// otherwise.
+pub async fn invoke_tool_calls(
+    toolbox: Toolbox,
+    tool_calls: &[ToolCall],
+    policy: MultipleActionsPolicy,
+    parallel: bool,
+) -> InvokeResult {
+    if tool_calls.is_empty() {
+        return InvokeResult::NoInvocationsFound {
+            e: Error::NoInvocationFound,
+        };
+    }
+
+    invoke_found(
+        toolbox,
+        Ok(tools::tool_call_invocations(tool_calls)),
+        policy,
+        parallel,
+    )
+    .await
+}
+
+/// Try to find the tool invocations from the chat message and invoke the
+/// corresponding tools.
+///
+/// If multiple tool invocations are found and all the tools are
+/// parallel-safe (see [`Tool::is_parallel_safe`]), they are all invoked
+/// concurrently and their results are returned together. Otherwise, behaves
+/// like [`invoke_tool_with_policy`].
+pub async fn invoke_tools_in_parallel(
+    toolbox: Toolbox,
+    data: &str,
+    policy: MultipleActionsPolicy,
+) -> InvokeResult {
+    invoke_found(toolbox, tools::invocation::find_all(data), policy, true).await
+}
+
+/// Invoke the `tool_invocations` found in a message - like
+/// [`invoke_tools_in_parallel`] if `parallel`, like
+/// [`invoke_tool_with_policy`] otherwise
+#[tracing::instrument(name = "invoke_tool", skip(toolbox, tool_invocations))]
+pub(crate) async fn invoke_found(
+    toolbox: Toolbox,
+    tool_invocations: Result<ExtractedInvocations, Error>,
+    policy: MultipleActionsPolicy,
+    parallel: bool,
+) -> InvokeResult {
+    let tool_invocations = match tool_invocations {
         Ok(invocations) => invocations,
         Err(e) => return InvokeResult::NoInvocationsFound { e },
     };
-    let invocation_count = tool_invocations.invocations.len();
     info!(
         "{} YAML blocks and {} Tool invocations found",
-        tool_invocations.yaml_block_count, invocation_count
+        tool_invocations.yaml_block_count,
+        tool_invocations.invocations.len()
     );
 
+    if parallel {
+        invoke_all(toolbox, tool_invocations, policy).await
+    } else {
+        invoke_chosen(toolbox, tool_invocations, policy).await
+    }
+}
+
+/// Invoke all the `tool_invocations` concurrently if they are parallel-safe -
+/// see [`invoke_tools_in_parallel`]
+///
+/// [`MultipleActionsPolicy::RejectMultiple`] rejects several invocations
+/// whether they are parallel-safe or not.
+async fn invoke_all(
+    toolbox: Toolbox,
+    tool_invocations: ExtractedInvocations,
+    policy: MultipleActionsPolicy,
+) -> InvokeResult {
+    let invocation_count = tool_invocations.invocations.len();
+
+    if invocation_count < 2 || policy == MultipleActionsPolicy::RejectMultiple {
+        return invoke_chosen(toolbox, tool_invocations, policy).await;
+    }
+
+    for invocation in &tool_invocations.invocations {
+        if !toolbox.is_parallel_safe(&invocation.tool_name).await {
+            debug!(
+                tool_name = invocation.tool_name,
+                ?policy,
+                "Not parallel-safe - only one invocation is considered"
+            );
+            return invoke_chosen(toolbox, tool_invocations, policy).await;
+        }
+    }
+
+    let invocations = tool_invocations.invocations;
+
+    let tool_name = invocations
+        .iter()
+        .map(|invocation| invocation.tool_name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let extracted_input = serde_yaml::to_string(
+        &invocations
+            .iter()
+            .map(|invocation| ParallelInvocation {
+                tool_name: invocation.tool_name.clone(),
+                parameters: invocation.parameters.clone(),
+            })
+            .collect::<Vec<_>>(),
+    )
+    .unwrap_or_else(|_| format!("Failed to serialize input for tools {tool_name}"));
+
+    let results = futures::future::join_all(invocations.into_iter().map(|invocation| {
+        let toolbox = toolbox.clone();
+        async move {
+            let result = invoke_from_toolbox(
+                toolbox.clone(),
+                &invocation.tool_name,
+                invocation.parameters,
+            )
+            .await;
+            match result {
+                Ok(mut output) => {
+                    let artifacts = take_artifacts(&mut output);
+                    let hint = toolbox
+                        .hint(&invocation.tool_name, &output)
+                        .await
+                        .map(|hint| format!("{}: {hint}", invocation.tool_name));
+                    let result = ParallelInvocationResult {
+                        tool_name: invocation.tool_name,
+                        result: Some(output),
+                        error: None,
+                    };
+                    (result, artifacts, hint)
+                }
+                Err(e) => {
+                    let result = ParallelInvocationResult {
+                        tool_name: invocation.tool_name,
+                        result: None,
+                        error: Some(e.to_string()),
+                    };
+                    (result, vec![], None)
+                }
+            }
+        }
+    }))
+    .await;
+
+    let mut outputs = Vec::with_capacity(results.len());
+    let mut artifacts = vec![];
+    let mut hints: Vec<String> = vec![];
+    for (result, more_artifacts, hint) in results {
+        outputs.push(result);
+        artifacts.extend(more_artifacts);
+        // the same tool invoked several times suggests the same thing
+        if let Some(hint) = hint.filter(|hint| !hints.contains(hint)) {
+            hints.push(hint);
+        }
+    }
+
+    let result = serde_yaml::to_string(&outputs)
+        .unwrap_or_else(|_| format!("Failed to serialize output for tools {tool_name}"));
+
+    InvokeResult::Success {
+        invocation_count,
+        invocation_index: 0,
+        all_ran: true,
+        tool_name,
+        extracted_input,
+        result,
+        hint: (!hints.is_empty()).then(|| hints.join("\n")),
+        rendered: None,
+        artifacts,
+    }
+}
+
+/// Take the [`Artifact`]s out of the `output` of a tool - they are replaced by
+/// their references, see [`Artifact::reference`]
+///
+/// `output` is left as is if its [`ARTIFACTS_FIELD`] does not hold
+/// [`Artifact`]s.
+fn take_artifacts(output: &mut serde_yaml::Value) -> Vec<Artifact> {
+    let Some(field) = output.get_mut(ARTIFACTS_FIELD) else {
+        return vec![];
+    };
+    let Ok(artifacts) = serde_yaml::from_value::<Vec<Artifact>>(field.clone()) else {
+        return vec![];
+    };
+
+    *field = serde_yaml::Value::Sequence(
+        artifacts
+            .iter()
+            .map(|artifact| serde_yaml::Value::String(artifact.reference()))
+            .collect(),
+    );
+
+    artifacts
+}
+
+/// One of the invocations run by [`invoke_tools_in_parallel`]
+#[derive(Serialize)]
+struct ParallelInvocation {
+    tool_name: String,
+    parameters: serde_yaml::Value,
+}
+
+/// The outcome of one of the invocations run by [`invoke_tools_in_parallel`]
+#[derive(Serialize)]
+struct ParallelInvocationResult {
+    tool_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<serde_yaml::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Invoke the extracted invocation chosen by `policy`
+async fn invoke_chosen(
+    toolbox: Toolbox,
+    tool_invocations: ExtractedInvocations,
+    policy: MultipleActionsPolicy,
+) -> InvokeResult {
+    let invocation_count = tool_invocations.invocations.len();
+
     // FUTURE(ssoudan) feature to control this
     // if more than one tool_invocations are found, we return an error
     // if tool_invocations.len() > 1 {
@@ -328,8 +903,8 @@ pub async fn invoke_tool(toolbox: Toolbox, data: &str) -> InvokeResult {
     // FUTURE(ssoudan) invoke corresponding tools one by one. Fail on first error.
     // FUTURE(ssoudan) document this in the initial prompt
 
-    let invocation = match tools::choose_invocation(tool_invocations) {
-        Ok(invocation) => invocation,
+    let (invocation_index, invocation) = match tools::choose_invocation(tool_invocations, policy) {
+        Ok(chosen) => chosen,
         Err(e) => {
             return InvokeResult::NoValidInvocationsFound {
                 e,
@@ -339,7 +914,10 @@ pub async fn invoke_tool(toolbox: Toolbox, data: &str) -> InvokeResult {
     };
 
     // We found an invocation, let's invoke the tool
-    debug!(tool_name = invocation.tool_name, "Invocation found");
+    debug!(
+        tool_name = invocation.tool_name,
+        invocation_index, "Invocation found"
+    );
 
     let tool_name = invocation.tool_name.clone();
     let input = invocation.parameters;
@@ -351,29 +929,943 @@ pub async fn invoke_tool(toolbox: Toolbox, data: &str) -> InvokeResult {
         )
     });
 
-    let result = invoke_from_toolbox(toolbox, &tool_name, input.clone()).await;
+    let result = invoke_from_toolbox(toolbox.clone(), &tool_name, input.clone()).await;
 
     match result {
-        Ok(output) => {
+        Ok(mut output) => {
+            let artifacts = take_artifacts(&mut output);
+            let rendered = toolbox.format_result(&tool_name, &output).await;
             let result = serde_yaml::to_string(&output).unwrap_or_else(|_| {
                 format!(
                     "Failed to serialize output for tool {}",
                     invocation.tool_name
                 )
             });
+            let hint = toolbox.hint(&tool_name, &output).await;
 
             InvokeResult::Success {
                 tool_name,
                 extracted_input,
                 invocation_count,
+                invocation_index,
+                all_ran: invocation_count == 1,
                 result,
+                hint,
+                rendered,
+                artifacts,
             }
         }
         Err(e) => InvokeResult::Error {
             tool_name,
             extracted_input,
             invocation_count,
+            invocation_index,
             e,
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use indoc::indoc;
+
+    use super::*;
+    use crate::tools::Format;
+
+    struct ConstantTool {
+        name: &'static str,
+        value: &'static str,
+        parallel_safe: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl Tool for ConstantTool {
+        fn description(&self) -> ToolDescription {
+            ToolDescription::new(
+                self.name,
+                "Returns a constant",
+                Format::default(),
+                Format::default(),
+            )
+        }
+
+        fn is_parallel_safe(&self) -> bool {
+            self.parallel_safe
+        }
+
+        fn hint(&self, _output: &serde_yaml::Value) -> Option<String> {
+            Some(format!("{} never changes.", self.value))
+        }
+
+        async fn invoke(
+            &self,
+            _input: serde_yaml::Value,
+        ) -> Result<serde_yaml::Value, ToolUseError> {
+            Ok(serde_yaml::Value::String(self.value.to_string()))
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl AdvancedTool for ConstantTool {
+        async fn invoke_with_toolbox(
+            &self,
+            _toolbox: Toolbox,
+            input: serde_yaml::Value,
+        ) -> Result<serde_yaml::Value, ToolUseError> {
+            self.invoke(input).await
+        }
+    }
+
+    /// A tool that counts its invocations
+    struct CountingTool {
+        name: &'static str,
+        side_effects: SideEffects,
+        count: Arc<AtomicUsize>,
+    }
+
+    impl CountingTool {
+        fn new(name: &'static str, side_effects: SideEffects) -> Self {
+            Self {
+                name,
+                side_effects,
+                count: Arc::default(),
+            }
+        }
+
+        fn count(&self) -> Arc<AtomicUsize> {
+            self.count.clone()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Tool for CountingTool {
+        fn description(&self) -> ToolDescription {
+            ToolDescription::new(
+                self.name,
+                "Counts its invocations",
+                Format::default(),
+                Format::default(),
+            )
+        }
+
+        fn side_effects(&self) -> SideEffects {
+            self.side_effects
+        }
+
+        async fn invoke(
+            &self,
+            _input: serde_yaml::Value,
+        ) -> Result<serde_yaml::Value, ToolUseError> {
+            let count = self.count.fetch_add(1, Ordering::SeqCst);
+            Ok(serde_yaml::Value::from(count + 1))
+        }
+    }
+
+    fn action(tool_name: &str, filter: &str) -> String {
+        format!("```yaml\ntool_name: {tool_name}\nparameters:\n  filter: {filter}\n```\n")
+    }
+
+    #[tokio::test]
+    async fn caches_the_results_of_read_only_tools() {
+        let read_only = CountingTool::new("Status", SideEffects::ReadOnly);
+        let read_only_count = read_only.count();
+        let mutating = CountingTool::new("Switch", SideEffects::Mutating);
+        let mutating_count = mutating.count();
+
+        let toolbox = Toolbox::builder()
+            .with_tool(read_only)
+            .with_tool(mutating)
+            .with_result_cache()
+            .build();
+
+        for _ in 0..2 {
+            let res = invoke_tool(toolbox.clone(), &action("Status", "all")).await;
+            let InvokeResult::Success { result, .. } = res else {
+                panic!("unexpected result: {res:?}");
+            };
+            assert_eq!(result, "1\n");
+        }
+        assert_eq!(read_only_count.load(Ordering::SeqCst), 1);
+        assert_eq!(toolbox.stats().await.success_count["Status"], 2);
+
+        // other parameters are not served from the cache
+        invoke_tool(toolbox.clone(), &action("Status", "kitchen")).await;
+        assert_eq!(read_only_count.load(Ordering::SeqCst), 2);
+
+        // neither are the tools with side effects
+        for _ in 0..2 {
+            invoke_tool(toolbox.clone(), &action("Switch", "all")).await;
+        }
+        assert_eq!(mutating_count.load(Ordering::SeqCst), 2);
+
+        toolbox.clear_result_cache().await;
+        invoke_tool(toolbox.clone(), &action("Status", "all")).await;
+        assert_eq!(read_only_count.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn forgets_the_cached_results_after_a_tool_with_side_effects() {
+        let read_only = CountingTool::new("Status", SideEffects::ReadOnly);
+        let read_only_count = read_only.count();
+
+        let toolbox = Toolbox::builder()
+            .with_tool(read_only)
+            .with_tool(CountingTool::new("Switch", SideEffects::Mutating))
+            .with_result_cache()
+            .build();
+
+        invoke_tool(toolbox.clone(), &action("Status", "all")).await;
+        invoke_tool(toolbox.clone(), &action("Switch", "all")).await;
+
+        let res = invoke_tool(toolbox.clone(), &action("Status", "all")).await;
+        let InvokeResult::Success { result, .. } = res else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert_eq!(result, "2\n");
+        assert_eq!(read_only_count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn does_not_share_the_cached_results_between_tasks() {
+        let read_only = CountingTool::new("Status", SideEffects::ReadOnly);
+        let count = read_only.count();
+
+        let toolbox = Toolbox::builder()
+            .with_tool(read_only)
+            .with_result_cache()
+            .build();
+
+        invoke_tool(toolbox.for_task(), &action("Status", "all")).await;
+        invoke_tool(toolbox.for_task(), &action("Status", "all")).await;
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn keeps_the_most_recently_used_stashed_results() {
+        let toolbox = Toolbox::default();
+
+        let first = toolbox.stash_result("first".to_string());
+        let second = toolbox.stash_result("second".to_string());
+        for i in 2..MAX_STASHED_RESULTS {
+            let _ = toolbox.stash_result(i.to_string());
+        }
+        // the first one is now the most recently used
+        assert_eq!(toolbox.fetch_result(&first).unwrap(), "first");
+
+        let _ = toolbox.stash_result("last".to_string());
+        assert_eq!(toolbox.fetch_result(&first).unwrap(), "first");
+        assert!(toolbox.fetch_result(&second).is_none());
+
+        // shared by the clones - not by the toolboxes of the other tasks
+        let clone = toolbox.clone();
+        let handle = clone.stash_result("shared".to_string());
+        assert_eq!(toolbox.fetch_result(&handle).unwrap(), "shared");
+        assert!(toolbox.for_task().fetch_result(&handle).is_none());
+    }
+
+    #[tokio::test]
+    async fn does_not_cache_by_default() {
+        let read_only = CountingTool::new("Status", SideEffects::ReadOnly);
+        let count = read_only.count();
+
+        let toolbox = Toolbox::builder().with_tool(read_only).build();
+
+        for _ in 0..2 {
+            invoke_tool(toolbox.clone(), &action("Status", "all")).await;
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn reports_tools_with_the_same_name() {
+        let toolbox = Toolbox::default();
+        for name in ["Same", "Other"] {
+            toolbox
+                .add_tool(ConstantTool {
+                    name,
+                    value: "a tool",
+                    parallel_safe: false,
+                })
+                .await;
+        }
+        assert!(toolbox.duplicate_tool_names().await.is_empty());
+
+        toolbox
+            .add_advanced_tool(ConstantTool {
+                name: "Same",
+                value: "an advanced tool",
+                parallel_safe: false,
+            })
+            .await;
+
+        assert_eq!(toolbox.duplicate_tool_names().await, vec!["Same"]);
+    }
+
+    #[tokio::test]
+    async fn builds_a_toolbox() {
+        let toolbox = Toolbox::builder()
+            .with_tool(ConstantTool {
+                name: "Constant",
+                value: "42",
+                parallel_safe: true,
+            })
+            .with_tool(ConstantTool {
+                name: "Another",
+                value: "43",
+                parallel_safe: false,
+            })
+            .build();
+
+        let names = toolbox.describe().await.into_keys().collect::<Vec<_>>();
+        assert_eq!(names, vec!["Another", "Constant"]);
+        assert!(toolbox.is_parallel_safe("Constant").await);
+        assert!(!toolbox.has_terminal_tools().await);
+    }
+
+    #[tokio::test]
+    async fn describes_the_tools_in_a_stable_order() {
+        let toolbox = Toolbox::default();
+        for name in ["Zebra", "Alpaca", "Moose", "Beaver"] {
+            toolbox
+                .add_tool(ConstantTool {
+                    name,
+                    value: "value",
+                    parallel_safe: false,
+                })
+                .await;
+        }
+
+        let names = |descriptions: BTreeMap<String, ToolDescription>| {
+            descriptions
+                .into_values()
+                .map(|d| d.name)
+                .collect::<Vec<_>>()
+        };
+
+        let first = names(toolbox.describe().await);
+        let second = names(toolbox.describe().await);
+
+        assert_eq!(first, vec!["Alpaca", "Beaver", "Moose", "Zebra"]);
+        assert_eq!(first, second);
+    }
+
+    fn constant_tool() -> ConstantTool {
+        ConstantTool {
+            name: "Constant",
+            value: "value",
+            parallel_safe: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn validates_an_invocation_without_invoking_it() {
+        let toolbox = Toolbox::default();
+        toolbox.add_tool(constant_tool()).await;
+
+        let data = indoc! {r"
+        ```yaml
+        tool_name: Constant
+        parameters:
+          q: something
+        ```
+        "};
+
+        let (tool_name, parameters) = toolbox
+            .validate_invocation(data, MultipleActionsPolicy::default())
+            .await
+            .unwrap();
+
+        assert_eq!(tool_name, "Constant");
+        assert_eq!(parameters["q"], "something");
+        assert!(toolbox.stats().await.success_count.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_an_invocation_of_an_unknown_tool() {
+        let toolbox = Toolbox::default();
+        toolbox.add_tool(constant_tool()).await;
+
+        let data = indoc! {r"
+        ```yaml
+        tool_name: Unknown
+        parameters: {}
+        ```
+        "};
+
+        let e = toolbox
+            .validate_invocation(data, MultipleActionsPolicy::default())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(e, ToolUseError::ToolNotFound(name) if name == "Unknown"));
+    }
+
+    #[tokio::test]
+    async fn rejects_an_invocation_with_an_output() {
+        let toolbox = Toolbox::default();
+        toolbox.add_tool(constant_tool()).await;
+
+        let data = indoc! {r"
+        ```yaml
+        tool_name: Constant
+        parameters: {}
+        output: value
+        ```
+        "};
+
+        let e = toolbox
+            .validate_invocation(data, MultipleActionsPolicy::default())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(e, ToolUseError::InvalidInput(msg) if msg.contains("output")));
+    }
+
+    const TWO_ACTIONS: &str = indoc! {r"
+    ```yaml
+    - tool_name: Left
+      parameters: {}
+    - tool_name: Right
+      parameters: {}
+    ```
+    "};
+
+    #[tokio::test]
+    async fn validates_several_invocations_with_the_policy() {
+        let toolbox = Toolbox::default();
+        for (name, value) in [("Left", "left value"), ("Right", "right value")] {
+            toolbox
+                .add_tool(ConstantTool {
+                    name,
+                    value,
+                    parallel_safe: false,
+                })
+                .await;
+        }
+
+        let (tool_name, _) = toolbox
+            .validate_invocation(TWO_ACTIONS, MultipleActionsPolicy::TakeLast)
+            .await
+            .unwrap();
+        assert_eq!(tool_name, "Right");
+
+        let e = toolbox
+            .validate_invocation(TWO_ACTIONS, MultipleActionsPolicy::RejectMultiple)
+            .await
+            .unwrap_err();
+        assert!(matches!(e, ToolUseError::InvalidInput(_)), "{e:?}");
+    }
+
+    #[tokio::test]
+    async fn invokes_parallel_safe_tools_concurrently() {
+        let toolbox = Toolbox::default();
+        for (name, value) in [("Left", "left value"), ("Right", "right value")] {
+            toolbox
+                .add_tool(ConstantTool {
+                    name,
+                    value,
+                    parallel_safe: true,
+                })
+                .await;
+        }
+
+        let res = invoke_tools_in_parallel(
+            toolbox.clone(),
+            TWO_ACTIONS,
+            MultipleActionsPolicy::default(),
+        )
+        .await;
+
+        let InvokeResult::Success {
+            invocation_count,
+            all_ran,
+            tool_name,
+            result,
+            hint,
+            ..
+        } = res
+        else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert_eq!(invocation_count, 2);
+        assert!(all_ran);
+        assert_eq!(
+            hint.as_deref(),
+            Some("Left: left value never changes.\nRight: right value never changes.")
+        );
+        assert_eq!(tool_name, "Left, Right");
+        assert_eq!(
+            result,
+            indoc! {"
+            - tool_name: Left
+              result: left value
+            - tool_name: Right
+              result: right value
+            "}
+        );
+
+        let stats = toolbox.stats().await;
+        assert_eq!(stats.success_count.get("Left"), Some(&1));
+        assert_eq!(stats.success_count.get("Right"), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn rejects_the_parallel_invocations_if_asked_to() {
+        let toolbox = Toolbox::default();
+        for (name, value) in [("Left", "left value"), ("Right", "right value")] {
+            toolbox
+                .add_tool(ConstantTool {
+                    name,
+                    value,
+                    parallel_safe: true,
+                })
+                .await;
+        }
+
+        let res = invoke_tools_in_parallel(
+            toolbox.clone(),
+            TWO_ACTIONS,
+            MultipleActionsPolicy::RejectMultiple,
+        )
+        .await;
+
+        let InvokeResult::NoValidInvocationsFound {
+            e,
+            invocation_count,
+        } = res
+        else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert!(matches!(e, Error::TooManyInvocations(2)));
+        assert_eq!(invocation_count, 2);
+        assert!(toolbox.stats().await.success_count.is_empty());
+    }
+
+    #[tokio::test]
+    async fn invokes_only_the_first_tool_if_not_parallel_safe() {
+        let toolbox = Toolbox::default();
+        for (name, value, parallel_safe) in [
+            ("Left", "left value", true),
+            ("Right", "right value", false),
+        ] {
+            toolbox
+                .add_tool(ConstantTool {
+                    name,
+                    value,
+                    parallel_safe,
+                })
+                .await;
+        }
+
+        let res =
+            invoke_tools_in_parallel(toolbox, TWO_ACTIONS, MultipleActionsPolicy::default()).await;
+
+        let InvokeResult::Success {
+            all_ran,
+            tool_name,
+            result,
+            ..
+        } = res
+        else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert!(!all_ran);
+        assert_eq!(tool_name, "Left");
+        assert_eq!(result, "left value\n");
+    }
+
+    async fn two_tools() -> Toolbox {
+        let toolbox = Toolbox::default();
+        for (name, value) in [("Left", "left value"), ("Right", "right value")] {
+            toolbox
+                .add_tool(ConstantTool {
+                    name,
+                    value,
+                    parallel_safe: false,
+                })
+                .await;
+        }
+        toolbox
+    }
+
+    #[tokio::test]
+    async fn takes_the_first_action() {
+        let res = invoke_tool_with_policy(
+            two_tools().await,
+            TWO_ACTIONS,
+            MultipleActionsPolicy::TakeFirst,
+        )
+        .await;
+
+        let InvokeResult::Success {
+            invocation_count,
+            tool_name,
+            ..
+        } = res
+        else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert_eq!(invocation_count, 2);
+        assert_eq!(tool_name, "Left");
+    }
+
+    #[tokio::test]
+    async fn takes_the_last_action() {
+        let res = invoke_tool_with_policy(
+            two_tools().await,
+            TWO_ACTIONS,
+            MultipleActionsPolicy::TakeLast,
+        )
+        .await;
+
+        let InvokeResult::Success {
+            invocation_count,
+            tool_name,
+            ..
+        } = res
+        else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert_eq!(invocation_count, 2);
+        assert_eq!(tool_name, "Right");
+    }
+
+    #[tokio::test]
+    async fn reports_the_chosen_action() {
+        let three_actions = indoc! {r"
+        ```yaml
+        - tool_name: Left
+          parameters: {}
+        - tool_name: Right
+          parameters: {}
+        - tool_name: Left
+          parameters: {}
+        ```
+        "};
+
+        for (policy, expected_index) in [
+            (MultipleActionsPolicy::TakeFirst, 0),
+            (MultipleActionsPolicy::TakeLast, 2),
+        ] {
+            let res = invoke_tool_with_policy(two_tools().await, three_actions, policy).await;
+
+            let InvokeResult::Success {
+                invocation_count,
+                invocation_index,
+                ..
+            } = res
+            else {
+                panic!("unexpected result: {res:?}");
+            };
+            assert_eq!(invocation_count, 3);
+            assert_eq!(invocation_index, expected_index, "{policy:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_multiple_actions() {
+        let toolbox = two_tools().await;
+        let res = invoke_tool_with_policy(
+            toolbox.clone(),
+            TWO_ACTIONS,
+            MultipleActionsPolicy::RejectMultiple,
+        )
+        .await;
+
+        let InvokeResult::NoValidInvocationsFound {
+            e,
+            invocation_count,
+        } = res
+        else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert_eq!(invocation_count, 2);
+        assert!(matches!(e, Error::TooManyInvocations(2)));
+        assert!(toolbox.stats().await.success_count.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_the_syntax_error_of_a_malformed_action() {
+        let toolbox = two_tools().await;
+        let data = indoc! {"
+            ```yaml
+            tool_name: Left
+            parameters: {a: 1
+            ```
+        "};
+
+        let res = invoke_tool(toolbox.clone(), data).await;
+
+        let InvokeResult::NoInvocationsFound { e } = res else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert!(matches!(e, Error::InvalidYaml(_)));
+        let msg = e.to_string();
+        assert!(msg.starts_with("The Action is not valid YAML: "));
+        assert!(msg.contains("line 2"), "{msg}");
+        assert!(toolbox.stats().await.success_count.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_a_message_without_action() {
+        let toolbox = two_tools().await;
+        let data = "## Observations:\n- Nothing to do here.\n";
+
+        let res = invoke_tool(toolbox, data).await;
+
+        let InvokeResult::NoInvocationsFound { e } = res else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert!(matches!(e, Error::NoInvocationFound));
+        assert!(e.to_string().starts_with("No Action found. "));
+    }
+
+    /// A tool that takes some time and records how many of its invocations
+    /// overlap at most
+    struct BusyTool {
+        name: &'static str,
+        concurrency_key: Option<&'static str>,
+        running: Arc<AtomicUsize>,
+        max_running: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl Tool for BusyTool {
+        fn description(&self) -> ToolDescription {
+            ToolDescription::new(
+                self.name,
+                "Keeps busy for a while",
+                Format::default(),
+                Format::default(),
+            )
+        }
+
+        fn concurrency_key(&self) -> Option<String> {
+            self.concurrency_key.map(ToString::to_string)
+        }
+
+        async fn invoke(
+            &self,
+            _input: serde_yaml::Value,
+        ) -> Result<serde_yaml::Value, ToolUseError> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+
+            Ok(serde_yaml::Value::Null)
+        }
+    }
+
+    /// Invoke `Busy` and `AlsoBusy` concurrently - how many invocations
+    /// overlapped at most
+    async fn max_overlap(
+        concurrency_key: Option<&'static str>,
+        other_concurrency_key: Option<&'static str>,
+    ) -> usize {
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+
+        let toolbox = Toolbox::builder()
+            .with_tool(BusyTool {
+                name: "Busy",
+                concurrency_key,
+                running: running.clone(),
+                max_running: max_running.clone(),
+            })
+            .with_tool(BusyTool {
+                name: "AlsoBusy",
+                concurrency_key: other_concurrency_key,
+                running,
+                max_running: max_running.clone(),
+            })
+            .build();
+
+        let (left, right) = tokio::join!(
+            invoke_from_toolbox(toolbox.clone(), "Busy", serde_yaml::Value::Null),
+            invoke_from_toolbox(toolbox.clone(), "AlsoBusy", serde_yaml::Value::Null),
+        );
+        left.unwrap();
+        right.unwrap();
+
+        max_running.load(Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn serializes_the_invocations_sharing_a_concurrency_key() {
+        assert_eq!(max_overlap(Some("bridge"), Some("bridge")).await, 1);
+
+        let toolbox = Toolbox::builder()
+            .with_tool(BusyTool {
+                name: "Busy",
+                concurrency_key: Some("bridge"),
+                running: Arc::default(),
+                max_running: Arc::default(),
+            })
+            .build();
+        assert_eq!(
+            toolbox.concurrency_key("Busy").await.as_deref(),
+            Some("bridge")
+        );
+        assert_eq!(toolbox.concurrency_key("Missing").await, None);
+    }
+
+    #[tokio::test]
+    async fn runs_the_other_invocations_concurrently() {
+        assert_eq!(max_overlap(None, None).await, 2);
+        assert_eq!(max_overlap(Some("bridge"), Some("other")).await, 2);
+        assert_eq!(max_overlap(Some("bridge"), None).await, 2);
+    }
+
+    /// A tool that invokes `Busy` - sharing its concurrency key
+    struct NestingTool {}
+
+    #[async_trait::async_trait]
+    impl Tool for NestingTool {
+        fn description(&self) -> ToolDescription {
+            ToolDescription::new(
+                "Nesting",
+                "Invokes Busy",
+                Format::default(),
+                Format::default(),
+            )
+        }
+
+        fn concurrency_key(&self) -> Option<String> {
+            Some("bridge".to_string())
+        }
+
+        async fn invoke(
+            &self,
+            _input: serde_yaml::Value,
+        ) -> Result<serde_yaml::Value, ToolUseError> {
+            unimplemented!()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl AdvancedTool for NestingTool {
+        async fn invoke_with_toolbox(
+            &self,
+            toolbox: Toolbox,
+            input: serde_yaml::Value,
+        ) -> Result<serde_yaml::Value, ToolUseError> {
+            invoke_simple_from_toolbox(toolbox, "Busy", input).await
+        }
+    }
+
+    #[tokio::test]
+    async fn serializes_the_nested_invocations_sharing_a_concurrency_key() {
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let running = Arc::new(AtomicUsize::new(0));
+
+        let toolbox = Toolbox::builder()
+            .with_advanced_tool(NestingTool {})
+            .with_tool(BusyTool {
+                name: "Busy",
+                concurrency_key: Some("bridge"),
+                running: running.clone(),
+                max_running: max_running.clone(),
+            })
+            .with_tool(BusyTool {
+                name: "AlsoBusy",
+                concurrency_key: Some("bridge"),
+                running,
+                max_running: max_running.clone(),
+            })
+            .build();
+
+        // the nested invocation does not wait for the key of `Nesting`...
+        let (nested, other) = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            tokio::join!(
+                invoke_from_toolbox(toolbox.clone(), "Nesting", serde_yaml::Value::Null),
+                invoke_from_toolbox(toolbox.clone(), "AlsoBusy", serde_yaml::Value::Null),
+            )
+        })
+        .await
+        .expect("deadlock");
+        nested.unwrap();
+        other.unwrap();
+
+        // ... but for the other invocations with its key
+        assert_eq!(max_running.load(Ordering::SeqCst), 1);
+    }
+
+    /// A tool that draws a chart
+    struct ChartTool {}
+
+    #[async_trait::async_trait]
+    impl Tool for ChartTool {
+        fn description(&self) -> ToolDescription {
+            ToolDescription::new(
+                "Chart",
+                "Draws a chart",
+                Format::default(),
+                Format::default(),
+            )
+        }
+
+        async fn invoke(
+            &self,
+            _input: serde_yaml::Value,
+        ) -> Result<serde_yaml::Value, ToolUseError> {
+            let mut output = serde_yaml::Mapping::new();
+            output.insert("title".into(), "Moons of Mars".into());
+            output.insert(
+                ARTIFACTS_FIELD.into(),
+                serde_yaml::to_value(vec![Artifact {
+                    name: "moons.png".to_string(),
+                    mime: "image/png".to_string(),
+                    bytes: vec![0x89, b'P', b'N', b'G'],
+                }])
+                .unwrap(),
+            );
+
+            Ok(output.into())
+        }
+    }
+
+    #[tokio::test]
+    async fn separates_the_artifacts_from_the_result() {
+        let toolbox = Toolbox::builder().with_tool(ChartTool {}).build();
+        let data = indoc! {"
+            ```yaml
+            tool_name: Chart
+            parameters: {}
+            ```
+        "};
+
+        let res = invoke_tool(toolbox, data).await;
+
+        let InvokeResult::Success {
+            result, artifacts, ..
+        } = res
+        else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert_eq!(
+            result,
+            "title: Moons of Mars\nartifacts:\n- moons.png (image/png, 4 bytes)\n"
+        );
+        assert_eq!(
+            artifacts,
+            vec![Artifact {
+                name: "moons.png".to_string(),
+                mime: "image/png".to_string(),
+                bytes: vec![0x89, b'P', b'N', b'G'],
+            }]
+        );
+    }
+
+    #[test]
+    fn leaves_an_unrelated_artifacts_field_alone() {
+        let mut output: serde_yaml::Value =
+            serde_yaml::from_str("artifacts: [a, b]\ncount: 2\n").unwrap();
+        let expected = output.clone();
+
+        assert!(take_artifacts(&mut output).is_empty());
+        assert_eq!(output, expected);
+    }
+}
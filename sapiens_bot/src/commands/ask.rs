@@ -0,0 +1,59 @@
+use serenity::all::{CommandDataOption, CommandOptionType, CreateCommand, CreateCommandOption};
+
+/// The name of the option with the question
+const QUESTION: &str = "question";
+
+/// The question asked - if any
+pub(crate) fn question(options: &[CommandDataOption]) -> Option<String> {
+    options
+        .iter()
+        .find(|o| o.name == QUESTION)
+        .and_then(|o| o.value.as_str())
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .map(ToString::to_string)
+}
+
+pub(crate) fn register() -> CreateCommand {
+    CreateCommand::new("ask")
+        .description("Ask Sapiens to carry out a task")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, QUESTION, "The task to carry out")
+                .required(true),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn registers_the_question_option() {
+        let command = serde_json::to_value(register()).unwrap();
+
+        assert_eq!(command["name"], "ask");
+        assert_eq!(command["options"][0]["name"], QUESTION);
+        assert_eq!(command["options"][0]["type"], 3);
+        assert_eq!(command["options"][0]["required"], true);
+    }
+
+    #[test]
+    fn parses_the_question() {
+        let options: Vec<CommandDataOption> = serde_json::from_value(json!([
+            {"name": "question", "type": 3, "value": "  Tell me a joke.  "}
+        ]))
+        .unwrap();
+
+        assert_eq!(question(&options), Some("Tell me a joke.".to_string()));
+
+        let options: Vec<CommandDataOption> = serde_json::from_value(json!([
+            {"name": "question", "type": 3, "value": " "}
+        ]))
+        .unwrap();
+
+        assert_eq!(question(&options), None);
+        assert_eq!(question(&[]), None);
+    }
+}
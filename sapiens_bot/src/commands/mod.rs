@@ -1 +1,3 @@
+pub(crate) mod ask;
 pub(crate) mod ping;
+pub(crate) mod stop;
@@ -0,0 +1,5 @@
+use serenity::all::CreateCommand;
+
+pub(crate) fn register() -> CreateCommand {
+    CreateCommand::new("stop").description("Stop the task of this thread")
+}
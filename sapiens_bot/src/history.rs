@@ -0,0 +1,100 @@
+use sapiens::context::ChatEntry;
+use sapiens::models::Role;
+use serenity::model::channel::Message;
+
+/// Maximum number of prior messages fetched from a channel - the most Discord
+/// returns in a single request
+pub(crate) const MAX_HISTORY: u8 = 100;
+
+/// A prior message of a channel
+#[derive(Debug, Clone)]
+pub(crate) struct PriorMessage {
+    /// Whether the author is a bot
+    pub(crate) from_bot: bool,
+    /// The content of the message
+    pub(crate) content: String,
+}
+
+impl From<&Message> for PriorMessage {
+    fn from(msg: &Message) -> Self {
+        Self {
+            from_bot: msg.author.bot,
+            content: msg.content.clone(),
+        }
+    }
+}
+
+/// Convert the prior messages of a channel - newest first, as returned by
+/// Discord - to chat entries in chronological order
+///
+/// Messages from bots become [`Role::Assistant`] entries, the others
+/// [`Role::User`] ones. Only the [`MAX_HISTORY`] most recent messages are
+/// kept and empty ones are skipped.
+pub(crate) fn to_chat_entries(messages: impl IntoIterator<Item = PriorMessage>) -> Vec<ChatEntry> {
+    let mut entries = messages
+        .into_iter()
+        .take(MAX_HISTORY as usize)
+        .filter(|m| !m.content.trim().is_empty())
+        .map(|m| ChatEntry {
+            role: if m.from_bot {
+                Role::Assistant
+            } else {
+                Role::User
+            },
+            msg: m.content.trim().to_string(),
+        })
+        .collect::<Vec<_>>();
+
+    entries.reverse();
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prior(from_bot: bool, content: &str) -> PriorMessage {
+        PriorMessage {
+            from_bot,
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn converts_prior_messages() {
+        // newest first
+        let messages = vec![
+            prior(false, "And in Japan?"),
+            prior(true, "It is 12:00 in France."),
+            prior(false, "  "),
+            prior(false, "What time is it in France?"),
+        ];
+
+        let entries = to_chat_entries(messages);
+
+        let entries = entries
+            .iter()
+            .map(|e| (e.role.clone(), e.msg.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                (Role::User, "What time is it in France?"),
+                (Role::Assistant, "It is 12:00 in France."),
+                (Role::User, "And in Japan?"),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_the_most_recent_messages() {
+        let messages = (0..150).map(|i| prior(false, &format!("message {i}")));
+
+        let entries = to_chat_entries(messages);
+
+        assert_eq!(entries.len(), MAX_HISTORY as usize);
+        assert_eq!(entries.first().unwrap().msg, "message 99");
+        assert_eq!(entries.last().unwrap().msg, "message 0");
+    }
+}
@@ -1,14 +1,18 @@
 //! Discord bot for the Sapiens.
 mod commands;
+mod history;
 mod runner;
+mod thread;
 
+use std::collections::HashMap;
 use std::env;
 
 use dotenvy::dotenv_override;
 use pyo3::PyResult;
+use sapiens::context::ChatEntry;
 use serenity::all::{
-    AutoArchiveDuration, CreateAllowedMentions, CreateInteractionResponse,
-    CreateInteractionResponseMessage, CreateMessage, CreateThread, Interaction,
+    AutoArchiveDuration, CreateAllowedMentions, CreateAttachment, CreateInteractionResponse,
+    CreateInteractionResponseMessage, CreateMessage, CreateThread, GetMessages, Interaction,
 };
 use serenity::async_trait;
 use serenity::futures::channel::mpsc;
@@ -16,17 +20,23 @@ use serenity::futures::{SinkExt, StreamExt};
 use serenity::http::CacheHttp;
 use serenity::model::channel::Message;
 use serenity::model::gateway::Ready;
-use serenity::model::id::GuildId;
+use serenity::model::id::{ChannelId, GuildId};
+use serenity::model::user::User;
 use serenity::prelude::*;
 use tokio::spawn;
 use tracing::{debug, info, trace, warn};
 use tracing_subscriber::EnvFilter;
 
-use crate::runner::{JobUpdate, NewJob};
+use crate::history::PriorMessage;
+use crate::runner::{CancellationToken, JobUpdate, NewJob};
 
 struct Handler {
     guild_id: GuildId,
     tx: RwLock<mpsc::Sender<NewJob>>,
+    /// The tokens to cancel the running jobs - by thread
+    jobs: RwLock<HashMap<ChannelId, CancellationToken>>,
+    /// How long the threads of the tasks stay active without messages
+    auto_archive_duration: AutoArchiveDuration,
 }
 
 #[async_trait]
@@ -56,24 +66,6 @@ impl EventHandler for Handler {
             return;
         }
 
-        // let old_messages: Vec<Message> = new_message
-        //     .channel_id
-        //     .messages(&ctx.http, |messages| {
-        //         messages.before(new_message.id).limit(100)
-        //     })
-        //     .await
-        //     .unwrap()
-        //     .into_iter()
-        //     .collect();
-        //
-        // debug!(
-        //     "Old Messages: {:#?}",
-        //     old_messages
-        //         .iter()
-        //         .map(|m| m.content.clone())
-        //         .collect::<Vec<_>>()
-        // );
-
         new_message.channel_id.say(&ctx.http, "oui!").await.unwrap();
     }
 
@@ -81,10 +73,17 @@ impl EventHandler for Handler {
         info!("{} is connected!", ready.user.name);
 
         // Create new commands for this guild
-        let commands =
-            GuildId::set_commands(self.guild_id, &ctx.http, vec![commands::ping::register()])
-                .await
-                .unwrap();
+        let commands = GuildId::set_commands(
+            self.guild_id,
+            &ctx.http,
+            vec![
+                commands::ping::register(),
+                commands::ask::register(),
+                commands::stop::register(),
+            ],
+        )
+        .await
+        .unwrap();
 
         info!(
             "I now have the following guild slash commands: {:#?}",
@@ -96,8 +95,25 @@ impl EventHandler for Handler {
         if let Interaction::Command(command) = interaction {
             info!("Received command interaction: {:#?}", command);
 
+            let mut task = None;
+
             let content = match command.data.name.as_str() {
                 "ping" => commands::ping::run(&command.data.options),
+                "ask" => match commands::ask::question(&command.data.options) {
+                    Some(question) => {
+                        let content = format!("On it: {question}");
+                        task = Some(question);
+                        content
+                    }
+                    None => "Please provide a question.".to_string(),
+                },
+                "stop" => match self.jobs.read().await.get(&command.channel_id) {
+                    Some(cancellation) => {
+                        cancellation.cancel();
+                        "Stopping the task...".to_string()
+                    }
+                    None => "There is no task running in this thread.".to_string(),
+                },
                 _ => "not implemented :(".to_string(),
             };
 
@@ -112,13 +128,37 @@ impl EventHandler for Handler {
             {
                 info!("Cannot respond to slash command: {}", why);
             }
+
+            if let Some(task) = task {
+                let history = fetch_history(&ctx, command.channel_id, GetMessages::new()).await;
+
+                self.run_job(&ctx, command.channel_id, &command.user, task, history)
+                    .await;
+            }
+        }
+    }
+}
+
+/// Fetch the prior conversation of a channel
+async fn fetch_history(
+    ctx: &Context,
+    channel_id: ChannelId,
+    builder: GetMessages,
+) -> Vec<ChatEntry> {
+    match channel_id
+        .messages(&ctx.http, builder.limit(history::MAX_HISTORY))
+        .await
+    {
+        Ok(messages) => history::to_chat_entries(messages.iter().map(PriorMessage::from)),
+        Err(e) => {
+            warn!("Failed to fetch the channel history: {}", e);
+            vec![]
         }
     }
 }
 
 impl Handler {
     async fn do_task(&self, ctx: &Context, new_message: &Message) {
-        let max_steps = 12;
         let task = new_message.content[4..].to_string();
 
         // FUTURE(ssoudan) option to hide the warmup prompts
@@ -136,44 +176,73 @@ impl Handler {
             return;
         }
 
+        // the prior conversation of the channel
+        let history = fetch_history(
+            ctx,
+            new_message.channel_id,
+            GetMessages::new().before(new_message.id),
+        )
+        .await;
+
+        self.run_job(
+            ctx,
+            new_message.channel_id,
+            &new_message.author,
+            task,
+            history,
+        )
+        .await;
+    }
+
+    /// Run a task and post its updates in a new thread of the channel
+    async fn run_job(
+        &self,
+        ctx: &Context,
+        channel_id: ChannelId,
+        author: &User,
+        task: String,
+        history: Vec<ChatEntry>,
+    ) {
+        // the thread is named after the task
+        let thread_name = thread::thread_name(&author.name, &task);
+
         let (tx, mut rx) = mpsc::channel::<JobUpdate>(20);
 
+        let cancellation = CancellationToken::default();
+
         // Send the job to the runner
         self.tx
             .write()
             .await
-            .send(NewJob::new(task, max_steps, false, tx))
+            .send(
+                NewJob::new(task, false, tx)
+                    .with_history(history)
+                    .with_cancellation(cancellation.clone()),
+            )
             .await
             .unwrap();
 
         // create a thread to display the job updates
-        let thread_name = format!("{}'s task", new_message.author.name);
-        // max len in 100
-        let thread_name = if thread_name.len() > 100 {
-            thread_name[..100].to_string()
-        } else {
-            thread_name
-        };
-
-        let thread = new_message
-            .channel_id
+        let thread = channel_id
             .create_thread(
                 &ctx.http,
-                CreateThread::new(thread_name).auto_archive_duration(AutoArchiveDuration::OneHour),
+                CreateThread::new(thread_name).auto_archive_duration(self.auto_archive_duration),
             )
             .await
             .unwrap();
 
         thread.id.join_thread(&ctx.http).await.unwrap();
 
+        self.jobs.write().await.insert(thread.id, cancellation);
+
         // add the user who called the command to the thread
         thread
             .id
-            .add_thread_member(&ctx.http, new_message.author.id)
+            .add_thread_member(&ctx.http, author.id)
             .await
             .unwrap();
 
-        info!("Added {} to thread: {}", new_message.author.name, thread.id);
+        info!("Added {} to thread: {}", author.name, thread.id);
 
         // send a welcome message
         thread
@@ -193,8 +262,19 @@ impl Handler {
             debug!("Received job update: {:#?}", job_update);
 
             let msgs = match job_update {
+                JobUpdate::Artifacts(artifacts) => {
+                    let files = artifacts
+                        .into_iter()
+                        .map(|artifact| CreateAttachment::bytes(artifact.bytes, artifact.name));
+                    thread
+                        .send_message(&ctx.http, CreateMessage::new().add_files(files))
+                        .await
+                        .unwrap();
+                    None
+                }
                 JobUpdate::Completed(v) | JobUpdate::Vec(v) => Some(v),
                 JobUpdate::FailedToStart(e) | JobUpdate::ToolError(e) => Some(e),
+                JobUpdate::Cancelled => Some(vec!["The task has been stopped.".to_string()]),
                 JobUpdate::Over => None,
             };
 
@@ -213,6 +293,8 @@ impl Handler {
             }
         }
 
+        self.jobs.write().await.remove(&thread.id);
+
         // Say goodbye
         thread
             .send_message(
@@ -233,7 +315,6 @@ async fn main() -> PyResult<()> {
     let _ = dotenv_override();
 
     // FUTURE(ssoudan) graceful shutdown
-    // FUTURE(ssoudan) build the chat history from the channel history
     // FUTURE(ssoudan) Discord bot with long-lived conversations
     // FUTURE(ssoudan) log the conversation to build a dataset
 
@@ -253,10 +334,18 @@ async fn main() -> PyResult<()> {
     // Configure the client with your Discord bot token in the environment.
     let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
 
+    // How long the threads of the tasks stay active - in minutes
+    let auto_archive_duration =
+        env::var("THREAD_AUTO_ARCHIVE_MINUTES").map_or(AutoArchiveDuration::OneHour, |minutes| {
+            thread::parse_auto_archive_duration(&minutes)
+                .expect("THREAD_AUTO_ARCHIVE_MINUTES must be 60, 1440, 4320 or 10080")
+        });
+
     // Create Sapiens bot
     let (tx, rx) = mpsc::channel(100);
 
-    // Got to be created before the envs are removed
+    // Got to be created before the envs are removed - the model and the tools
+    // keep the API keys they read from them
     let mut runner = runner::Runner::new(rx).await;
 
     // Remove all environment variables from the environment
@@ -272,6 +361,8 @@ async fn main() -> PyResult<()> {
     let event_handler = Handler {
         guild_id,
         tx: RwLock::new(tx),
+        jobs: RwLock::new(HashMap::new()),
+        auto_archive_duration,
     };
 
     // Build our client.
@@ -1,30 +1,48 @@
 use std::env::VarError;
 use std::fmt::Debug;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
-use sapiens::context::{ChatEntryFormatter, ContextDump, MessageFormatter};
+use sapiens::chains::Checkpoint;
+use sapiens::context::{ChatEntry, ChatEntryFormatter, ContextDump, MessageFormatter};
 use sapiens::models::SupportedModel;
 use sapiens::tools::toolbox::Toolbox;
-use sapiens::tools::TerminationMessage;
+use sapiens::tools::{Artifact, TerminationMessage};
 use sapiens::{
-    models, wrap_observer, Error, InvalidInvocationNotification, InvocationFailureNotification,
-    InvocationResultNotification, InvocationSuccessNotification, MessageNotification,
-    ModelNotification, RuntimeObserver, SapiensConfig, TaskState, WeakRuntimeObserver,
+    chains, models, wrap_observer, Error, InvalidInvocationNotification,
+    InvocationFailureNotification, InvocationResultNotification, InvocationSuccessNotification,
+    MessageNotification, ModelNotification, RuntimeObserver, SapiensConfig, TaskState,
+    WeakRuntimeObserver,
 };
 use serenity::futures::channel::mpsc;
 use serenity::futures::{SinkExt, StreamExt};
 use tracing::{debug, error, info, warn};
 
-use crate::runner::utils::{sanitize_msgs_for_discord, Formatter};
+use crate::runner::utils::{sanitize_msgs_for_discord, split_sections, Formatter};
 
 /// Formatting utilities
 pub(crate) mod utils;
 
+/// The maximum number of steps of a task - unless `MAX_STEPS` is set
+const DEFAULT_MAX_STEPS: usize = 12;
+
+/// The number of times a step failing with a model error is retried - unless
+/// `STEP_RETRIES` is set
+const DEFAULT_STEP_RETRIES: usize = 2;
+
+/// How long to wait before retrying a failed step - doubled after each retry
+const STEP_RETRY_BACKOFF: Duration = Duration::from_secs(2);
+
 /// Sapiens bot
 pub(crate) struct SapiensBot {
     toolbox: Toolbox,
     config: SapiensConfig,
+    /// The number of times a step failing with a model error is retried
+    step_retries: usize,
+    /// How long to wait before the first retry of a step
+    retry_backoff: Duration,
 }
 
 impl SapiensBot {
@@ -71,37 +89,92 @@ impl SapiensBot {
                 models::ollama::build(host, port, model).expect("Failed to build model")
             }
             _ => {
-                let api_key = std::env::var("OPENAI_API_KEY").ok();
-                let api_base = std::env::var("OPENAI_API_BASE").ok();
+                let options = models::openai::ClientOptions::from_env();
 
-                models::openai::build(model, api_key, api_base, temperature)
-                    .expect("Failed to build model")
+                models::openai::build(model, options, temperature).expect("Failed to build model")
             }
         };
 
+        let max_steps = std::env::var("MAX_STEPS").map_or(DEFAULT_MAX_STEPS, |max_steps| {
+            max_steps
+                .parse::<usize>()
+                .expect("MAX_STEPS is not a valid number")
+        });
+
+        let step_retries =
+            std::env::var("STEP_RETRIES").map_or(DEFAULT_STEP_RETRIES, |step_retries| {
+                step_retries
+                    .parse::<usize>()
+                    .expect("STEP_RETRIES is not a valid number")
+            });
+
         let config = SapiensConfig {
             model,
+            max_steps,
             ..SapiensConfig::default()
         };
 
-        Self { toolbox, config }
+        Self {
+            toolbox,
+            config,
+            step_retries,
+            retry_backoff: STEP_RETRY_BACKOFF,
+        }
     }
 
-    /// Start a new task
+    /// Start a task coming from the prior messages of a channel - `history`,
+    /// oldest first
     pub(crate) async fn start_task(
         &self,
         task: String,
+        history: Vec<ChatEntry>,
         observer: WeakRuntimeObserver,
-    ) -> Result<TaskState, Error>
-where {
-        TaskState::with_observer(self.config.clone(), self.toolbox.clone(), task, observer).await
+    ) -> Result<TaskState, Error> {
+        TaskState::with_conversation(
+            self.config.clone(),
+            self.toolbox.clone(),
+            task,
+            history,
+            observer,
+        )
+        .await
+    }
+
+    /// Resume a task from a checkpoint - e.g. after a failed step
+    async fn resume_task(
+        &self,
+        checkpoint: Checkpoint,
+        observer: WeakRuntimeObserver,
+    ) -> Result<TaskState, Error> {
+        TaskState::resume(
+            self.config.clone(),
+            self.toolbox.clone(),
+            checkpoint,
+            observer,
+        )
+        .await
     }
 }
 
+/// Whether a step failing with `e` is worth retrying - the model errors are
+/// usually transient: rate limits, timeouts, ...
+const fn is_retryable(e: &Error) -> bool {
+    matches!(
+        e,
+        Error::ModelEvaluationError(_)
+            | Error::ChainError(chains::Error::AgentFailed(
+                chains::agents::Error::ModelError(_) | chains::agents::Error::ModelTimeout(_)
+            ))
+    )
+}
+
 /// Handler for task progress updates
 pub(crate) struct ProgressObserver {
     /// Whether to show the warm-up prompt
     pub show_warmup_prompt: bool,
+    /// Whether the task is being resumed after a failed step - its start is
+    /// not shown again
+    pub resuming: bool,
     pub job_tx: mpsc::Sender<JobUpdate>,
     entry_format: Box<dyn ChatEntryFormatter + 'static + Send + Sync>,
     message_format: Box<dyn MessageFormatter + 'static + Send + Sync>,
@@ -121,6 +194,10 @@ impl Debug for ProgressObserver {
 #[async_trait::async_trait]
 impl RuntimeObserver for ProgressObserver {
     async fn on_start(&mut self, context: ContextDump) {
+        if std::mem::take(&mut self.resuming) {
+            return;
+        }
+
         let format = self.message_format.as_ref();
         let msgs = context.format(format);
         let last_msg = msgs.last();
@@ -141,11 +218,19 @@ impl RuntimeObserver for ProgressObserver {
     }
 
     async fn on_model_update(&mut self, event: ModelNotification) {
-        let msg = self.entry_format.format(&event.chat_entry);
         debug!(msg = ?event.chat_entry, "on_model_update");
 
-        let msgs = sanitize_msgs_for_discord(vec![msg]);
-        self.job_tx.send(JobUpdate::Vec(msgs)).await.unwrap();
+        // the response is complete - the models are not streamed - but it is
+        // posted one section at a time to keep the messages short
+        for section in split_sections(&event.chat_entry.msg) {
+            let msg = self.entry_format.format(&ChatEntry {
+                role: event.chat_entry.role.clone(),
+                msg: section,
+            });
+
+            let msgs = sanitize_msgs_for_discord(vec![msg]);
+            self.job_tx.send(JobUpdate::Vec(msgs)).await.unwrap();
+        }
     }
 
     async fn on_message(&mut self, _event: MessageNotification) {
@@ -156,6 +241,7 @@ impl RuntimeObserver for ProgressObserver {
         match event {
             InvocationResultNotification::InvocationSuccess(InvocationSuccessNotification {
                 result,
+                artifacts,
                 ..
             }) => {
                 let msg = result;
@@ -163,6 +249,14 @@ impl RuntimeObserver for ProgressObserver {
                 let msgs = sanitize_msgs_for_discord(vec![msg]);
 
                 self.job_tx.send(JobUpdate::Vec(msgs)).await.unwrap();
+
+                // attached to a message of their own
+                if !artifacts.is_empty() {
+                    self.job_tx
+                        .send(JobUpdate::Artifacts(artifacts))
+                        .await
+                        .unwrap();
+                }
             }
             InvocationResultNotification::InvocationFailure(InvocationFailureNotification {
                 e,
@@ -186,35 +280,65 @@ impl RuntimeObserver for ProgressObserver {
 pub(crate) enum JobUpdate {
     Completed(Vec<String>),
     Vec(Vec<String>),
+    /// The files produced by a tool
+    Artifacts(Vec<Artifact>),
     FailedToStart(Vec<String>),
     ToolError(Vec<String>),
+    Cancelled,
     Over,
 }
 
+/// A token to cancel a job
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Ask for the job to stop - before its next step
+    pub(crate) fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the job has been asked to stop
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
 /// A job to run
 pub(crate) struct NewJob {
     task: String,
     tx: mpsc::Sender<JobUpdate>,
-    max_steps: usize,
     show_warmup_prompt: bool,
+    history: Vec<ChatEntry>,
+    cancellation: CancellationToken,
 }
 
 impl NewJob {
     /// Create a new job
     #[must_use]
-    pub(crate) const fn new(
-        task: String,
-        max_steps: usize,
-        show_warmup_prompt: bool,
-        tx: mpsc::Sender<JobUpdate>,
-    ) -> Self {
+    pub(crate) fn new(task: String, show_warmup_prompt: bool, tx: mpsc::Sender<JobUpdate>) -> Self {
         Self {
             task,
             tx,
-            max_steps,
             show_warmup_prompt,
+            history: vec![],
+            cancellation: CancellationToken::default(),
         }
     }
+
+    /// Let the job be cancelled with `cancellation`
+    #[must_use]
+    pub(crate) fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Give the prior conversation - in chronological order - to the task
+    #[must_use]
+    pub(crate) fn with_history(mut self, history: Vec<ChatEntry>) -> Self {
+        self.history = history;
+        self
+    }
 }
 
 pub(crate) struct Runner {
@@ -230,79 +354,428 @@ impl Runner {
 
     pub(crate) async fn run(&mut self) {
         while let Some(job) = self.rx.next().await {
-            let task = job.task.clone();
-            info!("Starting job: {}", task);
+            self.run_job(job).await;
+        }
+        warn!("Runner stopped");
+    }
 
-            let mut tx = job.tx.clone();
+    async fn run_job(&self, job: NewJob) {
+        let task = job.task.clone();
+        info!("Starting job: {}", task);
 
-            let observer = ProgressObserver {
-                show_warmup_prompt: job.show_warmup_prompt,
-                job_tx: job.tx,
-                entry_format: Box::new(Formatter {}),
-                message_format: Box::new(Formatter {}),
-            };
+        let mut tx = job.tx.clone();
 
-            let observer = wrap_observer(observer);
+        let observer = ProgressObserver {
+            show_warmup_prompt: job.show_warmup_prompt,
+            resuming: false,
+            job_tx: job.tx,
+            entry_format: Box::new(Formatter {}),
+            message_format: Box::new(Formatter {}),
+        };
 
-            let w_observer = Arc::downgrade(&observer);
+        let observer = wrap_observer(observer);
+
+        let w_observer = Arc::downgrade(&observer);
+
+        match self
+            .sapiens
+            .start_task(job.task.clone(), job.history.clone(), w_observer.clone())
+            .await
+        {
+            Ok(step) => {
+                let mut step = step;
+                let mut retries = 0;
+                let mut backoff = self.sapiens.retry_backoff;
+                loop {
+                    if job.cancellation.is_cancelled() {
+                        info!("Task cancelled: {}", task);
+
+                        tx.send(JobUpdate::Cancelled).await.unwrap();
+                        break;
+                    }
 
-            let max_steps = job.max_steps;
+                    let checkpoint = step.checkpoint();
 
-            let mut current_step = 0;
+                    match step.step().await {
+                        Ok(s @ TaskState::Step { .. }) => {
+                            step = s;
+                            retries = 0;
+                            backoff = self.sapiens.retry_backoff;
+                            // update is going to come through the handler
+                            debug!("Step for: {}", task);
+                        }
+                        Ok(TaskState::Stop { stop }) => {
+                            info!("Task finished: {}", task);
 
-            match self.sapiens.start_task(job.task, w_observer).await {
-                Ok(step) => {
-                    let mut step = step;
-                    loop {
-                        match step.step().await {
-                            Ok(s @ TaskState::Step { .. }) => {
-                                step = s;
-                                // update is going to come through the handler
-                                debug!("Step for: {}", task);
-                            }
-                            Ok(TaskState::Stop { stop }) => {
-                                info!("Task finished: {}", task);
-
-                                let messages = stop
-                                    .termination_messages.iter()
-                                    .flat_map(|m: &TerminationMessage| {
-                                        let msg = format!("# Termination message\n - original question: {}\n - conclusion: {}", m.original_question.trim(), m.conclusion.trim());
-                                        sanitize_msgs_for_discord(vec![msg])
-                                    }).collect();
-
-                                tx.send(JobUpdate::Completed(messages)).await.unwrap();
-                                break;
-                            }
-                            Err(e) => {
-                                error!("Error while running task: {}", e);
+                            let messages = stop
+                                .termination_messages.iter()
+                                .flat_map(|m: &TerminationMessage| {
+                                    let msg = format!("# Termination message\n - original question: {}\n - conclusion: {}", m.original_question.trim(), m.conclusion.trim());
+                                    sanitize_msgs_for_discord(vec![msg])
+                                }).collect();
 
-                                let msg = format!("Error: {e}");
-                                let msgs = sanitize_msgs_for_discord(vec![msg]);
+                            tx.send(JobUpdate::Completed(messages)).await.unwrap();
+                            break;
+                        }
+                        // the steps are capped by `SapiensConfig::max_steps`
+                        Err(
+                            Error::MaxStepsReached
+                            | Error::ChainError(chains::Error::MaxStepsReached),
+                        ) => {
+                            info!("Task aborted: {}", task);
 
-                                tx.send(JobUpdate::FailedToStart(msgs)).await.unwrap();
-                                break;
+                            tx.send(JobUpdate::Over).await.unwrap();
+                            break;
+                        }
+                        Err(e) if is_retryable(&e) && retries < self.sapiens.step_retries => {
+                            retries += 1;
+                            warn!("Step failed - retrying ({retries}) in {backoff:?}: {e}");
+
+                            let Some(checkpoint) = checkpoint else {
+                                unreachable!("a task that is not done has a checkpoint");
+                            };
+
+                            tokio::time::sleep(backoff).await;
+                            backoff *= 2;
+
+                            observer.lock().await.resuming = true;
+                            match self
+                                .sapiens
+                                .resume_task(checkpoint, w_observer.clone())
+                                .await
+                            {
+                                Ok(s) => step = s,
+                                Err(e) => {
+                                    error!("Error while resuming task: {}", e);
+
+                                    let msg = format!("Error: {e}");
+                                    let msgs = sanitize_msgs_for_discord(vec![msg]);
+
+                                    tx.send(JobUpdate::FailedToStart(msgs)).await.unwrap();
+                                    break;
+                                }
                             }
                         }
+                        Err(e) => {
+                            error!("Error while running task: {}", e);
 
-                        current_step += 1;
+                            let msg = format!("Error: {e}");
+                            let msgs = sanitize_msgs_for_discord(vec![msg]);
 
-                        if current_step >= max_steps {
-                            info!("Task aborted: {}", task);
-
-                            tx.send(JobUpdate::Over).await.unwrap();
+                            tx.send(JobUpdate::FailedToStart(msgs)).await.unwrap();
                             break;
                         }
                     }
                 }
-                Err(e) => {
-                    error!("Error while starting task: {}", e);
-                    let msg = format!("Error: {e}");
-                    let msgs = sanitize_msgs_for_discord(vec![msg]);
+            }
+            Err(e) => {
+                error!("Error while starting task: {}", e);
+                let msg = format!("Error: {e}");
+                let msgs = sanitize_msgs_for_discord(vec![msg]);
 
-                    tx.send(JobUpdate::FailedToStart(msgs)).await.unwrap();
+                tx.send(JobUpdate::FailedToStart(msgs)).await.unwrap();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use indoc::formatdoc;
+    use sapiens::models::{ChatEntryTokenNumber, ChatInput, Model, ModelResponse};
+    use sapiens_tools::conclude::ConcludeTool;
+
+    use super::*;
+
+    /// A model that never concludes and cancels the job after a few queries
+    struct EndlessModel {
+        queries: Arc<AtomicUsize>,
+        cancel_after: usize,
+        cancellation: CancellationToken,
+    }
+
+    #[async_trait::async_trait]
+    impl ChatEntryTokenNumber for EndlessModel {
+        async fn num_tokens(&self, _input: ChatInput) -> usize {
+            0
+        }
+
+        async fn context_size(&self) -> usize {
+            4096
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Model for EndlessModel {
+        async fn query(
+            &self,
+            _input: ChatInput,
+            _max_tokens: Option<usize>,
+        ) -> Result<ModelResponse, models::Error> {
+            let queries = self.queries.fetch_add(1, Ordering::SeqCst) + 1;
+            if queries == self.cancel_after {
+                self.cancellation.cancel();
+            }
+
+            Ok(ModelResponse {
+                msg: formatdoc! {"
+                    ## Observations:
+                    - Step {queries}.
+                    ## Orientation:
+                    - Keep going.
+                    ## Decision:
+                    - Think.
+                    ## The ONLY Action:
+                    ```yaml
+                    tool_name: Think
+                    parameters:
+                      step: {queries}
+                    ```
+                "},
+                alternatives: Vec::new(),
+                usage: None,
+                finish_reason: None,
+                tool_calls: Vec::new(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn stops_a_cancelled_job() {
+        let cancellation = CancellationToken::default();
+        let queries = Arc::new(AtomicUsize::new(0));
+
+        let toolbox = Toolbox::builder()
+            .with_terminal_tool(ConcludeTool::default())
+            .build();
+
+        let config = SapiensConfig {
+            model: Arc::new(Box::new(EndlessModel {
+                queries: queries.clone(),
+                cancel_after: 3,
+                cancellation: cancellation.clone(),
+            })),
+            max_steps: 100,
+            ..SapiensConfig::default()
+        };
+
+        let (_jobs_tx, jobs_rx) = mpsc::channel(1);
+        let runner = Runner {
+            rx: jobs_rx,
+            sapiens: SapiensBot {
+                toolbox,
+                config,
+                step_retries: 0,
+                retry_backoff: Duration::ZERO,
+            },
+        };
+
+        let (tx, rx) = mpsc::channel(100);
+        runner
+            .run_job(
+                NewJob::new("Count forever".to_string(), false, tx).with_cancellation(cancellation),
+            )
+            .await;
+
+        assert_eq!(queries.load(Ordering::SeqCst), 3);
+
+        let updates = rx.collect::<Vec<_>>().await;
+        assert!(matches!(updates.last(), Some(JobUpdate::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn posts_each_section_of_the_steps() {
+        let toolbox = Toolbox::builder()
+            .with_terminal_tool(ConcludeTool::default())
+            .build();
+
+        let config = SapiensConfig {
+            model: Arc::new(Box::new(EndlessModel {
+                queries: Arc::new(AtomicUsize::new(0)),
+                cancel_after: usize::MAX,
+                cancellation: CancellationToken::default(),
+            })),
+            max_steps: 2,
+            ..SapiensConfig::default()
+        };
+
+        let (_jobs_tx, jobs_rx) = mpsc::channel(1);
+        let runner = Runner {
+            rx: jobs_rx,
+            sapiens: SapiensBot {
+                toolbox,
+                config,
+                step_retries: 0,
+                retry_backoff: Duration::ZERO,
+            },
+        };
+
+        let (tx, rx) = mpsc::channel(100);
+        runner
+            .run_job(NewJob::new("Count".to_string(), false, tx))
+            .await;
+
+        let updates = rx.collect::<Vec<_>>().await;
+
+        let updates = updates
+            .iter()
+            .map(|u| match u {
+                JobUpdate::Vec(msgs) => {
+                    let msg = msgs.join("\n");
+                    let section = msg.lines().nth(1).unwrap_or_default().to_string();
+                    format!("Vec: {section}")
                 }
+                JobUpdate::ToolError(_) => "ToolError".to_string(),
+                other => format!("{other:?}"),
+            })
+            .collect::<Vec<_>>();
+
+        let step = [
+            "Vec: ## Observations:",
+            "Vec: ## Orientation:",
+            "Vec: ## Decision:",
+            "Vec: ## The ONLY Action:",
+            "ToolError",
+        ];
+        assert_eq!(updates.len(), 1 + 2 * step.len() + 1, "{updates:#?}");
+        assert_eq!(&updates[1..=step.len()], &step);
+        assert_eq!(&updates[step.len() + 1..=2 * step.len()], &step);
+        assert_eq!(updates.last().unwrap(), "Over");
+    }
+
+    #[tokio::test]
+    async fn stops_after_max_steps() {
+        let queries = Arc::new(AtomicUsize::new(0));
+
+        let toolbox = Toolbox::builder()
+            .with_terminal_tool(ConcludeTool::default())
+            .build();
+
+        let config = SapiensConfig {
+            model: Arc::new(Box::new(EndlessModel {
+                queries: queries.clone(),
+                cancel_after: usize::MAX,
+                cancellation: CancellationToken::default(),
+            })),
+            max_steps: 1,
+            ..SapiensConfig::default()
+        };
+
+        let (_jobs_tx, jobs_rx) = mpsc::channel(1);
+        let runner = Runner {
+            rx: jobs_rx,
+            sapiens: SapiensBot {
+                toolbox,
+                config,
+                step_retries: 0,
+                retry_backoff: Duration::ZERO,
+            },
+        };
+
+        let (tx, rx) = mpsc::channel(100);
+        runner
+            .run_job(NewJob::new("Count".to_string(), false, tx))
+            .await;
+
+        assert_eq!(queries.load(Ordering::SeqCst), 1);
+
+        let updates = rx.collect::<Vec<_>>().await;
+        assert!(matches!(updates.last(), Some(JobUpdate::Over)));
+    }
+
+    /// A model that fails once then concludes
+    struct FlakyModel {
+        queries: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl ChatEntryTokenNumber for FlakyModel {
+        async fn num_tokens(&self, _input: ChatInput) -> usize {
+            0
+        }
+
+        async fn context_size(&self) -> usize {
+            4096
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Model for FlakyModel {
+        async fn query(
+            &self,
+            _input: ChatInput,
+            _max_tokens: Option<usize>,
+        ) -> Result<ModelResponse, models::Error> {
+            if self.queries.fetch_add(1, Ordering::SeqCst) == 0 {
+                return Err(models::Error::NoResponseFromModel);
             }
+
+            Ok(ModelResponse {
+                msg: formatdoc! {"
+                    ## The ONLY Action:
+                    ```yaml
+                    tool_name: Conclude
+                    parameters:
+                      original_question: Count
+                      conclusion: Done
+                    ```
+                "},
+                alternatives: Vec::new(),
+                usage: None,
+                finish_reason: None,
+                tool_calls: Vec::new(),
+            })
         }
-        warn!("Runner stopped");
+    }
+
+    #[tokio::test]
+    async fn retries_a_step_failing_with_a_model_error() {
+        let queries = Arc::new(AtomicUsize::new(0));
+
+        let toolbox = Toolbox::builder()
+            .with_terminal_tool(ConcludeTool::default())
+            .build();
+
+        let config = SapiensConfig {
+            model: Arc::new(Box::new(FlakyModel {
+                queries: queries.clone(),
+            })),
+            ..SapiensConfig::default()
+        };
+
+        let (_jobs_tx, jobs_rx) = mpsc::channel(1);
+        let runner = Runner {
+            rx: jobs_rx,
+            sapiens: SapiensBot {
+                toolbox,
+                config,
+                step_retries: 1,
+                retry_backoff: Duration::ZERO,
+            },
+        };
+
+        let (tx, rx) = mpsc::channel(100);
+        runner
+            .run_job(NewJob::new("Count".to_string(), false, tx))
+            .await;
+
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+
+        let updates = rx.collect::<Vec<_>>().await;
+        // the start of the task is not shown again when the step is retried
+        let Some(JobUpdate::Vec(start)) = updates.first() else {
+            panic!("unexpected updates: {updates:?}");
+        };
+        let starts = updates
+            .iter()
+            .filter(|u| matches!(u, JobUpdate::Vec(msgs) if msgs == start))
+            .count();
+        assert_eq!(starts, 1, "{updates:?}");
+        let Some(JobUpdate::Completed(messages)) = updates.last() else {
+            panic!("unexpected updates: {updates:?}");
+        };
+        assert!(messages[0].contains("conclusion: Done"), "{messages:?}");
     }
 }
@@ -3,6 +3,7 @@ use pulldown_cmark_to_cmark::{cmark_resume, State};
 use sapiens::chains::Message;
 use sapiens::context::{ChatEntry, ChatEntryFormatter, MessageFormatter};
 use sapiens::models::Role;
+use sapiens::text::split_message;
 
 /// Chat entry formatter that renders the chat entry in markdown
 pub(crate) struct Formatter {}
@@ -26,6 +27,35 @@ impl MessageFormatter for Formatter {
     }
 }
 
+/// Split a response of the model on its `## ` sections - Observations,
+/// Orientation, Decision, Action - so each is posted as an update of its own
+///
+/// Headings inside code blocks are ignored. Text before the first section is
+/// kept as a section of its own.
+pub(crate) fn split_sections(msg: &str) -> Vec<String> {
+    let mut sections = vec![];
+    let mut section = String::new();
+    let mut in_code_block = false;
+
+    for line in msg.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+        }
+
+        if !in_code_block && line.starts_with("## ") && !section.trim().is_empty() {
+            sections.push(std::mem::take(&mut section).trim().to_string());
+        }
+
+        section.push_str(line);
+    }
+
+    if !section.trim().is_empty() {
+        sections.push(section.trim().to_string());
+    }
+
+    sections
+}
+
 /// Size in characters of an event once rendered in markdown
 #[allow(clippy::match_same_arms)]
 fn md_event_size(event: &Event) -> usize {
@@ -83,17 +113,12 @@ fn md_event_size(event: &Event) -> usize {
 /// Tries to split mardown messages into multiple messages if they are too
 /// long for Discord.
 ///
-/// If any split is still too long, truncate it.
+/// If any split is still too long, split it further with
+/// [`split_message`].
 pub(crate) fn sanitize_msgs_for_discord(msgs: Vec<String>) -> Vec<String> {
     msgs.into_iter()
         .flat_map(|m| split_msgs(m, 1800))
-        .map(|mut x| {
-            if x.len() > 1800 - 3 {
-                x.truncate(1800);
-                x.push_str("...");
-            }
-            x
-        })
+        .flat_map(|m| split_message(&m, 1800))
         .collect()
 }
 
@@ -302,4 +327,47 @@ mod tests {
             assert!(msg.len() <= max_size);
         }
     }
+
+    #[test]
+    fn sanitize_does_not_panic_on_multibyte_characters() {
+        let msg = "é".repeat(3000);
+
+        let msgs = sanitize_msgs_for_discord(vec![msg]);
+
+        for msg in &msgs {
+            assert!(msg.len() <= 1800);
+        }
+        assert_eq!(msgs.concat().chars().count(), 3000);
+    }
+
+    #[test]
+    fn split_response_in_sections() {
+        let msg = indoc! {"
+        ## Observations:
+        - The list is [2, 1].
+        ## Orientation:
+        - Sort it.
+        ## Decision:
+        - Use Python.
+        ## The ONLY Action:
+        ```yaml
+        tool_name: SandboxedPython
+        parameters:
+          code: |
+            ## not a section
+            print(sorted([2, 1]))
+        ```
+        "};
+
+        let sections = split_sections(msg);
+
+        assert_eq!(sections.len(), 4);
+        assert_eq!(sections[0], "## Observations:\n- The list is [2, 1].");
+        assert_eq!(sections[1], "## Orientation:\n- Sort it.");
+        assert_eq!(sections[2], "## Decision:\n- Use Python.");
+        assert!(sections[3].starts_with("## The ONLY Action:"));
+        assert!(sections[3].contains("## not a section"));
+
+        assert_eq!(split_sections("No section"), vec!["No section"]);
+    }
 }
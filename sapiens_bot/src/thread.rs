@@ -0,0 +1,80 @@
+use serenity::all::AutoArchiveDuration;
+
+/// Maximum length of a thread name - in characters
+pub(crate) const MAX_THREAD_NAME_LEN: usize = 100;
+
+/// Name of the thread of a task: the first line of the task, prefixed with
+/// the name of its author and truncated to [`MAX_THREAD_NAME_LEN`] characters
+pub(crate) fn thread_name(author: &str, task: &str) -> String {
+    let question = task.trim().lines().next().unwrap_or_default().trim();
+
+    let name = if question.is_empty() {
+        format!("{author}'s task")
+    } else {
+        format!("{author}: {question}")
+    };
+
+    if name.chars().count() <= MAX_THREAD_NAME_LEN {
+        return name;
+    }
+
+    let mut name = name
+        .chars()
+        .take(MAX_THREAD_NAME_LEN - 1)
+        .collect::<String>();
+    name.push('…');
+    name
+}
+
+/// Parse the auto-archive duration of the threads - in minutes
+///
+/// Discord only accepts 60, 1440, 4320 or 10080 minutes.
+pub(crate) fn parse_auto_archive_duration(minutes: &str) -> Result<AutoArchiveDuration, String> {
+    match minutes.trim().parse::<u16>() {
+        Ok(60) => Ok(AutoArchiveDuration::OneHour),
+        Ok(1440) => Ok(AutoArchiveDuration::OneDay),
+        Ok(4320) => Ok(AutoArchiveDuration::ThreeDays),
+        Ok(10080) => Ok(AutoArchiveDuration::OneWeek),
+        _ => Err(format!(
+            "Invalid auto-archive duration: '{minutes}' - expected 60, 1440, 4320 or 10080 \
+             minutes"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_the_thread_after_the_question() {
+        assert_eq!(
+            thread_name("alice", "  What is the capital of France?\nThanks!"),
+            "alice: What is the capital of France?"
+        );
+        assert_eq!(thread_name("alice", " \n "), "alice's task");
+    }
+
+    #[test]
+    fn truncates_the_thread_name() {
+        let name = thread_name("bob", &"é".repeat(200));
+
+        assert_eq!(name.chars().count(), MAX_THREAD_NAME_LEN);
+        assert!(name.starts_with("bob: éé"));
+        assert!(name.ends_with('…'));
+    }
+
+    #[test]
+    fn parses_the_auto_archive_duration() {
+        assert_eq!(
+            parse_auto_archive_duration("1440"),
+            Ok(AutoArchiveDuration::OneDay)
+        );
+        assert_eq!(
+            parse_auto_archive_duration(" 60 "),
+            Ok(AutoArchiveDuration::OneHour)
+        );
+        assert!(parse_auto_archive_duration("42").is_err());
+        assert!(parse_auto_archive_duration("a day").is_err());
+    }
+}
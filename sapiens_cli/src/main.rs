@@ -7,6 +7,7 @@ use dotenvy::dotenv_override;
 use sapiens::chains::Message;
 use sapiens::context::{ChatEntry, ChatEntryFormatter, ContextDump, MessageFormatter};
 use sapiens::models::{Role, SupportedModel};
+use sapiens::text::split_message;
 use sapiens::{
     models, run_to_the_end, wrap_observer, ChainType, InvocationResultNotification,
     ModelNotification, RuntimeObserver, SapiensConfig,
@@ -71,6 +72,10 @@ struct Args {
     /// The higher the temperature, the crazier the text.
     #[arg(long, default_value_t = 0.)]
     temperature: f32,
+
+    /// Split the results of the tools in chunks of at most this many bytes
+    #[arg(long)]
+    max_message_len: Option<usize>,
 }
 
 struct ColorFormatter;
@@ -99,6 +104,8 @@ impl MessageFormatter for ColorFormatter {
 struct Observer {
     /// Whether to show the warm-up prompt
     pub show_warmup_prompt: bool,
+    /// The maximum size of the results shown at once - if any
+    pub max_message_len: Option<usize>,
 }
 
 #[async_trait::async_trait]
@@ -130,9 +137,22 @@ impl RuntimeObserver for Observer {
     async fn on_invocation_result(&mut self, event: InvocationResultNotification) {
         match event {
             InvocationResultNotification::InvocationSuccess(i) => {
-                println!("{}", i.result.green());
+                print_ignored_actions(i.invocation_index, i.invocation_count, i.all_ran);
+
+                let chunks = self.max_message_len.map_or_else(
+                    || vec![i.result.clone()],
+                    |limit| split_message(&i.result, limit),
+                );
+
+                for (n, chunk) in chunks.iter().enumerate() {
+                    if n > 0 {
+                        println!("-------------");
+                    }
+                    println!("{}", chunk.green());
+                }
             }
             InvocationResultNotification::InvocationFailure(i) => {
+                print_ignored_actions(i.invocation_index, i.invocation_count, false);
                 println!("{}", i.extracted_input.magenta());
                 println!("{}", i.e.to_string().red());
             }
@@ -145,6 +165,27 @@ impl RuntimeObserver for Observer {
     }
 }
 
+/// Tell which of the Actions of a message was run - if there were several and
+/// not all of them ran
+fn print_ignored_actions(invocation_index: usize, invocation_count: usize, all_ran: bool) {
+    if let Some(msg) = ignored_actions_message(invocation_index, invocation_count, all_ran) {
+        println!("{}", msg.yellow());
+    }
+}
+
+fn ignored_actions_message(
+    invocation_index: usize,
+    invocation_count: usize,
+    all_ran: bool,
+) -> Option<String> {
+    (invocation_count > 1 && !all_ran).then(|| {
+        format!(
+            "Ran action {} of {invocation_count}; others ignored.",
+            invocation_index + 1
+        )
+    })
+}
+
 #[pyo3_asyncio::tokio::main]
 async fn main() -> Result<(), pyo3::PyErr> {
     let args = Args::parse();
@@ -181,18 +222,12 @@ async fn main() -> Result<(), pyo3::PyErr> {
 
             models::ollama::build(host, port, args.model).expect("Failed to build model")
         }
-        _ => {
-            let api_key = std::env::var("OPENAI_API_KEY").ok();
-            let api_base = std::env::var("OPENAI_API_BASE").ok();
-
-            models::openai::build(
-                args.model.clone(),
-                api_key,
-                api_base,
-                Some(args.temperature),
-            )
-            .expect("Failed to build model")
-        }
+        _ => models::openai::build(
+            args.model.clone(),
+            models::openai::ClientOptions::from_env(),
+            Some(args.temperature),
+        )
+        .expect("Failed to build model"),
     };
 
     let task = args.task.clone();
@@ -202,6 +237,7 @@ async fn main() -> Result<(), pyo3::PyErr> {
         max_steps: args.max_steps,
         min_tokens_for_completion: args.min_tokens_for_completion,
         max_tokens: args.max_tokens,
+        ..SapiensConfig::default()
     };
 
     // Sanitation
@@ -216,6 +252,7 @@ async fn main() -> Result<(), pyo3::PyErr> {
 
     let observer = Observer {
         show_warmup_prompt: args.show_warmup_prompt,
+        max_message_len: args.max_message_len,
     };
 
     let observer = wrap_observer(observer);
@@ -236,8 +273,31 @@ async fn main() -> Result<(), pyo3::PyErr> {
             "The original question was: {} ",
             message.original_question.green()
         );
-        println!("And the conclusion is: {} ", message.conclusion.blue());
+        if message.status.is_success() {
+            println!("And the conclusion is: {} ", message.conclusion.blue());
+        } else {
+            println!("But the task was aborted: {} ", message.conclusion.red());
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_which_action_ran() {
+        assert_eq!(
+            ignored_actions_message(1, 3, false).as_deref(),
+            Some("Ran action 2 of 3; others ignored.")
+        );
+    }
+
+    #[test]
+    fn says_nothing_when_no_action_was_ignored() {
+        assert_eq!(ignored_actions_message(0, 1, false), None);
+        assert_eq!(ignored_actions_message(0, 3, true), None);
+    }
+}
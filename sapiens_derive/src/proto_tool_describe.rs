@@ -1,4 +1,5 @@
 //! The `ProtoToolDescribe` derive macro.
+use darling::util::Flag;
 use darling::FromDeriveInput;
 use proc_macro::TokenStream;
 use quote::{quote, ToTokens};
@@ -6,7 +7,12 @@ use syn::Expr;
 
 /// A derive macro for the `ProtoToolDescribe` trait.
 #[derive(Debug, FromDeriveInput)]
-#[darling(attributes(tool), supports(struct_named), forward_attrs(doc))]
+#[darling(
+    attributes(tool),
+    supports(struct_named),
+    forward_attrs(doc),
+    and_then = DeriveReceiver::validate
+)]
 struct DeriveReceiver {
     /// The struct or enum that the derive macro is being applied to.
     ident: syn::Ident,
@@ -20,9 +26,40 @@ struct DeriveReceiver {
     input: syn::Path,
     /// The output type
     output: syn::Path,
+    /// Can the tool be invoked concurrently with other invocations?
+    #[darling(default)]
+    parallel_safe: Flag,
+    /// The invocations of the tools with the same key are run one at a time
+    concurrency_key: Option<String>,
+    /// Does the tool only read the state of the world? Unrelated to
+    /// `parallel_safe` - a read-only tool is not made parallel-safe
+    read_only: Flag,
+    /// Does the tool change the state of the world?
+    mutating: Flag,
+    /// A function returning worked examples of invocations
+    examples: Option<syn::Path>,
+    /// A suggestion of what to do next after a successful invocation
+    hint: Option<String>,
+    /// A function rendering the output of a successful invocation
+    format_result: Option<syn::Path>,
+}
+
+impl DeriveReceiver {
+    /// Reject the attributes that contradict each other
+    fn validate(self) -> darling::Result<Self> {
+        if self.read_only.is_present() && self.mutating.is_present() {
+            return Err(
+                darling::Error::custom("A tool cannot be both read_only and mutating")
+                    .with_span(&self.mutating.span()),
+            );
+        }
+
+        Ok(self)
+    }
 }
 
 impl ToTokens for DeriveReceiver {
+    #[allow(clippy::too_many_lines)]
     fn to_tokens(&self, out: &mut proc_macro2::TokenStream) {
         let Self {
             ref ident,
@@ -31,7 +68,13 @@ impl ToTokens for DeriveReceiver {
             ref name,
             ref input,
             ref output,
-            ..
+            ref parallel_safe,
+            ref concurrency_key,
+            ref read_only,
+            ref mutating,
+            ref examples,
+            ref hint,
+            ref format_result,
         } = *self;
 
         let (imp, ty, wher) = generics.split_for_impl();
@@ -75,6 +118,57 @@ impl ToTokens for DeriveReceiver {
         let input_ty = &input.segments.last().unwrap().ident;
         let output_ty = &output.segments.last().unwrap().ident;
 
+        let parallel_safe = parallel_safe.is_present().then(|| {
+            quote! {
+                fn is_parallel_safe(&self) -> bool {
+                    true
+                }
+            }
+        });
+
+        let concurrency_key = concurrency_key.as_ref().map(|key| {
+            quote! {
+                fn concurrency_key(&self) -> Option<String> {
+                    Some(#key.to_string())
+                }
+            }
+        });
+
+        let side_effects = match (read_only.is_present(), mutating.is_present()) {
+            // both are rejected by `validate`
+            (true, _) => Some(quote! {
+                fn side_effects(&self) -> SideEffects {
+                    SideEffects::ReadOnly
+                }
+            }),
+            (false, true) => Some(quote! {
+                fn side_effects(&self) -> SideEffects {
+                    SideEffects::Mutating
+                }
+            }),
+            (false, false) => None,
+        };
+
+        let examples = examples
+            .as_ref()
+            .map_or_else(|| quote! { Vec::new() }, |examples| quote! { #examples() });
+
+        let hint = hint.as_ref().map(|hint| {
+            quote! {
+                fn hint(&self, _output: &serde_yaml::Value) -> Option<String> {
+                    Some(#hint.to_string())
+                }
+            }
+        });
+
+        let format_result = format_result.as_ref().map(|format_result| {
+            quote! {
+                fn format_result(&self, output: &serde_yaml::Value) -> Option<String> {
+                    #format_result(self, output)
+                }
+            }
+        });
+
         // dbg!(fields);
         out.extend(quote! {
             impl #imp ProtoToolDescribe for #ident #ty #wher {
@@ -84,8 +178,20 @@ impl ToTokens for DeriveReceiver {
                         description: #doc.to_string(),
                         parameters: #input_ty::describe(),
                         responses_content: #output_ty::describe(),
+                        examples: #examples,
+                        side_effects: ProtoToolDescribe::side_effects(self),
                     }
                 }
+
+                #parallel_safe
+
+                #concurrency_key
+
+                #side_effects
+
+                #hint
+
+                #format_result
             }
         });
     }
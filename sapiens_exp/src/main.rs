@@ -118,7 +118,7 @@ async fn main() -> Result<(), pyo3::PyErr> {
 
     info!("Going to save trials in {} ", trial_path.to_str().unwrap());
 
-    let toolbox = setup::basic_toolbox().await;
+    let toolbox = setup::basic_toolbox();
 
     // prepare scenario
     let (toolbox, shared_state) = match args.scenario {
@@ -152,10 +152,9 @@ async fn main() -> Result<(), pyo3::PyErr> {
             models::ollama::build(host, port, args.model).expect("Failed to build model")
         }
         _ => {
-            let api_key = std::env::var("OPENAI_API_KEY").ok();
-            let api_base = std::env::var("OPENAI_API_BASE").ok();
+            let options = models::openai::ClientOptions::from_env();
 
-            models::openai::build(args.model.clone(), api_key, api_base, temperature)
+            models::openai::build(args.model.clone(), options, temperature)
                 .expect("Failed to build model")
         }
     };
@@ -166,6 +165,7 @@ async fn main() -> Result<(), pyo3::PyErr> {
         model,
         min_tokens_for_completion: args.min_tokens_for_completion,
         max_tokens: args.max_tokens,
+        ..sapiens::SapiensConfig::default()
     };
 
     // Sanitation
@@ -7,11 +7,10 @@ use sapiens_tools::python::PythonTool;
 /// Initially, the toolbox contains the `PythonTool` and the `ConcludeTool`.
 /// Scenario builders like [`crate::tools::scenario_0::build`] will add
 /// their tools to the toolbox.
-pub async fn basic_toolbox() -> toolbox::Toolbox {
-    let toolbox = toolbox::Toolbox::default();
-
-    toolbox.add_advanced_tool(PythonTool::default()).await;
-    toolbox.add_terminal_tool(ConcludeTool::default()).await;
-
-    toolbox
+#[must_use]
+pub fn basic_toolbox() -> toolbox::Toolbox {
+    toolbox::Toolbox::builder()
+        .with_advanced_tool(PythonTool::default())
+        .with_terminal_tool(ConcludeTool::default())
+        .build()
 }
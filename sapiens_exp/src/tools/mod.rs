@@ -16,7 +16,9 @@
 use std::marker::PhantomData;
 use std::sync::Arc;
 
-use sapiens::tools::{Describe, ProtoToolDescribe, ProtoToolInvoke, ToolDescription, ToolUseError};
+use sapiens::tools::{
+    Describe, ProtoToolDescribe, ProtoToolInvoke, SideEffects, ToolDescription, ToolUseError,
+};
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 
@@ -85,6 +87,8 @@ impl<I: Describe, S, O: Describe> GenericTool<I, S, O> {
                 description,
                 parameters: I::describe(),
                 responses_content: O::describe(),
+                examples: Vec::new(),
+                side_effects: SideEffects::default(),
             },
             state,
         )
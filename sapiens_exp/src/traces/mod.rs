@@ -201,7 +201,9 @@ impl Event {
                 | Message::Orientation { usage, .. }
                 | Message::Decision { usage, .. }
                 | Message::Action { usage, .. } => usage.as_ref().map(std::convert::Into::into),
-                Message::Task { .. } | Message::ActionResult { .. } => None,
+                Message::Conversation { .. }
+                | Message::Task { .. }
+                | Message::ActionResult { .. } => None,
             },
         }
     }
@@ -314,6 +316,7 @@ impl From<InvocationSuccessNotification> for Event {
             tool_name,
             extracted_input,
             result,
+            ..
         } = notification;
 
         Self::ToolInvocationSucceeded {
@@ -332,6 +335,7 @@ impl From<InvocationFailureNotification> for Event {
             tool_name,
             extracted_input,
             e,
+            ..
         } = notification;
 
         Self::ToolInvocationFailed {
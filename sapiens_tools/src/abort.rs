@@ -0,0 +1,154 @@
+use std::fmt::Debug;
+
+use sapiens::tools::{
+    Describe, ProtoToolDescribe, ProtoToolInvoke, TerminalTool, TerminationMessage,
+    TerminationStatus, ToolDescription, ToolUseError,
+};
+use sapiens_derive::{Describe, ProtoToolDescribe, ProtoToolInvoke};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+/// A tool to abort a task.
+/// Use this when the task cannot be completed - e.g. it is impossible or
+/// the Tools needed are not available - instead of concluding.
+#[derive(Default, ProtoToolDescribe, ProtoToolInvoke)]
+#[tool(name = "Abort", input = "AbortToolInput", output = "AbortToolOutput")]
+#[allow(clippy::module_name_repetitions)]
+pub struct AbortTool {
+    done: Mutex<Option<AbortToolInput>>,
+}
+
+impl Debug for AbortTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AbortTool").finish()
+    }
+}
+
+#[async_trait::async_trait]
+impl TerminalTool for AbortTool {
+    async fn is_done(&self) -> bool {
+        // lock
+        let done = self.done.lock().await;
+        done.is_some()
+    }
+
+    async fn take_done(&self) -> Option<TerminationMessage> {
+        // lock
+        {
+            let mut done = self.done.lock().await;
+            done.take().map(std::convert::Into::into)
+        }
+    }
+}
+
+/// A tool that is called to give up on the task.
+#[derive(Debug, Clone, Serialize, Deserialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct AbortToolInput {
+    /// Why the task cannot be completed. Plain text ONLY. MANDATORY.
+    pub reason: String,
+    /// The original question that was asked to the user. No string
+    /// interpolation supported, only plain text. MANDATORY.
+    pub original_question: String,
+}
+
+impl From<AbortToolInput> for TerminationMessage {
+    fn from(input: AbortToolInput) -> Self {
+        Self {
+            conclusion: input.reason,
+            original_question: input.original_question,
+            data: None,
+            tool_name: None,
+            status: TerminationStatus::Failure,
+        }
+    }
+}
+
+/// `AbortToolOutput` - empty
+#[derive(Serialize, Deserialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct AbortToolOutput {}
+
+impl AbortTool {
+    #[tracing::instrument(skip(self))]
+    async fn invoke_typed(&self, input: &AbortToolInput) -> Result<AbortToolOutput, ToolUseError> {
+        // lock
+        {
+            let mut done = self.done.lock().await;
+
+            if done.is_some() {
+                return Err(ToolUseError::InvocationFailed(
+                    "This task is already aborted.".to_string(),
+                ));
+            }
+
+            // set done
+            *done = Some(input.clone());
+        }
+
+        Ok(AbortToolOutput {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use sapiens::tools::toolbox::{invoke_tool, InvokeResult, Toolbox};
+
+    use super::*;
+    use crate::conclude::ConcludeTool;
+
+    async fn toolbox() -> Toolbox {
+        let toolbox = Toolbox::default();
+        toolbox.add_terminal_tool(ConcludeTool::default()).await;
+        toolbox.add_terminal_tool(AbortTool::default()).await;
+        toolbox
+    }
+
+    #[tokio::test]
+    async fn test_abort_is_reported() {
+        let data = indoc! {r"
+        ```yaml
+        tool_name: Abort
+        parameters:
+            original_question: Turn on the lights.
+            reason: There is no Tool to control the lights.
+        ```
+        "};
+
+        let toolbox = toolbox().await;
+
+        let res = invoke_tool(toolbox.clone(), data).await;
+        assert!(matches!(res, InvokeResult::Success { .. }), "{res:?}");
+
+        let messages = toolbox.termination_messages().await;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].tool_name.as_deref(), Some("Abort"));
+        assert_eq!(
+            messages[0].conclusion,
+            "There is no Tool to control the lights."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_conclude_is_reported() {
+        let data = indoc! {r"
+        ```yaml
+        tool_name: Conclude
+        parameters:
+            original_question: What is 1 + 1?
+            conclusion: '2'
+        ```
+        "};
+
+        let toolbox = toolbox().await;
+
+        let res = invoke_tool(toolbox.clone(), data).await;
+        assert!(matches!(res, InvokeResult::Success { .. }), "{res:?}");
+
+        let messages = toolbox.termination_messages().await;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].tool_name.as_deref(), Some("Conclude"));
+        assert_eq!(messages[0].conclusion, "2");
+    }
+}
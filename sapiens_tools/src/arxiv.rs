@@ -1,7 +1,9 @@
 use std::fmt::Display;
 
 use arxiv::{Arxiv, ArxivQuery};
-use sapiens::tools::{Describe, ProtoToolDescribe, ProtoToolInvoke, ToolDescription, ToolUseError};
+use sapiens::tools::{
+    Describe, ProtoToolDescribe, ProtoToolInvoke, SideEffects, ToolDescription, ToolUseError,
+};
 use sapiens_derive::{Describe, ProtoToolDescribe, ProtoToolInvoke};
 use serde::{Deserialize, Serialize};
 
@@ -13,7 +15,13 @@ use serde::{Deserialize, Serialize};
 /// engineering and systems science, and economics. Materials on this site are
 /// not peer-reviewed by arXiv.
 #[derive(Debug, ProtoToolInvoke, ProtoToolDescribe)]
-#[tool(name = "Arxiv", input = "ArxivToolInput", output = "ArxivToolOutput")]
+#[tool(
+    name = "Arxiv",
+    input = "ArxivToolInput",
+    output = "ArxivToolOutput",
+    parallel_safe,
+    read_only
+)]
 #[allow(clippy::module_name_repetitions)]
 pub struct ArxivTool {}
 
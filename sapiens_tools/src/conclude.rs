@@ -2,7 +2,7 @@ use std::fmt::Debug;
 
 use sapiens::tools::{
     Describe, ProtoToolDescribe, ProtoToolInvoke, TerminalTool, TerminationMessage,
-    ToolDescription, ToolUseError,
+    TerminationStatus, ToolDescription, ToolUseError,
 };
 use sapiens_derive::{Describe, ProtoToolDescribe, ProtoToolInvoke};
 use serde::{Deserialize, Serialize};
@@ -20,6 +20,43 @@ use tokio::sync::Mutex;
 #[allow(clippy::module_name_repetitions)]
 pub struct ConcludeTool {
     done: Mutex<Option<ConcludeToolInput>>,
+    /// The task `original_question` is checked against - if any
+    task: Option<String>,
+    /// How closely `original_question` has to match the task
+    question_match: QuestionMatch,
+}
+
+/// How closely the `original_question` given to [`ConcludeTool`] has to
+/// match the task
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QuestionMatch {
+    /// Same words - ignoring the case, the whitespaces, the quotes and the
+    /// final punctuation
+    #[default]
+    Normalized,
+    /// Same text - ignoring the leading and trailing whitespaces
+    Exact,
+}
+
+impl QuestionMatch {
+    /// Check if `question` matches `task`
+    fn matches(self, question: &str, task: &str) -> bool {
+        match self {
+            Self::Normalized => normalize(question) == normalize(task),
+            Self::Exact => question.trim() == task.trim(),
+        }
+    }
+}
+
+/// Lowercase `s`, collapse its whitespaces and drop its quotes and final
+/// punctuation
+fn normalize(s: &str) -> String {
+    s.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_matches(['"', '\'', '`'])
+        .trim_end_matches(['.', '?', '!'])
+        .to_lowercase()
 }
 
 impl Debug for ConcludeTool {
@@ -56,6 +93,14 @@ pub struct ConcludeToolInput {
     /// The original question that was asked to the user. No string
     /// interpolation supported, only plain text. MANDATORY.
     pub original_question: String,
+    /// Machine-readable results for this task, if any - e.g. a table or a
+    /// number - in addition to the `conclusion`. Optional.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_yaml::Value>,
+    /// `success` if the task is done, `failure` if it cannot be - the
+    /// `conclusion` then tells why. Optional, `success` by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<TerminationStatus>,
 }
 
 impl From<ConcludeToolInput> for TerminationMessage {
@@ -63,6 +108,9 @@ impl From<ConcludeToolInput> for TerminationMessage {
         Self {
             conclusion: input.conclusion,
             original_question: input.original_question,
+            data: input.data,
+            tool_name: None,
+            status: input.status.unwrap_or_default(),
         }
     }
 }
@@ -73,11 +121,35 @@ impl From<ConcludeToolInput> for TerminationMessage {
 pub struct ConcludeToolOutput {}
 
 impl ConcludeTool {
+    /// Only accept an `original_question` that matches `task` - see
+    /// [`ConcludeTool::with_question_match`]
+    #[must_use]
+    pub fn with_task(mut self, task: impl Into<String>) -> Self {
+        self.task = Some(task.into());
+        self
+    }
+
+    /// Set how closely `original_question` has to match the task - see
+    /// [`ConcludeTool::with_task`]
+    #[must_use]
+    pub const fn with_question_match(mut self, question_match: QuestionMatch) -> Self {
+        self.question_match = question_match;
+        self
+    }
+
     #[tracing::instrument(skip(self))]
     async fn invoke_typed(
         &self,
         input: &ConcludeToolInput,
     ) -> Result<ConcludeToolOutput, ToolUseError> {
+        if let Some(task) = &self.task {
+            if !self.question_match.matches(&input.original_question, task) {
+                return Err(ToolUseError::InvalidInput(format!(
+                    "`original_question` must be the question of the task - copy it verbatim: {task:?}"
+                )));
+            }
+        }
+
         // lock
         {
             let mut done = self.done.lock().await;
@@ -95,3 +167,120 @@ impl ConcludeTool {
         Ok(ConcludeToolOutput {})
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use sapiens::tools::toolbox::{invoke_tool, InvokeResult, Toolbox};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_conclude_with_data() {
+        let data = indoc! {r"
+        # Action
+        ```yaml
+        tool_name: Conclude
+        parameters:
+            original_question: What are the 2 largest countries?
+            conclusion: Russia and Canada.
+            data:
+              - country: Russia
+                area_km2: 17098246
+              - country: Canada
+                area_km2: 9984670
+        ```
+        "};
+
+        let toolbox = Toolbox::default();
+        toolbox.add_terminal_tool(ConcludeTool::default()).await;
+
+        let res = invoke_tool(toolbox.clone(), data).await;
+        assert!(matches!(res, InvokeResult::Success { .. }), "{res:?}");
+
+        let messages = toolbox.termination_messages().await;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].conclusion, "Russia and Canada.");
+
+        let data = messages[0].data.as_ref().unwrap();
+        assert_eq!(data[0]["country"], "Russia");
+        assert_eq!(data[1]["area_km2"], 9_984_670);
+        assert_eq!(messages[0].status, TerminationStatus::Success);
+    }
+
+    #[tokio::test]
+    async fn test_conclude_with_a_failure() {
+        let data = indoc! {r"
+        ```yaml
+        tool_name: Conclude
+        parameters:
+            original_question: What is the population of Atlantis?
+            conclusion: Atlantis is a legendary island, it has no population.
+            status: failure
+        ```
+        "};
+
+        let toolbox = Toolbox::default();
+        toolbox.add_terminal_tool(ConcludeTool::default()).await;
+
+        let res = invoke_tool(toolbox.clone(), data).await;
+        assert!(matches!(res, InvokeResult::Success { .. }), "{res:?}");
+
+        let messages = toolbox.termination_messages().await;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].status, TerminationStatus::Failure);
+    }
+
+    fn conclude(original_question: &str) -> String {
+        format!(
+            "```yaml\ntool_name: Conclude\nparameters:\n  original_question: {original_question:?}\n  conclusion: Paris.\n```\n"
+        )
+    }
+
+    #[tokio::test]
+    async fn test_conclude_checks_the_question() {
+        let task = "What is the capital of France?";
+
+        let toolbox = Toolbox::default();
+        toolbox
+            .add_terminal_tool(ConcludeTool::default().with_task(task))
+            .await;
+
+        let res = invoke_tool(toolbox.clone(), &conclude("What's France's capital?")).await;
+        let InvokeResult::Error { e, .. } = res else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert!(e.to_string().contains(task), "{e}");
+        assert!(toolbox.termination_messages().await.is_empty());
+
+        let res = invoke_tool(toolbox.clone(), &conclude("what is the  capital of France")).await;
+        assert!(matches!(res, InvokeResult::Success { .. }), "{res:?}");
+
+        let messages = toolbox.termination_messages().await;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            messages[0].original_question,
+            "what is the  capital of France"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_conclude_checks_the_exact_question() {
+        let task = "What is the capital of France?";
+
+        let toolbox = Toolbox::default();
+        toolbox
+            .add_terminal_tool(
+                ConcludeTool::default()
+                    .with_task(task)
+                    .with_question_match(QuestionMatch::Exact),
+            )
+            .await;
+
+        let res = invoke_tool(toolbox.clone(), &conclude("what is the capital of France")).await;
+        assert!(matches!(res, InvokeResult::Error { .. }), "{res:?}");
+
+        let res = invoke_tool(toolbox.clone(), &conclude(task)).await;
+        assert!(matches!(res, InvokeResult::Success { .. }), "{res:?}");
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use sapiens::tools::{
+    Describe, ProtoToolDescribe, ProtoToolInvoke, SideEffects, ToolDescription, ToolUseError,
+};
+use sapiens_derive::{Describe, ProtoToolDescribe, ProtoToolInvoke};
+use serde::{Deserialize, Serialize};
+
+/// A tool to look up a configuration value by name - e.g. a base URL or a
+/// default city.
+///
+/// Only the values it has been given are available: an unknown name fails
+/// with the list of the available ones.
+#[derive(Debug, Default, ProtoToolDescribe, ProtoToolInvoke)]
+#[tool(
+    name = "Context",
+    input = "ContextToolInput",
+    output = "ContextToolOutput",
+    read_only
+)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ContextTool {
+    values: HashMap<String, String>,
+}
+
+/// A tool to look up a configuration value by name.
+#[derive(Debug, Serialize, Deserialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ContextToolInput {
+    /// The name of the value to look up. MANDATORY.
+    pub key: String,
+}
+
+/// `ContextToolOutput` - the value
+#[derive(Debug, Serialize, Deserialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ContextToolOutput {
+    /// The value for the name.
+    pub value: String,
+}
+
+impl ContextTool {
+    /// Create a new [`ContextTool`] with the only `values` it can tell - the
+    /// environment of the process is never read.
+    #[must_use]
+    pub const fn new(values: HashMap<String, String>) -> Self {
+        Self { values }
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn invoke_typed(
+        &self,
+        input: &ContextToolInput,
+    ) -> Result<ContextToolOutput, ToolUseError> {
+        self.values
+            .get(&input.key)
+            .map(|value| ContextToolOutput {
+                value: value.clone(),
+            })
+            .ok_or_else(|| {
+                let mut keys = self.values.keys().map(String::as_str).collect::<Vec<_>>();
+                keys.sort_unstable();
+
+                ToolUseError::InvalidInput(format!(
+                    "No value for '{}'. Available: {}",
+                    input.key,
+                    keys.join(", ")
+                ))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use sapiens::tools::toolbox::{invoke_tool, InvokeResult, Toolbox};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_looks_up_the_given_values() {
+        let tool = ContextTool::new(HashMap::from([
+            (
+                "base_url".to_string(),
+                "https://example.com/api".to_string(),
+            ),
+            ("default_city".to_string(), "Paris".to_string()),
+        ]));
+
+        let toolbox = Toolbox::default();
+        toolbox.add_tool(tool).await;
+
+        let data = indoc! {r"
+        ```yaml
+        tool_name: Context
+        parameters:
+            key: default_city
+        ```
+        "};
+
+        let res = invoke_tool(toolbox.clone(), data).await;
+
+        let InvokeResult::Success { result, .. } = res else {
+            panic!("unexpected result: {res:?}");
+        };
+        let output: ContextToolOutput = serde_yaml::from_str(&result).unwrap();
+        assert_eq!(output.value, "Paris");
+
+        let data = indoc! {r"
+        ```yaml
+        tool_name: Context
+        parameters:
+            key: PATH
+        ```
+        "};
+
+        let res = invoke_tool(toolbox, data).await;
+
+        let InvokeResult::Error { e, .. } = res else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert_eq!(
+            e.to_string(),
+            ToolUseError::InvalidInput(
+                "No value for 'PATH'. Available: base_url, default_city".to_string()
+            )
+            .to_string()
+        );
+    }
+}
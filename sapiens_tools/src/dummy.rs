@@ -1,12 +1,19 @@
 use std::fmt::Debug;
 
-use sapiens::tools::{Describe, ProtoToolDescribe, ProtoToolInvoke, ToolDescription, ToolUseError};
+use sapiens::tools::{
+    Describe, ProtoToolDescribe, ProtoToolInvoke, ToolDescription, ToolExample, ToolUseError,
+};
 use sapiens_derive::{Describe, ProtoToolDescribe, ProtoToolInvoke};
 use serde::{Deserialize, Serialize};
 
 /// A tool that is called to test stuffs
 #[derive(Debug, Default, ProtoToolDescribe, ProtoToolInvoke)]
-#[tool(name = "Dummy", input = "DummyToolInput", output = "DummyToolOutput")]
+#[tool(
+    name = "Dummy",
+    input = "DummyToolInput",
+    output = "DummyToolOutput",
+    examples = "Self::examples"
+)]
 #[allow(clippy::module_name_repetitions)]
 pub struct DummyTool {}
 
@@ -27,6 +34,17 @@ pub struct DummyToolOutput {
 }
 
 impl DummyTool {
+    fn examples() -> Vec<ToolExample> {
+        vec![ToolExample::new(
+            &DummyToolInput {
+                blah: "something".to_string(),
+            },
+            &DummyToolOutput {
+                something: "something and something else".to_string(),
+            },
+        )]
+    }
+
     #[tracing::instrument(skip(self))]
     async fn invoke_typed(&self, input: &DummyToolInput) -> Result<DummyToolOutput, ToolUseError> {
         Ok(DummyToolOutput {
@@ -34,3 +52,180 @@ impl DummyTool {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use indoc::indoc;
+    use sapiens::models::replay::ReplayModel;
+    use sapiens::tools::toolbox::{invoke_tool, InvokeResult, Toolbox};
+    use sapiens::tools::{SideEffects, Tool};
+    use sapiens::{SapiensConfig, TaskState};
+
+    use super::*;
+    use crate::conclude::ConcludeTool;
+    use crate::scratchpad::ScratchpadTool;
+
+    #[test]
+    fn test_side_effects() {
+        let dummy = DummyTool::default();
+        assert_eq!(Tool::side_effects(&dummy), SideEffects::Unknown);
+        assert_eq!(Tool::description(&dummy).side_effects, SideEffects::Unknown);
+
+        let scratchpad = ScratchpadTool::default();
+        assert_eq!(Tool::side_effects(&scratchpad), SideEffects::ReadOnly);
+        assert_eq!(
+            Tool::description(&scratchpad).side_effects,
+            SideEffects::ReadOnly
+        );
+    }
+
+    /// Declare a tool echoing its input - with the given `tool` attribute
+    macro_rules! echo_tool {
+        ($(#[$meta:meta])* struct $name:ident;) => {
+            #[derive(Debug, Default, ProtoToolDescribe, ProtoToolInvoke)]
+            $(#[$meta])*
+            struct $name {}
+
+            impl $name {
+                #[allow(clippy::unused_async)]
+                async fn invoke_typed(
+                    &self,
+                    input: &DummyToolInput,
+                ) -> Result<DummyToolOutput, ToolUseError> {
+                    Ok(DummyToolOutput {
+                        something: input.blah.clone(),
+                    })
+                }
+            }
+        };
+    }
+
+    /// Invoke `tool_name` with `blah: something`
+    async fn invoke_with_something(toolbox: Toolbox, tool_name: &str) -> InvokeResult {
+        let action =
+            format!("```yaml\ntool_name: {tool_name}\nparameters:\n    blah: something\n```\n");
+        invoke_tool(toolbox, &action).await
+    }
+
+    echo_tool! {
+        /// A tool that is called to test the hints
+        #[tool(
+            name = "Hinted",
+            input = "DummyToolInput",
+            output = "DummyToolOutput",
+            hint = "Use the Conclude tool to end the task."
+        )]
+        struct HintedTool;
+    }
+
+    #[tokio::test]
+    async fn test_hint() {
+        let toolbox = Toolbox::builder()
+            .with_tool(DummyTool::default())
+            .with_tool(HintedTool::default())
+            .build();
+
+        let res = invoke_with_something(toolbox.clone(), "Hinted").await;
+        let InvokeResult::Success { hint, .. } = res else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert_eq!(
+            hint.as_deref(),
+            Some("Use the Conclude tool to end the task.")
+        );
+
+        let res = invoke_with_something(toolbox, "Dummy").await;
+        let InvokeResult::Success { hint, .. } = res else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert_eq!(hint, None);
+    }
+
+    echo_tool! {
+        /// A tool that is called to test the rendering of the results
+        #[tool(
+            name = "Formatted",
+            input = "DummyToolInput",
+            output = "DummyToolOutput",
+            format_result = "Self::render"
+        )]
+        struct FormattedTool;
+    }
+
+    impl FormattedTool {
+        #[allow(clippy::unused_self)]
+        fn render(&self, output: &serde_yaml::Value) -> Option<String> {
+            Some(format!("something={}\n", output["something"].as_str()?))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_format_result() {
+        let toolbox = Toolbox::builder()
+            .with_tool(FormattedTool::default())
+            .build();
+
+        let res = invoke_with_something(toolbox, "Formatted").await;
+        let InvokeResult::Success {
+            result, rendered, ..
+        } = res
+        else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert_eq!(result, "something: something\n");
+        assert_eq!(rendered.as_deref(), Some("something=something\n"));
+    }
+
+    #[tokio::test]
+    async fn test_replayed_task() {
+        let responses = [
+            indoc! {r"
+            ```yaml
+            tool_name: Dummy
+            parameters:
+                blah: something
+            ```
+            "},
+            indoc! {r#"
+            ```yaml
+            tool_name: Conclude
+            parameters:
+                original_question: Do something.
+                conclusion: "something and something else"
+            ```
+            "#},
+        ];
+        let model = ReplayModel::new(responses.iter().map(ToString::to_string).collect());
+
+        let toolbox = Toolbox::builder()
+            .with_tool(DummyTool::default())
+            .with_terminal_tool(ConcludeTool::default())
+            .build();
+
+        let config = SapiensConfig {
+            model: Arc::new(Box::new(model)),
+            ..SapiensConfig::default()
+        };
+
+        let stop = TaskState::new(config, toolbox.clone(), "Do something.".to_string())
+            .await
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(stop.steps, 2);
+        assert_eq!(stop.termination_messages.len(), 1);
+        assert_eq!(
+            stop.termination_messages[0].conclusion,
+            "something and something else"
+        );
+
+        let stats = toolbox.stats().await;
+        assert_eq!(stats.success_count["Dummy"], 1);
+        assert_eq!(stats.success_count["Conclude"], 1);
+        assert!(stats.error_count.is_empty());
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt::{Debug, Display};
+
+use base64::Engine;
+use sapiens::tools::{
+    Describe, ProtoToolDescribe, ProtoToolInvoke, SideEffects, ToolDescription, ToolUseError,
+};
+use sapiens_derive::{Describe, ProtoToolDescribe, ProtoToolInvoke};
+use serde::{Deserialize, Serialize};
+
+/// A Tool to convert data between common formats.
+///
+/// Encodes or decodes base64, converts JSON to YAML and back, encodes or
+/// decodes URL components. No need for Python for that.
+#[derive(Debug, Default, ProtoToolDescribe, ProtoToolInvoke)]
+#[tool(
+    name = "Encode",
+    input = "EncodeToolInput",
+    output = "EncodeToolOutput",
+    parallel_safe,
+    read_only
+)]
+#[allow(clippy::module_name_repetitions)]
+pub struct EncodeTool {}
+
+/// An operation of the [`EncodeTool`]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    /// Encode to base64
+    Base64Encode,
+    /// Decode from base64 - to UTF-8 text
+    Base64Decode,
+    /// Convert JSON to YAML
+    JsonToYaml,
+    /// Convert YAML to JSON
+    YamlToJson,
+    /// Percent-encode a URL component
+    UrlEncode,
+    /// Decode a percent-encoded URL component
+    UrlDecode,
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Base64Encode => write!(f, "base64_encode"),
+            Self::Base64Decode => write!(f, "base64_decode"),
+            Self::JsonToYaml => write!(f, "json_to_yaml"),
+            Self::YamlToJson => write!(f, "yaml_to_json"),
+            Self::UrlEncode => write!(f, "url_encode"),
+            Self::UrlDecode => write!(f, "url_decode"),
+        }
+    }
+}
+
+/// [`EncodeTool`] input
+#[derive(Debug, Deserialize, Serialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct EncodeToolInput {
+    /// The conversion to apply. One of `base64_encode`, `base64_decode`,
+    /// `json_to_yaml`, `yaml_to_json`, `url_encode` or `url_decode`.
+    /// MANDATORY.
+    pub operation: Operation,
+    /// The data to convert. MANDATORY.
+    pub data: String,
+}
+
+/// [`EncodeTool`] output
+#[derive(Debug, Deserialize, Serialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct EncodeToolOutput {
+    /// The converted data.
+    pub result: String,
+}
+
+impl EncodeTool {
+    #[tracing::instrument(skip(self))]
+    async fn invoke_typed(
+        &self,
+        input: &EncodeToolInput,
+    ) -> Result<EncodeToolOutput, ToolUseError> {
+        let result = encode(input.operation, &input.data)
+            .map_err(|e| ToolUseError::InvalidInput(format!("Cannot {}: {e}", input.operation)))?;
+
+        Ok(EncodeToolOutput { result })
+    }
+}
+
+/// Apply `operation` to `data`
+///
+/// # Errors
+///
+/// Why `data` is not valid for `operation`.
+pub fn encode(operation: Operation, data: &str) -> Result<String, String> {
+    let engine = base64::engine::general_purpose::STANDARD;
+
+    match operation {
+        Operation::Base64Encode => Ok(engine.encode(data)),
+        Operation::Base64Decode => {
+            let bytes = engine
+                .decode(data.trim())
+                .map_err(|e| format!("invalid base64: {e}"))?;
+
+            String::from_utf8(bytes).map_err(|_| "the decoded data is not UTF-8 text".to_string())
+        }
+        Operation::JsonToYaml => {
+            // a YAML value keeps the order of the keys
+            let value: serde_yaml::Value =
+                serde_json::from_str(data).map_err(|e| format!("invalid JSON: {e}"))?;
+
+            serde_yaml::to_string(&value).map_err(|e| e.to_string())
+        }
+        Operation::YamlToJson => {
+            let value: serde_yaml::Value =
+                serde_yaml::from_str(data).map_err(|e| format!("invalid YAML: {e}"))?;
+
+            serde_json::to_string(&value).map_err(|e| format!("not representable as JSON: {e}"))
+        }
+        Operation::UrlEncode => Ok(urlencoding::encode(data).into_owned()),
+        Operation::UrlDecode => urlencoding::decode(data)
+            .map(std::borrow::Cow::into_owned)
+            .map_err(|_| "the decoded data is not UTF-8 text".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use sapiens::tools::toolbox::{invoke_tool, InvokeResult, Toolbox};
+
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(
+            encode(Operation::Base64Encode, "hello world").unwrap(),
+            "aGVsbG8gd29ybGQ="
+        );
+        assert_eq!(
+            encode(Operation::Base64Decode, "aGVsbG8gd29ybGQ=\n").unwrap(),
+            "hello world"
+        );
+    }
+
+    #[test]
+    fn test_json_and_yaml() {
+        assert_eq!(
+            encode(
+                Operation::JsonToYaml,
+                r#"{"name": "Mars", "moons": [1, 2]}"#
+            )
+            .unwrap(),
+            "name: Mars\nmoons:\n- 1\n- 2\n"
+        );
+        assert_eq!(
+            encode(Operation::YamlToJson, "name: Mars\nmoons:\n- 1\n- 2\n").unwrap(),
+            r#"{"name":"Mars","moons":[1,2]}"#
+        );
+
+        let e = encode(Operation::JsonToYaml, "{name: Mars}").unwrap_err();
+        assert!(e.starts_with("invalid JSON: "), "{e}");
+        let e = encode(Operation::YamlToJson, "name: [Mars").unwrap_err();
+        assert!(e.starts_with("invalid YAML: "), "{e}");
+        let e = encode(Operation::YamlToJson, "[1, 2]: Mars").unwrap_err();
+        assert!(e.starts_with("not representable as JSON: "), "{e}");
+    }
+
+    #[test]
+    fn test_url() {
+        assert_eq!(
+            encode(Operation::UrlEncode, "moons of mars/é?").unwrap(),
+            "moons%20of%20mars%2F%C3%A9%3F"
+        );
+        assert_eq!(
+            encode(Operation::UrlDecode, "moons%20of%20mars%2F%C3%A9%3F").unwrap(),
+            "moons of mars/é?"
+        );
+        assert!(encode(Operation::UrlDecode, "%FF").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_malformed_base64() {
+        let data = indoc! {r"
+        ```yaml
+        tool_name: Encode
+        parameters:
+            operation: base64_decode
+            data: not base64!
+        ```
+        "};
+
+        let toolbox = Toolbox::default();
+        toolbox.add_tool(EncodeTool::default()).await;
+
+        let res = invoke_tool(toolbox, data).await;
+
+        let InvokeResult::Error { tool_name, e, .. } = res else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert_eq!(tool_name, "Encode");
+        assert!(matches!(e, ToolUseError::InvalidInput(_)), "{e:?}");
+        assert!(
+            e.to_string()
+                .contains("Cannot base64_decode: invalid base64: "),
+            "{e}"
+        );
+    }
+}
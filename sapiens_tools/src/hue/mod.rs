@@ -1,10 +1,129 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+
+use huelib2::resource::light::StateModifier;
+use huelib2::resource::ColorMode;
+use sapiens::tools::ToolUseError;
 use serde::{Deserialize, Serialize};
 
 /// Tools to get information about rooms and their lights.
 pub mod room;
+/// Tools to list and activate scenes
+pub mod scene;
 /// Tools to get information about the lights
 pub mod status;
 
+/// Connect to the bridge at `HUE_BRIDGE_IP` - or the one discovered on the
+/// network if not set - as `HUE_USERNAME` - or as a user registered on the
+/// bridge if not set
+///
+/// # Panics
+///
+/// If `HUE_BRIDGE_IP` is invalid, no bridge can be discovered or no user can
+/// be registered.
+#[must_use]
+pub fn bridge_from_env() -> huelib2::bridge::Bridge {
+    let bridge_ip = std::env::var("HUE_BRIDGE_IP").map_or_else(
+        |_| {
+            println!("HUE_BRIDGE_IP env not set. Trying to discover bridge.");
+            let bridge_ip = huelib2::bridge::discover_nupnp()
+                .expect("Failed to discover bridge")
+                .pop()
+                .expect("No bridges found");
+            println!("Discovered bridge at IP address: HUE_BRIDGE_IP={bridge_ip}");
+            bridge_ip
+        },
+        |ip| IpAddr::from_str(&ip).expect("Invalid HUE_BRIDGE_IP"),
+    );
+
+    let username = std::env::var("HUE_USERNAME").unwrap_or_else(|_| {
+        println!("HUE_USERNAME env not set. Trying to register a new user.");
+        let username =
+            huelib2::bridge::register_user(bridge_ip, "sapiens").expect("Failed to register user");
+        println!("Registered a new user - pass it as env: \nHUE_USERNAME={username}");
+        username
+    });
+
+    huelib2::bridge::Bridge::new(bridge_ip, username)
+}
+
+/// The bridge shared by all the tools - see [`bridge_from_env`].
+///
+/// The bridge is discovered the first time only.
+///
+/// # Panics
+///
+/// See [`bridge_from_env`].
+#[must_use]
+pub fn shared_bridge() -> Arc<huelib2::bridge::Bridge> {
+    static BRIDGE: OnceLock<Arc<huelib2::bridge::Bridge>> = OnceLock::new();
+
+    cached(&BRIDGE, bridge_from_env)
+}
+
+/// Get the bridge in `cell` or `connect` to it
+fn cached(
+    cell: &OnceLock<Arc<huelib2::bridge::Bridge>>,
+    connect: impl FnOnce() -> huelib2::bridge::Bridge,
+) -> Arc<huelib2::bridge::Bridge> {
+    cell.get_or_init(|| Arc::new(connect())).clone()
+}
+
+/// The operations on a Hue bridge the tools rely on
+pub trait HueBridge: Send + Sync {
+    /// Get all the lights
+    fn lights(&self) -> Result<Vec<Light>, ToolUseError>;
+
+    /// Modify the state of the light `id`
+    fn set_light_state(&self, id: &str, modifier: &StateModifier) -> Result<(), ToolUseError>;
+
+    /// Get all the scenes
+    fn scenes(&self) -> Result<Vec<Scene>, ToolUseError>;
+
+    /// Activate the scene `id`
+    fn activate_scene(&self, id: &str) -> Result<(), ToolUseError>;
+}
+
+impl HueBridge for huelib2::bridge::Bridge {
+    fn lights(&self) -> Result<Vec<Light>, ToolUseError> {
+        self.get_all_lights()
+            .map(|lights| lights.into_iter().map(Into::into).collect())
+            .map_err(|e| ToolUseError::InvocationFailed(e.to_string()))
+    }
+
+    fn set_light_state(&self, id: &str, modifier: &StateModifier) -> Result<(), ToolUseError> {
+        self.set_light_state(id, modifier).map(|_| ()).map_err(|e| {
+            ToolUseError::InvocationFailed(format!("Failed to set light state for light {id}: {e}"))
+        })
+    }
+
+    fn scenes(&self) -> Result<Vec<Scene>, ToolUseError> {
+        self.get_all_scenes()
+            .map(|scenes| scenes.into_iter().map(Into::into).collect())
+            .map_err(|e| ToolUseError::InvocationFailed(e.to_string()))
+    }
+
+    fn activate_scene(&self, id: &str) -> Result<(), ToolUseError> {
+        // group scenes are recalled on their group, light scenes on the
+        // special group 0 that contains all the lights
+        let group = HueBridge::scenes(self)?
+            .into_iter()
+            .find(|s| s.id == id)
+            .ok_or_else(|| ToolUseError::InvocationFailed(format!("No scene with ID {id}")))?
+            .group
+            .unwrap_or_else(|| "0".to_string());
+
+        let modifier = huelib2::resource::group::StateModifier::new().with_scene(id.to_string());
+
+        self.set_group_state(group, &modifier)
+            .map(|_| ())
+            .map_err(|e| {
+                ToolUseError::InvocationFailed(format!("Failed to activate scene {id}: {e}"))
+            })
+    }
+}
+
 /// State of a light.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Eq)]
 pub struct State {
@@ -33,10 +152,13 @@ pub struct State {
     // pub alert: Option<Alert>,
     // /// Dynamic effect of the light.
     // pub effect: Option<Effect>,
-    // /// Color mode of the light.
-    // pub color_mode: Option<ColorMode>,
-    // Whether the light can be reached by the bridge.
-    // pub reachable: bool,
+    /// Color mode of the light: `ct` for color temperature, `hs` for hue and
+    /// saturation or `xy` for color space coordinates.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color_mode: Option<String>,
+    /// Whether the light can be reached by the bridge.
+    #[serde(default)]
+    pub reachable: bool,
 }
 
 impl From<huelib2::resource::light::State> for State {
@@ -47,6 +169,15 @@ impl From<huelib2::resource::light::State> for State {
             hue: value.hue,
             saturation: value.saturation,
             color_temperature: value.color_temperature,
+            color_mode: value.color_mode.map(|mode| {
+                match mode {
+                    ColorMode::ColorTemperature => "ct",
+                    ColorMode::HueAndSaturation => "hs",
+                    ColorMode::ColorSpaceCoordinates => "xy",
+                }
+                .to_string()
+            }),
+            reachable: value.reachable,
         }
     }
 }
@@ -94,3 +225,182 @@ impl From<huelib2::resource::group::Group> for Room {
         }
     }
 }
+
+/// A scene.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Scene {
+    /// Identifier of the scene.
+    pub id: String,
+    /// Name of the scene.
+    pub name: String,
+    /// Identifier of the group the scene belongs to, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Identifiers of lights that are in this scene.
+    pub lights: Vec<String>,
+}
+
+impl From<huelib2::resource::Scene> for Scene {
+    fn from(value: huelib2::resource::Scene) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            group: value.group,
+            lights: value.lights.unwrap_or_default(),
+        }
+    }
+}
+
+/// A fake [`HueBridge`]
+pub mod fake {
+    use std::sync::Mutex;
+
+    use huelib2::resource::light::StateModifier;
+    use huelib2::resource::Adjust;
+    use sapiens::tools::ToolUseError;
+
+    use crate::hue::{HueBridge, Light, Scene, State};
+
+    /// A fake [`HueBridge`] keeping the state of its lights in memory
+    #[derive(Debug)]
+    #[allow(clippy::module_name_repetitions)]
+    pub struct FakeBridge {
+        lights: Mutex<Vec<Light>>,
+        scenes: Vec<Scene>,
+    }
+
+    impl Default for FakeBridge {
+        fn default() -> Self {
+            let light = |id: &str, name: &str| Light {
+                id: id.to_string(),
+                name: Some(name.to_string()),
+                state: State {
+                    on: Some(false),
+                    brightness: Some(0),
+                    hue: Some(0),
+                    saturation: Some(0),
+                    color_temperature: Some(153),
+                    color_mode: Some("hs".to_string()),
+                    reachable: true,
+                },
+            };
+
+            Self {
+                lights: Mutex::new(vec![light("1", "Bed"), light("2", "Ceiling")]),
+                scenes: vec![Scene {
+                    id: "abc".to_string(),
+                    name: "Relax".to_string(),
+                    group: Some("1".to_string()),
+                    lights: vec!["2".to_string()],
+                }],
+            }
+        }
+    }
+
+    /// Apply an [`Adjust::Override`] - the only one the tools use
+    fn apply<T>(value: &mut Option<T>, adjust: Option<&Adjust<T>>)
+    where
+        T: Copy,
+    {
+        if let Some(Adjust::Override(v)) = adjust {
+            *value = Some(*v);
+        }
+    }
+
+    impl HueBridge for FakeBridge {
+        fn lights(&self) -> Result<Vec<Light>, ToolUseError> {
+            Ok(self.lights.lock().unwrap().clone())
+        }
+
+        fn set_light_state(&self, id: &str, modifier: &StateModifier) -> Result<(), ToolUseError> {
+            let mut lights = self.lights.lock().unwrap();
+            let light = lights
+                .iter_mut()
+                .find(|l| l.id == id)
+                .ok_or_else(|| ToolUseError::InvocationFailed(format!("No light {id}")))?;
+
+            if let Some(on) = modifier.on {
+                light.state.on = Some(on);
+            }
+            apply(&mut light.state.brightness, modifier.brightness.as_ref());
+            apply(&mut light.state.hue, modifier.hue.as_ref());
+            apply(&mut light.state.saturation, modifier.saturation.as_ref());
+            apply(
+                &mut light.state.color_temperature,
+                modifier.color_temperature.as_ref(),
+            );
+            drop(lights);
+
+            Ok(())
+        }
+
+        fn scenes(&self) -> Result<Vec<Scene>, ToolUseError> {
+            Ok(self.scenes.clone())
+        }
+
+        /// Turn on the lights of the scene at half brightness
+        fn activate_scene(&self, id: &str) -> Result<(), ToolUseError> {
+            let scene = self
+                .scenes
+                .iter()
+                .find(|s| s.id == id)
+                .ok_or_else(|| ToolUseError::InvocationFailed(format!("No scene {id}")))?;
+
+            let mut lights = self.lights.lock().unwrap();
+            for light in lights.iter_mut().filter(|l| scene.lights.contains(&l.id)) {
+                light.state.on = Some(true);
+                light.state.brightness = Some(127);
+            }
+            drop(lights);
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::hue::room::RoomTool;
+    use crate::hue::status::StatusTool;
+
+    #[test]
+    fn discovers_the_bridge_at_most_once() {
+        let cell = OnceLock::new();
+        let discoveries = AtomicUsize::new(0);
+        let connect = || {
+            discoveries.fetch_add(1, Ordering::SeqCst);
+            huelib2::bridge::Bridge::new(IpAddr::V4(Ipv4Addr::LOCALHOST), "test")
+        };
+
+        let status = StatusTool::new(cached(&cell, connect));
+        let room = RoomTool::new(cached(&cell, connect));
+
+        assert_eq!(discoveries.load(Ordering::SeqCst), 1);
+        assert!(Arc::ptr_eq(&status.bridge, &room.bridge));
+    }
+
+    #[test]
+    fn exposes_reachability_and_color_mode() {
+        let state: huelib2::resource::light::State = serde_json::from_str(
+            r#"{"on": true, "bri": 126, "hue": 2456, "sat": 55, "ct": 250,
+                "colormode": "ct", "reachable": false}"#,
+        )
+        .unwrap();
+        let light = Light {
+            id: "1".to_string(),
+            name: Some("Corridor".to_string()),
+            state: state.into(),
+        };
+
+        let light = serde_json::to_value(light).unwrap();
+
+        assert_eq!(light["color_mode"], "ct");
+        assert_eq!(light["reachable"], false);
+        assert_eq!(light["brightness"], 126);
+        assert_eq!(light["color_temperature"], 250);
+    }
+}
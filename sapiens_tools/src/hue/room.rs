@@ -1,8 +1,11 @@
 use std::fmt::Debug;
+use std::sync::Arc;
 
 use huelib2::resource::group::CreatableKind;
 use huelib2::resource::group::Kind::Creatable;
-use sapiens::tools::{Describe, ProtoToolDescribe, ProtoToolInvoke, ToolDescription, ToolUseError};
+use sapiens::tools::{
+    Describe, ProtoToolDescribe, ProtoToolInvoke, SideEffects, ToolDescription, ToolUseError,
+};
 use sapiens_derive::{Describe, ProtoToolDescribe, ProtoToolInvoke};
 use serde::{Deserialize, Serialize};
 
@@ -10,10 +13,16 @@ use crate::hue::Room;
 
 /// A tool to use that the source of truth for the Lights of a Room.
 #[derive(ProtoToolDescribe, ProtoToolInvoke)]
-#[tool(name = "Room", input = "RoomToolInput", output = "RoomToolOutput")]
+#[tool(
+    name = "Room",
+    input = "RoomToolInput",
+    output = "RoomToolOutput",
+    concurrency_key = "hue",
+    read_only
+)]
 #[allow(clippy::module_name_repetitions)]
 pub struct RoomTool {
-    bridge: huelib2::bridge::Bridge,
+    pub(crate) bridge: Arc<huelib2::bridge::Bridge>,
 }
 
 impl Debug for RoomTool {
@@ -25,23 +34,14 @@ impl Debug for RoomTool {
 impl RoomTool {
     /// Create a new `RoomTool`
     #[must_use]
-    pub const fn new(bridge: huelib2::bridge::Bridge) -> Self {
+    pub const fn new(bridge: Arc<huelib2::bridge::Bridge>) -> Self {
         Self { bridge }
     }
 }
 
 impl Default for RoomTool {
     fn default() -> Self {
-        let bridge_ip = huelib2::bridge::discover_nupnp()
-            .expect("Failed to discover bridge")
-            .pop()
-            .expect("No bridges found");
-
-        let username = std::env::var("HUE_USERNAME").expect("HUE_USERNAME not set");
-
-        let bridge = huelib2::bridge::Bridge::new(bridge_ip, username);
-
-        Self::new(bridge)
+        Self::new(crate::hue::shared_bridge())
     }
 }
 
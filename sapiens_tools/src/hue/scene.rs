@@ -0,0 +1,161 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use sapiens::tools::{
+    Describe, ProtoToolDescribe, ProtoToolInvoke, SideEffects, ToolDescription, ToolUseError,
+};
+use sapiens_derive::{Describe, ProtoToolDescribe, ProtoToolInvoke};
+use serde::{Deserialize, Serialize};
+
+use crate::hue::{HueBridge, Scene};
+
+/// A tool to list the Scenes available on the bridge or to activate one of
+/// them.
+#[derive(ProtoToolDescribe, ProtoToolInvoke)]
+#[tool(
+    name = "Scene",
+    input = "SceneToolInput",
+    output = "SceneToolOutput",
+    concurrency_key = "hue",
+    mutating
+)]
+#[allow(clippy::module_name_repetitions)]
+pub struct SceneTool {
+    bridge: Arc<dyn HueBridge>,
+}
+
+impl Debug for SceneTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SceneTool").finish()
+    }
+}
+
+impl Default for SceneTool {
+    fn default() -> Self {
+        Self::new(crate::hue::shared_bridge())
+    }
+}
+
+/// What to do with the Scenes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SceneAction {
+    /// List the Scenes
+    List,
+    /// Activate a Scene
+    Activate,
+}
+
+/// The input of the tool
+#[derive(Debug, Serialize, Deserialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct SceneToolInput {
+    /// Either `list` to get the available Scenes or `activate` to activate
+    /// the Scene with the ID `scene_id`.
+    pub action: SceneAction,
+    /// The ID of the Scene to activate, e.g.: `"4e1c6b20e-on-0"`. Required to
+    /// `activate`.
+    pub scene_id: Option<String>,
+}
+
+/// The output of the tool
+#[derive(Debug, Serialize, Deserialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct SceneToolOutput {
+    /// The available Scenes - or the one that has been activated. E.g.:
+    /// `[{"id": "4e1c6b20e-on-0", "name": "Relax", "group": "1", "lights":
+    /// ["1", "2"]}]`
+    pub scenes: Vec<Scene>,
+}
+
+impl SceneTool {
+    /// Create a new `SceneTool`
+    #[must_use]
+    pub fn new(bridge: Arc<dyn HueBridge>) -> Self {
+        Self { bridge }
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn invoke_typed(&self, input: &SceneToolInput) -> Result<SceneToolOutput, ToolUseError> {
+        let scenes = self.bridge.scenes()?;
+
+        match input.action {
+            SceneAction::List => Ok(SceneToolOutput { scenes }),
+            SceneAction::Activate => {
+                let scene_id = input.scene_id.as_ref().ok_or_else(|| {
+                    ToolUseError::InvalidInput("`scene_id` is required to `activate`.".to_string())
+                })?;
+
+                let scene = scenes
+                    .into_iter()
+                    .find(|s| &s.id == scene_id)
+                    .ok_or_else(|| {
+                        ToolUseError::InvalidInput(format!("No Scene with ID {scene_id}."))
+                    })?;
+
+                self.bridge.activate_scene(scene_id)?;
+
+                Ok(SceneToolOutput {
+                    scenes: vec![scene],
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hue::fake::FakeBridge;
+
+    fn input(yaml: &str) -> SceneToolInput {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[tokio::test]
+    async fn lists_the_scenes() {
+        let tool = SceneTool::new(Arc::new(FakeBridge::default()));
+
+        let output = tool.invoke_typed(&input("action: list")).await.unwrap();
+
+        assert_eq!(output.scenes.len(), 1);
+        assert_eq!(output.scenes[0].id, "abc");
+        assert_eq!(output.scenes[0].name, "Relax");
+    }
+
+    #[tokio::test]
+    async fn activates_a_scene() {
+        let bridge = Arc::new(FakeBridge::default());
+        let tool = SceneTool::new(bridge.clone());
+
+        let output = tool
+            .invoke_typed(&input("action: activate\nscene_id: abc"))
+            .await
+            .unwrap();
+
+        assert_eq!(output.scenes.len(), 1);
+        assert_eq!(output.scenes[0].name, "Relax");
+
+        let lights = bridge.lights().unwrap();
+        assert_eq!(lights[0].state.on, Some(false));
+        assert_eq!(lights[1].state.on, Some(true));
+        assert_eq!(lights[1].state.brightness, Some(127));
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_or_missing_scene() {
+        let tool = SceneTool::new(Arc::new(FakeBridge::default()));
+
+        let e = tool
+            .invoke_typed(&input("action: activate"))
+            .await
+            .unwrap_err();
+        assert!(matches!(e, ToolUseError::InvalidInput(_)));
+
+        let e = tool
+            .invoke_typed(&input("action: activate\nscene_id: xyz"))
+            .await
+            .unwrap_err();
+        assert!(matches!(e, ToolUseError::InvalidInput(msg) if msg == "No Scene with ID xyz."));
+    }
+}
@@ -1,36 +1,35 @@
 use std::fmt::Debug;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
 
+use huelib2::resource::light::StateModifier;
 use huelib2::resource::Adjust;
-use sapiens::tools::{Describe, ProtoToolDescribe, ProtoToolInvoke, ToolDescription, ToolUseError};
+use sapiens::tools::{
+    Describe, ProtoToolDescribe, ProtoToolInvoke, SideEffects, ToolDescription, ToolUseError,
+};
 use sapiens_derive::{Describe, ProtoToolDescribe, ProtoToolInvoke};
 use serde::{Deserialize, Serialize};
 
-use crate::hue::Light;
+use crate::hue::{HueBridge, Light};
 
 /// A tool to use as the source of truth for the Light statuses.
 #[derive(ProtoToolDescribe, ProtoToolInvoke)]
 #[tool(
     name = "LightStatus",
     input = "StatusToolInput",
-    output = "StatusToolOutput"
+    output = "StatusToolOutput",
+    concurrency_key = "hue",
+    read_only,
+    hint = "Use SetLightStatus to change the state of a light."
 )]
 #[allow(clippy::module_name_repetitions)]
 pub struct StatusTool {
-    bridge: huelib2::bridge::Bridge,
+    pub(crate) bridge: Arc<huelib2::bridge::Bridge>,
 }
 
 impl Default for StatusTool {
     fn default() -> Self {
-        let bridge_ip = huelib2::bridge::discover_nupnp()
-            .expect("Failed to discover bridge")
-            .pop()
-            .expect("No bridges found");
-
-        let username = std::env::var("HUE_USERNAME").expect("HUE_USERNAME not set");
-
-        let bridge = huelib2::bridge::Bridge::new(bridge_ip, username);
-
-        Self::new(bridge)
+        Self::new(crate::hue::shared_bridge())
     }
 }
 
@@ -49,14 +48,14 @@ pub struct StatusToolInput {
 pub struct StatusToolOutput {
     /// A list of Lights with their statuses. E.g.: `[{"id": "1", "name":
     /// "Corridor", "on": True, "brightness": 126, "hue": 2456, "saturation":
-    /// 55, "color_temperature": 2500}]`
+    /// 55, "color_temperature": 2500, "color_mode": "hs", "reachable": True}]`
     pub lights: Vec<Light>,
 }
 
 impl StatusTool {
     /// Create a new `StatusTool`
     #[must_use]
-    pub const fn new(bridge: huelib2::bridge::Bridge) -> Self {
+    pub const fn new(bridge: Arc<huelib2::bridge::Bridge>) -> Self {
         Self { bridge }
     }
 
@@ -86,16 +85,18 @@ impl StatusTool {
     }
 }
 
-/// A tool to use as the set the Light statuses.
+/// A tool to use as the set the Light statuses: on/off, brightness and color.
 #[derive(ProtoToolDescribe, ProtoToolInvoke)]
 #[tool(
     name = "SetLightStatus",
     input = "SetStatusToolInput",
-    output = "StatusToolOutput"
+    output = "StatusToolOutput",
+    concurrency_key = "hue",
+    mutating
 )]
 #[allow(clippy::module_name_repetitions)]
 pub struct SetStatusTool {
-    bridge: huelib2::bridge::Bridge,
+    pub(crate) bridge: Arc<huelib2::bridge::Bridge>,
 }
 
 impl Debug for SetStatusTool {
@@ -107,7 +108,7 @@ impl Debug for SetStatusTool {
 impl SetStatusTool {
     /// Create a new `StatusTool`
     #[must_use]
-    pub const fn new(bridge: huelib2::bridge::Bridge) -> Self {
+    pub const fn new(bridge: Arc<huelib2::bridge::Bridge>) -> Self {
         Self { bridge }
     }
 
@@ -116,79 +117,45 @@ impl SetStatusTool {
         &self,
         input: &SetStatusToolInput,
     ) -> Result<StatusToolOutput, ToolUseError> {
-        if let Some(lights) = &input.lights {
-            for light in lights {
-                let state = huelib2::resource::light::StateModifier {
-                    on: light.state.on,
-                    brightness: light.state.brightness.map(Adjust::Override),
-                    hue: light.state.hue.map(Adjust::Override),
-                    saturation: light.state.saturation.map(Adjust::Override),
-                    color_space_coordinates: None,
-                    color_temperature: light.state.color_temperature.map(Adjust::Override),
-                    alert: None,
-                    effect: None,
-                    transition_time: None,
-                };
-
-                self.bridge
-                    .set_light_state(&light.id, &state)
-                    .map_err(|e| {
-                        ToolUseError::InvocationFailed(format!(
-                            "Failed to set light state for light {}: {}",
-                            light.id, e
-                        ))
-                    })?;
-            }
-        }
+        set_status(self.bridge.as_ref(), input)
+    }
+}
 
-        // collect light IDs
-        let light_ids: Vec<String> = input
-            .lights
-            .as_ref()
-            .map(|lights| lights.iter().map(|l| l.id.clone()).collect())
-            .unwrap_or_default();
-
-        if light_ids.is_empty() {
-            return Err(ToolUseError::InvocationFailed(
-                "No lights to set status for".to_string(),
-            ));
-        }
+/// Set the statuses of the Lights in `input` - all the values are checked
+/// before any Light is changed
+fn set_status(
+    bridge: &dyn HueBridge,
+    input: &SetStatusToolInput,
+) -> Result<StatusToolOutput, ToolUseError> {
+    let lights = input.lights.as_deref().unwrap_or_default();
+
+    if lights.is_empty() {
+        return Err(ToolUseError::InvocationFailed(
+            "No lights to set status for".to_string(),
+        ));
+    }
 
-        // get all lights
-        let lights_status: Vec<Light> = self
-            .bridge
-            .get_all_lights()
-            .map_err(|e| ToolUseError::InvocationFailed(e.to_string()))?
-            .into_iter()
-            .map(std::convert::Into::into)
-            .collect();
-
-        // filter lights
-        let lights = if light_ids.is_empty() {
-            lights_status
-        } else {
-            lights_status
-                .into_iter()
-                .filter(|l| light_ids.contains(&l.id))
-                .collect()
-        };
+    let modifiers = lights
+        .iter()
+        .map(|light| Ok((light.id.as_str(), light.modifier()?)))
+        .collect::<Result<Vec<_>, ToolUseError>>()?;
 
-        Ok(StatusToolOutput { lights })
+    for (id, modifier) in &modifiers {
+        bridge.set_light_state(id, modifier)?;
     }
+
+    let lights = bridge
+        .lights()?
+        .into_iter()
+        .filter(|l| modifiers.iter().any(|(id, _)| *id == l.id))
+        .collect();
+
+    Ok(StatusToolOutput { lights })
 }
 
 impl Default for SetStatusTool {
     fn default() -> Self {
-        let bridge_ip = huelib2::bridge::discover_nupnp()
-            .expect("Failed to discover bridge")
-            .pop()
-            .expect("No bridges found");
-
-        let username = std::env::var("HUE_USERNAME").expect("HUE_USERNAME not set");
-
-        let bridge = huelib2::bridge::Bridge::new(bridge_ip, username);
-
-        Self::new(bridge)
+        Self::new(crate::hue::shared_bridge())
     }
 }
 
@@ -197,8 +164,75 @@ impl Default for SetStatusTool {
 pub struct SetStatusToolInput {
     /// The list of Lights statuses to set for, e.g.: `[{"id": "1", "on": True,
     /// "brightness": 126, "hue": 2456, "saturation":
-    /// 55, "color_temperature": 2500}]`. Omitted fields will not be changed.
-    pub lights: Option<Vec<Light>>,
+    /// 55, "color_temperature": 250}]`. Omitted fields will not be changed.
+    pub lights: Option<Vec<LightStatus>>,
+}
+
+/// The status to set for a Light
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LightStatus {
+    /// The ID of the Light, e.g.: `"1"`.
+    pub id: String,
+    /// Whether the Light is on.
+    pub on: Option<bool>,
+    /// Brightness of the Light - between 0 and 254.
+    pub brightness: Option<u32>,
+    /// Hue of the Light - between 0 and 65535. Both 0 and 65535 are red, 25500
+    /// is green and 46920 is blue.
+    pub hue: Option<u32>,
+    /// Saturation of the Light - between 0 (white) and 254 (colored).
+    pub saturation: Option<u32>,
+    /// Mired color temperature of the Light - between 153 (cold) and 500
+    /// (warm).
+    pub color_temperature: Option<u32>,
+}
+
+impl LightStatus {
+    /// The modifier setting this status - if all the values are in range
+    fn modifier(&self) -> Result<StateModifier, ToolUseError> {
+        let brightness = in_range::<u8>("brightness", self.brightness, 0..=254)?;
+        let hue = in_range::<u16>("hue", self.hue, 0..=65535)?;
+        let saturation = in_range::<u8>("saturation", self.saturation, 0..=254)?;
+        let color_temperature =
+            in_range::<u16>("color_temperature", self.color_temperature, 153..=500)?;
+
+        Ok(StateModifier {
+            on: self.on,
+            brightness: brightness.map(Adjust::Override),
+            hue: hue.map(Adjust::Override),
+            saturation: saturation.map(Adjust::Override),
+            color_space_coordinates: None,
+            color_temperature: color_temperature.map(Adjust::Override),
+            alert: None,
+            effect: None,
+            transition_time: None,
+        })
+    }
+}
+
+/// Check `value` of the field `name` is in `range`
+fn in_range<T>(
+    name: &str,
+    value: Option<u32>,
+    range: RangeInclusive<u32>,
+) -> Result<Option<T>, ToolUseError>
+where
+    T: TryFrom<u32>,
+{
+    value
+        .map(|v| {
+            if range.contains(&v) {
+                T::try_from(v)
+                    .map_err(|_| ToolUseError::InvalidInput(format!("Invalid {name}: {v}")))
+            } else {
+                Err(ToolUseError::InvalidInput(format!(
+                    "`{name}` must be between {} and {}, got {v}.",
+                    range.start(),
+                    range.end()
+                )))
+            }
+        })
+        .transpose()
 }
 
 /// A fake `StatusTool`
@@ -269,6 +303,8 @@ pub mod fake {
                         hue: Some(2456),
                         saturation: Some(55),
                         color_temperature: Some(2500),
+                        color_mode: Some("hs".to_string()),
+                        reachable: true,
                     },
                 },
                 Light {
@@ -280,6 +316,8 @@ pub mod fake {
                         hue: Some(0),
                         saturation: Some(0),
                         color_temperature: Some(0),
+                        color_mode: Some("ct".to_string()),
+                        reachable: true,
                     },
                 },
                 Light {
@@ -291,6 +329,8 @@ pub mod fake {
                         hue: Some(0),
                         saturation: Some(0),
                         color_temperature: Some(0),
+                        color_mode: Some("ct".to_string()),
+                        reachable: true,
                     },
                 },
             ];
@@ -312,3 +352,78 @@ pub mod fake {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hue::fake::FakeBridge;
+
+    fn input(yaml: &str) -> SetStatusToolInput {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn sets_the_status_of_a_light() {
+        let bridge = FakeBridge::default();
+
+        let output = set_status(
+            &bridge,
+            &input("lights:\n- id: '2'\n  on: true\n  brightness: 254\n  hue: 46920\n  saturation: 200"),
+        )
+        .unwrap();
+
+        assert_eq!(output.lights.len(), 1);
+        let light = &output.lights[0];
+        assert_eq!(light.id, "2");
+        assert_eq!(light.state.on, Some(true));
+        assert_eq!(light.state.brightness, Some(254));
+        assert_eq!(light.state.hue, Some(46920));
+        assert_eq!(light.state.saturation, Some(200));
+        assert_eq!(light.state.color_temperature, Some(153));
+
+        let lights = bridge.lights().unwrap();
+        assert_eq!(&lights[1], light);
+        assert_eq!(lights[0].state.on, Some(false));
+    }
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        let bridge = FakeBridge::default();
+
+        let e = set_status(
+            &bridge,
+            &input("lights:\n- id: '1'\n  on: true\n- id: '2'\n  brightness: 300"),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            e,
+            ToolUseError::InvalidInput(msg) if msg == "`brightness` must be between 0 and 254, got 300."
+        ));
+
+        let e = set_status(
+            &bridge,
+            &input("lights:\n- id: '1'\n  color_temperature: 100"),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            e,
+            ToolUseError::InvalidInput(msg) if msg == "`color_temperature` must be between 153 and 500, got 100."
+        ));
+
+        // nothing changed
+        assert_eq!(bridge.lights().unwrap()[0].state.on, Some(false));
+    }
+
+    #[test]
+    fn fails_on_unknown_light() {
+        let e = set_status(
+            &FakeBridge::default(),
+            &input("lights:\n- id: '42'\n  on: true"),
+        )
+        .unwrap_err();
+        assert!(matches!(e, ToolUseError::InvocationFailed(_)));
+
+        let e = set_status(&FakeBridge::default(), &input("lights: []")).unwrap_err();
+        assert!(matches!(e, ToolUseError::InvocationFailed(_)));
+    }
+}
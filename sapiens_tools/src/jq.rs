@@ -0,0 +1,371 @@
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use jaq_core::load::{self, Arena, File, Loader};
+use jaq_core::{Compiler, Ctx, Native, RcIter};
+use jaq_json::Val;
+use sapiens::tools::{
+    Describe, ProtoToolDescribe, ProtoToolInvoke, SideEffects, ToolDescription, ToolUseError,
+};
+use sapiens_derive::{Describe, ProtoToolDescribe, ProtoToolInvoke};
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of results of a query
+pub const MAX_RESULTS: usize = 1000;
+
+/// Maximum time to wait for the results of a query
+///
+/// The query itself cannot be stopped: one that never yields keeps running on
+/// its thread - see [`MAX_RUNNING_QUERIES`].
+pub const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum number of queries of a [`JqTool`] running at once - the new ones
+/// are rejected while that many are still running, e.g. stuck in a loop
+pub const MAX_RUNNING_QUERIES: usize = 2;
+
+/// A Tool to extract values from structured data with a jq filter.
+///
+/// Use this to select a field or a few items of a large YAML or JSON result,
+/// e.g. `.items[0].name` or `[.items[] | select(.size > 3) | .name]`. No need
+/// for Python for that.
+#[derive(Debug, ProtoToolDescribe, ProtoToolInvoke)]
+#[tool(
+    name = "Jq",
+    input = "JqToolInput",
+    output = "JqToolOutput",
+    parallel_safe,
+    read_only
+)]
+#[allow(clippy::module_name_repetitions)]
+pub struct JqTool {
+    /// How long to wait for the results of a query
+    timeout: Duration,
+    /// The number of queries still running - see [`MAX_RUNNING_QUERIES`]
+    running: Arc<AtomicUsize>,
+}
+
+impl Default for JqTool {
+    fn default() -> Self {
+        Self {
+            timeout: QUERY_TIMEOUT,
+            running: Arc::default(),
+        }
+    }
+}
+
+/// A query running on its thread - counted until dropped
+struct RunningQuery(Arc<AtomicUsize>);
+
+impl RunningQuery {
+    /// Count a new query in `running` - `None` if there are already
+    /// [`MAX_RUNNING_QUERIES`]
+    fn start(running: &Arc<AtomicUsize>) -> Option<Self> {
+        running
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < MAX_RUNNING_QUERIES).then_some(n + 1)
+            })
+            .ok()?;
+
+        Some(Self(running.clone()))
+    }
+}
+
+impl Drop for RunningQuery {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// [`JqTool`] input
+#[derive(Debug, Deserialize, Serialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct JqToolInput {
+    /// The data to query - YAML or JSON, as a string or as is. MANDATORY.
+    pub data: serde_yaml::Value,
+    /// The jq filter to apply to `data`, e.g. `.items[0].name`. MANDATORY.
+    pub query: String,
+}
+
+/// [`JqTool`] output
+#[derive(Debug, Deserialize, Serialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct JqToolOutput {
+    /// The values selected by the filter - one per output of the filter.
+    pub results: Vec<serde_yaml::Value>,
+}
+
+impl JqTool {
+    #[tracing::instrument(skip(self))]
+    async fn invoke_typed(&self, input: &JqToolInput) -> Result<JqToolOutput, ToolUseError> {
+        let data = match &input.data {
+            serde_yaml::Value::String(s) => serde_yaml::from_str(s),
+            data => serde_yaml::from_value(data.clone()),
+        }
+        .map_err(|e| ToolUseError::InvalidInput(format!("Invalid data: {e}")))?;
+
+        let running = RunningQuery::start(&self.running).ok_or_else(|| {
+            ToolUseError::InvocationFailed(format!(
+                "{MAX_RUNNING_QUERIES} queries are still running - they may never complete. Use \
+                 a simpler query."
+            ))
+        })?;
+
+        let query = input.query.clone();
+        let timeout = self.timeout;
+
+        // the filters can loop forever and cannot be stopped - run them on a
+        // thread of their own, counted until it ends, and stop waiting after
+        // the timeout. Not with `spawn_blocking` as the runtime would wait for
+        // such a thread to finish when shut down.
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        std::thread::Builder::new()
+            .name("jq".to_string())
+            .spawn(move || {
+                let results = run(&query, data, timeout);
+                drop(running);
+                tx.send(results)
+            })
+            .map_err(|e| ToolUseError::InvocationFailed(e.to_string()))?;
+
+        let results = tokio::time::timeout(timeout, rx)
+            .await
+            .map_err(|_| {
+                ToolUseError::InvocationFailed(format!(
+                    "The query did not complete within {timeout:?}"
+                ))
+            })?
+            .map_err(|e| ToolUseError::InvocationFailed(e.to_string()))??;
+
+        Ok(JqToolOutput { results })
+    }
+}
+
+/// Apply the jq filter `query` to `data` - at most [`MAX_RESULTS`] results
+///
+/// # Errors
+///
+/// [`ToolUseError::InvocationFailed`] if `query` is not a valid filter, if it
+/// fails on `data` or if it has more than [`MAX_RESULTS`] results.
+pub fn query(query: &str, data: serde_json::Value) -> Result<Vec<serde_yaml::Value>, ToolUseError> {
+    run(query, data, QUERY_TIMEOUT)
+}
+
+/// Apply the jq filter `query` to `data` - giving up on the results produced
+/// after `timeout`. The filter is not stopped while it produces none.
+fn run(
+    query: &str,
+    data: serde_json::Value,
+    timeout: Duration,
+) -> Result<Vec<serde_yaml::Value>, ToolUseError> {
+    let deadline = Instant::now() + timeout;
+    let program = File {
+        code: query,
+        path: (),
+    };
+
+    let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
+    let arena = Arena::default();
+
+    let modules = loader.load(&arena, program).map_err(|errors| {
+        let errors = errors
+            .iter()
+            .flat_map(|(_, e)| describe_load_error(query, e))
+            .collect::<Vec<_>>();
+        invalid_query(&errors)
+    })?;
+
+    let filter = Compiler::<_, Native<Val>>::default()
+        .with_funs(jaq_std::funs().chain(jaq_json::funs()))
+        .compile(modules)
+        .map_err(|errors| {
+            let errors = errors
+                .iter()
+                .flat_map(|(_, e)| e)
+                .map(|(name, undefined)| format!("undefined {} `{name}`", undefined.as_str()))
+                .collect::<Vec<_>>();
+            invalid_query(&errors)
+        })?;
+
+    let inputs = RcIter::new(core::iter::empty());
+
+    let results = filter
+        .run((Ctx::new([], &inputs), Val::from(data)))
+        .take(MAX_RESULTS + 1)
+        .map(|value| {
+            if Instant::now() > deadline {
+                return Err(ToolUseError::InvocationFailed(format!(
+                    "The query did not complete within {timeout:?}"
+                )));
+            }
+
+            let value = value
+                .map_err(|e| ToolUseError::InvocationFailed(format!("The query failed: {e}")))?;
+
+            serde_yaml::to_value(serde_json::Value::from(value))
+                .map_err(|e| ToolUseError::InvalidOutput(e.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if results.len() > MAX_RESULTS {
+        return Err(ToolUseError::InvocationFailed(format!(
+            "The query has more than {MAX_RESULTS} results - select fewer values"
+        )));
+    }
+
+    Ok(results)
+}
+
+/// The error for a query that cannot be compiled
+fn invalid_query(errors: &[String]) -> ToolUseError {
+    ToolUseError::InvocationFailed(format!("Invalid jq query: {}", errors.join(", ")))
+}
+
+/// What is wrong with `query` - the errors are located by their offset in it
+fn describe_load_error(query: &str, e: &load::Error<&str>) -> Vec<String> {
+    let offset = |rest: &str| query.len() - rest.len();
+
+    match e {
+        load::Error::Io(errors) => errors
+            .iter()
+            .map(|(path, e)| format!("cannot load `{path}`: {e}"))
+            .collect(),
+        load::Error::Lex(errors) => errors
+            .iter()
+            .map(|(expected, rest)| {
+                format!("expected {} at offset {}", expected.as_str(), offset(rest))
+            })
+            .collect(),
+        load::Error::Parse(errors) => errors
+            .iter()
+            .map(|(expected, rest)| {
+                format!("expected {} at offset {}", expected.as_str(), offset(rest))
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use sapiens::tools::toolbox::{invoke_tool, InvokeResult, Toolbox};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_select_a_nested_field() {
+        let data = indoc! {r#"
+        ```yaml
+        tool_name: Jq
+        parameters:
+            data: '{"planet": {"name": "Mars", "moons": [{"name": "Phobos"}, {"name": "Deimos"}]}}'
+            query: .planet.moons[].name
+        ```
+        "#};
+
+        let toolbox = Toolbox::default();
+        toolbox.add_tool(JqTool::default()).await;
+
+        let res = invoke_tool(toolbox, data).await;
+
+        let InvokeResult::Success { result, .. } = res else {
+            panic!("unexpected result: {res:?}");
+        };
+        assert_eq!(result, "results:\n- Phobos\n- Deimos\n");
+    }
+
+    #[tokio::test]
+    async fn test_query_structured_data() {
+        let input = JqToolInput {
+            data: serde_yaml::from_str("planet:\n  name: Mars\n  radius_km: 3389.5\n").unwrap(),
+            query: "{name: .planet.name, big: (.planet.radius_km > 3000)}".to_string(),
+        };
+
+        let output = JqTool::default().invoke_typed(&input).await.unwrap();
+
+        assert_eq!(
+            output.results,
+            vec![serde_yaml::from_str::<serde_yaml::Value>("name: Mars\nbig: true\n").unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_invalid_query() {
+        let data = serde_json::json!({"name": "Mars"});
+
+        let e = query(".name | ", data.clone()).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Tool invocation failed: Invalid jq query: expected term at offset 8"
+        );
+
+        let e = query("nope(.name)", data.clone()).unwrap_err();
+        assert!(
+            e.to_string()
+                .contains("Invalid jq query: undefined filter `nope`"),
+            "{e}"
+        );
+
+        let e = query(".name[0]", data).unwrap_err();
+        assert!(matches!(e, ToolUseError::InvocationFailed(_)), "{e:?}");
+    }
+
+    #[test]
+    fn test_too_many_results() {
+        let e = query("range(1; infinite)", serde_json::Value::Null).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "Tool invocation failed: The query has more than {MAX_RESULTS} results - select \
+                 fewer values"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_running_too_long() {
+        let tool = JqTool {
+            timeout: Duration::from_millis(10),
+            ..JqTool::default()
+        };
+        let input = JqToolInput {
+            data: serde_yaml::Value::Null,
+            query: "last(range(1; 3000000))".to_string(),
+        };
+
+        let e = tool.invoke_typed(&input).await.unwrap_err();
+        assert!(e.to_string().contains("did not complete"), "{e}");
+        assert_eq!(tool.running.load(Ordering::SeqCst), 1);
+
+        // the worker stops once the filter is done
+        tokio::time::timeout(Duration::from_mins(1), async {
+            while tool.running.load(Ordering::SeqCst) > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the worker is still running");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_the_queries_while_too_many_are_running() {
+        let tool = JqTool::default();
+        let running = (0..MAX_RUNNING_QUERIES)
+            .map(|_| RunningQuery::start(&tool.running).unwrap())
+            .collect::<Vec<_>>();
+
+        let input = JqToolInput {
+            data: serde_yaml::from_str("a: 1").unwrap(),
+            query: ".a".to_string(),
+        };
+
+        let e = tool.invoke_typed(&input).await.unwrap_err();
+        assert!(e.to_string().contains("still running"), "{e}");
+
+        drop(running);
+        let output = tool.invoke_typed(&input).await.unwrap();
+        assert_eq!(output.results, vec![serde_yaml::Value::from(1)]);
+        assert_eq!(tool.running.load(Ordering::SeqCst), 0);
+    }
+}
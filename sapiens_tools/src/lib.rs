@@ -7,12 +7,24 @@ pub mod hue;
 /// Tool to conclude a chain
 pub mod conclude;
 
+/// Tool to abort a chain
+pub mod abort;
+
+/// Tool to think out loud
+pub mod scratchpad;
+
 /// Tool to run some (limited) python
 pub mod python;
 
 /// Tool to test stuffs
 pub mod dummy;
 
+/// Tool to look up the configuration values given to it
+pub mod context;
+
+/// Tool to list the tools of the toolbox
+pub mod list_tools;
+
 /// Tools related to mediawiki: Wikipedia, Wikidata, etc.
 #[cfg(feature = "wiki")]
 pub mod wiki;
@@ -31,3 +43,15 @@ pub mod summarize;
 /// Search the web
 #[cfg(feature = "search")]
 pub mod search;
+
+/// Read webpages as text
+#[cfg(feature = "read_page")]
+pub mod read_page;
+
+/// Convert data between common formats
+#[cfg(feature = "encode")]
+pub mod encode;
+
+/// Query structured data with jq filters
+#[cfg(feature = "jq")]
+pub mod jq;
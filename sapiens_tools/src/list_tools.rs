@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+use sapiens::tools::toolbox::Toolbox;
+use sapiens::tools::{
+    AdvancedTool, Describe, ProtoToolDescribe, ProtoToolInvoke, SideEffects, ToolDescription,
+    ToolUseError,
+};
+use sapiens_derive::{Describe, ProtoToolDescribe};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+/// A tool to list the tools available for the task.
+///
+/// Each tool is given in a compact form: its name, the first line of its
+/// description and the types of its parameters.
+#[derive(Debug, Default, ProtoToolDescribe)]
+#[tool(
+    name = "ListTools",
+    input = "ListToolsToolInput",
+    output = "ListToolsToolOutput",
+    read_only
+)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ListToolsTool {}
+
+/// A tool to list the available tools and their parameters.
+#[derive(Debug, Serialize, Deserialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ListToolsToolInput {
+    /// The name of the only tool to list. Optional.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+}
+
+/// `ListToolsToolOutput` - the available tools
+#[derive(Debug, Serialize, Deserialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ListToolsToolOutput {
+    /// The tools - sorted by name.
+    pub tools: Vec<ToolSummary>,
+}
+
+/// A compact description of a tool
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolSummary {
+    /// The name of the tool
+    pub name: String,
+    /// The first line of the description of the tool
+    pub description: String,
+    /// The types of the parameters of the tool - by name
+    pub parameters: BTreeMap<String, String>,
+}
+
+impl From<ToolDescription> for ToolSummary {
+    fn from(description: ToolDescription) -> Self {
+        Self {
+            name: description.name,
+            description: description
+                .description
+                .lines()
+                .map(str::trim)
+                .find(|l| !l.is_empty())
+                .unwrap_or_default()
+                .to_string(),
+            parameters: description
+                .parameters
+                .fields
+                .into_iter()
+                .map(|f| (f.name, f.r#type))
+                .collect(),
+        }
+    }
+}
+
+impl ListToolsTool {
+    #[tracing::instrument(skip(self, toolbox))]
+    async fn invoke_typed(
+        &self,
+        toolbox: Toolbox,
+        input: &ListToolsToolInput,
+    ) -> Result<ListToolsToolOutput, ToolUseError> {
+        let tools = toolbox
+            .describe()
+            .await
+            .into_iter()
+            .filter(|(name, _)| input.tool_name.as_ref().is_none_or(|n| n == name))
+            .map(|(_, description)| description.into())
+            .collect::<Vec<_>>();
+
+        if let (Some(tool_name), true) = (&input.tool_name, tools.is_empty()) {
+            return Err(ToolUseError::ToolNotFound(tool_name.clone()));
+        }
+
+        Ok(ListToolsToolOutput { tools })
+    }
+}
+
+#[async_trait::async_trait]
+impl ProtoToolInvoke for ListToolsTool {
+    async fn invoke(&self, _input: Value) -> Result<Value, ToolUseError> {
+        Err(ToolUseError::InvocationFailed(
+            "The tools can only be listed from a toolbox".to_string(),
+        ))
+    }
+}
+
+#[async_trait::async_trait]
+impl AdvancedTool for ListToolsTool {
+    async fn invoke_with_toolbox(
+        &self,
+        toolbox: Toolbox,
+        input: Value,
+    ) -> Result<Value, ToolUseError> {
+        let input =
+            serde_yaml::from_value(input).map_err(|e| ToolUseError::InvalidInput(e.to_string()))?;
+        let output = self.invoke_typed(toolbox, &input).await?;
+        Ok(serde_yaml::to_value(output).map_err(|e| ToolUseError::InvalidOutput(e.to_string()))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use sapiens::tools::toolbox::{invoke_tool, InvokeResult};
+
+    use super::*;
+    use crate::conclude::ConcludeTool;
+    use crate::scratchpad::ScratchpadTool;
+
+    #[tokio::test]
+    async fn test_lists_the_registered_tools() {
+        let toolbox = Toolbox::default();
+        toolbox.add_terminal_tool(ConcludeTool::default()).await;
+        toolbox.add_tool(ScratchpadTool::default()).await;
+        toolbox.add_advanced_tool(ListToolsTool::default()).await;
+
+        let data = indoc! {r"
+        ```yaml
+        tool_name: ListTools
+        parameters: {}
+        ```
+        "};
+
+        let res = invoke_tool(toolbox.clone(), data).await;
+
+        let InvokeResult::Success { result, .. } = res else {
+            panic!("unexpected result: {res:?}");
+        };
+        let output: ListToolsToolOutput = serde_yaml::from_str(&result).unwrap();
+
+        let names = output
+            .tools
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["Conclude", "ListTools", "Scratchpad"]);
+
+        let scratchpad = &output.tools[2];
+        assert_eq!(scratchpad.description, "A tool to think out loud.");
+        assert_eq!(scratchpad.parameters["notes"], "str");
+
+        let data = indoc! {r"
+        ```yaml
+        tool_name: ListTools
+        parameters:
+            tool_name: Wikipedia
+        ```
+        "};
+
+        let res = invoke_tool(toolbox, data).await;
+        assert!(matches!(res, InvokeResult::Error { .. }), "{res:?}");
+    }
+}
@@ -1,5 +1,5 @@
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashSet};
 
 use convert_case::{Case, Casing};
 use pyo3::indoc::{formatdoc, indoc};
@@ -7,12 +7,13 @@ use pyo3::prelude::*;
 use pyo3::types::{IntoPyDict, PyDict};
 use sapiens::tools::toolbox::{invoke_simple_from_toolbox, Toolbox};
 use sapiens::tools::{
-    AdvancedTool, Describe, ProtoToolDescribe, ProtoToolInvoke, ToolDescription, ToolUseError,
+    AdvancedTool, Describe, ProtoToolDescribe, ProtoToolInvoke, SideEffects, ToolDescription,
+    ToolExample, ToolUseError,
 };
 use sapiens_derive::{Describe, ProtoToolDescribe};
 use serde::{Deserialize, Serialize};
 use serde_yaml::Value;
-use tracing::trace;
+use tracing::{debug, trace};
 
 /// Conversion tools
 pub(crate) mod utils;
@@ -38,6 +39,8 @@ const MAX_OUTPUT_SIZE: usize = 512;
 /// - List available tools with `tools.list()`. And returns a list of
 ///   `{'name':.., 'description':.., 'parameters':.., 'responses_content':..,
 ///   }`.
+/// - Get a result stored under a handle with `tools.fetch(handle)` - as a
+///   string.
 /// - `open`|`exec` are forbidden.
 /// - Limited libraries available: urllib3, requests, sympy, numpy,
 ///   `BeautifulSoup4`, feedparser, arxiv.
@@ -46,10 +49,16 @@ const MAX_OUTPUT_SIZE: usize = 512;
 #[tool(
     name = "SandboxedPython",
     input = "PythonToolInput",
-    output = "PythonToolOutput"
+    output = "PythonToolOutput",
+    examples = "Self::examples",
+    mutating
 )]
 #[allow(clippy::module_name_repetitions)]
-pub struct PythonTool {}
+pub struct PythonTool {
+    /// Run the code in a clean namespace - without `tools` and `toolbox` -
+    /// even when a [`Toolbox`] is available
+    without_tools: bool,
+}
 
 /// The input of the Python tool
 #[derive(Debug, Serialize, Deserialize, Describe)]
@@ -129,6 +138,9 @@ impl ToolsWrapper {
             Value::default()
         };
 
+        // not the input - it can hold anything the code was given
+        debug!(tool_name, "Invoking a Tool from Python");
+
         let (tx, mut rx) = tokio::sync::oneshot::channel::<Result<Value, ToolUseError>>();
 
         // release the GIL to allow the thread to run
@@ -176,9 +188,84 @@ impl ToolsWrapper {
             }
         }
     }
+
+    // fetch a stashed result
+    #[pyo3(signature = (handle))]
+    fn fetch(&self, py: Python<'_>, handle: &str) -> PyResult<PyObject> {
+        debug!(handle, "Fetching a stashed result from Python");
+
+        // the stash lock is only held briefly - no runtime needed
+        let result = self.toolbox.fetch_result(handle);
+
+        result.map(|result| result.to_object(py)).ok_or_else(|| {
+            pyo3::exceptions::PyException::new_err(format!("No result stored under '{handle}'"))
+        })
+    }
+}
+
+/// The Python keywords - they cannot be used as method names
+const PYTHON_KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield",
+];
+
+/// Turn `name` into a valid Python identifier: the characters that are
+/// not allowed are replaced by `_`, a leading digit is prefixed with `_`
+/// and a keyword is suffixed with `_`.
+fn python_identifier(name: &str) -> String {
+    let mut identifier = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+
+    if identifier.is_empty() || identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        identifier.insert(0, '_');
+    }
+
+    if PYTHON_KEYWORDS.contains(&identifier.as_str()) {
+        identifier.push('_');
+    }
+
+    identifier
+}
+
+/// Make `name` different from all the `taken` ones - by adding a numeric
+/// suffix if needed - and mark it as taken.
+fn unique_name(name: &str, taken: &mut HashSet<String>) -> String {
+    let mut unique = name.to_string();
+    let mut i = 2;
+    while taken.contains(&unique) {
+        unique = format!("{name}_{i}");
+        i += 1;
+    }
+
+    taken.insert(unique.clone());
+    unique
 }
 
 impl PythonTool {
+    /// Run the code in a clean namespace: the other tools are not made
+    /// available as `tools` and `toolbox` - for pure computation.
+    #[must_use]
+    pub const fn without_tools(mut self) -> Self {
+        self.without_tools = true;
+        self
+    }
+
+    fn examples() -> Vec<ToolExample> {
+        vec![ToolExample::new(
+            &PythonToolInput {
+                code: "import math\nprint(math.sqrt(2))\n".to_string(),
+            },
+            &PythonToolOutput {
+                stdout: "1.4142135623730951\n".to_string(),
+                stderr: String::new(),
+            },
+        )]
+    }
+
     #[tracing::instrument(skip(self, toolbox))]
     async fn invoke_typed(
         &self,
@@ -241,7 +328,7 @@ impl PythonTool {
     #[allow(clippy::too_many_lines)]
     fn transform_code(
         code: &str,
-        tools: HashMap<String, ToolDescription>,
+        tools: BTreeMap<String, ToolDescription>,
     ) -> Result<String, ToolUseError> {
         lazy_static::lazy_static! {
             static ref EXEC_RE: regex::Regex = regex::Regex::new(r"(exec|pip)").unwrap();
@@ -287,6 +374,12 @@ impl PythonTool {
         );
         tool_class_code.push('\n');
 
+        // the attributes of the class
+        let mut taken_names: HashSet<String> = ["fetch", "list", "toolbox"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+
         for (name, description) in tools {
             let inputs_parts = description.parameters.fields;
             let output_parts = description.responses_content.fields;
@@ -336,9 +429,12 @@ impl PythonTool {
                 docstring.push_str(&format!("    {l}\n"));
             });
             // Arguments
-            docstring.push_str("    Args:\n");
+            if !inputs_parts.is_empty() {
+                docstring.push_str("    Args:\n");
+            }
             for f in &inputs_parts {
-                docstring.push_str(&format!("        {}: <{}> ", f.name, f.r#type));
+                let optional = if f.optional { " (Optional)" } else { "" };
+                docstring.push_str(&format!("        {}: <{}>{optional} ", f.name, f.r#type));
                 // first line goes with the name, the rest is indented
                 f.description.lines().enumerate().for_each(|(i, l)| {
                     if i == 0 {
@@ -380,8 +476,16 @@ impl PythonTool {
                 .collect::<Vec<_>>()
                 .join(", ");
 
+            let mut method_names = vec![];
             for cased_name in [name.to_case(Case::Snake), name.to_case(Case::Pascal)] {
-                // in Pascal case
+                let method_name = python_identifier(&cased_name);
+                if !method_names.contains(&method_name) {
+                    method_names.push(method_name);
+                }
+            }
+
+            for method_name in method_names {
+                let method_name = unique_name(&method_name, &mut taken_names);
                 tool_class_code.push_str(&indent(
                     4,
                     &formatdoc! {r#"
@@ -389,10 +493,10 @@ impl PythonTool {
                 """{}    """
                 return self.toolbox.invoke("{}", {{{}}})
             "#, 
-                        cased_name,
+                        method_name,
                         inputs,
                         docstring,
-                        name,
+                        name.replace('\\', "\\\\").replace('"', "\\\""),
                         dict
                     },
                 ));
@@ -411,6 +515,17 @@ impl PythonTool {
         ));
         tool_class_code.push('\n');
 
+        // add fetch function
+        tool_class_code.push_str(&indent(
+            4,
+            indoc! {r#"
+            def fetch(self, handle):
+                """Fetch a result too long to be shown - by its handle."""
+                return self.toolbox.fetch(handle)
+            "#},
+        ));
+        tool_class_code.push('\n');
+
         // instantiate the class
         tool_class_code.push_str("tools = Tools(toolbox)\n");
 
@@ -512,19 +627,29 @@ impl AdvancedTool for PythonTool {
     ) -> Result<Value, ToolUseError> {
         let input =
             serde_yaml::from_value(input).map_err(|e| ToolUseError::InvalidInput(e.to_string()))?;
-        let output = self.invoke_typed(toolbox, &input).await?;
+        let output = if self.without_tools {
+            self.invoke_sync_typed(&input)?
+        } else {
+            self.invoke_typed(toolbox, &input).await?
+        };
         Ok(serde_yaml::to_value(output).map_err(|e| ToolUseError::InvalidOutput(e.to_string()))?)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+    use std::fmt::{Debug, Write};
+    use std::sync::{Arc, Mutex};
+
     use indoc::indoc;
     use insta::assert_snapshot;
     use sapiens::tools::toolbox::Toolbox;
+    use sapiens::tools::AdvancedTool;
 
     use crate::conclude::ConcludeTool;
-    use crate::python::{PythonTool, PythonToolInput};
+    use crate::dummy::DummyTool;
+    use crate::python::{PythonTool, PythonToolInput, PythonToolOutput};
 
     #[tokio::test]
     async fn test_code_transformation() {
@@ -563,4 +688,137 @@ mod tests {
 
         assert_snapshot!(code);
     }
+
+    #[test]
+    fn test_python_identifier() {
+        assert_eq!(super::python_identifier("status"), "status");
+        assert_eq!(super::python_identifier("3M-Status"), "_3M_Status");
+        assert_eq!(super::python_identifier("light status"), "light_status");
+        assert_eq!(super::python_identifier("class"), "class_");
+        assert_eq!(super::python_identifier(""), "_");
+    }
+
+    #[test]
+    fn test_unique_name() {
+        let mut taken = HashSet::new();
+
+        assert_eq!(super::unique_name("status", &mut taken), "status");
+        assert_eq!(super::unique_name("status", &mut taken), "status_2");
+        assert_eq!(super::unique_name("status", &mut taken), "status_3");
+    }
+
+    #[tokio::test]
+    async fn test_without_tools() {
+        pyo3::prepare_freethreaded_python();
+
+        let toolbox = Toolbox::default();
+        toolbox.add_tool(DummyTool::default()).await;
+
+        let input = serde_yaml::to_value(PythonToolInput {
+            code: "print('tools' in globals(), 'toolbox' in globals())\nprint(6 * 7)\n".to_string(),
+        })
+        .unwrap();
+
+        let output = PythonTool::default()
+            .without_tools()
+            .invoke_with_toolbox(toolbox, input)
+            .await
+            .unwrap();
+        let output: PythonToolOutput = serde_yaml::from_value(output).unwrap();
+
+        assert_eq!(output.stdout, "False False\n42\n");
+        assert_eq!(output.stderr, "");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_stashed_result() {
+        pyo3::prepare_freethreaded_python();
+
+        let toolbox = Toolbox::default();
+        let handle = toolbox.stash_result("1,".repeat(999) + "1");
+
+        let input = serde_yaml::to_value(PythonToolInput {
+            code: format!("print(sum(int(x) for x in tools.fetch('{handle}').split(',')))\n"),
+        })
+        .unwrap();
+
+        let output = PythonTool::default()
+            .invoke_with_toolbox(toolbox, input)
+            .await
+            .unwrap();
+        let output: PythonToolOutput = serde_yaml::from_value(output).unwrap();
+
+        assert_eq!(output.stdout, "1000\n");
+        assert_eq!(output.stderr, "");
+    }
+
+    /// Record the fields of the events
+    #[derive(Default)]
+    struct EventRecorder {
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl tracing::Subscriber for EventRecorder {
+        fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            tracing::span::Id::from_u64(1)
+        }
+
+        fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+
+        fn event(&self, event: &tracing::Event<'_>) {
+            let mut fields = String::new();
+            event.record(&mut |field: &tracing::field::Field, value: &dyn Debug| {
+                write!(fields, "{}={value:?} ", field.name()).unwrap();
+            });
+            self.events.lock().unwrap().push(fields);
+        }
+
+        fn enter(&self, _span: &tracing::span::Id) {}
+
+        fn exit(&self, _span: &tracing::span::Id) {}
+    }
+
+    #[tokio::test]
+    async fn test_nested_invocation_does_not_log_its_input() {
+        pyo3::prepare_freethreaded_python();
+
+        // the code runs on this thread
+        let recorder = EventRecorder::default();
+        let events = recorder.events.clone();
+        let _guard = tracing::subscriber::set_default(recorder);
+
+        let toolbox = Toolbox::default();
+        toolbox.add_tool(DummyTool::default()).await;
+
+        let input = serde_yaml::to_value(PythonToolInput {
+            code: "output = tools.Dummy(blah='a secret')\nprint(output['something'])\n".to_string(),
+        })
+        .unwrap();
+
+        let output = PythonTool::default()
+            .invoke_with_toolbox(toolbox, input)
+            .await
+            .unwrap();
+        let output: PythonToolOutput = serde_yaml::from_value(output).unwrap();
+
+        // only what the code printed
+        assert_eq!(output.stdout, "a secret and something else\n");
+        assert_eq!(output.stderr, "");
+
+        let invocation = events
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|e| e.contains("Invoking a Tool from Python"))
+            .cloned()
+            .expect("the nested invocation is traced");
+        assert!(invocation.contains("tool_name=\"Dummy\""), "{invocation}");
+        assert!(!invocation.contains("a secret"), "{invocation}");
+    }
 }
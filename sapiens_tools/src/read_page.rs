@@ -0,0 +1,614 @@
+use std::fmt::Debug;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{redirect, Client, Url};
+use sapiens::tools::{
+    Describe, ProtoToolDescribe, ProtoToolInvoke, SideEffects, ToolDescription, ToolUseError,
+};
+use sapiens_derive::{Describe, ProtoToolDescribe, ProtoToolInvoke};
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::{Deserialize, Serialize};
+
+/// Default maximum size of a page - in bytes
+pub const DEFAULT_MAX_BYTES: usize = 2 * 1024 * 1024;
+
+/// Default maximum length of the text of a page - in characters
+pub const DEFAULT_MAX_CHARS: usize = 8000;
+
+/// The elements whose content is not part of the text of a page: code,
+/// navigation, forms, ...
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "script", "style", "noscript", "template", "svg", "canvas", "iframe", "nav", "header",
+    "footer", "aside", "form", "button", "select",
+];
+
+/// The elements starting a new line of text
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "main",
+    "br",
+    "li",
+    "ul",
+    "ol",
+    "dl",
+    "dt",
+    "dd",
+    "tr",
+    "table",
+    "blockquote",
+    "pre",
+    "figure",
+    "figcaption",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+];
+
+/// Maximum number of redirections followed
+const MAX_REDIRECTS: usize = 10;
+
+/// A Tool to read a webpage as text.
+///
+/// Fetches the page and returns its title and its main text - without the
+/// markup, the scripts, the styles and the navigation.
+///
+/// The pages can only be fetched from the allowed hosts - none by default -
+/// and never from a loopback, private or link-local address unless
+/// explicitly allowed. The redirections are checked the same way.
+#[derive(ProtoToolInvoke, ProtoToolDescribe)]
+#[tool(
+    name = "ReadPage",
+    input = "ReadPageToolInput",
+    output = "ReadPageToolOutput",
+    parallel_safe,
+    read_only
+)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ReadPageTool {
+    /// HTTP client - following the redirections to the allowed hosts only
+    client: Client,
+    /// Where the pages can be fetched from
+    policy: Arc<HostPolicy>,
+    /// Maximum size of a page - in bytes. The rest of the page is not read.
+    max_bytes: usize,
+    /// Maximum length of the text - in characters. The rest is cut.
+    max_chars: usize,
+}
+
+impl Debug for ReadPageTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReadPageTool")
+            .field("allowed_hosts", &self.policy.allowed_hosts)
+            .field("allow_private_addresses", &self.policy.allow_private)
+            .field("max_bytes", &self.max_bytes)
+            .field("max_chars", &self.max_chars)
+            .finish_non_exhaustive()
+    }
+}
+
+/// [`ReadPageTool`] input
+#[derive(Debug, Deserialize, Serialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ReadPageToolInput {
+    /// The URL of the page to read - http or https.
+    pub url: String,
+}
+
+/// [`ReadPageTool`] output
+#[derive(Debug, Deserialize, Serialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ReadPageToolOutput {
+    /// The title of the page - if any.
+    pub title: Option<String>,
+    /// The main text of the page.
+    pub text: String,
+    /// Whether the text has been cut.
+    pub truncated: bool,
+}
+
+/// Where the pages can be fetched from
+#[derive(Debug, Default)]
+struct HostPolicy {
+    /// The hosts the pages can be fetched from - with their subdomains
+    allowed_hosts: Vec<String>,
+    /// Whether the loopback, private and link-local addresses can be reached
+    allow_private: bool,
+}
+
+impl HostPolicy {
+    /// Check that `url` can be fetched - its scheme, its host and, if it is
+    /// one, its IP address
+    fn check(&self, url: &Url) -> Result<(), ToolUseError> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(ToolUseError::InvalidInput(format!(
+                "Unsupported scheme: '{}' - only http and https are",
+                url.scheme()
+            )));
+        }
+
+        let host = url.host_str().unwrap_or_default();
+
+        let allowed = self.allowed_hosts.iter().any(|h| {
+            host == h
+                || host
+                    .strip_suffix(h.as_str())
+                    .is_some_and(|sub| sub.ends_with('.'))
+        });
+
+        if !allowed {
+            return Err(ToolUseError::InvalidInput(format!(
+                "The host '{host}' is not allowed"
+            )));
+        }
+
+        // The IP addresses are not resolved - so checked here
+        let ip = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>();
+
+        match ip {
+            Ok(ip) if !self.allow_private && !is_public(ip) => Err(ToolUseError::InvalidInput(
+                format!("The address '{ip}' is not allowed"),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Resolve the host names to their public addresses only - unless the
+/// private ones are allowed - so that a name cannot point to an internal
+/// service.
+impl Resolve for HostPolicy {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allow_private = self.allow_private;
+        let host = name.as_str().to_string();
+
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| allow_private || is_public(addr.ip()))
+                .collect::<Vec<_>>();
+
+            if addrs.is_empty() {
+                return Err(format!("The host '{host}' has no allowed address").into());
+            }
+
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether `ip` is a public address - not a loopback, private, link-local,
+/// ... one
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                // Shared address space - 100.64.0.0/10
+                || (a == 100 && (b & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local - fc00::/7
+                || (first & 0xfe00) == 0xfc00
+                // Link-local - fe80::/10
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+impl Default for ReadPageTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReadPageTool {
+    /// Create a new [`ReadPageTool`] - allowed to fetch from no host
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_policy(HostPolicy::default())
+    }
+
+    /// Create a new [`ReadPageTool`] fetching the pages as `policy` allows
+    fn with_policy(policy: HostPolicy) -> Self {
+        let policy = Arc::new(policy);
+
+        let redirect = {
+            let policy = policy.clone();
+            redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() >= MAX_REDIRECTS {
+                    attempt.error("Too many redirections")
+                } else if let Err(e) = policy.check(attempt.url()) {
+                    attempt.error(e)
+                } else {
+                    attempt.follow()
+                }
+            })
+        };
+
+        let client = Client::builder()
+            .redirect(redirect)
+            .dns_resolver(policy.clone())
+            .build()
+            .expect("the HTTP client cannot be created");
+
+        Self {
+            client,
+            policy,
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_chars: DEFAULT_MAX_CHARS,
+        }
+    }
+
+    /// Only fetch the pages from `hosts` - and their subdomains
+    #[must_use]
+    pub fn with_allowed_hosts(self, hosts: Vec<String>) -> Self {
+        Self {
+            max_bytes: self.max_bytes,
+            max_chars: self.max_chars,
+            ..Self::with_policy(HostPolicy {
+                allowed_hosts: hosts,
+                allow_private: self.policy.allow_private,
+            })
+        }
+    }
+
+    /// Also fetch the pages from the loopback, private and link-local
+    /// addresses - of the allowed hosts
+    #[must_use]
+    pub fn with_private_addresses(self) -> Self {
+        Self {
+            max_bytes: self.max_bytes,
+            max_chars: self.max_chars,
+            ..Self::with_policy(HostPolicy {
+                allowed_hosts: self.policy.allowed_hosts.clone(),
+                allow_private: true,
+            })
+        }
+    }
+
+    /// Set the maximum size of a page - in bytes
+    #[must_use]
+    pub const fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Set the maximum length of the text - in characters
+    #[must_use]
+    pub const fn with_max_chars(mut self, max_chars: usize) -> Self {
+        self.max_chars = max_chars;
+        self
+    }
+
+    /// Check that `url` can be fetched
+    fn check_url(&self, url: &str) -> Result<Url, ToolUseError> {
+        let url = Url::parse(url)
+            .map_err(|e| ToolUseError::InvalidInput(format!("Invalid URL '{url}': {e}")))?;
+
+        self.policy.check(&url)?;
+
+        Ok(url)
+    }
+
+    /// Fetch the first `max_bytes` of the page at `url`
+    async fn fetch(&self, url: Url) -> Result<String, ToolUseError> {
+        let mut resp = self
+            .client
+            .get(url)
+            .header("Accept", "text/html")
+            .send()
+            .await
+            .map_err(|e| ToolUseError::InvocationFailed(describe_error(&e)))?;
+
+        if !resp.status().is_success() {
+            return Err(ToolUseError::InvocationFailed(format!(
+                "Error code {}",
+                resp.status()
+            )));
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = resp
+            .chunk()
+            .await
+            .map_err(|e| ToolUseError::InvocationFailed(e.to_string()))?
+        {
+            let remaining = self.max_bytes - body.len();
+            if chunk.len() >= remaining {
+                body.extend_from_slice(&chunk[..remaining]);
+                break;
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn invoke_typed(
+        &self,
+        input: &ReadPageToolInput,
+    ) -> Result<ReadPageToolOutput, ToolUseError> {
+        let url = self.check_url(&input.url)?;
+
+        let html = self.fetch(url).await?;
+
+        let (title, text) = extract_text(&html);
+
+        let truncated = text.chars().count() > self.max_chars;
+        let text = if truncated {
+            let mut text = text.chars().take(self.max_chars).collect::<String>();
+            text.push('…');
+            text
+        } else {
+            text
+        };
+
+        Ok(ReadPageToolOutput {
+            title,
+            text,
+            truncated,
+        })
+    }
+}
+
+/// Describe `e` with its causes - such as a redirection not allowed
+fn describe_error(e: &dyn std::error::Error) -> String {
+    let mut description = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        description.push_str(&format!(": {e}"));
+        source = e.source();
+    }
+    description
+}
+
+/// Extract the title and the main text of an HTML page
+///
+/// The text is taken from the `<article>` or the `<main>` element if there is
+/// one, from the `<body>` otherwise. The scripts, the styles, the navigation,
+/// ... are left out. Each block (paragraph, heading, list item, ...) is on a
+/// line of its own.
+#[must_use]
+pub fn extract_text(html: &str) -> (Option<String>, String) {
+    let document = Html::parse_document(html);
+
+    let select = |selector: &str| {
+        Selector::parse(selector)
+            .ok()
+            .and_then(|selector| document.select(&selector).next())
+    };
+
+    let title = select("title")
+        .map(|t| collapse_whitespace(&t.text().collect::<String>()))
+        .filter(|t| !t.is_empty());
+
+    let root = select("article")
+        .or_else(|| select("main"))
+        .or_else(|| select("body"))
+        .unwrap_or_else(|| document.root_element());
+
+    let mut lines = vec![String::new()];
+    collect_text(root, &mut lines);
+
+    let text = lines
+        .iter()
+        .map(|l| collapse_whitespace(l))
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    (title, text)
+}
+
+/// Append the text of `element` to `lines` - starting a new line for each
+/// block
+fn collect_text(element: ElementRef, lines: &mut Vec<String>) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => {
+                if let Some(line) = lines.last_mut() {
+                    line.push_str(text);
+                }
+            }
+            Node::Element(e) if SKIPPED_ELEMENTS.contains(&e.name()) => {}
+            Node::Element(e) => {
+                let is_block = BLOCK_ELEMENTS.contains(&e.name());
+                if is_block {
+                    lines.push(String::new());
+                }
+
+                if let Some(child) = ElementRef::wrap(child) {
+                    collect_text(child, lines);
+                }
+
+                if is_block {
+                    lines.push(String::new());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Replace the runs of whitespaces with a single space
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    const PAGE: &str = include_str!("../tests/fixtures/page.html");
+
+    #[test]
+    fn extracts_the_text_of_a_page() {
+        let (title, text) = extract_text(PAGE);
+
+        assert_eq!(title.as_deref(), Some("The Rust Programming Language"));
+        assert_eq!(
+            text,
+            "Ownership\n\
+             Ownership is a set of rules that govern how a Rust program manages memory.\n\
+             Each value has an owner.\n\
+             There can only be one owner at a time."
+        );
+        assert!(!text.contains('<'));
+    }
+
+    #[tokio::test]
+    async fn reads_a_page() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/book/ownership.html"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(PAGE))
+            .mount(&server)
+            .await;
+
+        let tool = ReadPageTool::default()
+            .with_allowed_hosts(vec!["127.0.0.1".to_string()])
+            .with_private_addresses()
+            .with_max_chars(9);
+
+        let output = tool
+            .invoke_typed(&ReadPageToolInput {
+                url: format!("{}/book/ownership.html", server.uri()),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            output.title.as_deref(),
+            Some("The Rust Programming Language")
+        );
+        assert_eq!(output.text, "Ownership…");
+        assert!(output.truncated);
+    }
+
+    #[tokio::test]
+    async fn reads_at_most_max_bytes() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(PAGE))
+            .mount(&server)
+            .await;
+
+        let tool = ReadPageTool::default()
+            .with_allowed_hosts(vec!["127.0.0.1".to_string()])
+            .with_private_addresses()
+            .with_max_bytes(PAGE.find("</h1>").unwrap());
+
+        let output = tool
+            .invoke_typed(&ReadPageToolInput { url: server.uri() })
+            .await
+            .unwrap();
+
+        assert_eq!(output.text, "Ownership");
+        assert!(!output.truncated);
+    }
+
+    #[tokio::test]
+    async fn rejects_the_hosts_not_allowed() {
+        let tool = ReadPageTool::default().with_allowed_hosts(vec!["rust-lang.org".to_string()]);
+
+        assert!(tool.check_url("https://doc.rust-lang.org/book/").is_ok());
+        assert!(tool.check_url("https://rust-lang.org/").is_ok());
+        assert!(tool.check_url("https://evil-rust-lang.org/").is_err());
+        assert!(tool.check_url("file:///etc/passwd").is_err());
+
+        let e = tool
+            .invoke_typed(&ReadPageToolInput {
+                url: "https://example.com/".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(e, ToolUseError::InvalidInput(_)), "{e:?}");
+    }
+
+    #[test]
+    fn allows_no_host_by_default() {
+        let tool = ReadPageTool::default();
+
+        assert!(tool.check_url("https://doc.rust-lang.org/book/").is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_the_private_addresses() {
+        let tool = ReadPageTool::default().with_allowed_hosts(vec![
+            "127.0.0.1".to_string(),
+            "169.254.169.254".to_string(),
+            "10.0.0.1".to_string(),
+            "[::1]".to_string(),
+            "localhost".to_string(),
+        ]);
+
+        assert!(tool.check_url("http://127.0.0.1/").is_err());
+        assert!(tool
+            .check_url("http://169.254.169.254/latest/meta-data/")
+            .is_err());
+        assert!(tool.check_url("http://10.0.0.1/").is_err());
+        assert!(tool.check_url("http://[::1]/").is_err());
+
+        // Checked once resolved
+        let e = tool
+            .invoke_typed(&ReadPageToolInput {
+                url: "http://localhost/".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(e, ToolUseError::InvocationFailed(_)), "{e:?}");
+    }
+
+    #[tokio::test]
+    async fn does_not_follow_the_redirections_to_other_hosts() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(302)
+                    .insert_header("Location", "http://169.254.169.254/latest/meta-data/"),
+            )
+            .mount(&server)
+            .await;
+
+        let tool = ReadPageTool::default()
+            .with_allowed_hosts(vec!["127.0.0.1".to_string()])
+            .with_private_addresses();
+
+        let e = tool
+            .invoke_typed(&ReadPageToolInput { url: server.uri() })
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("is not allowed"), "{e}");
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt::Debug;
+
+use sapiens::tools::{
+    Describe, ProtoToolDescribe, ProtoToolInvoke, SideEffects, ToolDescription, ToolUseError,
+};
+use sapiens_derive::{Describe, ProtoToolDescribe, ProtoToolInvoke};
+use serde::{Deserialize, Serialize};
+
+/// A tool to think out loud.
+///
+/// The notes are echoed back so they are recorded in the history - nothing
+/// else happens. Using it does not get the task any closer to its end: use
+/// the Conclude Tool for that.
+#[derive(Debug, Default, ProtoToolDescribe, ProtoToolInvoke)]
+#[tool(
+    name = "Scratchpad",
+    input = "ScratchpadToolInput",
+    output = "ScratchpadToolOutput",
+    read_only
+)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ScratchpadTool {}
+
+/// A tool to write down your reasoning without doing anything else.
+#[derive(Debug, Serialize, Deserialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ScratchpadToolInput {
+    /// Your reasoning. Plain text. MANDATORY.
+    pub notes: String,
+}
+
+/// `ScratchpadToolOutput` - the notes, unchanged
+#[derive(Debug, Serialize, Deserialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ScratchpadToolOutput {
+    /// The notes that were given.
+    pub notes: String,
+}
+
+impl ScratchpadTool {
+    #[tracing::instrument(skip(self))]
+    async fn invoke_typed(
+        &self,
+        input: &ScratchpadToolInput,
+    ) -> Result<ScratchpadToolOutput, ToolUseError> {
+        Ok(ScratchpadToolOutput {
+            notes: input.notes.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use sapiens::tools::toolbox::{invoke_tool, InvokeResult, Toolbox};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_notes_are_echoed() {
+        let data = indoc! {r"
+        ```yaml
+        tool_name: Scratchpad
+        parameters:
+            notes: The capital of France is Paris, so I should look up Paris.
+        ```
+        "};
+
+        let toolbox = Toolbox::default();
+        toolbox.add_tool(ScratchpadTool::default()).await;
+
+        let res = invoke_tool(toolbox.clone(), data).await;
+
+        let InvokeResult::Success { result, .. } = res else {
+            panic!("unexpected result: {res:?}");
+        };
+        let output: ScratchpadToolOutput = serde_yaml::from_str(&result).unwrap();
+        assert_eq!(
+            output.notes,
+            "The capital of France is Paris, so I should look up Paris."
+        );
+        assert!(toolbox.termination_messages().await.is_empty());
+    }
+}
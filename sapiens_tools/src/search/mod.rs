@@ -1,7 +1,9 @@
 use std::fmt::Debug;
 
 use reqwest::{Client, Response, Url};
-use sapiens::tools::{Describe, ProtoToolDescribe, ProtoToolInvoke, ToolDescription, ToolUseError};
+use sapiens::tools::{
+    Describe, ProtoToolDescribe, ProtoToolInvoke, SideEffects, ToolDescription, ToolUseError,
+};
 use sapiens_derive::{Describe, ProtoToolDescribe, ProtoToolInvoke};
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
@@ -21,7 +23,9 @@ pub mod gce;
 #[tool(
     name = "Search",
     input = "SearchToolInput",
-    output = "SearchToolOutput"
+    output = "SearchToolOutput",
+    parallel_safe,
+    read_only
 )]
 #[allow(clippy::module_name_repetitions)]
 pub struct SearchTool {
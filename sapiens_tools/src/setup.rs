@@ -1,19 +1,42 @@
 //! Sapiens CLI library
-use sapiens::tools::toolbox::Toolbox;
+use sapiens::tools::toolbox::{Toolbox, ToolboxBuilder};
 
+use crate::abort::AbortTool;
 use crate::conclude::ConcludeTool;
+use crate::dummy::DummyTool;
 use crate::python::PythonTool;
+use crate::scratchpad::ScratchpadTool;
+
+/// Register the standard tools with a [`ToolboxBuilder`]
+pub trait WithDefaultTools {
+    /// Add the [`PythonTool`], the [`ConcludeTool`] and the [`DummyTool`]
+    #[must_use]
+    fn with_default_tools(self) -> Self;
+}
+
+impl WithDefaultTools for ToolboxBuilder {
+    fn with_default_tools(self) -> Self {
+        self.with_advanced_tool(PythonTool::default())
+            .with_terminal_tool(ConcludeTool::default())
+            .with_tool(DummyTool::default())
+    }
+}
 
 /// Assemble the toolbox of tools.
 ///
 /// - Uses features to enable/disable tools.
 /// - Gets API keys from environment variables.
 /// - Uses environment variables to configure tools: `HUE_BRIDGE_IP`,
-///   `HUE_USERNAME`
+///   `HUE_USERNAME`, `READ_PAGE_ALLOWED_HOSTS`, `OPENAI_API_KEY`,
+///   `OPENAI_ORG_ID`
+///
+/// The tools keep what they need from the environment: call it before the
+/// environment is cleared.
 ///
 /// # Panics
 ///
 /// if the required environment variables are not set.
+#[allow(clippy::too_many_lines)]
 pub async fn toolbox_from_env() -> Toolbox {
     let toolbox = Toolbox::default();
 
@@ -26,33 +49,7 @@ pub async fn toolbox_from_env() -> Toolbox {
 
     #[cfg(feature = "hue")]
     {
-        use std::net::IpAddr;
-        use std::str::FromStr;
-
-        use huelib2::bridge;
-
-        let bridge_ip = if let Ok(ip) = std::env::var("HUE_BRIDGE_IP") {
-            IpAddr::from_str(&ip).expect("Invalid IP address")
-        } else {
-            println!("HUE_BRIDGE_IP env not set. Trying to discover bridge.");
-            let bridge_ip = bridge::discover_nupnp().unwrap().pop().unwrap();
-            println!("Discovered bridge at IP address: HUE_BRIDGE_IP={bridge_ip}");
-            bridge_ip
-        };
-
-        let username = if let Ok(username) = std::env::var("HUE_USERNAME") {
-            username
-        } else {
-            println!("HUE_USERNAME env not set. Trying to register a new user.");
-
-            // Register a new user.
-            let username =
-                bridge::register_user(bridge_ip, "sapiens").expect("Failed to register user");
-            println!("Registered a new user - pass it as env: \nHUE_USERNAME={username}");
-            username
-        };
-
-        let bridge = bridge::Bridge::new(bridge_ip, username);
+        let bridge = crate::hue::shared_bridge();
 
         toolbox
             .add_tool(crate::hue::room::RoomTool::new(bridge.clone()))
@@ -60,6 +57,9 @@ pub async fn toolbox_from_env() -> Toolbox {
         toolbox
             .add_tool(crate::hue::status::SetStatusTool::new(bridge.clone()))
             .await;
+        toolbox
+            .add_tool(crate::hue::scene::SceneTool::new(bridge.clone()))
+            .await;
         toolbox
             .add_tool(crate::hue::status::StatusTool::new(bridge))
             .await;
@@ -73,6 +73,9 @@ pub async fn toolbox_from_env() -> Toolbox {
         toolbox
             .add_tool(wikipedia::WikipediaTool::new().await)
             .await;
+        toolbox
+            .add_tool(wikipedia::WikipediaLookupTool::new().await)
+            .await;
     }
 
     #[cfg(feature = "arxiv")]
@@ -82,12 +85,64 @@ pub async fn toolbox_from_env() -> Toolbox {
 
     #[cfg(feature = "summarize")]
     {
+        use sapiens::models::openai::ClientOptions;
+
+        let client = sapiens::models::openai::client(ClientOptions {
+            api_base: None,
+            ..ClientOptions::from_env()
+        });
+
         toolbox
-            .add_tool(crate::summarize::SummarizeTool::default())
+            .add_tool(crate::summarize::SummarizeTool::new(client))
             .await;
     }
 
+    #[cfg(feature = "read_page")]
+    {
+        use crate::read_page::ReadPageTool;
+
+        // Only if some hosts are allowed - none are by default
+        if let Ok(hosts) = std::env::var("READ_PAGE_ALLOWED_HOSTS") {
+            let tool = ReadPageTool::default().with_allowed_hosts(
+                hosts
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|h| !h.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            );
+
+            toolbox.add_tool(tool).await;
+        }
+    }
+
+    #[cfg(feature = "encode")]
+    {
+        toolbox.add_tool(crate::encode::EncodeTool::default()).await;
+    }
+
+    #[cfg(feature = "jq")]
+    {
+        toolbox.add_tool(crate::jq::JqTool::default()).await;
+    }
+
+    toolbox.add_tool(ScratchpadTool::default()).await;
     toolbox.add_terminal_tool(ConcludeTool::default()).await;
+    toolbox.add_terminal_tool(AbortTool::default()).await;
     toolbox.add_advanced_tool(PythonTool::default()).await;
     toolbox
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_default_tools() {
+        let toolbox = Toolbox::builder().with_default_tools().build();
+
+        let names = toolbox.describe().await.into_keys().collect::<Vec<_>>();
+        assert_eq!(names, vec!["Conclude", "Dummy", "SandboxedPython"]);
+        assert!(toolbox.has_terminal_tools().await);
+    }
+}
@@ -3,7 +3,9 @@ use std::fmt::Debug;
 use async_openai::config::OpenAIConfig;
 use async_openai::types::{CreateCompletionRequest, Prompt};
 use async_openai::Client;
-use sapiens::tools::{Describe, ProtoToolDescribe, ProtoToolInvoke, ToolDescription, ToolUseError};
+use sapiens::tools::{
+    Describe, ProtoToolDescribe, ProtoToolInvoke, SideEffects, ToolDescription, ToolUseError,
+};
 use sapiens_derive::{Describe, ProtoToolDescribe, ProtoToolInvoke};
 use serde::{Deserialize, Serialize};
 
@@ -12,7 +14,8 @@ use serde::{Deserialize, Serialize};
 #[tool(
     name = "Summarize",
     input = "SummarizeToolInput",
-    output = "SummarizeToolOutput"
+    output = "SummarizeToolOutput",
+    read_only
 )]
 #[allow(clippy::module_name_repetitions)]
 pub struct SummarizeTool {
@@ -43,6 +46,8 @@ impl SummarizeTool {
     }
 }
 
+/// Reads the API key from `OPENAI_API_KEY` - see
+/// [`sapiens::models::openai::client`] to pass it explicitly.
 impl Default for SummarizeTool {
     fn default() -> Self {
         Self {
@@ -1,3 +1,5 @@
+/// Rate-limiting and retries of the `MediaWiki` requests
+pub mod throttle;
 /// Tool to leverage Wikidata
 pub mod wikidata;
 /// Tool to leverage Wikipedia
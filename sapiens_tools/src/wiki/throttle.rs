@@ -0,0 +1,233 @@
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use mediawiki::reqwest::StatusCode;
+use mediawiki::MediaWikiError;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// Default minimum interval between two requests
+pub const DEFAULT_MIN_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Default maximum number of retries of a failed request
+pub const DEFAULT_MAX_RETRIES: usize = 3;
+
+/// Default delay before the first retry - doubled after each attempt
+pub const DEFAULT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// An error of a request that can be retried
+pub trait Retryable {
+    /// Whether the request can be retried
+    fn is_retryable(&self) -> bool;
+
+    /// How long to wait before retrying - as requested by the server
+    fn retry_after(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Retryable for MediaWikiError {
+    /// `429 Too Many Requests` are already retried by [`mediawiki`] after
+    /// `Retry-After` seconds - this covers the other transient failures.
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::Reqwest(e) => {
+                e.is_timeout()
+                    || e.is_connect()
+                    || e.status()
+                        .is_some_and(|s| s.is_server_error() || s == StatusCode::TOO_MANY_REQUESTS)
+            }
+            Self::String(s) => s.contains("maxlag"),
+            _ => false,
+        }
+    }
+}
+
+/// Spaces out and retries the requests to the `MediaWiki` APIs.
+///
+/// Shared by all the wiki tools so that an agent looping on them does not get
+/// throttled.
+#[derive(Debug)]
+pub struct Throttle {
+    min_interval: Duration,
+    max_retries: usize,
+    backoff: Duration,
+    last_request: Mutex<Option<Instant>>,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self::new(DEFAULT_MIN_INTERVAL)
+    }
+}
+
+impl Throttle {
+    /// Create a new [`Throttle`] with at least `min_interval` between two
+    /// requests
+    #[must_use]
+    pub const fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            max_retries: DEFAULT_MAX_RETRIES,
+            backoff: DEFAULT_BACKOFF,
+            last_request: Mutex::const_new(None),
+        }
+    }
+
+    /// Retry a failed request at most `max_retries` times, waiting `backoff`
+    /// before the first retry and doubling it after each one.
+    #[must_use]
+    pub const fn with_retries(mut self, max_retries: usize, backoff: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// The [`Throttle`] shared by the wiki tools
+    #[must_use]
+    pub fn shared() -> Arc<Self> {
+        static THROTTLE: OnceLock<Arc<Throttle>> = OnceLock::new();
+
+        THROTTLE.get_or_init(|| Arc::new(Self::default())).clone()
+    }
+
+    /// Wait until the next request is allowed
+    async fn wait_turn(&self) {
+        let mut last_request = self.last_request.lock().await;
+        if let Some(last) = *last_request {
+            tokio::time::sleep_until(last + self.min_interval).await;
+        }
+        *last_request = Some(Instant::now());
+    }
+
+    /// Run the request made by `f` - retrying it if it fails with a
+    /// [`Retryable`] error
+    ///
+    /// # Errors
+    ///
+    /// The error of the last attempt.
+    pub async fn run<T, E, F, Fut>(&self, mut f: F) -> Result<T, E>
+    where
+        E: Retryable,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut backoff = self.backoff;
+        let mut retries = 0;
+
+        loop {
+            self.wait_turn().await;
+
+            match f().await {
+                Err(e) if e.is_retryable() && retries < self.max_retries => {
+                    let delay = e.retry_after().unwrap_or(backoff);
+                    tracing::warn!(?delay, retries, "request failed - retrying");
+
+                    tokio::time::sleep(delay).await;
+                    backoff *= 2;
+                    retries += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum MockError {
+        TooManyRequests { retry_after: Duration },
+        NotFound,
+    }
+
+    impl Retryable for MockError {
+        fn is_retryable(&self) -> bool {
+            matches!(self, Self::TooManyRequests { .. })
+        }
+
+        fn retry_after(&self) -> Option<Duration> {
+            match self {
+                Self::TooManyRequests { retry_after } => Some(*retry_after),
+                Self::NotFound => None,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn waits_and_retries_on_too_many_requests() {
+        let throttle = Throttle::new(Duration::ZERO);
+        let attempts = AtomicUsize::new(0);
+
+        let start = Instant::now();
+        let res = throttle
+            .run(|| async {
+                if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    Err(MockError::TooManyRequests {
+                        retry_after: Duration::from_millis(50),
+                    })
+                } else {
+                    Ok("ok")
+                }
+            })
+            .await;
+
+        assert_eq!(res, Ok("ok"));
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let throttle = Throttle::new(Duration::ZERO).with_retries(2, Duration::from_millis(1));
+        let attempts = AtomicUsize::new(0);
+
+        let res: Result<(), _> = throttle
+            .run(|| async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(MockError::TooManyRequests {
+                    retry_after: Duration::from_millis(1),
+                })
+            })
+            .await;
+
+        assert!(res.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_other_errors() {
+        let throttle = Throttle::default();
+        let attempts = AtomicUsize::new(0);
+
+        let res: Result<(), _> = throttle
+            .run(|| async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(MockError::NotFound)
+            })
+            .await;
+
+        assert_eq!(res, Err(MockError::NotFound));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn spaces_out_the_requests() {
+        let throttle = Throttle::new(Duration::from_millis(50));
+
+        let start = Instant::now();
+        for _ in 0..3 {
+            throttle
+                .run(|| async { Ok::<_, MockError>(()) })
+                .await
+                .unwrap();
+        }
+
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
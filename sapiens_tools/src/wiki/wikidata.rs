@@ -1,10 +1,16 @@
+use std::collections::BTreeMap;
 use std::fmt::Debug;
+use std::sync::Arc;
 
 use mediawiki::api::Api;
-use sapiens::tools::{Describe, ProtoToolDescribe, ProtoToolInvoke, ToolDescription, ToolUseError};
+use sapiens::tools::{
+    Describe, ProtoToolDescribe, ProtoToolInvoke, SideEffects, ToolDescription, ToolUseError,
+};
 use sapiens_derive::{Describe, ProtoToolDescribe, ProtoToolInvoke};
 use serde::{Deserialize, Serialize};
 
+use crate::wiki::throttle::Throttle;
+
 /// A Tool to query Wikidata using SPARQL.
 ///
 /// Wikidata is a free and open knowledge base that can be read and edited by
@@ -17,13 +23,20 @@ use serde::{Deserialize, Serialize};
 #[tool(
     name = "Wikidata",
     input = "WikidataToolInput",
-    output = "WikidataToolOutput"
+    output = "WikidataToolOutput",
+    parallel_safe,
+    read_only
 )]
 #[allow(clippy::module_name_repetitions)]
 pub struct WikidataTool {
     client: Api,
+    throttle: Arc<Throttle>,
+    max_rows: usize,
 }
 
+/// Default maximum number of rows returned by [`WikidataTool`]
+pub const DEFAULT_MAX_ROWS: usize = 50;
+
 impl Debug for WikidataTool {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("WikidataTool").finish()
@@ -42,8 +55,13 @@ pub struct WikidataToolInput {
 #[derive(Debug, Deserialize, Serialize, Describe)]
 #[allow(clippy::module_name_repetitions)]
 pub struct WikidataToolOutput {
-    /// SPARQL query result - in JSON.
-    result: String,
+    /// SPARQL query result - one row per solution, mapping the variables to
+    /// their values. E.g.: `[{"country": "http://www.wikidata.org/entity/Q889",
+    /// "countryLabel": "Afghanistan"}]`
+    rows: Vec<BTreeMap<String, String>>,
+    /// Total number of rows of the result - only the first ones are returned
+    /// if there are too many. Use `LIMIT` to get less.
+    total_rows: usize,
 }
 
 impl WikidataTool {
@@ -56,7 +74,25 @@ impl WikidataTool {
         let client = Api::new("https://www.wikidata.org/w/api.php")
             .await
             .unwrap();
-        Self { client }
+        Self {
+            client,
+            throttle: Throttle::shared(),
+            max_rows: DEFAULT_MAX_ROWS,
+        }
+    }
+
+    /// Use `throttle` instead of the one shared by the wiki tools
+    #[must_use]
+    pub fn with_throttle(mut self, throttle: Arc<Throttle>) -> Self {
+        self.throttle = throttle;
+        self
+    }
+
+    /// Set the maximum number of rows returned
+    #[must_use]
+    pub const fn with_max_rows(mut self, max_rows: usize) -> Self {
+        self.max_rows = max_rows;
+        self
     }
 
     #[tracing::instrument(skip(self))]
@@ -65,13 +101,106 @@ impl WikidataTool {
         input: &WikidataToolInput,
     ) -> Result<WikidataToolOutput, ToolUseError> {
         let result = self
-            .client
-            .sparql_query(&input.query)
+            .throttle
+            .run(|| self.client.sparql_query(&input.query))
             .await
             .map_err(|e| ToolUseError::InvocationFailed(e.to_string()))?;
 
-        Ok(WikidataToolOutput {
-            result: serde_json::to_string(&result).unwrap(),
+        flatten_bindings(&result, self.max_rows)
+    }
+}
+
+/// Flatten the bindings of a SPARQL JSON `result` into rows - keeping at most
+/// `max_rows` of them
+fn flatten_bindings(
+    result: &serde_json::Value,
+    max_rows: usize,
+) -> Result<WikidataToolOutput, ToolUseError> {
+    let bindings = result["results"]["bindings"].as_array().ok_or_else(|| {
+        ToolUseError::InvocationFailed(format!("Unexpected SPARQL result: {result}"))
+    })?;
+
+    let rows = bindings
+        .iter()
+        .take(max_rows)
+        .map(|binding| {
+            binding
+                .as_object()
+                .map(|vars| {
+                    vars.iter()
+                        .map(|(var, v)| {
+                            let value = v["value"]
+                                .as_str()
+                                .map_or_else(|| v["value"].to_string(), ToString::to_string);
+                            (var.clone(), value)
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
         })
+        .collect();
+
+    Ok(WikidataToolOutput {
+        rows,
+        total_rows: bindings.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESULT: &str = r#"{
+        "head": {"vars": ["country", "countryLabel", "population"]},
+        "results": {"bindings": [
+            {
+                "country": {"type": "uri", "value": "http://www.wikidata.org/entity/Q889"},
+                "countryLabel": {"xml:lang": "en", "type": "literal", "value": "Afghanistan"},
+                "population": {"datatype": "http://www.w3.org/2001/XMLSchema#decimal", "type": "literal", "value": "41128771"}
+            },
+            {
+                "country": {"type": "uri", "value": "http://www.wikidata.org/entity/Q222"},
+                "countryLabel": {"xml:lang": "en", "type": "literal", "value": "Albania"}
+            }
+        ]}
+    }"#;
+
+    #[test]
+    fn flattens_the_bindings_into_rows() {
+        let result = serde_json::from_str(RESULT).unwrap();
+
+        let output = flatten_bindings(&result, DEFAULT_MAX_ROWS).unwrap();
+
+        assert_eq!(output.total_rows, 2);
+        assert_eq!(output.rows.len(), 2);
+        assert_eq!(
+            output.rows[0]["country"],
+            "http://www.wikidata.org/entity/Q889"
+        );
+        assert_eq!(output.rows[0]["countryLabel"], "Afghanistan");
+        assert_eq!(output.rows[0]["population"], "41128771");
+        // unbound variables are omitted
+        assert!(!output.rows[1].contains_key("population"));
+    }
+
+    #[test]
+    fn caps_the_number_of_rows() {
+        let result = serde_json::from_str(RESULT).unwrap();
+
+        let output = flatten_bindings(&result, 1).unwrap();
+
+        assert_eq!(output.total_rows, 2);
+        assert_eq!(output.rows.len(), 1);
+        assert_eq!(output.rows[0]["countryLabel"], "Afghanistan");
+    }
+
+    #[test]
+    fn rejects_unexpected_results() {
+        let result = serde_json::json!({"error": "oops"});
+
+        assert!(matches!(
+            flatten_bindings(&result, 1),
+            Err(ToolUseError::InvocationFailed(_))
+        ));
     }
 }
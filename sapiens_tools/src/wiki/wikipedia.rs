@@ -1,12 +1,17 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::sync::Arc;
 
 use mediawiki::api::Api;
-use sapiens::tools::{Describe, ProtoToolDescribe, ProtoToolInvoke, ToolDescription, ToolUseError};
+use sapiens::tools::{
+    Describe, ProtoToolDescribe, ProtoToolInvoke, SideEffects, ToolDescription, ToolUseError,
+};
 use sapiens_derive::{Describe, ProtoToolDescribe, ProtoToolInvoke};
 use serde::{Deserialize, Serialize};
 use serde_yaml::Value;
 
+use crate::wiki::throttle::Throttle;
+
 /// A Tool to query Wikipedia using SPARQL.
 ///
 /// Wikipedia is a free online encyclopedia, created and edited by volunteers
@@ -15,11 +20,14 @@ use serde_yaml::Value;
 #[tool(
     name = "Wikipedia",
     input = "WikipediaToolInput",
-    output = "WikipediaToolOutput"
+    output = "WikipediaToolOutput",
+    parallel_safe,
+    read_only
 )]
 #[allow(clippy::module_name_repetitions)]
 pub struct WikipediaTool {
     client: Api,
+    throttle: Arc<Throttle>,
 }
 
 impl Debug for WikipediaTool {
@@ -68,7 +76,17 @@ impl WikipediaTool {
             .await
             .unwrap();
 
-        Self { client }
+        Self {
+            client,
+            throttle: Throttle::shared(),
+        }
+    }
+
+    /// Use `throttle` instead of the one shared by the wiki tools
+    #[must_use]
+    pub fn with_throttle(mut self, throttle: Arc<Throttle>) -> Self {
+        self.throttle = throttle;
+        self
     }
 
     #[tracing::instrument(skip(self))]
@@ -103,8 +121,8 @@ impl WikipediaTool {
             .collect::<Result<_, _>>()?;
 
         let result = self
-            .client
-            .get_query_api_json_limit(&query, input.limit)
+            .throttle
+            .run(|| self.client.get_query_api_json_limit(&query, input.limit))
             .await
             .map_err(|e| ToolUseError::InvocationFailed(e.to_string()))?;
 
@@ -114,6 +132,185 @@ impl WikipediaTool {
     }
 }
 
+/// Maximum number of characters of an extract returned by
+/// [`WikipediaLookupTool`]
+pub const MAX_EXTRACT_CHARS: usize = 4000;
+
+/// A Tool to look up a Wikipedia page by its title.
+///
+/// Simpler to use than [`WikipediaTool`] to get the summary, the content or
+/// the categories of a page.
+#[derive(ProtoToolInvoke, ProtoToolDescribe)]
+#[tool(
+    name = "WikipediaLookup",
+    input = "WikipediaLookupToolInput",
+    output = "WikipediaLookupToolOutput",
+    parallel_safe,
+    read_only
+)]
+#[allow(clippy::module_name_repetitions)]
+pub struct WikipediaLookupTool {
+    client: Api,
+    throttle: Arc<Throttle>,
+}
+
+impl Debug for WikipediaLookupTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WikipediaLookupTool").finish()
+    }
+}
+
+/// What to look up on a Wikipedia page
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WikipediaProperty {
+    /// The introduction of the page - as plain text
+    #[default]
+    Summary,
+    /// The whole page - as plain text
+    Extract,
+    /// The categories of the page
+    Categories,
+}
+
+/// [`WikipediaLookupTool`] input
+#[derive(Debug, Deserialize, Serialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct WikipediaLookupToolInput {
+    /// Title of the page. E.g.: `Albert Einstein`.
+    title: String,
+    /// What to look up: `summary` (default), `extract` for the whole page or
+    /// `categories`.
+    #[serde(default)]
+    property: WikipediaProperty,
+}
+
+/// [`WikipediaLookupTool`] output
+#[derive(Debug, Default, Deserialize, Serialize, Describe)]
+#[allow(clippy::module_name_repetitions)]
+pub struct WikipediaLookupToolOutput {
+    /// Title of the page.
+    title: String,
+    /// Summary or content of the page - truncated if too long.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extract: Option<String>,
+    /// Categories of the page. E.g.: `["Category:1879 births", ...]`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    categories: Option<Vec<String>>,
+}
+
+impl WikipediaLookupTool {
+    /// Create a new [`WikipediaLookupTool`]
+    ///
+    /// # Panics
+    /// Panics if the API URL is invalid.
+    pub async fn new() -> Self {
+        let client = Api::new("https://en.wikipedia.org/w/api.php")
+            .await
+            .unwrap();
+
+        Self {
+            client,
+            throttle: Throttle::shared(),
+        }
+    }
+
+    /// Use `throttle` instead of the one shared by the wiki tools
+    #[must_use]
+    pub fn with_throttle(mut self, throttle: Arc<Throttle>) -> Self {
+        self.throttle = throttle;
+        self
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn invoke_typed(
+        &self,
+        input: &WikipediaLookupToolInput,
+    ) -> Result<WikipediaLookupToolOutput, ToolUseError> {
+        let params = lookup_parameters(&input.title, input.property);
+
+        // follows the continuations
+        let result = self
+            .throttle
+            .run(|| self.client.get_query_api_json_all(&params))
+            .await
+            .map_err(|e| ToolUseError::InvocationFailed(e.to_string()))?;
+
+        parse_lookup(&result, &input.title, input.property)
+    }
+}
+
+/// The query parameters to look up `property` of the page `title`
+fn lookup_parameters(title: &str, property: WikipediaProperty) -> HashMap<String, String> {
+    let mut params = vec![("action", "query"), ("titles", title), ("redirects", "1")];
+    match property {
+        WikipediaProperty::Summary => {
+            params.extend([("prop", "extracts"), ("exintro", "1"), ("explaintext", "1")]);
+        }
+        WikipediaProperty::Extract => {
+            params.extend([("prop", "extracts"), ("explaintext", "1")]);
+        }
+        WikipediaProperty::Categories => {
+            params.extend([("prop", "categories"), ("cllimit", "max")]);
+        }
+    }
+
+    params
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+/// Extract `property` of the page from the query `result`
+fn parse_lookup(
+    result: &serde_json::Value,
+    title: &str,
+    property: WikipediaProperty,
+) -> Result<WikipediaLookupToolOutput, ToolUseError> {
+    let page = result["query"]["pages"]
+        .as_object()
+        .and_then(|pages| pages.values().next())
+        .filter(|page| page.get("missing").is_none())
+        .ok_or_else(|| {
+            ToolUseError::InvocationFailed(format!("No Wikipedia page titled {title:?}"))
+        })?;
+
+    let mut output = WikipediaLookupToolOutput {
+        title: page["title"].as_str().unwrap_or(title).to_string(),
+        ..WikipediaLookupToolOutput::default()
+    };
+
+    match property {
+        WikipediaProperty::Summary | WikipediaProperty::Extract => {
+            let extract = page["extract"].as_str().unwrap_or_default();
+            output.extract = Some(truncate(extract, MAX_EXTRACT_CHARS));
+        }
+        WikipediaProperty::Categories => {
+            output.categories = Some(
+                page["categories"]
+                    .as_array()
+                    .map(|categories| {
+                        categories
+                            .iter()
+                            .filter_map(|c| c["title"].as_str().map(ToString::to_string))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            );
+        }
+    }
+
+    Ok(output)
+}
+
+/// Keep at most `max_chars` characters of `text`
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((idx, _)) => format!("{}...", &text[..idx]),
+        None => text.to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use indoc::indoc;
@@ -220,4 +417,67 @@ mod tests {
             })
             .await;
     }
+
+    #[test]
+    fn test_wikipedia_lookup_categories() {
+        let params = lookup_parameters("Albert Einstein", WikipediaProperty::Categories);
+        assert_eq!(params["prop"], "categories");
+        assert_eq!(params["titles"], "Albert Einstein");
+
+        let result = serde_json::json!({
+            "batchcomplete": "",
+            "query": {"pages": {"736": {
+                "pageid": 736,
+                "ns": 0,
+                "title": "Albert Einstein",
+                "categories": [
+                    {"ns": 14, "title": "Category:1879 births"},
+                    {"ns": 14, "title": "Category:Nobel laureates in Physics"}
+                ]
+            }}}
+        });
+
+        let output =
+            parse_lookup(&result, "Albert Einstein", WikipediaProperty::Categories).unwrap();
+
+        assert_eq!(output.title, "Albert Einstein");
+        assert_eq!(
+            output.categories.unwrap(),
+            vec![
+                "Category:1879 births",
+                "Category:Nobel laureates in Physics"
+            ]
+        );
+        assert!(output.extract.is_none());
+    }
+
+    #[test]
+    fn test_wikipedia_lookup_summary() {
+        let input: WikipediaLookupToolInput =
+            serde_yaml::from_str("title: Albert Einstein").unwrap();
+        assert_eq!(input.property, WikipediaProperty::Summary);
+
+        let extract = "é".repeat(MAX_EXTRACT_CHARS + 1);
+        let result = serde_json::json!({
+            "query": {"pages": {"736": {"title": "Albert Einstein", "extract": extract}}}
+        });
+
+        let output = parse_lookup(&result, &input.title, input.property).unwrap();
+
+        let extract = output.extract.unwrap();
+        assert_eq!(extract.chars().count(), MAX_EXTRACT_CHARS + 3);
+        assert!(extract.ends_with("é..."));
+    }
+
+    #[test]
+    fn test_wikipedia_lookup_missing_page() {
+        let result = serde_json::json!({
+            "query": {"pages": {"-1": {"ns": 0, "title": "Nope", "missing": ""}}}
+        });
+
+        assert!(matches!(
+            parse_lookup(&result, "Nope", WikipediaProperty::Summary),
+            Err(ToolUseError::InvocationFailed(_))
+        ));
+    }
 }
@@ -3,9 +3,10 @@ use insta::assert_snapshot;
 use pyo3::PyResult;
 use sapiens::tools::invocation::Error;
 use sapiens::tools::toolbox::{invoke_tool, InvokeResult, Toolbox};
+use sapiens::tools::{FieldFormat, Format, Tool, ToolDescription, ToolUseError};
 use sapiens_tools::conclude::ConcludeTool;
 use sapiens_tools::dummy::DummyTool;
-use sapiens_tools::python::PythonTool;
+use sapiens_tools::python::{PythonTool, PythonToolOutput};
 
 #[pyo3_asyncio::tokio::test]
 async fn test_tool_invocation() -> PyResult<()> {
@@ -246,3 +247,185 @@ async fn test_python_docstring() -> PyResult<()> {
 
     Ok(())
 }
+
+/// A tool without parameters
+struct ClockTool;
+
+#[async_trait::async_trait]
+impl Tool for ClockTool {
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "Clock",
+            "Tells the time",
+            Format::default(),
+            Format {
+                fields: vec![FieldFormat {
+                    name: "time".to_string(),
+                    r#type: "str".to_string(),
+                    optional: false,
+                    description: "The time".to_string(),
+                }],
+            },
+        )
+    }
+
+    async fn invoke(&self, input: serde_yaml::Value) -> Result<serde_yaml::Value, ToolUseError> {
+        assert_eq!(
+            input,
+            serde_yaml::Value::Mapping(serde_yaml::Mapping::new())
+        );
+
+        Ok(serde_yaml::from_str("time: '12:00'").unwrap())
+    }
+}
+
+#[pyo3_asyncio::tokio::test]
+async fn test_tool_without_parameters_invocation_in_python() -> PyResult<()> {
+    let data = indoc! {r"
+    # Action
+    ```yaml
+    tool_name: SandboxedPython
+    parameters:
+        code: |
+            print(tools.clock()['time'])
+            print(tools.Clock()['time'])
+    ```
+    "};
+
+    let toolbox = Toolbox::default();
+    toolbox.add_advanced_tool(PythonTool::default()).await;
+    toolbox.add_tool(ClockTool).await;
+
+    let res = invoke_tool(toolbox, data).await;
+
+    match res {
+        InvokeResult::Success {
+            tool_name, result, ..
+        } => {
+            assert_eq!(tool_name, "SandboxedPython");
+            assert_eq!(result, "stdout: |\n  12:00\n  12:00\nstderr: ''\n");
+        }
+        _ => panic!("Unexpected response: {res:?}"),
+    }
+
+    Ok(())
+}
+
+/// A tool with a name that is not a valid Python identifier
+struct ThreeMStatusTool;
+
+#[async_trait::async_trait]
+impl Tool for ThreeMStatusTool {
+    fn description(&self) -> ToolDescription {
+        ToolDescription::new(
+            "3M-Status",
+            "Tells the status of 3M",
+            Format::default(),
+            Format::default(),
+        )
+    }
+
+    async fn invoke(&self, _input: serde_yaml::Value) -> Result<serde_yaml::Value, ToolUseError> {
+        Ok(serde_yaml::from_str("status: ok").unwrap())
+    }
+}
+
+#[pyo3_asyncio::tokio::test]
+async fn test_tool_with_invalid_python_name_invocation_in_python() -> PyResult<()> {
+    let data = indoc! {r"
+    # Action
+    ```yaml
+    tool_name: SandboxedPython
+    parameters:
+        code: |
+            print(sorted(m for m in dir(tools) if '3' in m))
+            print(tools._3_m_status()['status'])
+    ```
+    "};
+
+    let toolbox = Toolbox::default();
+    toolbox.add_advanced_tool(PythonTool::default()).await;
+    toolbox.add_tool(ThreeMStatusTool).await;
+
+    let res = invoke_tool(toolbox, data).await;
+
+    match res {
+        InvokeResult::Success {
+            tool_name, result, ..
+        } => {
+            assert_eq!(tool_name, "SandboxedPython");
+            assert_eq!(
+                result,
+                "stdout: |\n  ['_3MStatus', '_3_m_status']\n  ok\nstderr: ''\n"
+            );
+        }
+        _ => panic!("Unexpected response: {res:?}"),
+    }
+
+    Ok(())
+}
+
+#[pyo3_asyncio::tokio::test]
+async fn test_python_docstring_describes_the_parameters() -> PyResult<()> {
+    let toolbox = Toolbox::default();
+    toolbox.add_terminal_tool(ConcludeTool::default()).await;
+    toolbox.add_advanced_tool(PythonTool::default()).await;
+
+    let data = indoc! {r"
+    ```yaml
+    tool_name: SandboxedPython
+    parameters:
+        code: |
+            print(tools.conclude.__doc__)
+    ```
+    "};
+
+    let res = invoke_tool(toolbox, data).await;
+
+    match res {
+        InvokeResult::Success { result, .. } => {
+            let output: PythonToolOutput = serde_yaml::from_str(&result).unwrap();
+            assert!(output.stdout.contains("Args:\n"), "{}", output.stdout);
+            assert!(output.stdout.contains("original_question: <str> "));
+            assert!(output.stdout.contains("conclusion: <str> "));
+            assert!(output.stdout.contains("data: <"));
+            assert!(output.stdout.contains("> (Optional) "));
+        }
+        _ => panic!("Unexpected response: {res:?}"),
+    }
+
+    Ok(())
+}
+
+#[pyo3_asyncio::tokio::test]
+async fn test_fetch_a_stashed_result_in_python() -> PyResult<()> {
+    let data = indoc! {r#"
+    # Action
+    ```yaml
+    tool_name: SandboxedPython
+    parameters:
+        code: |
+            data = tools.fetch("result-1")
+            print(len(data.split(",")))
+    ```
+    "#};
+
+    let toolbox = Toolbox::default();
+    toolbox.add_advanced_tool(PythonTool::default()).await;
+    let handle = toolbox.stash_result("1,".repeat(999) + "1");
+    assert_eq!(handle, "result-1");
+
+    let res = invoke_tool(toolbox, data).await;
+
+    match res {
+        InvokeResult::Success {
+            tool_name, result, ..
+        } => {
+            assert_eq!(tool_name, "SandboxedPython");
+            assert_eq!(result, "stdout: |\n  1000\nstderr: ''\n");
+        }
+        _ => panic!("Unexpected response: {res:?}"),
+    }
+
+    Ok(())
+}